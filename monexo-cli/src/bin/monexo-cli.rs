@@ -2,16 +2,27 @@ use clap::{Parser, Subcommand};
 use console::{style, Term};
 use dialoguer::Confirm;
 use monexo_core::{
-    primitives::{MeltOnchainState, PostMeltOnchainResponse, PostMintQuoteOnchainResponse},
+    primitives::{
+        CurrencyUnit, MeltOnchainState, PostMeltOnchainResponse, PostMintQuoteOnchainResponse,
+    },
     token::TokenV3,
 };
-use monexo_wallet::{http::CrossPlatformHttpClient, localstore::WalletKeysetFilter};
+use monexo_wallet::{
+    http::CrossPlatformHttpClient, localstore::sqlite::SqliteLocalStore, wallet::Wallet,
+};
 use monexocli::cli::{self, choose_mint, get_mints_with_balance};
 use num_format::{Locale, ToFormattedString};
 use qrcode::{render::unicode, QrCode};
+use tracing_subscriber::EnvFilter;
 use url::Url;
 
-use std::{path::PathBuf, str::FromStr};
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+/// limits used if the mint doesn't return them in its info
+const DEFAULT_MIN_AMOUNT: u64 = 10_000_000;
+const DEFAULT_MAX_AMOUNT: u64 = 1_000_000_000;
+/// mint used if no mint url is given and the wallet doesn't know any mint
+const DEFAULT_MINT_URL: &str = "http://127.0.0.1:3338/";
 
 #[derive(Parser)]
 #[command(arg_required_else_help(true))]
@@ -19,6 +30,22 @@ struct Opts {
     #[clap(short, long)]
     db_dir: Option<PathBuf>,
 
+    /// Mint to use, defaults to the known mint with the largest balance
+    #[clap(short, long, env = "MONEXO_MINT_URL")]
+    mint_url: Option<Url>,
+
+    /// Seconds after which a request to the mint is aborted
+    #[clap(long, default_value_t = 30)]
+    timeout: u64,
+
+    /// How often a request to the mint is retried if the mint is unreachable
+    #[clap(long, default_value_t = 3)]
+    max_retries: u32,
+
+    /// Api key sent as bearer token, required by mints that are configured with one
+    #[clap(long, env = "MONEXO_MINT_API_KEY")]
+    api_key: Option<String>,
+
     #[clap(subcommand)]
     command: Command,
 }
@@ -26,30 +53,91 @@ struct Opts {
 #[derive(Subcommand, Clone)]
 enum Command {
     /// Mint tokens
-    Mint { amount: u64 },
+    Mint {
+        amount: u64,
+        /// Number of payment references, the payment may be split into one transfer per
+        /// reference
+        #[clap(long)]
+        references: Option<u8>,
+        /// Milliseconds between two checks whether the quote has been paid
+        #[clap(long, default_value_t = 500)]
+        poll_interval: u64,
+        /// Seconds to wait for the payment before giving up
+        #[clap(long, default_value_t = 600)]
+        max_wait: u64,
+    },
+
+    /// Create a mint quote and show how to pay it without waiting for the payment, the tokens
+    /// are minted by resume once it is paid
+    Quote {
+        amount: u64,
+        /// Number of payment references, the payment may be split into one transfer per
+        /// reference
+        #[clap(long)]
+        references: Option<u8>,
+    },
 
     /// Pay micro USDC on chain
     PayOnchain { address: String, amount: u64 },
 
     /// Send tokens
-    Send { amount: u64 },
+    Send {
+        amount: u64,
+        /// Send existing proofs without contacting the mint, fails if they don't add up to
+        /// exactly the amount
+        #[clap(long, default_value_t = false)]
+        offline: bool,
+        /// Note for the receiver that is attached to the token
+        #[clap(long)]
+        memo: Option<String>,
+    },
 
     /// Receive tokens
-    Receive { token: String },
+    Receive {
+        token: String,
+        /// Receive only the proofs of the token that haven't been spent yet
+        #[clap(long, default_value_t = false)]
+        partial: bool,
+    },
 
     /// Show local balance
-    Balance,
+    Balance {
+        /// Show how many tokens of each denomination are held per keyset
+        #[clap(short, long, default_value_t = false)]
+        verbose: bool,
+    },
 
     /// Show version and configuration
     Info,
+
+    /// Show past mints, melts, sends and receives
+    History,
+
+    /// Retry minting the tokens of paid quotes that haven't been minted
+    Resume,
+
+    /// Recompute the balance from the seed and compare it with the local balance
+    Audit,
+
+    /// Swap the tokens of the active keyset into as few tokens as possible
+    Consolidate,
+
+    /// Show the supported NUTs and capabilities of a mint
+    MintCaps { url: Url },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    use monexo_wallet::localstore::sqlite::SqliteLocalStore;
-
     let cli = Opts::parse();
 
+    // the wallet logs to stderr only if enabled, e.g. with MONEXO_LOG=debug
+    if let Ok(filter) = EnvFilter::try_from_env("MONEXO_LOG") {
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+
     let db_path = match cli.db_dir {
         Some(dir) => {
             std::fs::create_dir_all(dir.clone())?;
@@ -61,7 +149,9 @@ async fn main() -> anyhow::Result<()> {
 
     let term = Term::stdout();
     let localstore = SqliteLocalStore::with_path(db_path.clone()).await?;
-    let client = CrossPlatformHttpClient::new();
+    let client =
+        CrossPlatformHttpClient::with_config(Duration::from_secs(cli.timeout), cli.max_retries)
+            .with_api_key(cli.api_key);
 
     let wallet = monexo_wallet::wallet::WalletBuilder::default()
         .with_client(client)
@@ -80,82 +170,67 @@ async fn main() -> anyhow::Result<()> {
             e
         })?;
 
-    let mint_url = Url::parse("http://127.0.0.1:3338/").unwrap();
+    let mint_url = match cli.mint_url {
+        Some(mint_url) => mint_url,
+        None => match choose_mint(&wallet).await? {
+            Some((mint_url, _)) => mint_url,
+            None => Url::parse(DEFAULT_MINT_URL)?,
+        },
+    };
 
-    let wallet_keysets = wallet.get_wallet_keysets().await;
-    if wallet_keysets.unwrap().len() == 0 {
-        wallet.add_mint_keysets(&mint_url).await?;
+    if !wallet.list_mints().await?.contains(&mint_url) {
+        wallet.add_mint(&mint_url).await?;
     }
 
-    match cli.command {
-        Command::Mint { amount } => {
-            let mint_info = wallet.get_mint_info(&mint_url).await?;
-
-            let (quote, fee) = {
-                // TODO: Fetch this from backend
-                let min_amount: u64 = 10_000_000;
-                if amount < min_amount {
-                    term.write_line(&format!(
-                        "Amount too low. Minimum amount is {} (micro usd)",
-                        min_amount.to_formatted_string(&Locale::en)
-                    ))?;
-                    return Ok(());
-                }
-
-                // TODO: Fetch this from backend
-                let max_amount: u64 = 1_000_000_000;
-                if amount > max_amount {
-                    term.write_line(&format!(
-                        "Amount too high. Maximum amount is {} (micro usd)",
-                        max_amount.to_formatted_string(&Locale::en)
-                    ))?;
-                    return Ok(());
-                }
-
-                let PostMintQuoteOnchainResponse {
-                    reference,
-                    quote,
-                    fee,
-                    ..
-                } = wallet.create_quote_onchain(&mint_url, amount).await?;
-
-                term.write_line(&format!(
-                    "Pay onchain to mint tokens,
-                    \n amount: {amount}
-                    \n fee: {fee}
-                    \n you will receive tokens worth {} micro usd",
-                    (amount - fee)
-                ))?;
+    // recover the outputs of a swap that was interrupted before its proofs were stored
+    let recovered = wallet.resume_swaps().await?;
+    if recovered > 0 {
+        term.write_line(&format!(
+            "Recovered {} from an interrupted swap",
+            cli::format_amount(recovered, &CurrencyUnit::Usd)
+        ))?;
+    }
 
-                let amount_usd = amount as f64 / 1_000_000 as f64;
-                let address_string = mint_info.usdc_address;
-                let token_mint = mint_info.usdc_token_mint;
-                let bip21_code = format!("solana:{}?amount={}&spl-token={}&reference={}&label=Monexo&message=Thank%20you!", address_string, amount_usd, token_mint, reference);
-                let image = QrCode::new(bip21_code)?
-                    .render::<unicode::Dense1x2>()
-                    .quiet_zone(true)
-                    .build();
-                term.write_line(&image)?;
-                (quote, fee)
+    match cli.command {
+        Command::Mint {
+            amount,
+            references,
+            poll_interval,
+            max_wait,
+        } => {
+            let Some((quote, fee)) =
+                create_mint_quote(&term, &wallet, &mint_url, amount, references).await?
+            else {
+                return Ok(());
             };
 
-            let wallet_keysets = wallet.get_wallet_keysets().await?;
-            let wallet_keyset = wallet_keysets.get_active().expect("Keyset not found");
+            let wallet_keysets = wallet.get_wallet_keysets_for_mint(&mint_url).await?;
+            let wallet_keyset = cli::get_active_keyset(&wallet_keysets, &CurrencyUnit::Usd);
 
             let progress_bar = cli::progress_bar()?;
             progress_bar.set_message("Waiting for payment ...");
 
-            loop {
-                tokio::time::sleep_until(
-                    tokio::time::Instant::now() + std::time::Duration::from_millis(500),
+            let poll_interval = Duration::from_millis(poll_interval);
+            let paid = wallet
+                .wait_for_mint_payment(
+                    &mint_url,
+                    quote.clone(),
+                    poll_interval,
+                    Duration::from_secs(max_wait),
+                    |status| {
+                        progress_bar.set_message(cli::payment_progress_message(status, amount))
+                    },
                 )
-                .await;
-
-                if !wallet.is_quote_paid(&mint_url, quote.clone()).await? {
-                    continue;
-                }
+                .await?;
+            if !paid {
+                progress_bar.finish_with_message(format!(
+                    "Timeout: quote {quote} has not been paid within {max_wait} seconds. \
+                     Run resume to mint the tokens once it is paid.\n"
+                ));
+                return Ok(());
+            }
 
-                // FIXME store quote in db and add option to retry minting later
+            loop {
                 let amount = amount - fee;
                 let mint_result = wallet
                     .mint_tokens(&mint_url, wallet_keyset, amount.into(), quote.clone())
@@ -163,11 +238,15 @@ async fn main() -> anyhow::Result<()> {
 
                 match mint_result {
                     Ok(_) => {
-                        progress_bar.finish_with_message("Tokens minted successfully.\n");
+                        progress_bar.finish_with_message(format!(
+                            "Minted {} successfully.\n",
+                            cli::format_amount(amount, &wallet_keyset.currency_unit)
+                        ));
                         cli::show_total_balance(&wallet).await?;
                         break;
                     }
                     Err(monexo_wallet::error::MonexoWalletError::InvoiceNotPaidYet(_, _)) => {
+                        tokio::time::sleep(poll_interval).await;
                         continue;
                     }
                     Err(e) => {
@@ -177,7 +256,17 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
         }
-        Command::Balance => {
+        Command::Quote { amount, references } => {
+            let Some((quote, _)) =
+                create_mint_quote(&term, &wallet, &mint_url, amount, references).await?
+            else {
+                return Ok(());
+            };
+            term.write_line(&format!(
+                "Stored quote {quote}. Run resume to mint the tokens once it is paid."
+            ))?;
+        }
+        Command::Balance { verbose } => {
             let total_balance = wallet.get_balance().await?;
             if total_balance > 0 {
                 let mints = get_mints_with_balance(&wallet).await?;
@@ -186,13 +275,71 @@ async fn main() -> anyhow::Result<()> {
                     style(mints.len()).cyan()
                 ))?;
 
-                for mint in mints {
+                for (mint, balance) in mints {
                     term.write_line(&format!(
-                        " - {} (micro usd)",
-                        style(mint.to_formatted_string(&Locale::en)).cyan()
+                        " - {mint}: {}",
+                        style(cli::format_amount(balance, &CurrencyUnit::Usd)).cyan()
                     ))?;
                 }
             }
+            cli::show_balance_by_currency(&wallet).await?;
+            if verbose {
+                cli::show_denominations(&wallet.denomination_report().await?)?;
+            }
+        }
+        Command::History => {
+            let history = wallet.get_history().await?;
+            if history.is_empty() {
+                term.write_line("No transactions found")?;
+                return Ok(());
+            }
+            cli::show_history(&history)?;
+        }
+        Command::Resume => {
+            let quotes = wallet.get_pending_mint_quotes().await?;
+            if quotes.is_empty() {
+                term.write_line("No pending quotes found")?;
+                return Ok(());
+            }
+
+            for quote in quotes {
+                // the quote is minted by the mint it was requested from
+                let wallet_keysets = wallet.get_wallet_keysets_for_mint(&quote.mint_url).await?;
+                let wallet_keyset = cli::get_active_keyset(&wallet_keysets, &CurrencyUnit::Usd);
+                let minted = wallet.resume_mint_quote(wallet_keyset, &quote).await?;
+                let status = match minted {
+                    Some(tokens) => style(format!(
+                        "minted {} (micro usd)",
+                        tokens.total_amount().to_formatted_string(&Locale::en)
+                    ))
+                    .green(),
+                    None => style("not minted".to_owned()).yellow(),
+                };
+                term.write_line(&format!(
+                    "Quote {} {} (micro usd): {status}",
+                    quote.quote,
+                    quote.amount.to_formatted_string(&Locale::en)
+                ))?;
+            }
+            cli::show_total_balance(&wallet).await?;
+        }
+        Command::Audit => {
+            let progress_bar = cli::progress_bar()?;
+            progress_bar.set_message("Auditing wallet ...");
+            let report = wallet.deep_audit(&mint_url).await?;
+            progress_bar.finish_and_clear();
+            cli::show_audit_report(&report)?;
+        }
+        Command::Consolidate => {
+            let wallet_keysets = wallet.get_wallet_keysets_for_mint(&mint_url).await?;
+            let wallet_keyset = cli::get_active_keyset(&wallet_keysets, &CurrencyUnit::Usd);
+
+            let (before, after) = wallet.consolidate(&mint_url, wallet_keyset).await?;
+            if before == after {
+                term.write_line(&format!("Already optimal: {before} tokens"))?;
+            } else {
+                term.write_line(&format!("Consolidated {before} tokens into {after}"))?;
+            }
             cli::show_total_balance(&wallet).await?;
         }
         Command::Info => {
@@ -200,37 +347,80 @@ async fn main() -> anyhow::Result<()> {
             let db_path = style(db_path).cyan();
             term.write_line(&format!("Version: {wallet_version}"))?;
             term.write_line(&format!("DB: {db_path}"))?;
+
+            let mint_info = wallet.get_mint_info(&mint_url).await?;
+            cli::show_mint_info(&mint_url, &mint_info)?;
         }
-        Command::Send { amount } => {
-            let mint_balance = choose_mint(&wallet).await?;
+        Command::MintCaps { url } => {
+            let mint_info = wallet.get_mint_info(&url).await?;
+            if mint_info.is_stale {
+                term.write_line("Mint is unreachable, showing cached capabilities")?;
+            }
+            cli::show_mint_capabilities(&url, &mint_info.info.nuts)?;
+        }
+        Command::Send {
+            amount,
+            offline,
+            memo,
+        } => {
+            let mint_balance = wallet.get_mint_balance(&mint_url).await?;
             if mint_balance < amount {
                 term.write_line("Error: Not enough tokens in mint")?;
                 return Ok(());
             }
 
-            let wallet_keysets = wallet.get_wallet_keysets().await?;
-            let wallet_keyset = wallet_keysets.get_active().expect("no active keyset found");
-
-            term.write_line(&format!("Sending tokens from mint"))?;
-            let result = wallet.send_tokens(&mint_url, wallet_keyset, amount).await?;
+            let wallet_keysets = wallet.get_wallet_keysets_for_mint(&mint_url).await?;
+            let wallet_keyset = cli::get_active_keyset(&wallet_keysets, &CurrencyUnit::Usd);
+
+            term.write_line("Sending tokens from mint")?;
+            let result = if offline {
+                wallet
+                    .send_tokens_offline(&mint_url, wallet_keyset, amount)
+                    .await?
+                    .with_memo(memo)
+            } else {
+                wallet
+                    .send_tokens_with_memo(&mint_url, wallet_keyset, amount, memo)
+                    .await?
+            };
             let tokens: String = result.try_into()?;
 
-            term.write_line(&format!("Result {amount} (micro usd):\n{tokens}"))?;
+            term.write_line(&format!(
+                "Result {}:\n{tokens}",
+                cli::format_amount(amount, &wallet_keyset.currency_unit)
+            ))?;
             cli::show_total_balance(&wallet).await?;
         }
-        Command::Receive { token } => {
+        Command::Receive { token, partial } => {
             let token: TokenV3 = TokenV3::from_str(&token)?;
-            let wallet_keysets = wallet.get_wallet_keysets().await?;
-            let wallet_keyset = wallet_keysets.get_active().expect("no active keyset found");
-
-            wallet
-                .receive_tokens(&mint_url, wallet_keyset, &token)
-                .await?;
+            let token_mint_url = token.mint().unwrap_or_else(|| mint_url.clone());
+            if !wallet.list_mints().await?.contains(&token_mint_url) {
+                wallet.add_mint(&token_mint_url).await?;
+            }
+            let wallet_keysets = wallet.get_wallet_keysets_for_mint(&token_mint_url).await?;
+            let unit = token.currency_unit.clone().unwrap_or(CurrencyUnit::Usd);
+            let wallet_keyset = cli::get_active_keyset(&wallet_keysets, &unit);
+
+            if partial {
+                let amount = wallet
+                    .receive_tokens_partial(&token_mint_url, wallet_keyset, &token)
+                    .await?;
+                term.write_line(&format!(
+                    "Received {amount} of {}",
+                    cli::format_amount(token.total_amount(), &unit)
+                ))?;
+            } else {
+                let amount = wallet.receive(wallet_keyset, &token).await?;
+                term.write_line(&format!("Received {}", cli::format_amount(amount, &unit)))?;
+            }
+            if let Some(memo) = &token.memo {
+                term.write_line(&format!("Memo: {memo}"))?;
+            }
             cli::show_total_balance(&wallet).await?;
         }
         Command::PayOnchain { address, amount } => {
-            // TODO: Fetch this from backend
-            let min_amount: u64 = 10_000_000;
+            let mint_info = wallet.get_mint_info(&mint_url).await?.info;
+            let min_amount = mint_info.min_amount.unwrap_or(DEFAULT_MIN_AMOUNT);
             if amount < min_amount {
                 term.write_line(&format!(
                     "Amount too low. Minimum amount is {} (micro usd)",
@@ -239,8 +429,7 @@ async fn main() -> anyhow::Result<()> {
                 return Ok(());
             }
 
-            // TODO: Fetch this from backend
-            let max_amount: u64 = 1_000_000_000;
+            let max_amount = mint_info.max_amount.unwrap_or(DEFAULT_MAX_AMOUNT);
             if amount > max_amount {
                 term.write_line(&format!(
                     "Amount too high. Maximum amount is {} (micro usd)",
@@ -249,14 +438,14 @@ async fn main() -> anyhow::Result<()> {
                 return Ok(());
             }
 
-            let mint_balance = choose_mint(&wallet).await?;
+            let mint_balance = wallet.get_mint_balance(&mint_url).await?;
             if mint_balance < amount {
                 term.write_line("Error: Not enough tokens in mint")?;
                 return Ok(());
             }
 
-            let wallet_keysets = wallet.get_wallet_keysets().await?;
-            let wallet_keyset = wallet_keysets.get_active().expect("no active keyset found");
+            let wallet_keysets = wallet.get_wallet_keysets_for_mint(&mint_url).await?;
+            let wallet_keyset = cli::get_active_keyset(&wallet_keysets, &CurrencyUnit::Usd);
 
             let quotes = wallet
                 .get_melt_quote_onchain(&mint_url, address.clone(), amount)
@@ -283,7 +472,7 @@ async fn main() -> anyhow::Result<()> {
                 return Ok(());
             }
 
-            let PostMeltOnchainResponse { state, txid } =
+            let PostMeltOnchainResponse { state, txid, .. } =
                 wallet.pay_onchain(&mint_url, wallet_keyset, quote).await?;
 
             if let Some(txid) = txid.clone() {
@@ -313,3 +502,76 @@ async fn main() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+/// Creates a mint quote, which the wallet keeps until its tokens are minted, and shows the
+/// Solana Pay request to pay it. Returns the quote id and fee, None if the amount is outside
+/// the limits of the mint.
+async fn create_mint_quote(
+    term: &Term,
+    wallet: &Wallet<SqliteLocalStore, CrossPlatformHttpClient>,
+    mint_url: &Url,
+    amount: u64,
+    references: Option<u8>,
+) -> anyhow::Result<Option<(String, u64)>> {
+    let mint_info = wallet.get_mint_info(mint_url).await?;
+    if mint_info.is_stale {
+        term.write_line("Warning: mint info is unavailable, using the last known info")?;
+    }
+    let mint_info = mint_info.info;
+
+    let min_amount = mint_info.min_amount.unwrap_or(DEFAULT_MIN_AMOUNT);
+    if amount < min_amount {
+        term.write_line(&format!(
+            "Amount too low. Minimum amount is {} (micro usd)",
+            min_amount.to_formatted_string(&Locale::en)
+        ))?;
+        return Ok(None);
+    }
+
+    let max_amount = mint_info.max_amount.unwrap_or(DEFAULT_MAX_AMOUNT);
+    if amount > max_amount {
+        term.write_line(&format!(
+            "Amount too high. Maximum amount is {} (micro usd)",
+            max_amount.to_formatted_string(&Locale::en)
+        ))?;
+        return Ok(None);
+    }
+
+    let PostMintQuoteOnchainResponse {
+        reference,
+        additional_references,
+        quote,
+        fee,
+        ..
+    } = wallet
+        .create_quote_onchain(mint_url, amount, references)
+        .await?;
+
+    term.write_line(&format!(
+        "Pay onchain to mint tokens,
+        \n amount: {amount}
+        \n fee: {fee}
+        \n you will receive tokens worth {}",
+        cli::format_amount(amount - fee, &CurrencyUnit::Usd)
+    ))?;
+
+    let bip21_code = cli::solana_pay_uri(
+        &mint_info.usdc_address,
+        amount,
+        &mint_info.usdc_token_mint,
+        &reference,
+    );
+    let image = QrCode::new(&bip21_code)?
+        .render::<unicode::Dense1x2>()
+        .quiet_zone(true)
+        .build();
+    term.write_line(&image)?;
+    term.write_line(&bip21_code)?;
+    if !additional_references.is_empty() {
+        term.write_line(&format!(
+            "The payment may be split into transfers to the references {reference}, {}",
+            additional_references.join(", ")
+        ))?;
+    }
+    Ok(Some((quote, fee)))
+}
@@ -3,12 +3,16 @@ use std::time::Duration;
 
 use console::{style, Term};
 use indicatif::{ProgressBar, ProgressStyle};
+use monexo_core::primitives::{CurrencyUnit, NutSupported, Nuts, PaymentMethodSettings};
 use monexo_wallet::error::MonexoWalletError;
 use monexo_wallet::{
-    http::CrossPlatformHttpClient, localstore::sqlite::SqliteLocalStore, wallet::Wallet,
+    http::CrossPlatformHttpClient,
+    localstore::{sqlite::SqliteLocalStore, HistoryEntry, WalletKeyset, WalletKeysetFilter},
+    wallet::{AuditReport, KeysetDenominations, MintInfo, MintQuoteStatus, Wallet},
 };
 use num_format::Locale;
 use num_format::ToFormattedString;
+use url::Url;
 
 pub fn progress_bar() -> anyhow::Result<ProgressBar> {
     let pb = ProgressBar::new_spinner();
@@ -17,43 +21,453 @@ pub fn progress_bar() -> anyhow::Result<ProgressBar> {
     Ok(pb)
 }
 
+/// Label of the unit in cli output, usd amounts are denominated in micro usd
+pub fn unit_label(unit: &CurrencyUnit) -> String {
+    match unit {
+        CurrencyUnit::Usd => "micro usd".to_owned(),
+        CurrencyUnit::MUsd => "musd".to_owned(),
+        CurrencyUnit::Ugx => "ugx".to_owned(),
+        CurrencyUnit::Sat => "sat".to_owned(),
+        CurrencyUnit::Other(unit) => unit.to_owned(),
+    }
+}
+
+/// Formats the amount with thousands separators followed by the label of its unit, e.g. `1,000 (micro usd)`
+pub fn format_amount(amount: u64, unit: &CurrencyUnit) -> String {
+    format!(
+        "{} ({})",
+        amount.to_formatted_string(&Locale::en),
+        unit_label(unit)
+    )
+}
+
+/// Formats micro usd as an amount of USDC, which has 6 decimals, e.g. `1.5` for 1,500,000
+pub fn micro_usd_to_usdc_string(micro_usd: u64) -> String {
+    let whole = micro_usd / 1_000_000;
+    match micro_usd % 1_000_000 {
+        0 => whole.to_string(),
+        fraction => format!("{whole}.{}", format!("{fraction:06}").trim_end_matches('0')),
+    }
+}
+
+/// Builds the Solana Pay transfer request paying `micro_usd` of the token to the recipient
+pub fn solana_pay_uri(
+    recipient: &str,
+    micro_usd: u64,
+    token_mint: &str,
+    reference: &str,
+) -> String {
+    format!(
+        "solana:{recipient}?amount={}&spl-token={token_mint}&reference={reference}&label=Monexo&message=Thank%20you!",
+        micro_usd_to_usdc_string(micro_usd)
+    )
+}
+
+/// Message shown while waiting for the payment of a quote of `amount` micro usd. Tells the user
+/// if the mint received only part of the amount so far.
+pub fn payment_progress_message(status: &MintQuoteStatus, amount: u64) -> String {
+    match status.amount_paid {
+        Some(amount_paid) if amount_paid > 0 && amount_paid < amount => format!(
+            "Received {} of {} USDC, still waiting ...",
+            micro_usd_to_usdc_string(amount_paid),
+            micro_usd_to_usdc_string(amount)
+        ),
+        _ => "Waiting for payment ...".to_owned(),
+    }
+}
+
 pub async fn show_total_balance(
     wallet: &Wallet<SqliteLocalStore, CrossPlatformHttpClient>,
 ) -> anyhow::Result<()> {
     let term = Term::stdout();
     term.write_line(&format!(
-        "New total balance {} (micro usd)",
-        style(wallet.get_balance().await?.to_formatted_string(&Locale::en)).cyan()
+        "New total balance {}",
+        style(format_amount(
+            wallet.get_balance().await?,
+            &CurrencyUnit::Usd
+        ))
+        .cyan()
     ))?;
     Ok(())
 }
 
-pub async fn choose_mint(
+pub async fn show_balance_by_currency(
     wallet: &Wallet<SqliteLocalStore, CrossPlatformHttpClient>,
-) -> Result<u64, MonexoWalletError> {
-    let mints = get_mints_with_balance(wallet).await?;
+) -> anyhow::Result<()> {
+    let term = Term::stdout();
+    let mut balances = wallet
+        .get_balance_by_currency()
+        .await?
+        .into_iter()
+        .collect::<Vec<_>>();
+    if balances.is_empty() {
+        term.write_line("Total balance 0")?;
+        return Ok(());
+    }
 
-    if mints.is_empty() {
-        println!("No mints found.");
-        exit(0)
+    balances.sort_by_key(|(unit, _)| unit.to_string());
+    for (unit, amount) in balances {
+        term.write_line(&format!(
+            "Total balance {}",
+            style(format_amount(amount, &unit)).cyan()
+        ))?;
     }
+    Ok(())
+}
+
+/// Returns the active keyset of the unit, prints a message and exits if the mint has none
+pub fn get_active_keyset<'a>(
+    wallet_keysets: &'a [WalletKeyset],
+    unit: &CurrencyUnit,
+) -> &'a WalletKeyset {
+    match wallet_keysets.get_active_for_currency(unit) {
+        Ok(keyset) => keyset,
+        Err(_) => {
+            println!(
+                "Error: The mint has no active {unit} keyset. It may have deactivated its keysets, try again later or use another mint."
+            );
+            exit(1)
+        }
+    }
+}
+
+/// Returns the known mint with the largest balance, the oldest mint if no mint has a balance
+pub async fn choose_mint(
+    wallet: &Wallet<SqliteLocalStore, CrossPlatformHttpClient>,
+) -> Result<Option<(Url, u64)>, MonexoWalletError> {
+    let mints = get_mints_with_balance(wallet).await?;
 
-    Ok(mints[0].clone())
+    Ok(mints
+        .into_iter()
+        .reduce(|chosen, mint| if mint.1 > chosen.1 { mint } else { chosen }))
 }
 
+/// Returns the known mints with their balance, oldest mint first
 pub async fn get_mints_with_balance(
     wallet: &Wallet<SqliteLocalStore, CrossPlatformHttpClient>,
-) -> Result<Vec<u64>, MonexoWalletError> {
-    let all_proofs = wallet.get_proofs().await?;
+) -> Result<Vec<(Url, u64)>, MonexoWalletError> {
+    let mut mints = vec![];
+    for mint_url in wallet.list_mints().await? {
+        let balance = wallet.get_mint_balance(&mint_url).await?;
+        mints.push((mint_url, balance));
+    }
+    Ok(mints)
+}
+
+pub fn show_mint_capabilities(mint_url: &Url, nuts: &Nuts) -> anyhow::Result<()> {
+    let term = Term::stdout();
+    term.write_line(&format!("Mint: {}", style(mint_url).cyan()))?;
+    // swap is mandatory, mints usually don't list it
+    let nut03 = nuts.nut03.clone().or(Some(true.into()));
+    term.write_line(&format!("Swap (NUT-03): {}", format_supported(&nut03)))?;
+    term.write_line(&format!("Mint (NUT-04): {}", format_methods(&nuts.nut04)))?;
+    term.write_line(&format!("Melt (NUT-05): {}", format_methods(&nuts.nut05)))?;
+    term.write_line(&format!(
+        "Checkstate (NUT-07): {}",
+        format_supported(&nuts.nut07)
+    ))?;
+    term.write_line(&format!(
+        "Restore (NUT-09): {}",
+        format_supported(&nuts.nut09)
+    ))?;
+    term.write_line(&format!(
+        "Spending conditions (NUT-10): {}",
+        format_supported(&nuts.nut10)
+    ))?;
+    term.write_line(&format!("P2PK (NUT-11): {}", format_supported(&nuts.nut11)))?;
+    term.write_line(&format!("DLEQ (NUT-12): {}", format_supported(&nuts.nut12)))?;
 
-    let keysets = wallet.get_wallet_keysets().await?;
-    if keysets.is_empty() {
-        println!("No mints found. Add a mint first with 'monexo-cli add-mint <mint-url>'");
-        exit(0)
+    let mut units = nuts
+        .nut04
+        .iter()
+        .chain(nuts.nut05.iter())
+        .flat_map(|settings| {
+            settings
+                .methods
+                .iter()
+                .map(|method| method.unit.to_string())
+        })
+        .collect::<Vec<_>>();
+    units.sort();
+    units.dedup();
+    term.write_line(&format!("Units: {}", style(units.join(", ")).cyan()))?;
+    Ok(())
+}
+
+/// Describes the mint for the `Info` command, values the mint didn't return are shown as unknown
+pub fn mint_info_lines(mint_url: &Url, mint_info: &MintInfo) -> Vec<String> {
+    let info = &mint_info.info;
+    let known = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_owned());
+    let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_owned());
+    let amount =
+        |amount: Option<u64>| known(amount.map(|amount| format_amount(amount, &CurrencyUnit::Usd)));
+
+    vec![
+        format!("Mint: {mint_url}"),
+        format!("Name: {}", known(info.name.clone())),
+        format!("Version: {}", known(info.version.clone())),
+        format!("USDC address: {}", known(non_empty(&info.usdc_address))),
+        format!(
+            "USDC token mint: {}",
+            known(non_empty(&info.usdc_token_mint))
+        ),
+        format!("Min amount: {}", amount(info.min_amount)),
+        format!("Max amount: {}", amount(info.max_amount)),
+    ]
+}
+
+pub fn show_mint_info(mint_url: &Url, mint_info: &MintInfo) -> anyhow::Result<()> {
+    let term = Term::stdout();
+    if mint_info.is_stale {
+        term.write_line(
+            &style("Warning: mint is unreachable, showing the last known info")
+                .yellow()
+                .to_string(),
+        )?;
+    }
+    for line in mint_info_lines(mint_url, mint_info) {
+        term.write_line(&line)?;
+    }
+    Ok(())
+}
+
+pub fn show_history(history: &[HistoryEntry]) -> anyhow::Result<()> {
+    let term = Term::stdout();
+    for entry in history {
+        let amount = entry.amount.to_formatted_string(&Locale::en);
+        let amount = match entry.kind.is_incoming() {
+            true => style(format!("+{amount}")).green(),
+            false => style(format!("-{amount}")).red(),
+        };
+        let memo = entry
+            .memo
+            .as_ref()
+            .map(|memo| format!(" \"{memo}\""))
+            .unwrap_or_default();
+        let kind = entry.kind.to_string();
+        term.write_line(&format!(
+            "{} {kind:<8} {amount} {} {}{memo}",
+            entry.timestamp, entry.unit, entry.mint_url
+        ))?;
+    }
+    Ok(())
+}
+
+pub fn show_denominations(report: &[KeysetDenominations]) -> anyhow::Result<()> {
+    let term = Term::stdout();
+    for keyset in report {
+        term.write_line(&format!("Keyset {} ({}):", keyset.keyset_id, keyset.unit))?;
+        for (amount, count) in keyset.denominations.iter() {
+            term.write_line(&format!(
+                " {:>12} x {}",
+                amount.to_formatted_string(&Locale::en),
+                style(count).cyan()
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn show_audit_report(report: &AuditReport) -> anyhow::Result<()> {
+    let term = Term::stdout();
+    term.write_line(&format!(
+        "Balance at the mint: {} (micro usd)",
+        style(report.mint_balance.to_formatted_string(&Locale::en)).cyan()
+    ))?;
+    term.write_line(&format!(
+        "Local balance: {} (micro usd)",
+        style(report.local_balance.to_formatted_string(&Locale::en)).cyan()
+    ))?;
+
+    if report.missing_proofs.is_empty() && report.spent_local_proofs.is_empty() {
+        term.write_line(
+            &style("Wallet is consistent with the mint")
+                .green()
+                .to_string(),
+        )?;
+        return Ok(());
+    }
+    term.write_line(&format!(
+        "Unspent at the mint but missing locally: {} (micro usd), includes sent tokens that are not claimed yet",
+        style(report.missing_proofs.total_amount().to_formatted_string(&Locale::en)).yellow()
+    ))?;
+    term.write_line(&format!(
+        "Spent at the mint but stored locally: {} (micro usd)",
+        style(
+            report
+                .spent_local_proofs
+                .total_amount()
+                .to_formatted_string(&Locale::en)
+        )
+        .red()
+    ))?;
+    Ok(())
+}
+
+fn format_supported(nut: &Option<NutSupported>) -> String {
+    match nut {
+        Some(NutSupported { supported: true }) => style("yes").green().to_string(),
+        _ => style("no").red().to_string(),
+    }
+}
+
+fn format_methods(settings: &Option<PaymentMethodSettings>) -> String {
+    match settings {
+        Some(settings) if !settings.disabled => settings
+            .methods
+            .iter()
+            .map(|method| {
+                let limits = match (method.min_amount, method.max_amount) {
+                    (Some(min), Some(max)) => format!(" {min}-{max}"),
+                    (Some(min), None) => format!(" min {min}"),
+                    (None, Some(max)) => format!(" max {max}"),
+                    (None, None) => String::new(),
+                };
+                style(format!("{} ({}{limits})", method.method, method.unit))
+                    .green()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => style("no").red().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monexo_core::primitives::{CurrencyUnit, MintInfoResponse, MintOnchainState};
+    use monexo_wallet::wallet::{MintInfo, MintQuoteStatus};
+    use url::Url;
+
+    use super::{
+        format_amount, micro_usd_to_usdc_string, mint_info_lines, payment_progress_message,
+        solana_pay_uri,
+    };
+
+    #[test]
+    fn test_mint_info_lines() -> anyhow::Result<()> {
+        let mint_info = MintInfo {
+            info: MintInfoResponse {
+                name: Some("monexo".to_owned()),
+                version: Some("monexo-mint/0.1.0-abc123".to_owned()),
+                usdc_address: "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM".to_owned(),
+                usdc_token_mint: "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU".to_owned(),
+                min_amount: Some(5_000),
+                max_amount: Some(50_000),
+                ..Default::default()
+            },
+            is_stale: false,
+        };
+
+        let lines = mint_info_lines(&Url::parse("http://127.0.0.1:3338")?, &mint_info);
+        assert_eq!(
+            vec![
+                "Mint: http://127.0.0.1:3338/",
+                "Name: monexo",
+                "Version: monexo-mint/0.1.0-abc123",
+                "USDC address: HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+                "USDC token mint: 4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU",
+                "Min amount: 5,000 (micro usd)",
+                "Max amount: 50,000 (micro usd)",
+            ],
+            lines
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mint_info_lines_unknown_mint() -> anyhow::Result<()> {
+        // a mint that doesn't send the optional fields
+        let mint_info = MintInfo {
+            info: MintInfoResponse::default(),
+            is_stale: true,
+        };
+
+        let lines = mint_info_lines(&Url::parse("http://127.0.0.1:3338")?, &mint_info);
+        assert_eq!(7, lines.len());
+        assert!(lines[1..].iter().all(|line| line.ends_with(": unknown")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_amount_usd() {
+        assert_eq!(
+            "1,000 (micro usd)",
+            format_amount(1_000, &CurrencyUnit::Usd)
+        );
+    }
+
+    #[test]
+    fn test_format_amount_musd() {
+        assert_eq!("21 (musd)", format_amount(21, &CurrencyUnit::MUsd));
+    }
+
+    #[test]
+    fn test_format_amount_ugx() {
+        assert_eq!(
+            "1,234,567 (ugx)",
+            format_amount(1_234_567, &CurrencyUnit::Ugx)
+        );
+    }
+
+    #[test]
+    fn test_format_amount_sat() {
+        assert_eq!("64 (sat)", format_amount(64, &CurrencyUnit::Sat));
+    }
+
+    #[test]
+    fn test_format_amount_other() {
+        assert_eq!(
+            "5 (eur)",
+            format_amount(5, &CurrencyUnit::Other("eur".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_micro_usd_to_usdc_string() {
+        assert_eq!("0", micro_usd_to_usdc_string(0));
+        assert_eq!("0.000001", micro_usd_to_usdc_string(1));
+        assert_eq!("0.3", micro_usd_to_usdc_string(300_000));
+        assert_eq!("1", micro_usd_to_usdc_string(1_000_000));
+        assert_eq!("10", micro_usd_to_usdc_string(10_000_000));
+        assert_eq!("10.000001", micro_usd_to_usdc_string(10_000_001));
+        assert_eq!("1.5005", micro_usd_to_usdc_string(1_500_500));
+        assert_eq!("1000", micro_usd_to_usdc_string(1_000_000_000));
+    }
+
+    #[test]
+    fn test_solana_pay_uri() {
+        assert_eq!(
+            "solana:HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM?amount=10.5\
+             &spl-token=4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU\
+             &reference=5t6gQ7Mnr3mmsFYquFGwgEKokq9wrrUgCpwWab93LmLL\
+             &label=Monexo&message=Thank%20you!",
+            solana_pay_uri(
+                "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+                10_500_000,
+                "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU",
+                "5t6gQ7Mnr3mmsFYquFGwgEKokq9wrrUgCpwWab93LmLL",
+            )
+        );
+    }
+
+    #[test]
+    fn test_payment_progress_message() {
+        let status = |amount_paid| MintQuoteStatus {
+            state: MintOnchainState::Unpaid,
+            amount_paid,
+        };
+        assert_eq!(
+            "Received 5 of 10.5 USDC, still waiting ...",
+            payment_progress_message(&status(Some(5_000_000)), 10_500_000)
+        );
+        assert_eq!(
+            "Waiting for payment ...",
+            payment_progress_message(&status(Some(0)), 10_500_000)
+        );
+        assert_eq!(
+            "Waiting for payment ...",
+            payment_progress_message(&status(None), 10_500_000)
+        );
     }
-    Ok(keysets
-        .into_iter()
-        .filter(|k| k.active)
-        .map(|k| all_proofs.proofs_by_keyset(&k.keyset_id).total_amount())
-        .collect::<Vec<u64>>())
 }
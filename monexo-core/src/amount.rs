@@ -1,12 +1,15 @@
 //! This module defines the `Amount` and `SplitAmount` structs, which are used for representing and splitting amounts.
 //!
-//! The `Amount` struct represents an amount in dollars, with a single `u64` field for the amount. The struct provides a `split` method that splits the amount into a `SplitAmount` struct.
+//! The `Amount` struct represents an amount in dollars, with a single `u64` field for the amount. The struct provides a `split` method that splits the amount into a `SplitAmount` struct, and `split_with_max` for keysets that don't have keys for every power of 2.
 //!
 //! The `SplitAmount` struct represents a split amount, with a `Vec<u64>` field for the split amounts. The struct provides a `create_secrets` method that generates a vector of random strings for use as secrets in the split transaction. The struct also implements the `IntoIterator` trait, which allows it to be iterated over as a vector of `u64` values.
 //!
 //! Both the `Amount` and `SplitAmount` structs are serializable and deserializable using serde.
 
 use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::MonexoCoreError;
 
 // #[derive(Debug, Clone)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
@@ -16,6 +19,21 @@ impl Amount {
     pub fn split(&self) -> SplitAmount {
         split_amount(self.0).into()
     }
+
+    /// Splits the amount into powers of 2 that are not bigger than `max_denomination`.
+    /// The part above `max_denomination` is split into multiple `max_denomination` amounts.
+    pub fn split_with_max(&self, max_denomination: u64) -> SplitAmount {
+        if max_denomination == 0 {
+            return self.split();
+        }
+
+        let mut split = split_amount(self.0 % max_denomination);
+        split.extend(std::iter::repeat_n(
+            max_denomination,
+            (self.0 / max_denomination) as usize,
+        ));
+        split.into()
+    }
 }
 
 impl From<u64> for Amount {
@@ -45,16 +63,13 @@ impl std::ops::AddAssign for Amount {
 }
 
 impl std::ops::Sub for Amount {
-    type Output = Amount;
+    type Output = Result<Amount, MonexoCoreError>;
 
     fn sub(self, rhs: Amount) -> Self::Output {
-        Amount(self.0 - rhs.0)
-    }
-}
-
-impl std::ops::SubAssign for Amount {
-    fn sub_assign(&mut self, other: Self) {
-        self.0 -= other.0;
+        self.0
+            .checked_sub(rhs.0)
+            .map(Amount)
+            .ok_or(MonexoCoreError::AmountUnderflow(self.0, rhs.0))
     }
 }
 
@@ -102,6 +117,22 @@ impl IntoIterator for SplitAmount {
     }
 }
 
+/// Sums the amounts, saturating at `u64::MAX` instead of overflowing. A sum that large can only
+/// come from bogus input, so it fails every comparison against a real amount.
+pub fn sum_amounts(amounts: impl IntoIterator<Item = u64>) -> u64 {
+    let mut total: u64 = 0;
+    for amount in amounts {
+        match total.checked_add(amount) {
+            Some(sum) => total = sum,
+            None => {
+                warn!("sum of amounts overflows, saturating at {}", u64::MAX);
+                return u64::MAX;
+            }
+        }
+    }
+    total
+}
+
 /// split a decimal amount into a vector of powers of 2
 fn split_amount(amount: u64) -> Vec<u64> {
     format!("{amount:b}")
@@ -119,6 +150,8 @@ fn split_amount(amount: u64) -> Vec<u64> {
 
 #[cfg(test)]
 mod tests {
+    use super::Amount;
+    use crate::error::MonexoCoreError;
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -133,4 +166,48 @@ mod tests {
         assert_eq!(bits, vec![64]);
         Ok(())
     }
+
+    #[test]
+    fn test_split_with_max() -> anyhow::Result<()> {
+        let split: Vec<u64> = Amount(13).split_with_max(64).into_iter().collect();
+        assert_eq!(split, vec![1, 4, 8]);
+
+        let split: Vec<u64> = Amount(200).split_with_max(64).into_iter().collect();
+        assert_eq!(split, vec![8, 64, 64, 64]);
+
+        let split: Vec<u64> = Amount(128).split_with_max(64).into_iter().collect();
+        assert_eq!(split, vec![64, 64]);
+
+        let split: Vec<u64> = Amount(13).split_with_max(0).into_iter().collect();
+        assert_eq!(split, vec![1, 4, 8]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sub() -> anyhow::Result<()> {
+        assert_eq!(Amount(3), (Amount(10) - Amount(7))?);
+        assert_eq!(Amount(0), (Amount(7) - Amount(7))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sub_underflow() {
+        assert!(matches!(
+            Amount(7) - Amount(10),
+            Err(MonexoCoreError::AmountUnderflow(7, 10))
+        ));
+    }
+
+    #[test]
+    fn test_sum_amounts() {
+        assert_eq!(0, super::sum_amounts(vec![]));
+        assert_eq!(7, super::sum_amounts(vec![1, 2, 4]));
+        assert_eq!(u64::MAX, super::sum_amounts(vec![u64::MAX, 0]));
+    }
+
+    #[test]
+    fn test_sum_amounts_overflow() {
+        assert_eq!(u64::MAX, super::sum_amounts(vec![u64::MAX, 1]));
+        assert_eq!(u64::MAX, super::sum_amounts(vec![1 << 63, 1 << 63, 1]));
+    }
 }
@@ -2,25 +2,130 @@
 //!
 //! The `BlindedMessage` struct represents a blinded message, with an `amount` field for the amount in USDs and a `b_` field for the public key of the blinding factor.
 //!
-//! The `BlindedSignature` struct represents a blinded signature, with an `amount` field for the amount in USDs, a `c_` field for the public key of the blinding factor, and an optional `id` field for the ID of the signature.
+//! The `BlindedSignature` struct represents a blinded signature, with an `amount` field for the amount in USDs, a `c_` field for the public key of the blinding factor, and an `id` field for the ID of the keyset and an optional `dleq` proof as described in [Nut-12](https://github.com/cashubtc/nuts/blob/main/12.md).
 //!
 //! Both the `BlindedMessage` and `BlindedSignature` structs are serializable and deserializable using serde.
 //!
-//! The `TotalAmount` trait is also defined in this module, which provides a `total_amount` method for calculating the total amount of a vector of `BlindedMessage` or `BlindedSignature` structs. The trait is implemented for both `Vec<BlindedMessage>` and `Vec<BlindedSignature>`.
+//! The `TotalAmount` trait is also defined in this module, which provides a `total_amount` method for calculating the total amount of a slice of `BlindedMessage` or `BlindedSignature` structs. The trait is implemented for both `[BlindedMessage]` and `[BlindedSignature]`.
 
-use secp256k1::{PublicKey, SecretKey};
+use std::sync::LazyLock;
+
+use bitcoin_hashes::{sha256, Hash};
+use secp256k1::{All, PublicKey, Scalar, Secp256k1, SecretKey};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::error::MonexoCoreError;
+use crate::{amount::sum_amounts, error::MonexoCoreError};
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+static SECP: LazyLock<Secp256k1<All>> = LazyLock::new(Secp256k1::new);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub struct BlindedSignature {
     pub amount: u64,
     #[serde(rename = "C_")]
     #[schema(value_type=String)]
     pub c_: PublicKey,
     pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dleq: Option<BlindSignatureDleq>,
+}
+
+impl BlindedSignature {
+    /// Signs the blinded message with the mint's private key `a` for its amount, `c_ = a*b_`.
+    ///
+    /// Attaches a DLEQ proof if `with_dleq` is set.
+    pub fn new(
+        blinded_message: &BlindedMessage,
+        a: &SecretKey,
+        with_dleq: bool,
+    ) -> Result<Self, MonexoCoreError> {
+        let c_ = blinded_message.b_.mul_tweak(&SECP, &Scalar::from(*a))?;
+        let dleq = match with_dleq {
+            true => Some(calculate_dleq(a, &blinded_message.b_, &c_)?),
+            false => None,
+        };
+        Ok(Self {
+            amount: blinded_message.amount,
+            c_,
+            id: blinded_message.id.clone(),
+            dleq,
+        })
+    }
+}
+
+/// DLEQ proof that a blinded signature was created with the mint's private key, see [Nut-12](https://github.com/cashubtc/nuts/blob/main/12.md)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct BlindSignatureDleq {
+    #[schema(value_type=String)]
+    pub e: SecretKey,
+    #[schema(value_type=String)]
+    pub s: SecretKey,
+}
+
+/// Creates the DLEQ proof for the blinded signature `c_ = a*b_`
+///
+/// ```text
+/// r = random nonce
+/// R1 = r*G, R2 = r*B'
+/// e = hash(R1, R2, A, C')
+/// s = r + e*a
+/// ```
+pub fn calculate_dleq(
+    a: &SecretKey,
+    b_: &PublicKey,
+    c_: &PublicKey,
+) -> Result<BlindSignatureDleq, MonexoCoreError> {
+    let r = SecretKey::new(&mut secp256k1::rand::thread_rng());
+    let r1 = r.public_key(&SECP);
+    let r2 = b_.mul_tweak(&SECP, &Scalar::from(r))?;
+    let e = hash_e([r1, r2, a.public_key(&SECP), *c_])?;
+    let s = r.add_tweak(&Scalar::from(a.mul_tweak(&Scalar::from(e))?))?;
+    Ok(BlindSignatureDleq { e, s })
+}
+
+/// Verifies the DLEQ proof of a blinded signature against the mint's public key `A` for its amount
+///
+/// ```text
+/// R1 = s*G - e*A
+/// R2 = s*B' - e*C'
+/// e == hash(R1, R2, A, C')
+/// ```
+///
+/// Signatures without DLEQ proof are accepted, because the mint isn't required to send one.
+pub fn verify_dleq(
+    blinded_message: &BlindedMessage,
+    blinded_signature: &BlindedSignature,
+    mint_pubkey: &PublicKey,
+) -> Result<(), MonexoCoreError> {
+    let Some(dleq) = &blinded_signature.dleq else {
+        return Ok(());
+    };
+
+    let e = Scalar::from(dleq.e);
+    let r1 = dleq
+        .s
+        .public_key(&SECP)
+        .combine(&mint_pubkey.mul_tweak(&SECP, &e)?.negate(&SECP))?;
+    let r2 = blinded_message
+        .b_
+        .mul_tweak(&SECP, &Scalar::from(dleq.s))?
+        .combine(&blinded_signature.c_.mul_tweak(&SECP, &e)?.negate(&SECP))?;
+
+    match hash_e([r1, r2, *mint_pubkey, blinded_signature.c_])? == dleq.e {
+        true => Ok(()),
+        false => Err(MonexoCoreError::InvalidDleq(blinded_signature.amount)),
+    }
+}
+
+/// Hashes the hex encoded uncompressed public keys
+pub(crate) fn hash_e(public_keys: [PublicKey; 4]) -> Result<SecretKey, MonexoCoreError> {
+    let message = public_keys
+        .iter()
+        .map(|key| hex::encode(key.serialize_uncompressed()))
+        .collect::<String>();
+    Ok(SecretKey::from_slice(
+        &sha256::Hash::hash(message.as_bytes()).to_byte_array(),
+    )?)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -65,14 +170,90 @@ pub trait TotalAmount {
     fn total_amount(&self) -> u64;
 }
 
-impl TotalAmount for Vec<BlindedSignature> {
+impl TotalAmount for [BlindedSignature] {
     fn total_amount(&self) -> u64 {
-        self.iter().fold(0, |acc, x| acc + x.amount)
+        sum_amounts(self.iter().map(|x| x.amount))
     }
 }
 
-impl TotalAmount for Vec<BlindedMessage> {
+impl TotalAmount for [BlindedMessage] {
     fn total_amount(&self) -> u64 {
-        self.iter().fold(0, |acc, x| acc + x.amount)
+        sum_amounts(self.iter().map(|x| x.amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::SecretKey;
+
+    use crate::{
+        blind::{verify_dleq, BlindedMessage, BlindedSignature, TotalAmount, SECP},
+        dhke::Dhke,
+    };
+
+    fn create_signature() -> anyhow::Result<(BlindedMessage, BlindedSignature, SecretKey)> {
+        let a = SecretKey::new(&mut secp256k1::rand::thread_rng());
+        let message = BlindedMessage {
+            amount: 8,
+            b_: Dhke::hash_to_curve(b"test_message")?,
+            id: "00d31cecf59d18c0".to_string(),
+        };
+        let signature = BlindedSignature::new(&message, &a, true)?;
+        Ok((message, signature, a))
+    }
+
+    #[test]
+    fn test_blinded_signature_new() -> anyhow::Result<()> {
+        let (message, signature, a) = create_signature()?;
+
+        assert_eq!(Dhke::new().step2_bob(message.b_, &a)?, signature.c_);
+        assert_eq!(message.id, signature.id);
+        assert!(BlindedSignature::new(&message, &a, false)?.dleq.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_dleq() -> anyhow::Result<()> {
+        let (message, signature, a) = create_signature()?;
+
+        assert!(verify_dleq(&message, &signature, &a.public_key(&SECP)).is_ok());
+
+        let without_dleq = BlindedSignature {
+            dleq: None,
+            ..signature
+        };
+        assert!(verify_dleq(&message, &without_dleq, &a.public_key(&SECP)).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_dleq_tampered() -> anyhow::Result<()> {
+        let (message, signature, a) = create_signature()?;
+
+        let mut tampered = signature.clone();
+        if let Some(dleq) = tampered.dleq.as_mut() {
+            dleq.s = SecretKey::new(&mut secp256k1::rand::thread_rng());
+        }
+        assert!(verify_dleq(&message, &tampered, &a.public_key(&SECP)).is_err());
+
+        // a signature of another key doesn't verify against the mint's key
+        let other_key = SecretKey::new(&mut secp256k1::rand::thread_rng()).public_key(&SECP);
+        assert!(verify_dleq(&message, &signature, &other_key).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_total_amount_saturates() -> anyhow::Result<()> {
+        let (message, _, _) = create_signature()?;
+        let messages = [
+            BlindedMessage {
+                amount: u64::MAX,
+                ..message.clone()
+            },
+            message.clone(),
+        ];
+        assert_eq!(u64::MAX, messages.total_amount());
+        assert_eq!(8, [message].total_amount());
+        Ok(())
     }
 }
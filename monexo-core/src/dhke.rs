@@ -41,7 +41,11 @@
 //! If true, C must have originated from Bob
 //!
 //!
-use crate::{blind::BlindingFactor, error::MonexoCoreError};
+use crate::{
+    blind::{hash_e, BlindingFactor},
+    error::MonexoCoreError,
+    proof::ProofDleq,
+};
 use bitcoin_hashes::{sha256, Hash};
 use secp256k1::{All, PublicKey, Scalar, Secp256k1, SecretKey};
 use std::iter::once;
@@ -129,6 +133,37 @@ impl Dhke {
             MonexoCoreError::Secp256k1Error(secp256k1::Error::InvalidPublicKey),
         )
     }
+
+    /// Checks that the unblinded signature `c` for `secret_msg` is `k*Y` with the DLEQ proof
+    /// of its signature, see [Nut-12](https://github.com/cashubtc/nuts/blob/main/12.md). The
+    /// mint's public key `K` alone can't prove it.
+    ///
+    /// ```text
+    /// B' = Y + r*G, C' = C + r*K
+    /// R1 = s*G - e*K, R2 = s*B' - e*C'
+    /// e == hash(R1, R2, K, C')
+    /// ```
+    pub fn verify_dleq(
+        &self,
+        k: PublicKey,
+        c: PublicKey,
+        secret_msg: impl Into<String>,
+        dleq: &ProofDleq,
+    ) -> Result<bool, MonexoCoreError> {
+        let y = Self::hash_to_curve(secret_msg.into().as_bytes())?;
+        let b_ = y.combine(&dleq.r.public_key(&self.secp))?;
+        let c_ = c.combine(&k.mul_tweak(&self.secp, &Scalar::from(dleq.r))?)?;
+
+        let e = Scalar::from(dleq.e);
+        let r1 = dleq
+            .s
+            .public_key(&self.secp)
+            .combine(&k.mul_tweak(&self.secp, &e)?.negate(&self.secp))?;
+        let r2 = b_
+            .mul_tweak(&self.secp, &Scalar::from(dleq.s))?
+            .combine(&c_.mul_tweak(&self.secp, &e)?.negate(&self.secp))?;
+        Ok(hash_e([r1, r2, k, c_])? == dleq.e)
+    }
 }
 
 pub fn public_key_from_hex(hex: &str) -> secp256k1::PublicKey {
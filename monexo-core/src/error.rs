@@ -37,4 +37,22 @@ pub enum MonexoCoreError {
 
     #[error("Invalid Keyset-ID")]
     Slice(#[from] std::array::TryFromSliceError),
+
+    #[error("Keyset not found {0}")]
+    KeysetNotFound(String),
+
+    #[error("Pubkey for amount {0} not found")]
+    PubkeyNotFound(u64),
+
+    #[error("Invalid proof for amount {0}")]
+    InvalidProof(u64),
+
+    #[error("Invalid DLEQ proof for amount {0}")]
+    InvalidDleq(u64),
+
+    #[error("Proof for amount {0} has no DLEQ proof")]
+    MissingDleq(u64),
+
+    #[error("Can't subtract {1} from {0}")]
+    AmountUnderflow(u64, u64),
 }
@@ -2,7 +2,7 @@
 //!
 //! The `MintKeyset` struct represents a keyset for the Mint, with a `private_keys` field for the private keys, a `public_keys` field for the public keys, a `keyset_id` field for the ID of the keyset, and a `mint_pubkey` field for the public key of the Mint.
 //!
-//! The `MintKeyset` struct provides a `new` method for creating a new keyset from a seed and derivation path.
+//! The `MintKeyset` struct provides a `new` method for creating a new keyset from a seed and derivation path, and a `with_prefix` method that namespaces the derivation path so several keysets can share one seed.
 //!
 //! The `Keysets` struct represents a collection of keysets, with a `keysets` field for the keysets and a `current_keyset_id` field for the ID of the current keyset.
 //!
@@ -46,6 +46,15 @@ impl MintKeyset {
             mint_pubkey: derive_pubkey(seed).expect("invalid seed"),
         }
     }
+
+    /// Creates a keyset whose derivation path is namespaced with a prefix and an account index,
+    /// see [`namespaced_derivation_path`]. This allows several keysets or mints to share one seed.
+    pub fn with_prefix(seed: &str, prefix: &str, account: u32, derivation_path: &str) -> Self {
+        Self::new(
+            seed,
+            &namespaced_derivation_path(prefix, account, derivation_path),
+        )
+    }
 }
 
 // FIXME rename to keyset
@@ -54,6 +63,10 @@ pub struct Keyset {
     pub id: String, // FIXME use KeysetId
     pub unit: CurrencyUnit,
     pub active: bool,
+    /// fee in parts per thousand of the unit the mint charges for every input of this keyset
+    /// (NUT-02)
+    #[serde(default)]
+    pub input_fee_ppk: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -111,7 +124,7 @@ impl Keysets {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
 pub struct KeysetId(KeysetIdType, String);
 
 impl KeysetId {
@@ -143,7 +156,7 @@ impl Display for KeysetId {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
 pub enum KeysetIdType {
     V1,
 }
@@ -185,6 +198,24 @@ pub fn derive_keys(master_key: &str, derivation_path: &str) -> HashMap<u64, Secr
     keys
 }
 
+/// Builds the derivation path `{prefix}/{account}/{derivation_path}` for a keyset.
+///
+/// The keyset id only depends on the seed and the resulting path, so the same seed, prefix,
+/// account index and derivation path always reproduce the same keyset id.
+///
+/// # Arguments
+///
+/// * `prefix` - A string slice that namespaces all keysets of one mint.
+/// * `account` - The account index of the keyset within the prefix.
+/// * `derivation_path` - A string slice that holds the derivation path of the keyset.
+///
+/// # Returns
+///
+/// The namespaced derivation path.
+pub fn namespaced_derivation_path(prefix: &str, account: u32, derivation_path: &str) -> String {
+    format!("{prefix}/{account}/{derivation_path}")
+}
+
 /// Derives public keys from a given set of secret keys.
 ///
 /// # Arguments
@@ -201,6 +232,19 @@ pub fn derive_pubkeys(keys: &HashMap<u64, SecretKey>) -> HashMap<u64, PublicKey>
         .collect()
 }
 
+/// Calculates the fee a mint charges for spending inputs (NUT-02).
+///
+/// # Arguments
+///
+/// * `input_fee_ppks` - The `input_fee_ppk` of the keyset of every input.
+///
+/// # Returns
+///
+/// The sum of the fees of all inputs, rounded up to a whole amount.
+pub fn input_fee(input_fee_ppks: impl IntoIterator<Item = u64>) -> u64 {
+    input_fee_ppks.into_iter().sum::<u64>().div_ceil(1000)
+}
+
 fn derive_keyset_id(keys: &HashMap<u64, PublicKey>) -> String {
     let pubkeys = keys
         .iter()
@@ -229,7 +273,7 @@ pub fn derive_pubkey(seed: &str) -> Result<PublicKey, MonexoCoreError> {
 
 #[cfg(test)]
 mod tests {
-    use crate::keyset::{derive_pubkey, KeysetId};
+    use crate::keyset::{derive_pubkey, input_fee, KeysetId};
     use pretty_assertions::assert_eq;
     use secp256k1::PublicKey;
     use std::collections::HashMap;
@@ -241,6 +285,15 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_input_fee() {
+        assert_eq!(0, input_fee([]));
+        assert_eq!(0, input_fee([0, 0]));
+        assert_eq!(1, input_fee([100]));
+        assert_eq!(1, input_fee([500, 500]));
+        assert_eq!(2, input_fee([500, 500, 100]));
+    }
+
     #[test]
     fn test_derive_pubkey() -> anyhow::Result<()> {
         let result = derive_pubkey("supersecretprivatekey")?;
@@ -263,6 +316,30 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_keyset_with_prefix() -> anyhow::Result<()> {
+        assert_eq!(
+            "mint-a/1/0/0/0/0",
+            super::namespaced_derivation_path("mint-a", 1, "0/0/0/0")
+        );
+
+        let keyset = super::MintKeyset::with_prefix("supersecretprivatekey", "mint-a", 0, "");
+        assert_eq!(
+            super::MintKeyset::new("supersecretprivatekey", "mint-a/0/").keyset_id,
+            keyset.keyset_id
+        );
+
+        let ids = [("mint-a", 0), ("mint-a", 1), ("mint-b", 0)]
+            .iter()
+            .map(|(prefix, account)| {
+                super::MintKeyset::with_prefix("supersecretprivatekey", prefix, *account, "")
+                    .keyset_id
+            })
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(3, ids.len());
+        Ok(())
+    }
+
     #[test]
     fn test_derive_keyset_id() -> anyhow::Result<()> {
         let keys: &str = r#"
@@ -1,10 +1,10 @@
 //! This module contains all the request and response objects that are used for interacting between the Mint and Wallet in Cashu.
 //! All of these structs are serializable and deserializable using serde.
 
-use std::{collections::HashMap, fmt::Display, str::FromStr};
+use std::{collections::HashMap, convert::Infallible, fmt::Display, str::FromStr};
 
 use secp256k1::PublicKey;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_with::skip_serializing_none;
 use utoipa::ToSchema;
 use uuid::Uuid;
@@ -14,13 +14,24 @@ use crate::{
     proof::Proofs,
 };
 
-#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, ToSchema, Hash)]
-#[serde(rename_all = "lowercase")]
+/// The unit of a keyset or token.
+///
+/// Units this library doesn't model are kept in [`CurrencyUnit::Other`], so a mint advertising
+/// an unknown unit doesn't break deserializing its keysets.
+#[derive(Debug, PartialEq, Eq, Clone, ToSchema, Hash)]
 pub enum CurrencyUnit {
     Ugx,
     Usd,
     MUsd,
     Sat,
+    Other(String),
+}
+
+impl CurrencyUnit {
+    /// Returns true if the unit is natively supported
+    pub const fn is_supported(&self) -> bool {
+        !matches!(self, Self::Other(_))
+    }
 }
 
 impl Display for CurrencyUnit {
@@ -30,37 +41,69 @@ impl Display for CurrencyUnit {
             Self::Usd => write!(f, "usd"),
             Self::MUsd => write!(f, "musd"),
             Self::Sat => write!(f, "sat"),
+            Self::Other(unit) => write!(f, "{unit}"),
         }
     }
 }
 
 impl FromStr for CurrencyUnit {
-    type Err = ();
+    type Err = Infallible;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "ugx" => Ok(Self::Ugx),
-            "usd" => Ok(Self::Usd),
-            "musd" => Ok(Self::MUsd),
-            "sat" => Ok(Self::Sat),
-            _ => Err(()),
-        }
+        Ok(match s {
+            "ugx" => Self::Ugx,
+            "usd" => Self::Usd,
+            "musd" => Self::MUsd,
+            "sat" => Self::Sat,
+            _ => Self::Other(s.to_owned()),
+        })
     }
 }
 
+impl Serialize for CurrencyUnit {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for CurrencyUnit {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let Ok(unit) = Self::from_str(&String::deserialize(deserializer)?);
+        Ok(unit)
+    }
+}
+
+#[skip_serializing_none]
 #[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
 pub struct PostMintQuoteOnchainRequest {
     #[schema(example = "1500")]
     pub amount: u64,
+    /// number of payment references of the quote, the transfers to all of them add up to the
+    /// amount. Defaults to 1
+    #[serde(default)]
+    pub references: Option<u8>,
 }
 
+#[skip_serializing_none]
 #[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
 pub struct PostMintQuoteOnchainResponse {
     pub quote: String,
     pub reference: String,
+    /// further references the payment may be split across, empty for a single payment
+    #[serde(default)]
+    pub additional_references: Vec<String>,
     pub fee: u64,
     pub state: MintOnchainState,
     pub expiry: u64,
+    /// micro usd received so far by an unpaid quote, None if the mint didn't check the chain
+    #[serde(default)]
+    pub amount_paid: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, ToSchema)]
@@ -105,6 +148,8 @@ impl FromStr for MintOnchainState {
 pub struct OnchainMintQuote {
     pub quote_id: Uuid,
     pub reference: String,
+    /// further references of the quote, the transfers to all references add up to the amount
+    pub additional_references: Vec<String>,
     pub fee_total: u64,
     // pub unit: CurrencyUnit,
     pub amount: u64,
@@ -112,22 +157,37 @@ pub struct OnchainMintQuote {
     pub state: MintOnchainState,
 }
 
+impl OnchainMintQuote {
+    /// Returns all payment references of the quote, starting with [`OnchainMintQuote::reference`]
+    pub fn references(&self) -> Vec<&str> {
+        std::iter::once(self.reference.as_str())
+            .chain(self.additional_references.iter().map(String::as_str))
+            .collect()
+    }
+}
+
 impl From<OnchainMintQuote> for PostMintQuoteOnchainResponse {
     fn from(quote: OnchainMintQuote) -> Self {
         Self {
             quote: quote.quote_id.to_string(),
             reference: quote.reference,
+            additional_references: quote.additional_references,
             fee: quote.fee_total,
             state: quote.state,
             expiry: quote.expiry,
+            amount_paid: None,
         }
     }
 }
 
+#[skip_serializing_none]
 #[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
 pub struct PostMintOnchainRequest {
     pub quote: String,
     pub outputs: Vec<BlindedMessage>,
+    /// a retried request with the same key gets the response of the first one
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
@@ -172,10 +232,10 @@ pub enum MeltOnchainState {
     /// initial state. No payment received from the wallet yet
     Unpaid,
 
-    /// the mint received the payment from the wallet, but did not broadcast the transaction yet
+    /// the mint broadcasted the onchain transaction, but it isn't confirmed yet
     Pending,
 
-    /// the mint broadcasted the onchain transaction
+    /// the onchain transaction is confirmed
     Paid,
 }
 
@@ -209,22 +269,40 @@ pub struct OnchainMeltQuote {
     pub address: String,
     pub reference: String,
     pub fee_total: u64,
-    pub fee_sat_per_vbyte: u32,
+    /// estimated network fee in micro-usd, `fee_total` adds the mint's margin on top
+    pub fee_micro_usd: u64,
+    /// fee in micro-usd the mint charges, part of `fee_total` and never returned as change
+    pub fee_mint: u64,
     pub expiry: u64,
     pub state: MeltOnchainState,
     pub description: Option<String>,
+    /// signature of the transaction the mint broadcasted for this quote
+    pub txid: Option<String>,
+    /// true if the recipient had no token account when quoting, the transfer creates it and the
+    /// fee includes its rent
+    pub create_recipient_account: bool,
 }
 
+#[skip_serializing_none]
 #[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
 pub struct PostMeltOnchainRequest {
     pub quote: String,
     pub inputs: Proofs,
+    /// blank outputs for the change of an overpaid fee (NUT-08)
+    #[serde(default)]
+    pub outputs: Vec<BlindedMessage>,
+    /// a retried request with the same key gets the response of the first one
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
 pub struct PostMeltOnchainResponse {
     pub state: MeltOnchainState,
     pub txid: Option<String>,
+    /// signatures for the blank outputs holding the change
+    #[serde(default)]
+    pub change: Vec<BlindedSignature>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -260,7 +338,7 @@ pub struct KeyResponse {
 }
 
 #[skip_serializing_none]
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default, ToSchema)]
 pub struct MintInfoResponse {
     pub name: Option<String>,
     // #[schema(value_type = String)]
@@ -272,6 +350,88 @@ pub struct MintInfoResponse {
     // pub motd: Option<String>,
     pub usdc_address: String,
     pub usdc_token_mint: String,
+    #[serde(default)]
+    pub nuts: Nuts,
+    /// smallest amount that can be minted or melted onchain
+    pub min_amount: Option<u64>,
+    /// largest amount that can be minted or melted onchain
+    pub max_amount: Option<u64>,
+    /// fee in percent the mint deducts from the amount of a mint quote
+    pub fee_percent: Option<f64>,
+    /// amount of the ugx keyset's unit one unit of the usd keyset is exchanged for, the mint
+    /// doesn't support exchanges if not set
+    pub ugx_exchange_rate: Option<f64>,
+}
+
+/// Supported NUTs of a mint as described in NUT-06
+#[skip_serializing_none]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default, ToSchema)]
+pub struct Nuts {
+    /// Swapping, mandatory so mints usually don't list it
+    #[serde(rename = "3")]
+    pub nut03: Option<NutSupported>,
+    /// Minting
+    #[serde(rename = "4")]
+    pub nut04: Option<PaymentMethodSettings>,
+    /// Melting
+    #[serde(rename = "5")]
+    pub nut05: Option<PaymentMethodSettings>,
+    /// Token state check
+    #[serde(rename = "7")]
+    pub nut07: Option<NutSupported>,
+    /// Restore signatures
+    #[serde(rename = "9")]
+    pub nut09: Option<NutSupported>,
+    /// Spending conditions
+    #[serde(rename = "10")]
+    pub nut10: Option<NutSupported>,
+    /// Pay-to-Pubkey (P2PK)
+    #[serde(rename = "11")]
+    pub nut11: Option<NutSupported>,
+    /// DLEQ proofs
+    #[serde(rename = "12")]
+    pub nut12: Option<NutSupported>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default, ToSchema)]
+pub struct PaymentMethodSettings {
+    pub methods: Vec<PaymentMethod>,
+    pub disabled: bool,
+}
+
+#[skip_serializing_none]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct PaymentMethod {
+    pub method: String,
+    pub unit: CurrencyUnit,
+    pub min_amount: Option<u64>,
+    pub max_amount: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default, ToSchema)]
+pub struct NutSupported {
+    pub supported: bool,
+}
+
+impl From<bool> for NutSupported {
+    fn from(supported: bool) -> Self {
+        Self { supported }
+    }
+}
+
+/// Error body returned by a mint as described in NUT-00
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct CashuErrorResponse {
+    pub code: u16,
+    pub detail: String,
+}
+
+impl CashuErrorResponse {
+    pub const TOKEN_ALREADY_SPENT: u16 = 11001;
+    pub const TRANSACTION_NOT_BALANCED: u16 = 11002;
+    pub const DUPLICATE_INPUTS: u16 = 11007;
+    pub const KEYSET_NOT_FOUND: u16 = 12001;
+    pub const QUOTE_NOT_PAID: u16 = 20001;
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -324,6 +484,18 @@ pub struct PostCheckStateResponse {
     pub states: Vec<ProofStatus>,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PostRestoreRequest {
+    pub outputs: Vec<BlindedMessage>,
+}
+
+/// Contains only the outputs the mint has signed, in the same order as their signatures
+#[derive(Clone, Debug, Serialize, Deserialize, Default, ToSchema)]
+pub struct PostRestoreResponse {
+    pub outputs: Vec<BlindedMessage>,
+    pub signatures: Vec<BlindedSignature>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
 pub struct PostCurrencyExchangeRequest {
     #[schema(example = "1500")]
@@ -341,7 +513,14 @@ pub struct PostCurrencyExchangeResponse {
 mod tests {
     use pretty_assertions::assert_eq;
 
-    use crate::primitives::{KeyResponse, MintInfoResponse, PostSwapResponse};
+    use crate::{
+        fixture::read_fixture_as,
+        primitives::{
+            CurrencyUnit, KeyResponse, MintInfoResponse, NutSupported, Nuts,
+            PostMeltOnchainRequest, PostMeltOnchainResponse, PostMintOnchainRequest,
+            PostSwapResponse,
+        },
+    };
 
     #[test]
     fn test_serialize_empty_swap_response() -> anyhow::Result<()> {
@@ -366,6 +545,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_mint_request_without_idempotency_key() -> anyhow::Result<()> {
+        let request =
+            serde_json::from_str::<PostMintOnchainRequest>(r#"{"quote":"abc","outputs":[]}"#)?;
+        assert_eq!(request.idempotency_key, None);
+        assert_eq!(
+            serde_json::to_string(&request)?,
+            r#"{"quote":"abc","outputs":[]}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_melt_without_change() -> anyhow::Result<()> {
+        let request =
+            serde_json::from_str::<PostMeltOnchainRequest>(r#"{"quote":"abc","inputs":[]}"#)?;
+        assert!(request.outputs.is_empty());
+
+        let response =
+            serde_json::from_str::<PostMeltOnchainResponse>(r#"{"state":"PENDING","txid":null}"#)?;
+        assert!(response.change.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_unknown_currency_unit() -> anyhow::Result<()> {
+        let unit = serde_json::from_str::<CurrencyUnit>("\"eur\"")?;
+        assert_eq!(unit, CurrencyUnit::Other("eur".to_string()));
+        assert!(!unit.is_supported());
+        assert_eq!(serde_json::to_string(&unit)?, "\"eur\"");
+
+        let unit = serde_json::from_str::<CurrencyUnit>("\"usd\"")?;
+        assert_eq!(unit, CurrencyUnit::Usd);
+        Ok(())
+    }
+
     #[test]
     fn test_serialize_mint_info() -> anyhow::Result<()> {
         let mint_info = MintInfoResponse {
@@ -377,11 +592,55 @@ mod tests {
             usdc_token_mint: String::from(
                 "02a9acc1e48c25eeeb9289b5031cc57da9fe72f3fe2861d264bdc074209b107ba1",
             ),
+            nuts: Default::default(),
+            ..Default::default()
         };
         let out = serde_json::to_string_pretty(&mint_info)?;
         assert!(!out.is_empty());
         assert!(out.contains("02a9acc1e48c25eeeb9289b5031cc57da9fe72f3fe2861d264bdc074209b107ba2"));
         assert!(out.contains("02a9acc1e48c25eeeb9289b5031cc57da9fe72f3fe2861d264bdc074209b107ba1"));
+        assert!(!out.contains("min_amount"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialize_mint_info_limits() -> anyhow::Result<()> {
+        let mint_info = MintInfoResponse {
+            min_amount: Some(10_000),
+            max_amount: Some(1_000_000),
+            fee_percent: Some(1.0),
+            ..Default::default()
+        };
+        let out = serde_json::to_value(&mint_info)?;
+        assert_eq!(10_000, out["min_amount"]);
+        assert_eq!(1_000_000, out["max_amount"]);
+        assert_eq!(1.0, out["fee_percent"]);
+
+        let deserialized = serde_json::from_value::<MintInfoResponse>(out)?;
+        assert_eq!(mint_info, deserialized);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_nuts() -> anyhow::Result<()> {
+        let mint_info = read_fixture_as::<serde_json::Value>("nutshell_mint_info.json")?;
+        let nuts = serde_json::from_value::<Nuts>(mint_info["nuts"].clone())?;
+
+        assert_eq!(Some(NutSupported::from(true)), nuts.nut07);
+        assert_eq!(Some(NutSupported::from(true)), nuts.nut12);
+        let nut04 = nuts.nut04.expect("nut04 is missing");
+        assert_eq!("bolt11", nut04.methods[0].method);
+        assert_eq!(CurrencyUnit::Sat, nut04.methods[0].unit);
+        assert_eq!(Some(21), nut04.methods[0].max_amount);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_mint_info_without_nuts() -> anyhow::Result<()> {
+        let mint_info = serde_json::from_str::<MintInfoResponse>(
+            r#"{"usdc_address":"address","usdc_token_mint":"token_mint"}"#,
+        )?;
+        assert_eq!(Nuts::default(), mint_info.nuts);
         Ok(())
     }
 
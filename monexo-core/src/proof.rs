@@ -10,13 +10,15 @@
 //!
 //! Both the `Proof` and `Proofs` structs are serializable and deserializable using serde.
 
+use std::collections::{BTreeMap, HashMap};
+
 use crate::dhke::Dhke;
-use secp256k1::PublicKey;
+use secp256k1::{PublicKey, SecretKey};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use utoipa::ToSchema;
 
-use crate::{error::MonexoCoreError, keyset::KeysetId};
+use crate::{amount::sum_amounts, error::MonexoCoreError, keyset::KeysetId};
 
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
@@ -29,6 +31,19 @@ pub struct Proof {
     #[schema(value_type = String)]
     pub c: PublicKey,
     pub script: Option<P2SHScript>,
+    pub dleq: Option<ProofDleq>,
+}
+
+/// DLEQ proof of the signature of a proof, the DLEQ proof of its blinded signature together with
+/// the blinding factor `r`, see [Nut-12](https://github.com/cashubtc/nuts/blob/main/12.md)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct ProofDleq {
+    #[schema(value_type=String)]
+    pub e: SecretKey,
+    #[schema(value_type=String)]
+    pub s: SecretKey,
+    #[schema(value_type=String)]
+    pub r: SecretKey,
 }
 
 impl Proof {
@@ -39,6 +54,7 @@ impl Proof {
             c,
             keyset_id: id,
             script: None,
+            dleq: None,
         }
     }
 
@@ -46,13 +62,24 @@ impl Proof {
     ///
     /// Where y is `hash_to_curve(secret)`
     pub fn y(&self) -> Result<PublicKey, MonexoCoreError> {
-        Ok(Dhke::hash_to_curve(self.secret.as_bytes())?)
+        Dhke::hash_to_curve(self.secret.as_bytes())
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub struct P2SHScript;
 
+/// Strategy used by [`Proofs::select_proofs`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionStrategy {
+    /// Takes the largest proofs first, may overshoot the amount and require a swap
+    #[default]
+    LargestFirst,
+    /// Prefers proofs that add up to exactly the amount, so no swap is needed. Falls back to the
+    /// selection with the least change
+    ExactMatch,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub struct Proofs(pub(super) Vec<Proof>);
 
@@ -70,7 +97,7 @@ impl Proofs {
     }
 
     pub fn total_amount(&self) -> u64 {
-        self.0.iter().map(|proof| proof.amount).sum()
+        sum_amounts(self.0.iter().map(|proof| proof.amount))
     }
 
     pub fn proofs(&self) -> Vec<Proof> {
@@ -94,28 +121,114 @@ impl Proofs {
             .into()
     }
 
+    /// Returns the number of proofs per amount, smallest amount first
+    pub fn denomination_histogram(&self) -> BTreeMap<u64, usize> {
+        let mut histogram = BTreeMap::new();
+        for proof in self.0.iter() {
+            *histogram.entry(proof.amount).or_insert(0) += 1;
+        }
+        histogram
+    }
+
     pub fn proofs_for_amount(&self, amount: u64) -> Result<Self, MonexoCoreError> {
-        let mut all_proofs = self.0.clone();
+        self.select_proofs(amount, SelectionStrategy::LargestFirst)
+    }
+
+    /// Selects proofs that add up to at least `amount` using the given strategy
+    pub fn select_proofs(
+        &self,
+        amount: u64,
+        strategy: SelectionStrategy,
+    ) -> Result<Self, MonexoCoreError> {
         if amount > self.total_amount() {
             return Err(MonexoCoreError::NotEnoughTokens);
         }
 
-        all_proofs.sort_by(|a, b| a.amount.cmp(&b.amount));
+        let mut all_proofs = self.0.clone();
+        all_proofs.sort_by_key(|proof| std::cmp::Reverse(proof.amount));
+
+        let selected_proofs = match strategy {
+            SelectionStrategy::LargestFirst => Self::select_largest_first(all_proofs, amount),
+            SelectionStrategy::ExactMatch => Self::select_exact(&all_proofs, amount)
+                .or_else(|| {
+                    // a single proof leaves the least change if no subset fits exactly
+                    all_proofs
+                        .iter()
+                        .rev()
+                        .find(|proof| proof.amount >= amount)
+                        .map(|proof| vec![proof.clone()])
+                })
+                .unwrap_or_else(|| Self::select_largest_first(all_proofs, amount)),
+        };
 
-        let mut selected_proofs = vec![];
+        Ok(selected_proofs.into())
+    }
+
+    /// Takes the largest proofs until the amount is reached, `proofs` must be sorted descending
+    fn select_largest_first(proofs: Vec<Proof>, amount: u64) -> Vec<Proof> {
         let mut selected_amount = 0;
+        proofs
+            .into_iter()
+            .take_while(|proof| {
+                let take = selected_amount < amount;
+                selected_amount += proof.amount;
+                take
+            })
+            .collect()
+    }
 
-        while selected_amount < amount {
-            if all_proofs.is_empty() {
+    /// Returns proofs that sum up to exactly `amount`, `proofs` must be sorted descending.
+    ///
+    /// Greedily takes every proof that still fits, which finds the subset with the fewest proofs
+    /// for power of two denominations.
+    fn select_exact(proofs: &[Proof], amount: u64) -> Option<Vec<Proof>> {
+        let mut remaining = amount;
+        let mut selected_proofs = vec![];
+        for proof in proofs {
+            if remaining == 0 {
                 break;
             }
-
-            let proof = all_proofs.pop().expect("proofs is empty");
-            selected_amount += proof.amount;
-            selected_proofs.push(proof);
+            if proof.amount <= remaining {
+                remaining -= proof.amount;
+                selected_proofs.push(proof.clone());
+            }
         }
+        (remaining == 0).then_some(selected_proofs)
+    }
 
-        Ok(selected_proofs.into())
+    /// Verifies that the `C` of every proof is the signature of its secret with the key of its
+    /// keyset and amount.
+    ///
+    /// Fails if a proof references an unknown keyset, an amount the keyset has no key for, has no
+    /// DLEQ proof or its DLEQ proof doesn't pass [`Dhke::verify_dleq`].
+    pub fn verify(
+        &self,
+        keys: &HashMap<KeysetId, HashMap<u64, PublicKey>>,
+        dhke: &Dhke,
+    ) -> Result<(), MonexoCoreError> {
+        for proof in &self.0 {
+            let keyset_keys = KeysetId::new(&proof.keyset_id)
+                .ok()
+                .and_then(|keyset_id| keys.get(&keyset_id))
+                .ok_or_else(|| MonexoCoreError::KeysetNotFound(proof.keyset_id.clone()))?;
+
+            let key = keyset_keys
+                .get(&proof.amount)
+                .ok_or(MonexoCoreError::PubkeyNotFound(proof.amount))?;
+
+            let dleq = proof
+                .dleq
+                .as_ref()
+                .ok_or(MonexoCoreError::MissingDleq(proof.amount))?;
+            // a signature that doesn't sum up to a valid point can't be valid either
+            if !matches!(
+                dhke.verify_dleq(*key, proof.c, proof.secret.clone(), dleq),
+                Ok(true)
+            ) {
+                return Err(MonexoCoreError::InvalidProof(proof.amount));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -133,15 +246,50 @@ impl From<Proof> for Proofs {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use serde_json::json;
 
     use crate::{
+        blind::{BlindedMessage, BlindedSignature, BlindingFactor},
+        dhke::Dhke,
+        error::MonexoCoreError,
         fixture::read_fixture,
-        proof::{Proof, Proofs},
+        keyset::{KeysetId, MintKeyset},
+        proof::{Proof, ProofDleq, Proofs, SelectionStrategy},
         token::TokenV3,
     };
     use pretty_assertions::assert_eq;
 
+    /// Returns a proof signed by the keyset with the DLEQ proof of its signature
+    fn create_signed_proof(keyset: &MintKeyset, amount: u64) -> anyhow::Result<Proof> {
+        let dhke = Dhke::new();
+        let secret = "test_secret".to_string();
+        let blinding_factor: BlindingFactor =
+            "0000000000000000000000000000000000000000000000000000000000000002".try_into()?;
+        let blinded_message = BlindedMessage {
+            amount,
+            b_: dhke.step1_alice(secret.clone(), &blinding_factor)?,
+            id: keyset.keyset_id.clone(),
+        };
+        let signature =
+            BlindedSignature::new(&blinded_message, &keyset.private_keys[&amount], true)?;
+        let dleq = signature.dleq.expect("dleq is missing");
+        let c = dhke.step3_alice(
+            signature.c_,
+            blinding_factor.clone(),
+            keyset.public_keys[&amount],
+        )?;
+        Ok(Proof {
+            dleq: Some(ProofDleq {
+                e: dleq.e,
+                s: dleq.s,
+                r: blinding_factor.to_secret_key(),
+            }),
+            ..Proof::new(amount, secret, c, keyset.keyset_id.clone())
+        })
+    }
+
     #[test]
     fn test_proofs_for_amount_empty() -> anyhow::Result<()> {
         let proofs = Proofs::empty();
@@ -168,6 +316,131 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_denomination_histogram() -> anyhow::Result<()> {
+        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
+        let token: TokenV3 = fixture.try_into()?;
+
+        let histogram = token.proofs().denomination_histogram();
+        assert_eq!(
+            vec![(4, 1), (8, 1), (16, 1), (32, 1)],
+            histogram.into_iter().collect::<Vec<_>>()
+        );
+
+        let histogram = create_proofs(&[1, 1, 2, 8, 1])?.denomination_histogram();
+        assert_eq!(
+            vec![(1, 3), (2, 1), (8, 1)],
+            histogram.into_iter().collect::<Vec<_>>()
+        );
+        assert!(Proofs::empty().denomination_histogram().is_empty());
+        Ok(())
+    }
+
+    fn create_proofs(amounts: &[u64]) -> anyhow::Result<Proofs> {
+        let keyset = MintKeyset::new("mykey", "");
+        Ok(Proofs::new(
+            amounts
+                .iter()
+                .map(|amount| create_signed_proof(&keyset, *amount))
+                .collect::<anyhow::Result<Vec<Proof>>>()?,
+        ))
+    }
+
+    #[test]
+    fn test_select_proofs_exact_match() -> anyhow::Result<()> {
+        let proofs = create_proofs(&[1, 1, 2, 2, 4, 8, 16, 32])?;
+
+        let largest_first = proofs.select_proofs(14, SelectionStrategy::LargestFirst)?;
+        assert_eq!(1, largest_first.len());
+        assert_eq!(32, largest_first.total_amount());
+
+        let exact = proofs.select_proofs(14, SelectionStrategy::ExactMatch)?;
+        assert_eq!(3, exact.len());
+        assert_eq!(14, exact.total_amount());
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_proofs_many_small_denominations() -> anyhow::Result<()> {
+        let proofs = create_proofs(&[1; 20])?;
+
+        let largest_first = proofs.select_proofs(7, SelectionStrategy::LargestFirst)?;
+        let exact = proofs.select_proofs(7, SelectionStrategy::ExactMatch)?;
+        assert_eq!(7, largest_first.len());
+        assert_eq!(7, exact.len());
+        assert_eq!(7, exact.total_amount());
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_proofs_no_exact_match() -> anyhow::Result<()> {
+        let proofs = create_proofs(&[4, 8, 16, 32])?;
+
+        // no subset sums to 10, a single 16 leaves less change than the largest-first 32
+        let exact = proofs.select_proofs(10, SelectionStrategy::ExactMatch)?;
+        assert_eq!(1, exact.len());
+        assert_eq!(16, exact.total_amount());
+
+        // no single proof covers 50, fall back to largest first
+        let exact = proofs.select_proofs(50, SelectionStrategy::ExactMatch)?;
+        assert_eq!(3, exact.len());
+        assert_eq!(56, exact.total_amount());
+
+        assert!(proofs
+            .select_proofs(61, SelectionStrategy::ExactMatch)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_proofs_verify() -> anyhow::Result<()> {
+        let keyset = MintKeyset::new("mykey", "");
+        let keys = HashMap::from([(
+            KeysetId::new(&keyset.keyset_id)?,
+            keyset.public_keys.clone(),
+        )]);
+        let proofs: Proofs = create_signed_proof(&keyset, 8)?.into();
+
+        assert!(proofs.verify(&keys, &Dhke::new()).is_ok());
+        assert!(proofs.verify(&HashMap::new(), &Dhke::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_proofs_verify_forged() -> anyhow::Result<()> {
+        let keyset = MintKeyset::new("mykey", "");
+        let keys = HashMap::from([(
+            KeysetId::new(&keyset.keyset_id)?,
+            keyset.public_keys.clone(),
+        )]);
+        let proof = create_signed_proof(&keyset, 8)?;
+        let forged: Proofs = Proof {
+            c: Dhke::hash_to_curve(proof.secret.as_bytes())?,
+            ..proof.clone()
+        }
+        .into();
+        let result = forged.verify(&keys, &Dhke::new());
+        assert!(matches!(result, Err(MonexoCoreError::InvalidProof(8))));
+
+        // signed with the key of another amount
+        let other_amount: Proofs = Proof {
+            amount: 16,
+            ..proof.clone()
+        }
+        .into();
+        let result = other_amount.verify(&keys, &Dhke::new());
+        assert!(matches!(result, Err(MonexoCoreError::InvalidProof(16))));
+
+        let without_dleq: Proofs = Proof {
+            dleq: None,
+            ..proof
+        }
+        .into();
+        let result = without_dleq.verify(&keys, &Dhke::new());
+        assert!(matches!(result, Err(MonexoCoreError::MissingDleq(8))));
+        Ok(())
+    }
+
     #[test]
     fn test_proof() -> anyhow::Result<()> {
         let js = json!(
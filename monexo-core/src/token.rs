@@ -9,7 +9,7 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_with::skip_serializing_none;
 use url::Url;
 
-use crate::{error::MonexoCoreError, primitives::CurrencyUnit, proof::Proofs};
+use crate::{amount::sum_amounts, error::MonexoCoreError, primitives::CurrencyUnit, proof::Proofs};
 
 const TOKEN_PREFIX_V3: &str = "cashuA";
 
@@ -55,6 +55,8 @@ pub struct TokenV3 {
     pub tokens: Vec<Token>,
     #[serde(rename = "unit")]
     pub currency_unit: Option<CurrencyUnit>,
+    /// human readable note for the receiver, tokens without memo leave out the key
+    #[serde(default)]
     pub memo: Option<String>,
 }
 
@@ -75,18 +77,12 @@ impl TokenV3 {
         }
     }
 
+    pub fn with_memo(self, memo: Option<String>) -> Self {
+        Self { memo, ..self }
+    }
+
     pub fn total_amount(&self) -> u64 {
-        self.tokens
-            .iter()
-            .map(|token| {
-                token
-                    .proofs
-                    .proofs()
-                    .iter()
-                    .map(|proof| proof.amount)
-                    .sum::<u64>()
-            })
-            .sum()
+        sum_amounts(self.tokens.iter().map(|token| token.proofs.total_amount()))
     }
 
     pub fn proofs(&self) -> Proofs {
@@ -281,6 +277,7 @@ mod tests {
                 ),
                 keyset_id: "someid".to_string(),
                 script: None,
+                dleq: None,
             }
             .into(),
         };
@@ -336,6 +333,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_tokens_memo_roundtrip() -> anyhow::Result<()> {
+        let input = read_fixture("token_60.cashu")?;
+        let tokens = TokenV3::deserialize(input)?.with_memo(Some("for the coffee".to_string()));
+
+        let serialized = tokens.serialize()?;
+        let deserialized = TokenV3::deserialize(serialized)?;
+        assert_eq!(tokens, deserialized);
+        assert_eq!(deserialized.memo, Some("for the coffee".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokens_without_memo_roundtrip() -> anyhow::Result<()> {
+        use base64::{engine::general_purpose, Engine as _};
+        let input = read_fixture("token_60.cashu")?;
+        let tokens = TokenV3::deserialize(input)?.with_memo(None);
+
+        let serialized = tokens.serialize()?;
+        let json = general_purpose::URL_SAFE.decode(serialized.strip_prefix("cashuA").unwrap())?;
+        let json: Value = serde_json::from_slice(&json)?;
+        assert!(json.get("memo").is_none());
+        assert_eq!(tokens, TokenV3::deserialize(serialized)?);
+        Ok(())
+    }
+
     #[test]
     fn test_empty_token() -> anyhow::Result<()> {
         let tokens = TokenV3::empty();
@@ -0,0 +1,12 @@
+-- proofs blocked by an operator are only known by their secret
+ALTER TABLE used_proofs
+    ALTER COLUMN amount DROP NOT NULL,
+    ALTER COLUMN c DROP NOT NULL,
+    ALTER COLUMN keyset_id DROP NOT NULL;
+
+CREATE TABLE audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    action TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
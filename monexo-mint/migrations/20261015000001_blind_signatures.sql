@@ -0,0 +1,6 @@
+CREATE TABLE blind_signatures (
+    b_ TEXT NOT NULL PRIMARY KEY,
+    amount BIGINT NOT NULL,
+    c_ TEXT NOT NULL,
+    keyset_id TEXT NOT NULL
+);
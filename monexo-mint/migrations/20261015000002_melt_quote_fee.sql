@@ -0,0 +1 @@
+ALTER TABLE onchain_melt_quotes RENAME COLUMN fee_sat_per_vbyte TO fee_micro_usd;
@@ -0,0 +1,2 @@
+ALTER TABLE blind_signatures ADD COLUMN dleq_e TEXT;
+ALTER TABLE blind_signatures ADD COLUMN dleq_s TEXT;
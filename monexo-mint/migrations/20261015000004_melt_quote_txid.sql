@@ -0,0 +1 @@
+ALTER TABLE onchain_melt_quotes ADD COLUMN txid TEXT;
@@ -0,0 +1 @@
+ALTER TABLE onchain_melt_quotes ADD COLUMN create_recipient_account BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,8 @@
+-- responses of mint and melt requests, returned again when a wallet retries with the same key
+CREATE TABLE idempotency_keys (
+    endpoint TEXT NOT NULL,
+    key TEXT NOT NULL,
+    response TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (endpoint, key)
+);
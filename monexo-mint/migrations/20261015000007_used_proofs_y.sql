@@ -0,0 +1,3 @@
+-- proofs are looked up by y = hash_to_curve(secret), existing rows are filled in by the mint on startup
+ALTER TABLE used_proofs ADD COLUMN y TEXT;
+CREATE UNIQUE INDEX used_proofs_y_idx ON used_proofs (y);
@@ -0,0 +1 @@
+ALTER TABLE onchain_mint_quotes ADD COLUMN additional_references TEXT[] NOT NULL DEFAULT '{}';
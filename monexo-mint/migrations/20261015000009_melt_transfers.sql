@@ -0,0 +1,16 @@
+-- proofs of a melt whose transfer hasn't landed yet, they stay in used_proofs so they can't be
+-- spent twice and are released if the transfer can no longer land
+CREATE TABLE pending_proofs (
+    y TEXT NOT NULL PRIMARY KEY,
+    quote_id uuid NOT NULL
+);
+CREATE INDEX pending_proofs_quote_id_idx ON pending_proofs (quote_id);
+
+-- the signed transfer of a melt, broadcast again until it lands or its blockhash expired
+CREATE TABLE melt_transfers (
+    quote_id uuid NOT NULL PRIMARY KEY,
+    transaction TEXT NOT NULL,
+    last_valid_block_height BIGINT NOT NULL,
+    inputs_amount BIGINT NOT NULL,
+    blanks TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- hash of the request a key was first used with, a key reused for another request is rejected
+ALTER TABLE idempotency_keys ADD COLUMN request_hash TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,2 @@
+-- fee the mint charges on a melt quote, part of fee_total but never returned as change
+ALTER TABLE onchain_melt_quotes ADD COLUMN fee_mint BIGINT NOT NULL DEFAULT 0;
@@ -31,6 +31,12 @@ pub async fn main() -> anyhow::Result<()> {
         privatekey,
         derivation_path,
         ugx_derivation_path,
+        keysets,
+        derivation_prefix,
+        enable_dleq,
+        require_dleq,
+        ugx_exchange_rate,
+        input_fee_ppk,
         info,
         server,
         onchain_backend,
@@ -46,6 +52,12 @@ pub async fn main() -> anyhow::Result<()> {
         .with_private_key(privatekey)
         .with_derivation_path(derivation_path)
         .with_ugx_derivation_path(ugx_derivation_path)
+        .with_keysets(keysets)
+        .with_derivation_prefix(derivation_prefix)
+        .with_enable_dleq(enable_dleq)
+        .with_require_dleq(require_dleq)
+        .with_ugx_exchange_rate(ugx_exchange_rate)
+        .with_input_fee_ppk(input_fee_ppk)
         .with_db(Some(database))
         .with_onchain(onchain_backend)
         .with_tracing(tracing)
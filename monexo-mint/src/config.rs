@@ -1,8 +1,15 @@
-use std::{env, net::SocketAddr};
+use std::{env, net::SocketAddr, str::FromStr};
 
 use clap::Parser;
+use monexo_core::primitives::CurrencyUnit;
 use serde::{Deserialize, Serialize};
 
+pub const DEFAULT_SOLANA_RPC_URL: &str = "https://api.devnet.solana.com";
+pub const DEFAULT_SOLANA_COMMITMENT: &str = "confirmed";
+
+/// largest fee in percent the mint accepts in [`OnchainConfig::fee_percent`]
+pub const MAX_FEE_PERCENT: f64 = 50.0;
+
 #[derive(Parser, Debug)]
 #[command(arg_required_else_help(true))]
 pub struct Opts {
@@ -12,6 +19,29 @@ pub struct Opts {
     pub derivation_path: Option<String>,
     #[clap(long, env = "UGX_MINT_DERIVATION_PATH")]
     pub ugx_derivation_path: Option<String>,
+    /// additional keysets as comma separated `{unit}:{derivation_path}` entries, e.g.
+    /// `sat:0/0/0/2`. Entries for usd or ugx replace the paths configured above
+    #[clap(long, env = "MINT_KEYSETS", value_delimiter = ',')]
+    pub keysets: Vec<KeysetConfig>,
+    /// namespaces the derivation paths as `{prefix}/{account}/{path}` with account 0 for the usd,
+    /// 1 for the ugx and the following for the additional keysets in the order they are
+    /// configured, so several mints can share one private key
+    #[clap(long, env = "MINT_DERIVATION_PREFIX")]
+    pub derivation_prefix: Option<String>,
+    /// attach DLEQ proofs (NUT-12) to all signatures, off by default for wallets that can't
+    /// handle them
+    #[clap(long, default_value_t = false, env = "MINT_ENABLE_DLEQ")]
+    pub enable_dleq: bool,
+    /// attach DLEQ proofs (NUT-12) to all signatures and refuse to start if they can't be created
+    #[clap(long, default_value_t = false, env = "MINT_REQUIRE_DLEQ")]
+    pub require_dleq: bool,
+    /// amount of the ugx keyset's unit one unit of the usd keyset is exchanged for. Returned in
+    /// the mint info, wallets can't exchange currencies if not set
+    #[clap(long, env = "MINT_UGX_EXCHANGE_RATE")]
+    pub ugx_exchange_rate: Option<f64>,
+    /// fee in parts per thousand of the unit charged for every input of a swap (NUT-02)
+    #[clap(long, default_value_t = 0, env = "MINT_INPUT_FEE_PPK")]
+    pub input_fee_ppk: u64,
     #[clap(flatten)]
     pub info: MintInfoConfig,
     #[clap(flatten)]
@@ -29,6 +59,18 @@ pub struct DatabaseConfig {
 
     #[clap(long, default_value_t = 5, env = "MINT_DB_MAX_CONNECTIONS")]
     pub max_connections: u32,
+
+    /// connections the pool keeps open even if they are idle
+    #[clap(long, default_value_t = 0, env = "MINT_DB_MIN_CONNECTIONS")]
+    pub min_connections: u32,
+
+    /// seconds a request waits for a free connection before it fails
+    #[clap(long, default_value_t = 10, env = "MINT_DB_ACQUIRE_TIMEOUT")]
+    pub acquire_timeout: u64,
+
+    /// seconds an idle connection above `min_connections` is kept open, 0 keeps them open
+    #[clap(long, default_value_t = 600, env = "MINT_DB_IDLE_TIMEOUT")]
+    pub idle_timeout: u64,
 }
 
 impl Default for DatabaseConfig {
@@ -36,6 +78,9 @@ impl Default for DatabaseConfig {
         Self {
             db_url: "".to_owned(),
             max_connections: 5,
+            min_connections: 0,
+            acquire_timeout: 10,
+            idle_timeout: 600,
         }
     }
 }
@@ -51,6 +96,12 @@ pub struct MintConfig {
     pub privatekey: String,
     pub derivation_path: Option<String>,
     pub ugx_derivation_path: Option<String>,
+    pub keysets: Vec<KeysetConfig>,
+    pub derivation_prefix: Option<String>,
+    pub enable_dleq: bool,
+    pub require_dleq: bool,
+    pub ugx_exchange_rate: Option<f64>,
+    pub input_fee_ppk: u64,
     pub info: MintInfoConfig,
     pub server: ServerConfig,
     pub onchain_backend: Option<OnchainConfig>,
@@ -64,6 +115,12 @@ impl From<(Opts, OnchainConfig)> for MintConfig {
             privatekey: opts.privatekey,
             derivation_path: opts.derivation_path,
             ugx_derivation_path: opts.ugx_derivation_path,
+            keysets: opts.keysets,
+            derivation_prefix: opts.derivation_prefix,
+            enable_dleq: opts.enable_dleq,
+            require_dleq: opts.require_dleq,
+            ugx_exchange_rate: opts.ugx_exchange_rate,
+            input_fee_ppk: opts.input_fee_ppk,
             info: opts.info,
             server: opts.server,
             onchain_backend: Some(onchain_config),
@@ -81,6 +138,57 @@ impl MintConfig {
 
         (opts, onchain_config).into()
     }
+
+    /// Returns the keysets the mint signs with: usd and ugx from their derivation paths followed
+    /// by the additional keysets. The position of a keyset is its account index if a derivation
+    /// prefix is configured.
+    pub fn keyset_configs(&self) -> Vec<KeysetConfig> {
+        let mut configs = vec![
+            KeysetConfig::new(
+                CurrencyUnit::Usd,
+                &self.derivation_path.clone().unwrap_or_default(),
+            ),
+            KeysetConfig::new(
+                CurrencyUnit::Ugx,
+                &self.ugx_derivation_path.clone().unwrap_or_default(),
+            ),
+        ];
+        for keyset in &self.keysets {
+            match configs.iter_mut().find(|config| config.unit == keyset.unit) {
+                Some(config) => *config = keyset.clone(),
+                None => configs.push(keyset.clone()),
+            }
+        }
+        configs
+    }
+}
+
+/// Keyset of the mint for a currency unit, parsed from `{unit}:{derivation_path}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeysetConfig {
+    pub unit: CurrencyUnit,
+    pub derivation_path: String,
+}
+
+impl KeysetConfig {
+    pub fn new(unit: CurrencyUnit, derivation_path: &str) -> Self {
+        Self {
+            unit,
+            derivation_path: derivation_path.to_owned(),
+        }
+    }
+}
+
+impl FromStr for KeysetConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (unit, derivation_path) = s
+            .split_once(':')
+            .ok_or_else(|| format!("invalid keyset {s}, expected {{unit}}:{{derivation_path}}"))?;
+        let Ok(unit) = unit.trim().parse();
+        Ok(Self::new(unit, derivation_path.trim()))
+    }
 }
 
 impl MintConfig {
@@ -89,6 +197,12 @@ impl MintConfig {
         private_key: String,
         derivation_path: Option<String>,
         ugx_derivation_path: Option<String>,
+        keysets: Vec<KeysetConfig>,
+        derivation_prefix: Option<String>,
+        enable_dleq: bool,
+        require_dleq: bool,
+        ugx_exchange_rate: Option<f64>,
+        input_fee_ppk: u64,
         info: MintInfoConfig,
         server: ServerConfig,
         database: DatabaseConfig,
@@ -100,6 +214,12 @@ impl MintConfig {
             server,
             derivation_path,
             ugx_derivation_path,
+            keysets,
+            derivation_prefix,
+            enable_dleq,
+            require_dleq,
+            ugx_exchange_rate,
+            input_fee_ppk,
             info,
             onchain_backend,
             database,
@@ -130,6 +250,53 @@ pub struct OnchainConfig {
         env = "MINT_ONCHAIN_BACKEND_MAX_AMOUNT"
     )]
     pub max_amount: u64,
+
+    #[clap(long, default_value = DEFAULT_SOLANA_RPC_URL, env = "MINT_SOLANA_RPC_URL")]
+    pub solana_rpc_url: String,
+
+    /// commitment level a payment must reach to be accepted: confirmed or finalized. The rpc
+    /// node doesn't look up signatures and transactions at processed
+    #[clap(long, default_value = DEFAULT_SOLANA_COMMITMENT, env = "MINT_SOLANA_COMMITMENT")]
+    pub solana_commitment: String,
+
+    /// fee in percent deducted from the amount of a mint or melt quote. Melt quotes add the
+    /// estimated network fee on top
+    #[clap(long, default_value_t = 1.0, env = "MINT_ONCHAIN_FEE_PERCENT")]
+    pub fee_percent: f64,
+
+    /// margin in percent added on top of the estimated network fee of a melt
+    #[clap(
+        long,
+        default_value_t = 10,
+        env = "MINT_ONCHAIN_BACKEND_FEE_MARGIN_PERCENT"
+    )]
+    pub fee_margin_percent: u64,
+
+    /// network fee in micro-usd used if the fee can't be estimated
+    #[clap(
+        long,
+        default_value_t = 1_000,
+        env = "MINT_ONCHAIN_BACKEND_DEFAULT_FEE"
+    )]
+    pub default_fee: u64,
+
+    /// seconds the result of an onchain payment check is reused for the same quote, 0 disables
+    /// the cache
+    #[clap(
+        long,
+        default_value_t = 5,
+        env = "MINT_ONCHAIN_BACKEND_PAYMENT_CACHE_TTL"
+    )]
+    pub payment_cache_ttl: u64,
+
+    /// maximum number of onchain payment checks sent to the solana rpc node at the same time,
+    /// further checks wait for a free slot
+    #[clap(
+        long,
+        default_value_t = 8,
+        env = "MINT_ONCHAIN_BACKEND_MAX_CONCURRENT_RPC_REQUESTS"
+    )]
+    pub max_concurrent_rpc_requests: usize,
 }
 
 impl Default for OnchainConfig {
@@ -138,6 +305,13 @@ impl Default for OnchainConfig {
             min_confirmations: 1,
             min_amount: 10_000,
             max_amount: 1_000_000,
+            solana_rpc_url: DEFAULT_SOLANA_RPC_URL.to_owned(),
+            solana_commitment: DEFAULT_SOLANA_COMMITMENT.to_owned(),
+            fee_percent: 1.0,
+            fee_margin_percent: 10,
+            default_fee: 1_000,
+            payment_cache_ttl: 5,
+            max_concurrent_rpc_requests: 8,
         }
     }
 }
@@ -177,6 +351,36 @@ pub struct ServerConfig {
     // pub serve_wallet_path: Option<PathBuf>,
     #[clap(long, env = "MINT_API_PREFIX")]
     pub api_prefix: Option<String>,
+    /// Bearer token for the admin routes. The admin routes are disabled if not set
+    #[clap(long, env = "MINT_ADMIN_API_KEY")]
+    pub admin_api_key: Option<String>,
+    /// Bearer token for the onchain mint and melt routes and /metrics. These routes are public if
+    /// not set
+    #[clap(long, env = "MINT_API_KEY")]
+    pub api_key: Option<String>,
+    /// seconds between two runs of the task deleting expired unpaid quotes, 0 disables the task
+    #[clap(long, default_value_t = 300, env = "MINT_QUOTE_CLEANUP_INTERVAL")]
+    pub quote_cleanup_interval: u64,
+    /// seconds an unpaid quote is kept after it expired, so a late payment can still be found
+    #[clap(
+        long,
+        default_value_t = 86_400,
+        env = "MINT_QUOTE_CLEANUP_GRACE_PERIOD"
+    )]
+    pub quote_cleanup_grace_period: u64,
+    /// requests a client may send per rate limit window, 0 disables the limit
+    #[clap(long, default_value_t = 120, env = "MINT_RATE_LIMIT_REQUESTS")]
+    pub rate_limit_requests: u32,
+    /// seconds of a rate limit window
+    #[clap(long, default_value_t = 60, env = "MINT_RATE_LIMIT_WINDOW")]
+    pub rate_limit_window: u64,
+    /// seconds open connections may take to finish after a shutdown signal, before the server
+    /// stops anyway
+    #[clap(long, default_value_t = 30, env = "MINT_SHUTDOWN_TIMEOUT")]
+    pub shutdown_timeout: u64,
+    /// serves counters and histograms of the mint in the Prometheus format on /metrics
+    #[clap(long, default_value_t = false, env = "MINT_METRICS_ENABLED")]
+    pub metrics_enabled: bool,
 }
 
 impl Default for ServerConfig {
@@ -185,6 +389,14 @@ impl Default for ServerConfig {
             host_port: "[::]:3338".to_string().parse().expect("invalid host port"),
             // serve_wallet_path: None,
             api_prefix: None,
+            admin_api_key: None,
+            api_key: None,
+            quote_cleanup_interval: 300,
+            quote_cleanup_grace_period: 86_400,
+            rate_limit_requests: 120,
+            rate_limit_window: 60,
+            shutdown_timeout: 30,
+            metrics_enabled: false,
         }
     }
 }
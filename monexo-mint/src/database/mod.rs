@@ -1,19 +1,49 @@
 use async_trait::async_trait;
 use monexo_core::{
+    blind::{BlindedMessage, BlindedSignature},
     primitives::{OnchainMeltQuote, OnchainMintQuote},
     proof::Proofs,
 };
+use secp256k1::PublicKey;
 
 use crate::error::MonexoMintError;
 use uuid::Uuid;
 
 pub mod postgres;
 
+/// The signed transfer of a melt, kept to broadcast it again until it lands or its blockhash
+/// expired
+#[derive(Debug, Clone)]
+pub struct MeltTransfer {
+    pub quote_id: Uuid,
+    /// json of the signed transaction
+    pub transaction: String,
+    /// the transaction can't land once the chain is past this block height
+    pub last_valid_block_height: u64,
+    pub inputs_amount: u64,
+    /// outputs the change is signed to once the transfer landed
+    pub blanks: Vec<BlindedMessage>,
+}
+
+/// The response stored for an idempotency key with the hash of the request it answered
+#[derive(Debug, Clone)]
+pub struct IdempotentResponse {
+    pub request_hash: String,
+    /// json of the response
+    pub response: String,
+}
+
 #[async_trait]
 pub trait Database {
     type DB: sqlx::Database;
     async fn begin_tx(&self) -> Result<sqlx::Transaction<Self::DB>, sqlx::Error>;
 
+    /// Runs a trivial query to check that the database is reachable
+    async fn ping(&self) -> Result<(), MonexoMintError>;
+
+    /// Closes all connections, waits for the connections in use to be returned first
+    async fn close(&self);
+
     async fn get_used_proofs(
         &self,
         tx: &mut sqlx::Transaction<Self::DB>,
@@ -25,6 +55,74 @@ pub trait Database {
         proofs: &Proofs,
     ) -> Result<(), MonexoMintError>;
 
+    /// Returns the secrets of all used and blocked proofs
+    async fn get_used_secrets(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+    ) -> Result<Vec<String>, MonexoMintError>;
+
+    /// Returns true if the proof with y = hash_to_curve(secret) has been used or blocked
+    async fn is_proof_used(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        y: &str,
+    ) -> Result<bool, MonexoMintError>;
+
+    /// Marks the used proofs as pending until the transfer of the melt quote landed
+    async fn add_pending_proofs(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        quote_id: &Uuid,
+        proofs: &Proofs,
+    ) -> Result<(), MonexoMintError>;
+
+    /// Returns true if the proof with y = hash_to_curve(secret) is pending
+    async fn is_proof_pending(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        y: &str,
+    ) -> Result<bool, MonexoMintError>;
+
+    /// Keeps the pending proofs of the melt quote as spent
+    async fn settle_pending_proofs(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        quote_id: &Uuid,
+    ) -> Result<(), MonexoMintError>;
+
+    /// Deletes the pending proofs of the melt quote from the used proofs, so they can be spent again
+    async fn release_pending_proofs(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        quote_id: &Uuid,
+    ) -> Result<(), MonexoMintError>;
+
+    async fn add_blocked_secrets(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        secrets: &[String],
+    ) -> Result<(), MonexoMintError>;
+
+    async fn add_audit_log(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        action: &str,
+        detail: &str,
+    ) -> Result<(), MonexoMintError>;
+
+    async fn add_blind_signatures(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        messages: &[BlindedMessage],
+        signatures: &[BlindedSignature],
+    ) -> Result<(), MonexoMintError>;
+
+    async fn get_blind_signature_by_b_(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        b_: &PublicKey,
+    ) -> Result<Option<BlindedSignature>, MonexoMintError>;
+
     async fn add_onchain_mint_quote(
         &self,
         tx: &mut sqlx::Transaction<Self::DB>,
@@ -37,6 +135,13 @@ pub trait Database {
         key: &Uuid,
     ) -> Result<OnchainMintQuote, MonexoMintError>;
 
+    /// Returns the mint quote and locks it until the transaction ends
+    async fn lock_onchain_mint_quote(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        key: &Uuid,
+    ) -> Result<OnchainMintQuote, MonexoMintError>;
+
     async fn update_onchain_mint_quote(
         &self,
         tx: &mut sqlx::Transaction<Self::DB>,
@@ -55,9 +160,74 @@ pub trait Database {
         key: &Uuid,
     ) -> Result<OnchainMeltQuote, MonexoMintError>;
 
+    /// Returns the melt quote and locks it until the transaction ends
+    async fn lock_onchain_melt_quote(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        key: &Uuid,
+    ) -> Result<OnchainMeltQuote, MonexoMintError>;
+
     async fn update_onchain_melt_quote(
         &self,
         tx: &mut sqlx::Transaction<Self::DB>,
         quote: &OnchainMeltQuote,
     ) -> Result<(), MonexoMintError>;
+
+    /// Returns the id of the transaction sent for the melt quote, None if nothing has been sent
+    async fn get_melt_txid_for_quote(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        key: &Uuid,
+    ) -> Result<Option<String>, MonexoMintError>;
+
+    async fn add_melt_transfer(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        transfer: &MeltTransfer,
+    ) -> Result<(), MonexoMintError>;
+
+    async fn get_melt_transfer(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        quote_id: &Uuid,
+    ) -> Result<Option<MeltTransfer>, MonexoMintError>;
+
+    async fn delete_melt_transfer(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        quote_id: &Uuid,
+    ) -> Result<(), MonexoMintError>;
+
+    /// Returns the json response stored for the idempotency key of the endpoint
+    async fn get_idempotent_response(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        endpoint: &str,
+        key: &str,
+    ) -> Result<Option<IdempotentResponse>, MonexoMintError>;
+
+    async fn add_idempotent_response(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        endpoint: &str,
+        key: &str,
+        request_hash: &str,
+        response: &str,
+    ) -> Result<(), MonexoMintError>;
+
+    /// Returns the unpaid mint quotes that expired before the given unix timestamp
+    async fn get_expired_mint_quotes(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        expired_before: u64,
+    ) -> Result<Vec<OnchainMintQuote>, MonexoMintError>;
+
+    /// Deletes the given mint quotes if they are still unpaid and the unpaid melt quotes that
+    /// expired before the given unix timestamp, returns how many were deleted
+    async fn delete_expired_quotes(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        expired_before: u64,
+        mint_quotes: &[Uuid],
+    ) -> Result<u64, MonexoMintError>;
 }
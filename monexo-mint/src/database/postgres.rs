@@ -1,19 +1,22 @@
-use std::str::FromStr;
+use std::{str::FromStr, time::Duration};
 
 use async_trait::async_trait;
 
 use monexo_core::{
+    blind::{BlindSignatureDleq, BlindedMessage, BlindedSignature},
     dhke,
+    error::MonexoCoreError,
     primitives::{MeltOnchainState, MintOnchainState, OnchainMeltQuote, OnchainMintQuote},
     proof::{Proof, Proofs},
 };
+use secp256k1::{PublicKey, SecretKey};
 use sqlx::postgres::PgPoolOptions;
 use tracing::instrument;
 use uuid::Uuid;
 
 use crate::{config::DatabaseConfig, error::MonexoMintError};
 
-use super::Database;
+use super::{Database, IdempotentResponse, MeltTransfer};
 
 #[derive(Clone)]
 pub struct PostgresDB {
@@ -25,6 +28,11 @@ impl PostgresDB {
         Ok(Self {
             pool: PgPoolOptions::new()
                 .max_connections(config.max_connections)
+                .min_connections(config.min_connections)
+                .acquire_timeout(Duration::from_secs(config.acquire_timeout))
+                .idle_timeout(
+                    (config.idle_timeout > 0).then(|| Duration::from_secs(config.idle_timeout)),
+                )
                 .connect(config.db_url.as_str())
                 .await?,
         })
@@ -35,9 +43,37 @@ impl PostgresDB {
             .run(&self.pool)
             .await
             .expect("Could not run migrations");
+        self.fill_used_proof_ys()
+            .await
+            .expect("Could not fill in the y of used proofs");
+    }
+
+    /// Sets the y of used proofs stored before it was part of the table
+    async fn fill_used_proof_ys(&self) -> Result<(), MonexoMintError> {
+        let mut tx = self.pool.begin().await?;
+        let secrets = sqlx::query!("SELECT secret FROM used_proofs WHERE y IS NULL")
+            .fetch_all(&mut *tx)
+            .await?;
+
+        for row in secrets {
+            sqlx::query!(
+                "UPDATE used_proofs SET y = $1 WHERE secret = $2",
+                proof_y(&row.secret)?,
+                row.secret
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
     }
 }
 
+/// Returns y = hash_to_curve(secret), the key a proof is looked up by
+fn proof_y(secret: &str) -> Result<String, MonexoMintError> {
+    Ok(dhke::Dhke::hash_to_curve(secret.as_bytes())?.to_string())
+}
+
 #[async_trait]
 impl Database for PostgresDB {
     type DB = sqlx::Postgres;
@@ -46,12 +82,23 @@ impl Database for PostgresDB {
         self.pool.begin().await
     }
 
+    async fn ping(&self) -> Result<(), MonexoMintError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+
     #[instrument(level = "debug", skip(self), err)]
     async fn get_used_proofs(
         &self,
         tx: &mut sqlx::Transaction<Self::DB>,
     ) -> Result<Proofs, MonexoMintError> {
-        let proofs = sqlx::query!("SELECT * FROM used_proofs")
+        let proofs = sqlx::query!(
+            r#"SELECT amount as "amount!", secret, c as "c!", keyset_id as "keyset_id!" FROM used_proofs WHERE c IS NOT NULL"#
+        )
             .fetch_all(&mut **tx)
             .await?
             .into_iter()
@@ -61,6 +108,7 @@ impl Database for PostgresDB {
                 c: dhke::public_key_from_hex(&row.c).to_owned(),
                 keyset_id: row.keyset_id,
                 script: None,
+                dleq: None,
             })
             .collect::<Vec<Proof>>();
 
@@ -75,19 +123,207 @@ impl Database for PostgresDB {
     ) -> Result<(), MonexoMintError> {
         for proof in proofs.proofs() {
             sqlx::query!(
-                "INSERT INTO used_proofs (amount, secret, c, keyset_id) VALUES ($1, $2, $3, $4)",
+                "INSERT INTO used_proofs (amount, secret, c, keyset_id, y) VALUES ($1, $2, $3, $4, $5)",
                 proof.amount as i64,
                 proof.secret,
                 proof.c.to_string(),
-                proof.keyset_id.to_string()
+                proof.keyset_id.to_string(),
+                proof.y().map_err(MonexoCoreError::from)?.to_string()
+            )
+            .execute(&mut **tx)
+            .await
+            .map_err(|err| match err {
+                // a concurrent request spent the proof after it has been checked
+                sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                    MonexoMintError::ProofAlreadyUsed(format!("{proof:?}"))
+                }
+                err => err.into(),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_used_secrets(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+    ) -> Result<Vec<String>, MonexoMintError> {
+        Ok(sqlx::query!("SELECT secret FROM used_proofs")
+            .fetch_all(&mut **tx)
+            .await?
+            .into_iter()
+            .map(|row| row.secret)
+            .collect())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn is_proof_used(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        y: &str,
+    ) -> Result<bool, MonexoMintError> {
+        Ok(sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM used_proofs WHERE y = $1) as "used!""#,
+            y
+        )
+        .fetch_one(&mut **tx)
+        .await?
+        .used)
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn add_pending_proofs(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        quote_id: &Uuid,
+        proofs: &Proofs,
+    ) -> Result<(), MonexoMintError> {
+        for proof in proofs.proofs() {
+            sqlx::query!(
+                "INSERT INTO pending_proofs (y, quote_id) VALUES ($1, $2)",
+                proof.y().map_err(MonexoCoreError::from)?.to_string(),
+                quote_id
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn is_proof_pending(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        y: &str,
+    ) -> Result<bool, MonexoMintError> {
+        Ok(sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM pending_proofs WHERE y = $1) as "pending!""#,
+            y
+        )
+        .fetch_one(&mut **tx)
+        .await?
+        .pending)
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn settle_pending_proofs(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        quote_id: &Uuid,
+    ) -> Result<(), MonexoMintError> {
+        sqlx::query!("DELETE FROM pending_proofs WHERE quote_id = $1", quote_id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn release_pending_proofs(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        quote_id: &Uuid,
+    ) -> Result<(), MonexoMintError> {
+        sqlx::query!(
+            "DELETE FROM used_proofs WHERE y IN (SELECT y FROM pending_proofs WHERE quote_id = $1)",
+            quote_id
+        )
+        .execute(&mut **tx)
+        .await?;
+        self.settle_pending_proofs(tx, quote_id).await
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn add_blocked_secrets(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        secrets: &[String],
+    ) -> Result<(), MonexoMintError> {
+        for secret in secrets {
+            sqlx::query!(
+                "INSERT INTO used_proofs (secret, y) VALUES ($1, $2) ON CONFLICT (secret) DO NOTHING",
+                secret,
+                proof_y(secret)?
             )
             .execute(&mut **tx)
             .await?;
         }
+        Ok(())
+    }
 
+    #[instrument(level = "debug", skip(self), err)]
+    async fn add_audit_log(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        action: &str,
+        detail: &str,
+    ) -> Result<(), MonexoMintError> {
+        sqlx::query!(
+            "INSERT INTO audit_log (action, detail) VALUES ($1, $2)",
+            action,
+            detail
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn add_blind_signatures(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        messages: &[BlindedMessage],
+        signatures: &[BlindedSignature],
+    ) -> Result<(), MonexoMintError> {
+        for (message, signature) in messages.iter().zip(signatures) {
+            let dleq = signature.dleq.as_ref();
+            sqlx::query!(
+                "INSERT INTO blind_signatures (b_, amount, c_, keyset_id, dleq_e, dleq_s) VALUES ($1, $2, $3, $4, $5, $6)",
+                message.b_.to_string(),
+                signature.amount as i64,
+                signature.c_.to_string(),
+                signature.id,
+                dleq.map(|dleq| dleq.e.display_secret().to_string()),
+                dleq.map(|dleq| dleq.s.display_secret().to_string())
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
         Ok(())
     }
 
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_blind_signature_by_b_(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        b_: &PublicKey,
+    ) -> Result<Option<BlindedSignature>, MonexoMintError> {
+        let Some(row) = sqlx::query!(
+            "SELECT amount, c_, keyset_id, dleq_e, dleq_s FROM blind_signatures WHERE b_ = $1",
+            b_.to_string()
+        )
+        .fetch_optional(&mut **tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let dleq = match (row.dleq_e, row.dleq_s) {
+            (Some(e), Some(s)) => Some(BlindSignatureDleq {
+                e: SecretKey::from_str(&e).map_err(MonexoCoreError::from)?,
+                s: SecretKey::from_str(&s).map_err(MonexoCoreError::from)?,
+            }),
+            _ => None,
+        };
+
+        Ok(Some(BlindedSignature {
+            amount: row.amount as u64,
+            c_: dhke::public_key_from_hex(&row.c_),
+            id: row.keyset_id,
+            dleq,
+        }))
+    }
+
     #[instrument(level = "debug", skip(self), err)]
     async fn add_onchain_mint_quote(
         &self,
@@ -95,9 +331,10 @@ impl Database for PostgresDB {
         quote: &OnchainMintQuote,
     ) -> Result<(), MonexoMintError> {
         sqlx::query!(
-            "INSERT INTO onchain_mint_quotes (id, reference, fee_total, amount, expiry, state) VALUES ($1, $2, $3, $4, $5, $6)",
+            "INSERT INTO onchain_mint_quotes (id, reference, additional_references, fee_total, amount, expiry, state) VALUES ($1, $2, $3, $4, $5, $6, $7)",
             quote.quote_id,
             quote.reference,
+            &quote.additional_references,
             quote.fee_total as i64,
             quote.amount as i64,
             quote.expiry as i64,
@@ -115,12 +352,38 @@ impl Database for PostgresDB {
         key: &Uuid,
     ) -> Result<OnchainMintQuote, MonexoMintError> {
         let quote: OnchainMintQuote = sqlx::query!(
-            "SELECT id, reference, fee_total, amount, expiry, state  FROM onchain_mint_quotes WHERE id = $1",
+            "SELECT id, reference, additional_references, fee_total, amount, expiry, state FROM onchain_mint_quotes WHERE id = $1",
+            key
+        )
+        .map(|row| OnchainMintQuote {
+            quote_id: row.id,
+            reference: row.reference,
+            additional_references: row.additional_references,
+            fee_total: row.fee_total as u64,
+            expiry: row.expiry as u64,
+            state: MintOnchainState::from_str(&row.state).expect("invalid state in mint quote"),
+            amount: row.amount as u64,
+        })
+        .fetch_one(&mut **tx)
+        .await?;
+
+        Ok(quote)
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn lock_onchain_mint_quote(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        key: &Uuid,
+    ) -> Result<OnchainMintQuote, MonexoMintError> {
+        let quote: OnchainMintQuote = sqlx::query!(
+            "SELECT id, reference, additional_references, fee_total, amount, expiry, state FROM onchain_mint_quotes WHERE id = $1 FOR UPDATE",
             key
         )
         .map(|row| OnchainMintQuote {
             quote_id: row.id,
             reference: row.reference,
+            additional_references: row.additional_references,
             fee_total: row.fee_total as u64,
             expiry: row.expiry as u64,
             state: MintOnchainState::from_str(&row.state).expect("invalid state in mint quote"),
@@ -155,16 +418,18 @@ impl Database for PostgresDB {
         quote: &OnchainMeltQuote,
     ) -> Result<(), MonexoMintError> {
         sqlx::query!(
-            "INSERT INTO onchain_melt_quotes (id, amount, address, reference, fee_total, fee_sat_per_vbyte, expiry, state, description) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            "INSERT INTO onchain_melt_quotes (id, amount, address, reference, fee_total, fee_micro_usd, fee_mint, expiry, state, description, create_recipient_account) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
             quote.quote_id,
             quote.amount as i64,
             quote.address,
             quote.reference,
             quote.fee_total as i64,
-            quote.fee_sat_per_vbyte as i64,
+            quote.fee_micro_usd as i64,
+            quote.fee_mint as i64,
             quote.expiry as i64,
             quote.state.to_string(),
-            quote.description
+            quote.description,
+            quote.create_recipient_account
         )
         .execute(&mut **tx)
         .await?;
@@ -178,7 +443,7 @@ impl Database for PostgresDB {
         key: &Uuid,
     ) -> Result<OnchainMeltQuote, MonexoMintError> {
         let quote: OnchainMeltQuote = sqlx::query!(
-            "SELECT id, amount,address, reference, fee_total, fee_sat_per_vbyte, expiry, state, description  FROM onchain_melt_quotes WHERE id = $1",
+            "SELECT id, amount,address, reference, fee_total, fee_micro_usd, fee_mint, expiry, state, description, txid, create_recipient_account FROM onchain_melt_quotes WHERE id = $1",
             key
         )
         .map(|row| OnchainMeltQuote {
@@ -187,10 +452,43 @@ impl Database for PostgresDB {
             reference: row.reference,
             amount: row.amount as u64,
             fee_total: row.fee_total as u64,
-            fee_sat_per_vbyte: row.fee_sat_per_vbyte as u32,
+            fee_micro_usd: row.fee_micro_usd as u64,
+            fee_mint: row.fee_mint as u64,
             expiry: row.expiry as u64,
             state: MeltOnchainState::from_str(&row.state).expect("invalid state in melt quote"),
-            description: row.description
+            description: row.description,
+            txid: row.txid,
+            create_recipient_account: row.create_recipient_account,
+        })
+        .fetch_one(&mut **tx)
+        .await?;
+
+        Ok(quote)
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn lock_onchain_melt_quote(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        key: &Uuid,
+    ) -> Result<OnchainMeltQuote, MonexoMintError> {
+        let quote: OnchainMeltQuote = sqlx::query!(
+            "SELECT id, amount,address, reference, fee_total, fee_micro_usd, fee_mint, expiry, state, description, txid, create_recipient_account FROM onchain_melt_quotes WHERE id = $1 FOR UPDATE",
+            key
+        )
+        .map(|row| OnchainMeltQuote {
+            quote_id: row.id,
+            address: row.address,
+            reference: row.reference,
+            amount: row.amount as u64,
+            fee_total: row.fee_total as u64,
+            fee_micro_usd: row.fee_micro_usd as u64,
+            fee_mint: row.fee_mint as u64,
+            expiry: row.expiry as u64,
+            state: MeltOnchainState::from_str(&row.state).expect("invalid state in melt quote"),
+            description: row.description,
+            txid: row.txid,
+            create_recipient_account: row.create_recipient_account,
         })
         .fetch_one(&mut **tx)
         .await?;
@@ -205,12 +503,178 @@ impl Database for PostgresDB {
         quote: &OnchainMeltQuote,
     ) -> Result<(), MonexoMintError> {
         sqlx::query!(
-            "UPDATE onchain_melt_quotes SET state = $1 WHERE id = $2",
+            "UPDATE onchain_melt_quotes SET state = $1, txid = $2 WHERE id = $3",
             quote.state.to_string(),
+            quote.txid,
             quote.quote_id
         )
         .execute(&mut **tx)
         .await?;
         Ok(())
     }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_melt_txid_for_quote(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        key: &Uuid,
+    ) -> Result<Option<String>, MonexoMintError> {
+        let row = sqlx::query!("SELECT txid FROM onchain_melt_quotes WHERE id = $1", key)
+            .fetch_optional(&mut **tx)
+            .await?;
+
+        Ok(row.and_then(|row| row.txid))
+    }
+
+    #[instrument(level = "debug", skip(self, transfer), err)]
+    async fn add_melt_transfer(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        transfer: &MeltTransfer,
+    ) -> Result<(), MonexoMintError> {
+        sqlx::query!(
+            "INSERT INTO melt_transfers (quote_id, transaction, last_valid_block_height, inputs_amount, blanks) VALUES ($1, $2, $3, $4, $5)",
+            transfer.quote_id,
+            transfer.transaction,
+            transfer.last_valid_block_height as i64,
+            transfer.inputs_amount as i64,
+            serde_json::to_string(&transfer.blanks)?
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_melt_transfer(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        quote_id: &Uuid,
+    ) -> Result<Option<MeltTransfer>, MonexoMintError> {
+        let Some(row) = sqlx::query!(
+            "SELECT transaction, last_valid_block_height, inputs_amount, blanks FROM melt_transfers WHERE quote_id = $1",
+            quote_id
+        )
+        .fetch_optional(&mut **tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(MeltTransfer {
+            quote_id: *quote_id,
+            transaction: row.transaction,
+            last_valid_block_height: row.last_valid_block_height as u64,
+            inputs_amount: row.inputs_amount as u64,
+            blanks: serde_json::from_str(&row.blanks)?,
+        }))
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn delete_melt_transfer(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        quote_id: &Uuid,
+    ) -> Result<(), MonexoMintError> {
+        sqlx::query!("DELETE FROM melt_transfers WHERE quote_id = $1", quote_id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_idempotent_response(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        endpoint: &str,
+        key: &str,
+    ) -> Result<Option<IdempotentResponse>, MonexoMintError> {
+        let row = sqlx::query!(
+            "SELECT request_hash, response FROM idempotency_keys WHERE endpoint = $1 AND key = $2",
+            endpoint,
+            key
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        Ok(row.map(|row| IdempotentResponse {
+            request_hash: row.request_hash,
+            response: row.response,
+        }))
+    }
+
+    #[instrument(level = "debug", skip(self, response), err)]
+    async fn add_idempotent_response(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        endpoint: &str,
+        key: &str,
+        request_hash: &str,
+        response: &str,
+    ) -> Result<(), MonexoMintError> {
+        sqlx::query!(
+            "INSERT INTO idempotency_keys (endpoint, key, request_hash, response) VALUES ($1, $2, $3, $4)",
+            endpoint,
+            key,
+            request_hash,
+            response
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_expired_mint_quotes(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        expired_before: u64,
+    ) -> Result<Vec<OnchainMintQuote>, MonexoMintError> {
+        let quotes = sqlx::query!(
+            "SELECT id, reference, additional_references, fee_total, amount, expiry, state FROM onchain_mint_quotes WHERE state = $1 AND expiry < $2",
+            MintOnchainState::Unpaid.to_string(),
+            expired_before as i64
+        )
+        .map(|row| OnchainMintQuote {
+            quote_id: row.id,
+            reference: row.reference,
+            additional_references: row.additional_references,
+            fee_total: row.fee_total as u64,
+            expiry: row.expiry as u64,
+            state: MintOnchainState::from_str(&row.state).expect("invalid state in mint quote"),
+            amount: row.amount as u64,
+        })
+        .fetch_all(&mut **tx)
+        .await?;
+
+        Ok(quotes)
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn delete_expired_quotes(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        expired_before: u64,
+        mint_quotes: &[Uuid],
+    ) -> Result<u64, MonexoMintError> {
+        // the state is checked again, the quote may have been issued since it was checked
+        let mint_quotes = sqlx::query!(
+            "DELETE FROM onchain_mint_quotes WHERE state = $1 AND id = ANY($2)",
+            MintOnchainState::Unpaid.to_string(),
+            mint_quotes
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        // a melt quote with a txid has been sent, even if its state wasn't updated
+        let melt_quotes = sqlx::query!(
+            "DELETE FROM onchain_melt_quotes WHERE state = $1 AND expiry < $2 AND txid IS NULL",
+            MeltOnchainState::Unpaid.to_string(),
+            expired_before as i64
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(mint_quotes.rows_affected() + melt_quotes.rows_affected())
+    }
 }
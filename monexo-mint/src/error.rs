@@ -3,14 +3,17 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use monexo_core::primitives::CashuErrorResponse;
 use thiserror::Error;
 use tracing::{event, Level};
 
 #[derive(Error, Debug)]
 pub enum MonexoMintError {
     #[error("DB Error {0}")]
-    Db(#[from] sqlx::Error),
+    Db(sqlx::Error),
+
+    #[error("Database is busy, no connection became available in time")]
+    DbBusy,
 
     #[error("Invalid amount: {0}")]
     InvalidAmount(String),
@@ -18,12 +21,24 @@ pub enum MonexoMintError {
     #[error("Invalid quote {0}")]
     InvalidQuote(String),
 
+    #[error("Quote {0} has not been paid yet")]
+    InvoiceNotPaidYet(String),
+
+    #[error("Quote {0} has expired")]
+    QuoteExpired(String),
+
     #[error("{0}")]
     SwapAmountMismatch(String),
 
     #[error("duplicate promises.")]
     SwapHasDuplicatePromises,
 
+    #[error("duplicate inputs.")]
+    DuplicateInput,
+
+    #[error("Some outputs have already been signed")]
+    OutputsAlreadySigned,
+
     #[error("Invalid quote uuid {0}")]
     InvalidUuid(#[from] uuid::Error),
 
@@ -36,31 +51,205 @@ pub enum MonexoMintError {
     #[error("PrivateKey in keyset not found")]
     PrivateKeyNotFound,
 
+    #[error("Invalid proof for amount {0}")]
+    InvalidProof(u64),
+
     #[error("MonexoCoreError: {0}")]
     MonexoCore(#[from] monexo_core::error::MonexoCoreError),
 
     #[error("Keyset not found {0}")]
     KeysetNotFound(String),
 
+    #[error("Keyset id {0} is derived more than once, use distinct derivation paths")]
+    DuplicateKeysetId(String),
+
+    #[error("Invalid fee percent {0}, must be between 0 and {max}", max = crate::config::MAX_FEE_PERCENT)]
+    InvalidFeePercent(f64),
+
+    #[error("Invalid solana commitment {0}, must be confirmed or finalized")]
+    InvalidSolanaCommitment(String),
+
+    #[error("Invalid number of references {0}, must be between 1 and {max}", max = crate::routes::onchain::MAX_QUOTE_REFERENCES)]
+    InvalidReferenceCount(u8),
+
+    #[error("Self-test failed for keyset {0}")]
+    SelfTestFailed(String),
+
+    #[error("DLEQ proofs are required but can't be created for keyset {0}")]
+    DleqNotAvailable(String),
+
+    #[error("Currency exchange is not supported by this mint")]
+    ExchangeNotSupported,
+
     #[error("Solana RPC client error: {0}")]
-    RpcError(#[from] solana_client::client_error::ClientError),
+    RpcError(#[from] Box<solana_client::client_error::ClientError>),
 
     #[error("Pubkey invalid {0}")]
     InvalidRecepientPublicKey(#[from] solana_sdk::pubkey::ParsePubkeyError),
 
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    #[error("Onchain backend error: {0}")]
+    OnchainBackend(String),
+
+    #[error("Solana keypair of the mint can't be loaded from {0:?}")]
+    KeypairNotFound(Option<String>),
+
+    #[error("Mint holds {0} micro USDC but the melt needs {1}, try again later")]
+    InsufficientMintLiquidity(u64, u64),
+
+    #[error("The transfer of melt quote {0} didn't land, its inputs have been released")]
+    MeltTransferReleased(String),
+
+    #[error("Idempotency key {0} has already been used for another request")]
+    IdempotencyKeyReused(String),
+
+    #[error("Invalid json {0}")]
+    Serialization(#[from] serde_json::Error),
+
     #[error("Failed to create transfer instruction: {0}")]
     TransactionFailed(#[from] solana_sdk::program_error::ProgramError),
 }
 
+impl From<solana_client::client_error::ClientError> for MonexoMintError {
+    fn from(err: solana_client::client_error::ClientError) -> Self {
+        Self::RpcError(Box::new(err))
+    }
+}
+
+impl From<sqlx::Error> for MonexoMintError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            // every connection of the pool stayed in use for the acquire timeout
+            sqlx::Error::PoolTimedOut => Self::DbBusy,
+            err => Self::Db(err),
+        }
+    }
+}
+
+impl MonexoMintError {
+    /// Returns the NUT-00 error code a wallet can act on, 0 if there is no specific one
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::ProofAlreadyUsed(_) => CashuErrorResponse::TOKEN_ALREADY_SPENT,
+            Self::SwapAmountMismatch(_) | Self::NotEnoughTokens(_) => {
+                CashuErrorResponse::TRANSACTION_NOT_BALANCED
+            }
+            Self::DuplicateInput => CashuErrorResponse::DUPLICATE_INPUTS,
+            Self::KeysetNotFound(_) => CashuErrorResponse::KEYSET_NOT_FOUND,
+            Self::InvoiceNotPaidYet(_) => CashuErrorResponse::QUOTE_NOT_PAID,
+            _ => 0,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvoiceNotPaidYet(_) => StatusCode::PAYMENT_REQUIRED,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            // a missing row is an unknown quote, not an outage
+            Self::Db(sqlx::Error::RowNotFound) => StatusCode::BAD_REQUEST,
+            Self::Db(_)
+            | Self::DbBusy
+            | Self::RpcError(_)
+            | Self::OnchainBackend(_)
+            | Self::KeypairNotFound(_)
+            | Self::InsufficientMintLiquidity(_, _) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
 impl IntoResponse for MonexoMintError {
     fn into_response(self) -> Response {
         event!(Level::ERROR, "error in mint: {:?}", self);
 
-        let body = Json(json!({
-            "code": 0,
-            "detail": self.to_string(),
-        }));
+        let body = Json(CashuErrorResponse {
+            code: self.code(),
+            detail: self.to_string(),
+        });
+        (self.status(), body).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::StatusCode, response::IntoResponse};
+    use monexo_core::primitives::CashuErrorResponse;
+
+    use super::MonexoMintError;
+
+    async fn response_of(err: MonexoMintError) -> anyhow::Result<(StatusCode, CashuErrorResponse)> {
+        let response = err.into_response();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        Ok((status, serde_json::from_slice(&body)?))
+    }
 
-        (StatusCode::BAD_REQUEST, body).into_response()
+    #[tokio::test]
+    async fn test_error_responses() -> anyhow::Result<()> {
+        let cases = [
+            (
+                MonexoMintError::InvalidAmount("amount is too low".to_owned()),
+                StatusCode::BAD_REQUEST,
+                0,
+            ),
+            (
+                MonexoMintError::ProofAlreadyUsed("proof".to_owned()),
+                StatusCode::BAD_REQUEST,
+                CashuErrorResponse::TOKEN_ALREADY_SPENT,
+            ),
+            (
+                MonexoMintError::SwapAmountMismatch("64 != 20".to_owned()),
+                StatusCode::BAD_REQUEST,
+                CashuErrorResponse::TRANSACTION_NOT_BALANCED,
+            ),
+            (
+                MonexoMintError::DuplicateInput,
+                StatusCode::BAD_REQUEST,
+                CashuErrorResponse::DUPLICATE_INPUTS,
+            ),
+            (
+                MonexoMintError::KeysetNotFound("00ffffffffffffff".to_owned()),
+                StatusCode::BAD_REQUEST,
+                CashuErrorResponse::KEYSET_NOT_FOUND,
+            ),
+            (
+                MonexoMintError::InvoiceNotPaidYet("quote".to_owned()),
+                StatusCode::PAYMENT_REQUIRED,
+                CashuErrorResponse::QUOTE_NOT_PAID,
+            ),
+            (
+                MonexoMintError::Db(sqlx::Error::RowNotFound),
+                StatusCode::BAD_REQUEST,
+                0,
+            ),
+            (
+                MonexoMintError::Db(sqlx::Error::PoolTimedOut),
+                StatusCode::SERVICE_UNAVAILABLE,
+                0,
+            ),
+            (MonexoMintError::DbBusy, StatusCode::SERVICE_UNAVAILABLE, 0),
+            (
+                MonexoMintError::OnchainBackend("rpc is down".to_owned()),
+                StatusCode::SERVICE_UNAVAILABLE,
+                0,
+            ),
+            (
+                MonexoMintError::InsufficientMintLiquidity(10, 64),
+                StatusCode::SERVICE_UNAVAILABLE,
+                0,
+            ),
+            (MonexoMintError::Unauthorized, StatusCode::UNAUTHORIZED, 0),
+        ];
+
+        for (err, status, code) in cases {
+            let detail = err.to_string();
+            assert_eq!(
+                (status, CashuErrorResponse { code, detail }),
+                response_of(err).await?
+            );
+        }
+        Ok(())
     }
 }
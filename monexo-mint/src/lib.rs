@@ -1,6 +1,13 @@
 pub mod config;
 pub mod database;
 pub mod error;
+pub mod metrics;
 pub mod mint;
+pub mod payment_cache;
+pub mod price;
+pub mod quote_subscriptions;
+pub mod rate_limit;
 mod routes;
 pub mod server;
+#[cfg(test)]
+mod test_utils;
@@ -0,0 +1,298 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use dashmap::DashMap;
+
+/// onchain mint and melt quotes created, labelled with the `kind` of the quote
+pub const QUOTES_CREATED: &str = "monexo_quotes_created_total";
+/// amount of the tokens issued for paid onchain mint quotes
+pub const TOKENS_MINTED: &str = "monexo_tokens_minted_total";
+/// proofs marked as spent, labelled with the `operation` that spent them
+pub const PROOFS_SPENT: &str = "monexo_proofs_spent_total";
+/// onchain payment checks, labelled with their `result`: paid, unpaid or error
+pub const ONCHAIN_VERIFICATIONS: &str = "monexo_onchain_verifications_total";
+/// failed requests to the solana rpc node
+pub const RPC_ERRORS: &str = "monexo_rpc_errors_total";
+/// handled http requests, labelled with `method`, `path` and `status`
+pub const HTTP_REQUESTS: &str = "monexo_http_requests_total";
+/// seconds it took to handle a http request, labelled with `method` and `path`
+pub const HTTP_REQUEST_DURATION: &str = "monexo_http_request_duration_seconds";
+
+/// upper bounds in seconds of the buckets of the request duration histogram
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+type Labels = Vec<(&'static str, String)>;
+type Key = (&'static str, Labels);
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// observations per bucket of [`DURATION_BUCKETS`], not cumulative
+    buckets: [AtomicU64; DURATION_BUCKETS.len()],
+    count: AtomicU64,
+    /// bits of the `f64` sum of the observations
+    sum: AtomicU64,
+}
+
+/// Values of a [`Histogram`] at the time it was rendered
+struct HistogramSnapshot {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// Counters and histograms of the mint, served in the Prometheus text format on `/metrics` if
+/// enabled. Clones share the same values.
+///
+/// The series are kept in sharded maps of atomics, so requests recording metrics only contend
+/// when they create the same series and never wait for a scrape.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    counters: Arc<DashMap<Key, AtomicU64>>,
+    histograms: Arc<DashMap<Key, Histogram>>,
+}
+
+impl Metrics {
+    /// Adds `value` to the counter with the labels
+    pub fn increment(&self, name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+        let key = (name, to_labels(labels));
+        match self.counters.get(&key) {
+            Some(counter) => counter.fetch_add(value, Ordering::Relaxed),
+            None => self
+                .counters
+                .entry(key)
+                .or_default()
+                .fetch_add(value, Ordering::Relaxed),
+        };
+    }
+
+    /// Records an observation in the histogram with the labels
+    pub fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let key = (name, to_labels(labels));
+        let record = |histogram: &Histogram| {
+            if let Some(bucket) = DURATION_BUCKETS.iter().position(|bound| value <= *bound) {
+                histogram.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+            }
+            histogram.count.fetch_add(1, Ordering::Relaxed);
+            let _ = histogram
+                .sum
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                    Some((f64::from_bits(sum) + value).to_bits())
+                });
+        };
+        match self.histograms.get(&key) {
+            Some(histogram) => record(&histogram),
+            None => record(&self.histograms.entry(key).or_default()),
+        }
+    }
+
+    /// Returns the current value of the counter with the labels, 0 if it was never incremented
+    pub fn counter(&self, name: &'static str, labels: &[(&'static str, &str)]) -> u64 {
+        self.counters
+            .get(&(name, to_labels(labels)))
+            .map(|counter| counter.load(Ordering::Relaxed))
+            .unwrap_or_default()
+    }
+
+    /// Renders all values in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        // sorted, so the series of a metric are rendered below a single TYPE line
+        let counters = self
+            .counters
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect::<BTreeMap<_, _>>();
+        let histograms = self
+            .histograms
+            .iter()
+            .map(|entry| {
+                let histogram = entry.value();
+                let snapshot = HistogramSnapshot {
+                    buckets: std::array::from_fn(|bucket| {
+                        histogram.buckets[bucket].load(Ordering::Relaxed)
+                    }),
+                    count: histogram.count.load(Ordering::Relaxed),
+                    sum: f64::from_bits(histogram.sum.load(Ordering::Relaxed)),
+                };
+                (entry.key().clone(), snapshot)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let mut output = String::new();
+
+        let mut last_name = None;
+        for ((name, labels), value) in &counters {
+            if last_name != Some(name) {
+                let _ = writeln!(output, "# TYPE {name} counter");
+                last_name = Some(name);
+            }
+            let _ = writeln!(output, "{name}{} {value}", format_labels(labels, None));
+        }
+
+        let mut last_name = None;
+        for ((name, labels), histogram) in &histograms {
+            if last_name != Some(name) {
+                let _ = writeln!(output, "# TYPE {name} histogram");
+                last_name = Some(name);
+            }
+            let mut cumulative = 0;
+            for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let le = bound.to_string();
+                let _ = writeln!(
+                    output,
+                    "{name}_bucket{} {cumulative}",
+                    format_labels(labels, Some(&le))
+                );
+            }
+            let _ = writeln!(
+                output,
+                "{name}_bucket{} {}",
+                format_labels(labels, Some("+Inf")),
+                histogram.count
+            );
+            let _ = writeln!(
+                output,
+                "{name}_sum{} {}",
+                format_labels(labels, None),
+                histogram.sum
+            );
+            let _ = writeln!(
+                output,
+                "{name}_count{} {}",
+                format_labels(labels, None),
+                histogram.count
+            );
+        }
+        output
+    }
+}
+
+fn to_labels(labels: &[(&'static str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|(name, value)| (*name, (*value).to_owned()))
+        .collect()
+}
+
+/// Formats the labels as `{name="value",...}`, empty if there are none
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let labels = labels
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect::<Vec<_>>();
+    match labels.is_empty() {
+        true => String::new(),
+        false => format!("{{{}}}", labels.join(",")),
+    }
+}
+
+/// Middleware counting the requests and their duration per route
+pub async fn track_metrics(
+    State(metrics): State<Metrics>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // the route pattern instead of the uri, so quote ids don't create a series each
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+    let method = request.method().to_string();
+
+    let started_at = Instant::now();
+    let response = next.run(request).await;
+
+    metrics.increment(
+        HTTP_REQUESTS,
+        &[
+            ("method", &method),
+            ("path", &path),
+            ("status", response.status().as_str()),
+        ],
+        1,
+    );
+    metrics.observe(
+        HTTP_REQUEST_DURATION,
+        &[("method", &method), ("path", &path)],
+        started_at.elapsed().as_secs_f64(),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Metrics, HTTP_REQUEST_DURATION, PROOFS_SPENT, RPC_ERRORS};
+
+    #[test]
+    fn test_render_counters() {
+        let metrics = Metrics::default();
+        metrics.increment(PROOFS_SPENT, &[("operation", "swap")], 2);
+        metrics.increment(PROOFS_SPENT, &[("operation", "swap")], 3);
+        metrics.increment(PROOFS_SPENT, &[("operation", "melt")], 1);
+        metrics.increment(RPC_ERRORS, &[], 1);
+
+        assert_eq!(5, metrics.counter(PROOFS_SPENT, &[("operation", "swap")]));
+        assert_eq!(
+            0,
+            metrics.counter(PROOFS_SPENT, &[("operation", "exchange")])
+        );
+        assert_eq!(
+            "# TYPE monexo_proofs_spent_total counter\n\
+             monexo_proofs_spent_total{operation=\"melt\"} 1\n\
+             monexo_proofs_spent_total{operation=\"swap\"} 5\n\
+             # TYPE monexo_rpc_errors_total counter\n\
+             monexo_rpc_errors_total 1\n",
+            metrics.render()
+        );
+    }
+
+    #[test]
+    fn test_render_histogram() {
+        let metrics = Metrics::default();
+        metrics.observe(HTTP_REQUEST_DURATION, &[("path", "/v1/swap")], 0.5);
+        metrics.observe(HTTP_REQUEST_DURATION, &[("path", "/v1/swap")], 20.0);
+
+        let output = metrics.render();
+        assert!(output.contains("# TYPE monexo_http_request_duration_seconds histogram\n"));
+        assert!(output.contains(
+            "monexo_http_request_duration_seconds_bucket{path=\"/v1/swap\",le=\"0.25\"} 0\n"
+        ));
+        assert!(output.contains(
+            "monexo_http_request_duration_seconds_bucket{path=\"/v1/swap\",le=\"0.5\"} 1\n"
+        ));
+        assert!(output.contains(
+            "monexo_http_request_duration_seconds_bucket{path=\"/v1/swap\",le=\"10\"} 1\n"
+        ));
+        assert!(output.contains(
+            "monexo_http_request_duration_seconds_bucket{path=\"/v1/swap\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(
+            output.contains("monexo_http_request_duration_seconds_sum{path=\"/v1/swap\"} 20.5\n")
+        );
+        assert!(
+            output.contains("monexo_http_request_duration_seconds_count{path=\"/v1/swap\"} 2\n")
+        );
+    }
+}
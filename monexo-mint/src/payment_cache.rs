@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Result of an onchain payment check
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OnchainPayment {
+    /// true if the transactions paid the expected amount
+    pub paid: bool,
+    /// usdc the checked transactions transferred, in base units
+    pub amount_paid: u64,
+}
+
+/// Keeps the result of onchain payment checks for a short time, so a wallet polling a quote
+/// doesn't trigger a solana rpc request on every call
+#[derive(Debug, Clone, Default)]
+pub struct PaymentCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, (Instant, OnchainPayment)>>>,
+}
+
+impl PaymentCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Default::default(),
+        }
+    }
+
+    /// Returns the cached result for the given reference if it hasn't expired yet
+    pub fn get(&self, reference: &str) -> Option<OnchainPayment> {
+        let entries = self.entries.lock().expect("payment cache lock poisoned");
+        entries
+            .get(reference)
+            .filter(|(checked_at, _)| checked_at.elapsed() < self.ttl)
+            .map(|(_, payment)| *payment)
+    }
+
+    pub fn insert(&self, reference: &str, payment: OnchainPayment) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().expect("payment cache lock poisoned");
+        entries.retain(|_, (checked_at, _)| checked_at.elapsed() < self.ttl);
+        entries.insert(reference.to_owned(), (Instant::now(), payment));
+    }
+
+    pub fn invalidate(&self, reference: &str) {
+        self.entries
+            .lock()
+            .expect("payment cache lock poisoned")
+            .remove(reference);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::{OnchainPayment, PaymentCache};
+
+    #[test]
+    fn test_payment_cache() {
+        let cache = PaymentCache::new(Duration::from_secs(60));
+        assert_eq!(None, cache.get("reference"));
+
+        let payment = OnchainPayment {
+            paid: false,
+            amount_paid: 5_000_000,
+        };
+        cache.insert("reference", payment);
+        assert_eq!(Some(payment), cache.get("reference"));
+
+        cache.invalidate("reference");
+        assert_eq!(None, cache.get("reference"));
+    }
+
+    #[test]
+    fn test_payment_cache_disabled() {
+        let cache = PaymentCache::new(Duration::ZERO);
+        cache.insert(
+            "reference",
+            OnchainPayment {
+                paid: true,
+                amount_paid: 10_000_000,
+            },
+        );
+        assert_eq!(None, cache.get("reference"));
+    }
+
+    #[test]
+    fn test_payment_cache_expired() {
+        let cache = PaymentCache::new(Duration::from_millis(10));
+        cache.insert("reference", OnchainPayment::default());
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(None, cache.get("reference"));
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tracing::warn;
+
+use crate::error::MonexoMintError;
+
+const COINGECKO_SOL_PRICE_URL: &str =
+    "https://api.coingecko.com/api/v3/simple/price?ids=solana&vs_currencies=usd";
+
+/// Converts lamports into micro-usd at the given SOL price, 1 SOL = 1B lamports
+pub fn lamports_to_micro_usd(lamports: u64, sol_usdc_price: f64) -> u64 {
+    (lamports as f64 * sol_usdc_price / 1_000.0).ceil() as u64
+}
+
+/// Source of the SOL price used to convert network fees into micro-usd
+#[async_trait]
+pub trait PriceOracle: std::fmt::Debug + Send + Sync {
+    async fn sol_usdc_price(&self) -> Result<f64, MonexoMintError>;
+}
+
+/// Fetches the SOL price from coingecko, retrying failed requests
+#[derive(Debug, Clone)]
+pub struct CoingeckoPriceOracle {
+    client: reqwest::Client,
+    url: String,
+    retries: u32,
+}
+
+impl CoingeckoPriceOracle {
+    pub fn new(url: impl Into<String>, timeout: Duration, retries: u32) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .expect("failed to create http client"),
+            url: url.into(),
+            retries,
+        }
+    }
+
+    async fn fetch_price(&self) -> Result<f64, MonexoMintError> {
+        let json = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| MonexoMintError::OnchainBackend(format!("price request failed: {err}")))?
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|err| {
+                MonexoMintError::OnchainBackend(format!("invalid price response: {err}"))
+            })?;
+
+        json["solana"]["usd"].as_f64().ok_or_else(|| {
+            MonexoMintError::OnchainBackend("failed to get SOL/USDC price".to_owned())
+        })
+    }
+}
+
+impl Default for CoingeckoPriceOracle {
+    fn default() -> Self {
+        Self::new(COINGECKO_SOL_PRICE_URL, Duration::from_secs(5), 2)
+    }
+}
+
+#[async_trait]
+impl PriceOracle for CoingeckoPriceOracle {
+    async fn sol_usdc_price(&self) -> Result<f64, MonexoMintError> {
+        let mut attempt = 0;
+        loop {
+            match self.fetch_price().await {
+                Ok(price) => return Ok(price),
+                Err(err) if attempt < self.retries => {
+                    attempt += 1;
+                    warn!("fetching SOL price failed (attempt {attempt}): {err}");
+                    tokio::time::sleep(Duration::from_millis(500 * u64::from(attempt))).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{routing::get, Json, Router};
+    use pretty_assertions::assert_eq;
+
+    use super::{CoingeckoPriceOracle, PriceOracle};
+
+    async fn create_mock_price_api(response: serde_json::Value) -> anyhow::Result<String> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/price", listener.local_addr()?);
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                Router::new().route("/price", get(move || async move { Json(response) })),
+            )
+            .await
+        });
+        Ok(url)
+    }
+
+    #[tokio::test]
+    async fn test_coingecko_price() -> anyhow::Result<()> {
+        let url = create_mock_price_api(serde_json::json!({ "solana": { "usd": 173.19 } })).await?;
+        let oracle = CoingeckoPriceOracle::new(url, Duration::from_secs(1), 0);
+        assert_eq!(173.19, oracle.sol_usdc_price().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_coingecko_invalid_response() -> anyhow::Result<()> {
+        let url = create_mock_price_api(serde_json::json!({ "status": "rate limited" })).await?;
+        let oracle = CoingeckoPriceOracle::new(url, Duration::from_secs(1), 1);
+        assert!(oracle.sol_usdc_price().await.is_err());
+        Ok(())
+    }
+}
+
+/// Source of the exchange rate between the ugx and the usd keyset
+#[async_trait]
+pub trait RateProvider: std::fmt::Debug + Send + Sync {
+    /// Returns the amount of the ugx keyset's unit one unit of the usd keyset is exchanged for
+    async fn ugx_usd_rate(&self) -> Result<f64, MonexoMintError>;
+}
+
+/// Returns the rate configured with `MINT_UGX_EXCHANGE_RATE`, exchanges fail if it's not set
+#[derive(Debug, Clone, Default)]
+pub struct FixedRateProvider(pub Option<f64>);
+
+#[async_trait]
+impl RateProvider for FixedRateProvider {
+    async fn ugx_usd_rate(&self) -> Result<f64, MonexoMintError> {
+        self.0
+            .filter(|rate| *rate > 0.0)
+            .ok_or(MonexoMintError::ExchangeNotSupported)
+    }
+}
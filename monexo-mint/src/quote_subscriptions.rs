@@ -0,0 +1,196 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+};
+
+use monexo_core::primitives::PostMintQuoteOnchainResponse;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+type QuoteSender = watch::Sender<Option<PostMintQuoteOnchainResponse>>;
+
+/// Polls of the subscribed mint quotes, a quote is checked by a single poll no matter how many
+/// wallets subscribed to it
+#[derive(Debug, Clone, Default)]
+pub struct QuoteSubscriptions {
+    polls: Arc<Mutex<HashMap<Uuid, Weak<QuoteSender>>>>,
+}
+
+impl QuoteSubscriptions {
+    /// Returns a receiver of the quote's states. If the quote isn't polled yet, `start_poll` is
+    /// called with a new poll, the receiver is closed once that poll is dropped.
+    pub fn subscribe(
+        &self,
+        quote_id: Uuid,
+        start_poll: impl FnOnce(QuotePoll),
+    ) -> watch::Receiver<Option<PostMintQuoteOnchainResponse>> {
+        let mut polls = self
+            .polls
+            .lock()
+            .expect("quote subscriptions lock poisoned");
+        if let Some(sender) = polls.get(&quote_id).and_then(Weak::upgrade) {
+            let mut receiver = sender.subscribe();
+            // a late subscriber gets the last state first
+            receiver.mark_changed();
+            return receiver;
+        }
+
+        let (sender, receiver) = watch::channel(None);
+        let sender = Arc::new(sender);
+        polls.insert(quote_id, Arc::downgrade(&sender));
+        drop(polls);
+
+        start_poll(QuotePoll {
+            quote_id,
+            sender,
+            subscriptions: self.clone(),
+        });
+        receiver
+    }
+
+    /// Returns the number of quotes that are polled
+    pub fn len(&self) -> usize {
+        self.polls
+            .lock()
+            .expect("quote subscriptions lock poisoned")
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Sends the states of a quote to its subscribers
+#[derive(Debug)]
+pub struct QuotePoll {
+    quote_id: Uuid,
+    sender: Arc<QuoteSender>,
+    subscriptions: QuoteSubscriptions,
+}
+
+impl QuotePoll {
+    /// Sends the quote to the subscribers if its state changed
+    pub fn publish(&self, quote: PostMintQuoteOnchainResponse) {
+        self.sender.send_if_modified(|current| {
+            if current.as_ref().map(|current| &current.state) == Some(&quote.state) {
+                return false;
+            }
+            *current = Some(quote);
+            true
+        });
+    }
+
+    /// Returns false once every subscriber is gone. The poll is removed then, so the next
+    /// subscriber of the quote starts a new one.
+    pub fn has_subscribers(&self) -> bool {
+        let mut polls = self
+            .subscriptions
+            .polls
+            .lock()
+            .expect("quote subscriptions lock poisoned");
+        if self.sender.receiver_count() > 0 {
+            return true;
+        }
+        self.remove(&mut polls);
+        false
+    }
+
+    fn remove(&self, polls: &mut HashMap<Uuid, Weak<QuoteSender>>) {
+        // a newer poll of the quote may have replaced this one
+        if polls
+            .get(&self.quote_id)
+            .is_some_and(|poll| Weak::as_ptr(poll) == Arc::as_ptr(&self.sender))
+        {
+            polls.remove(&self.quote_id);
+        }
+    }
+}
+
+impl Drop for QuotePoll {
+    fn drop(&mut self) {
+        if let Ok(mut polls) = self.subscriptions.polls.lock() {
+            self.remove(&mut polls);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monexo_core::primitives::{MintOnchainState, PostMintQuoteOnchainResponse};
+    use pretty_assertions::assert_eq;
+    use uuid::Uuid;
+
+    use super::{QuotePoll, QuoteSubscriptions};
+
+    fn quote_response(state: MintOnchainState) -> PostMintQuoteOnchainResponse {
+        PostMintQuoteOnchainResponse {
+            quote: "quote".to_owned(),
+            reference: "reference".to_owned(),
+            additional_references: vec![],
+            fee: 0,
+            state,
+            expiry: 0,
+            amount_paid: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_share_poll() -> anyhow::Result<()> {
+        let subscriptions = QuoteSubscriptions::default();
+        let quote_id = Uuid::new_v4();
+        let mut polls = vec![];
+
+        let mut first = subscriptions.subscribe(quote_id, |poll| polls.push(poll));
+        polls[0].publish(quote_response(MintOnchainState::Unpaid));
+        let mut second = subscriptions.subscribe(quote_id, |_: QuotePoll| {
+            panic!("the quote is polled already")
+        });
+        assert_eq!(1, subscriptions.len());
+
+        // both get the current state, the unchanged state isn't sent again
+        for receiver in [&mut first, &mut second] {
+            receiver.changed().await?;
+            assert_eq!(
+                Some(MintOnchainState::Unpaid),
+                receiver
+                    .borrow_and_update()
+                    .as_ref()
+                    .map(|q| q.state.clone())
+            );
+        }
+        polls[0].publish(quote_response(MintOnchainState::Unpaid));
+        assert!(!first.has_changed()?);
+
+        // the subscribers see the last state once the poll ended
+        polls[0].publish(quote_response(MintOnchainState::Paid));
+        polls.clear();
+        assert!(subscriptions.is_empty());
+        first.changed().await?;
+        assert_eq!(
+            Some(MintOnchainState::Paid),
+            first.borrow_and_update().as_ref().map(|q| q.state.clone())
+        );
+        assert!(first.changed().await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_without_subscribers() {
+        let subscriptions = QuoteSubscriptions::default();
+        let quote_id = Uuid::new_v4();
+        let mut polls = vec![];
+
+        let receiver = subscriptions.subscribe(quote_id, |poll| polls.push(poll));
+        assert!(polls[0].has_subscribers());
+        drop(receiver);
+        assert!(!polls[0].has_subscribers());
+        assert!(subscriptions.is_empty());
+
+        // the next subscriber starts a new poll, dropping the old one keeps it
+        let _receiver = subscriptions.subscribe(quote_id, |poll| polls.push(poll));
+        assert_eq!(2, polls.len());
+        polls.remove(0);
+        assert_eq!(1, subscriptions.len());
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use monexo_core::primitives::CashuErrorResponse;
+use tracing::warn;
+
+/// Counts the requests of every client in fixed windows, so a single client can't flood the mint
+/// with quotes
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    max_requests: u32,
+    window: Duration,
+    clients: Arc<Mutex<Clients>>,
+}
+
+/// Windows of the clients, windows that ended are pruned once per window
+#[derive(Debug)]
+struct Clients {
+    pruned_at: Instant,
+    windows: HashMap<IpAddr, (Instant, u32)>,
+}
+
+impl RateLimiter {
+    /// Allows `max_requests` per client and `window`, 0 disables the limit
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            clients: Arc::new(Mutex::new(Clients {
+                pruned_at: Instant::now(),
+                windows: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Counts a request of the client, returns false if it exceeds the limit of the current window
+    pub fn check(&self, client: IpAddr) -> bool {
+        if self.max_requests == 0 {
+            return true;
+        }
+
+        let mut clients = self.clients.lock().expect("rate limiter lock poisoned");
+        if clients.pruned_at.elapsed() >= self.window {
+            clients
+                .windows
+                .retain(|_, (started_at, _)| started_at.elapsed() < self.window);
+            clients.pruned_at = Instant::now();
+        }
+
+        let (started_at, requests) = clients.windows.entry(client).or_insert((Instant::now(), 0));
+        if started_at.elapsed() >= self.window {
+            *started_at = Instant::now();
+            *requests = 0;
+        }
+        *requests += 1;
+        *requests <= self.max_requests
+    }
+
+    /// Returns the number of clients with a window that isn't pruned yet
+    #[cfg(test)]
+    fn tracked_clients(&self) -> usize {
+        self.clients
+            .lock()
+            .expect("rate limiter lock poisoned")
+            .windows
+            .len()
+    }
+}
+
+/// Middleware answering with 429 once the client, identified by its ip address, exceeds the limit.
+/// Requests without the client's address are refused, they would all share a single limit.
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(ConnectInfo(addr)) = request.extensions().get::<ConnectInfo<SocketAddr>>() else {
+        warn!("rate-limit: request without client address, the server must be served with connect info");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(CashuErrorResponse {
+                code: 0,
+                detail: "Client address unknown".to_owned(),
+            }),
+        )
+            .into_response();
+    };
+
+    if !limiter.check(addr.ip()) {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(CashuErrorResponse {
+                code: 0,
+                detail: "Too many requests, try again later".to_owned(),
+            }),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::Duration,
+    };
+
+    use axum::{
+        body::Body,
+        extract::ConnectInfo,
+        http::{Request, StatusCode},
+        middleware,
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::{rate_limit, RateLimiter};
+
+    fn client(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, last))
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.check(client(1)));
+        assert!(limiter.check(client(1)));
+        assert!(!limiter.check(client(1)));
+        assert!(limiter.check(client(2)));
+    }
+
+    #[test]
+    fn test_rate_limiter_disabled() {
+        let limiter = RateLimiter::new(0, Duration::from_secs(60));
+        assert!((0..10).all(|_| limiter.check(client(1))));
+    }
+
+    #[test]
+    fn test_rate_limiter_window_expired() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(10));
+        assert!(limiter.check(client(1)));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(limiter.check(client(1)));
+    }
+
+    #[test]
+    fn test_rate_limiter_prunes_once_per_window() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(50));
+        assert!(limiter.check(client(1)));
+        assert!(limiter.check(client(2)));
+        assert_eq!(2, limiter.tracked_clients());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(limiter.check(client(3)));
+        assert_eq!(1, limiter.tracked_clients());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_without_client_address() -> anyhow::Result<()> {
+        let app = Router::new()
+            .route("/", get(|| async {}))
+            .layer(middleware::from_fn_with_state(
+                RateLimiter::new(1, Duration::from_secs(60)),
+                rate_limit,
+            ));
+
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri("/").body(Body::empty())?)
+            .await?;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+
+        // requests without an address don't use up the limit of a client
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .extension(ConnectInfo(std::net::SocketAddr::from((client(1), 4242))))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(StatusCode::OK, response.status());
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use axum::{extract::State, http::HeaderMap, Json};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::{error::MonexoMintError, mint::Mint};
+
+#[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
+pub struct PostBlockProofsRequest {
+    pub secrets: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
+pub struct PostBlockProofsResponse {
+    pub blocked: usize,
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/admin/proofs/block",
+    request_body = PostBlockProofsRequest,
+    responses(
+        (status = 200, description = "block proofs", body = [PostBlockProofsResponse]),
+        (status = 401, description = "missing or invalid admin api key"),
+    ),
+)]
+#[instrument(name = "post_block_proofs", skip(mint, headers), err)]
+pub async fn post_block_proofs(
+    State(mint): State<Mint>,
+    headers: HeaderMap,
+    Json(request): Json<PostBlockProofsRequest>,
+) -> Result<Json<PostBlockProofsResponse>, MonexoMintError> {
+    authorize(&mint, &headers)?;
+
+    mint.block_proofs(&request.secrets).await?;
+
+    Ok(Json(PostBlockProofsResponse {
+        blocked: request.secrets.len(),
+    }))
+}
+
+/// Checks the bearer token against the configured admin api key
+fn authorize(mint: &Mint, headers: &HeaderMap) -> Result<(), MonexoMintError> {
+    let api_key = mint
+        .config
+        .server
+        .admin_api_key
+        .as_ref()
+        .ok_or(MonexoMintError::Unauthorized)?;
+
+    // compared in constant time, the time taken must not reveal how much of it matched
+    match bearer_token(headers) {
+        Some(token) if bool::from(token.as_bytes().ct_eq(api_key.as_bytes())) => Ok(()),
+        _ => Err(MonexoMintError::Unauthorized),
+    }
+}
+
+/// Returns the token of an `Authorization: Bearer <token>` header
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashSet, str::FromStr};
+use std::str::FromStr;
 
 use axum::{
     extract::{Path, State},
@@ -7,15 +7,12 @@ use axum::{
 use monexo_core::{
     keyset::{Keyset, Keysets},
     primitives::{
-        CurrencyUnit, KeyResponse, KeysResponse, MintInfoResponse, PostCheckStateRequest,
-        PostCheckStateResponse, PostSwapRequest, PostSwapResponse, ProofState, ProofStatus,
+        KeyResponse, KeysResponse, MintInfoResponse, PostCheckStateRequest, PostCheckStateResponse,
+        PostRestoreRequest, PostRestoreResponse, PostSwapRequest, PostSwapResponse, ProofState,
+        ProofStatus,
     },
 };
-use solana_sdk::{
-    pubkey::Pubkey,
-    signature::Keypair,
-    signer::{EncodableKey, Signer},
-};
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
 
 use crate::{database::Database, error::MonexoMintError, mint::Mint};
 
@@ -43,6 +40,26 @@ pub async fn post_swap(
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/restore",
+    request_body = PostRestoreRequest,
+    responses(
+        (status = 200, description = "post restore", body = [PostRestoreResponse])
+    ),
+)]
+#[instrument(name = "post_restore", skip(mint), err)]
+pub async fn post_restore(
+    State(mint): State<Mint>,
+    Json(restore_request): Json<PostRestoreRequest>,
+) -> Result<Json<PostRestoreResponse>, MonexoMintError> {
+    let (outputs, signatures) = mint.restore(&restore_request.outputs).await?;
+    Ok(Json(PostRestoreResponse {
+        outputs,
+        signatures,
+    }))
+}
+
 #[utoipa::path(
     get,
     path = "/v1/info",
@@ -52,25 +69,28 @@ pub async fn post_swap(
 )]
 #[instrument(name = "get_info", skip(mint), err)]
 pub async fn get_info(State(mint): State<Mint>) -> Result<Json<MintInfoResponse>, MonexoMintError> {
-    // let mint_info = mint.config.info.clone();
-
     let usdc_mint_address =
         Pubkey::from_str("4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU").unwrap();
-    let monexo_wallet_keypair = Keypair::read_from_file(mint.config.derivation_path.unwrap())
-        .expect("Failed to load keypair");
     let monexo_usdc_ata = spl_associated_token_account::get_associated_token_address(
-        &monexo_wallet_keypair
-            .try_pubkey()
-            .expect("Failed to load mint pubkey"),
+        &mint.solana_keypair()?.pubkey(),
         &usdc_mint_address,
     );
 
+    let onchain_config = mint.config.onchain_backend.as_ref();
     let mint_info = MintInfoResponse {
-        // name: mint.config.info.name,
-        name: None,
-        version: None,
+        name: mint.config.info.name.clone(),
+        version: mint
+            .config
+            .info
+            .version
+            .then(|| mint.build_params.full_version()),
         usdc_address: monexo_usdc_ata.to_string(),
         usdc_token_mint: usdc_mint_address.to_string(),
+        nuts: mint.supported_nuts(),
+        min_amount: onchain_config.map(|onchain| onchain.min_amount),
+        max_amount: onchain_config.map(|onchain| onchain.max_amount),
+        fee_percent: onchain_config.map(|onchain| onchain.fee_percent),
+        ugx_exchange_rate: mint.rate_provider.ugx_usd_rate().await.ok(),
     };
 
     Ok(Json(mint_info))
@@ -86,18 +106,15 @@ pub async fn get_info(State(mint): State<Mint>) -> Result<Json<MintInfoResponse>
 #[instrument(name = "get_keys", skip(mint), err)]
 pub async fn get_keys(State(mint): State<Mint>) -> Result<Json<KeysResponse>, MonexoMintError> {
     Ok(Json(KeysResponse {
-        keysets: vec![
-            KeyResponse {
-                id: mint.keyset.keyset_id.clone(),
-                unit: CurrencyUnit::Usd,
-                keys: mint.keyset.public_keys.clone(),
-            },
-            KeyResponse {
-                id: mint.ugx_keyset.keyset_id.clone(),
-                unit: CurrencyUnit::Ugx,
-                keys: mint.ugx_keyset.public_keys.clone(),
-            },
-        ],
+        keysets: mint
+            .sorted_keysets()
+            .into_iter()
+            .map(|(unit, keyset)| KeyResponse {
+                id: keyset.keyset_id.clone(),
+                unit: unit.clone(),
+                keys: keyset.public_keys.clone(),
+            })
+            .collect(),
     }))
 }
 
@@ -110,18 +127,17 @@ pub async fn get_keys(State(mint): State<Mint>) -> Result<Json<KeysResponse>, Mo
 )]
 #[instrument(name = "get_keysets", skip(mint), err)]
 pub async fn get_keysets(State(mint): State<Mint>) -> Result<Json<Keysets>, MonexoMintError> {
-    Ok(Json(Keysets::new(vec![
-        Keyset {
-            id: mint.keyset.keyset_id,
-            unit: CurrencyUnit::Usd,
-            active: true,
-        },
-        Keyset {
-            id: mint.ugx_keyset.keyset_id,
-            unit: CurrencyUnit::Ugx,
-            active: true,
-        },
-    ])))
+    Ok(Json(Keysets::new(
+        mint.sorted_keysets()
+            .into_iter()
+            .map(|(unit, keyset)| Keyset {
+                id: keyset.keyset_id.clone(),
+                unit: unit.clone(),
+                active: true,
+                input_fee_ppk: mint.config.input_fee_ppk,
+            })
+            .collect(),
+    )))
 }
 
 #[instrument(skip(mint), err)]
@@ -129,15 +145,15 @@ pub async fn get_keys_by_id(
     Path(id): Path<String>,
     State(mint): State<Mint>,
 ) -> Result<Json<KeysResponse>, MonexoMintError> {
-    if id != mint.keyset.keyset_id {
-        return Err(MonexoMintError::KeysetNotFound(id));
-    }
+    let (unit, keyset) = mint
+        .find_keyset(&id)
+        .ok_or_else(|| MonexoMintError::KeysetNotFound(id.clone()))?;
 
     Ok(Json(KeysResponse {
         keysets: vec![KeyResponse {
-            id: mint.keyset.keyset_id.clone(),
-            unit: CurrencyUnit::Usd,
-            keys: mint.keyset.public_keys,
+            id: keyset.keyset_id.clone(),
+            unit: unit.clone(),
+            keys: keyset.public_keys.clone(),
         }],
     }))
 }
@@ -156,35 +172,24 @@ pub async fn post_check_state(
     Json(chek_state_request): Json<PostCheckStateRequest>,
 ) -> Result<Json<PostCheckStateResponse>, MonexoMintError> {
     let mut tx = mint.db.begin_tx().await?;
-    let spent_proofs = &mint.db.get_used_proofs(&mut tx).await?.proofs();
-
-    let used_secrets: HashSet<String> = spent_proofs
-        .iter()
-        .map(|proof| {
-            proof
-                .y()
-                .expect("Failed to determine `y` values for spent proof")
-                .to_string()
-        })
-        .collect();
-
-    let states: Vec<ProofStatus> = chek_state_request
-        .ys
-        .iter()
-        .map(|secret| {
-            let state = if used_secrets.contains(secret) {
-                ProofState::Spent
-            } else {
-                ProofState::Unspent
-            };
-
-            ProofStatus {
-                y: secret.clone(),
-                state,
-                witness: None,
-            }
-        })
-        .collect();
+    let mut states = vec![];
+    for y in chek_state_request.ys {
+        // inputs of a melt whose transfer hasn't landed yet are pending
+        let state = if mint.db.is_proof_pending(&mut tx, &y).await? {
+            ProofState::Pending
+        } else if mint.db.is_proof_used(&mut tx, &y).await? {
+            ProofState::Spent
+        } else {
+            ProofState::Unspent
+        };
+
+        states.push(ProofStatus {
+            y,
+            state,
+            witness: None,
+        });
+    }
+    tx.commit().await?;
 
     Ok(Json(PostCheckStateResponse { states }))
 }
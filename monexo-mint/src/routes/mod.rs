@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod default;
 pub mod exchange;
 pub mod onchain;
@@ -1,33 +1,67 @@
 use solana_client::{
     nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::RpcTransactionConfig,
 };
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
-use solana_sdk::signer::EncodableKey;
 use solana_sdk::{signature::Keypair, signer::Signer};
+use spl_token::solana_program::program_pack::Pack;
+use std::collections::HashSet;
 use std::str::FromStr;
 
 use axum::{
     extract::{Path, State},
+    response::sse::{Event, KeepAlive, Sse},
     Json,
 };
+use bitcoin_hashes::{sha256, Hash};
 use chrono::{Duration, Utc};
+use futures::Stream;
+use monexo_core::blind::TotalAmount;
 use monexo_core::primitives::{
     MeltOnchainState, MintOnchainState, OnchainMeltQuote, OnchainMintQuote, PostMeltOnchainRequest,
     PostMeltOnchainResponse, PostMeltQuoteOnchainRequest, PostMeltQuoteOnchainResponse,
     PostMintOnchainRequest, PostMintOnchainResponse, PostMintQuoteOnchainRequest,
     PostMintQuoteOnchainResponse,
 };
+use serde::Serialize;
 use solana_transaction_status::UiTransactionTokenBalance;
 use solana_transaction_status_client_types::option_serializer::OptionSerializer;
 use solana_transaction_status_client_types::{
     UiInstruction, UiMessage, UiParsedInstruction, UiTransactionEncoding,
 };
-use tracing::{info, instrument};
+use tracing::{debug, info, instrument, warn};
 use uuid::Uuid;
 
-use crate::{database::Database, error::MonexoMintError, mint::Mint};
+use crate::{
+    config::OnchainConfig,
+    database::Database,
+    error::MonexoMintError,
+    metrics,
+    mint::{amount_to_send, token_program_id, MeltTransferStatus, Mint},
+    payment_cache::OnchainPayment,
+    price::{lamports_to_micro_usd, PriceOracle},
+    quote_subscriptions::QuotePoll,
+};
+
+/// maximum number of payment references a mint quote can be split across
+pub const MAX_QUOTE_REFERENCES: u8 = 10;
+
+/// time between two checks of a subscribed mint quote
+const QUOTE_SUBSCRIPTION_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// time a subscription is kept open at most
+const QUOTE_SUBSCRIPTION_MAX_LIFETIME: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// endpoints whose responses are stored for their idempotency keys
+const MINT_ENDPOINT: &str = "/v1/mint/btconchain";
+const MELT_ENDPOINT: &str = "/v1/melt/btconchain";
+
+/// Returns the hex sha256 of the json request, the caller leaves out its idempotency key
+fn request_hash<T: Serialize>(request: &T) -> Result<String, MonexoMintError> {
+    Ok(sha256::Hash::hash(&serde_json::to_vec(request)?).to_string())
+}
 
 #[utoipa::path(
     post,
@@ -58,15 +92,24 @@ pub async fn post_mint_quote_onchain(
         )));
     }
 
+    let references = request.references.unwrap_or(1);
+    if references == 0 || references > MAX_QUOTE_REFERENCES {
+        return Err(MonexoMintError::InvalidReferenceCount(references));
+    }
+
     let quote_id = Uuid::new_v4();
     let reference = Keypair::new().pubkey().to_string();
     // let reference = Pubkey::new_unique(); ??
+    let additional_references = (1..references)
+        .map(|_| Keypair::new().pubkey().to_string())
+        .collect();
 
     let quote = OnchainMintQuote {
         quote_id,
         reference,
+        additional_references,
         amount: request.amount,
-        fee_total: ((request.amount as f64) * 0.01) as u64,
+        fee_total: ((request.amount as f64) * onchain_config.fee_percent / 100.0) as u64,
         expiry: quote_onchain_expiry(),
         state: MintOnchainState::Unpaid,
     };
@@ -74,6 +117,8 @@ pub async fn post_mint_quote_onchain(
     let mut tx = mint.db.begin_tx().await?;
     mint.db.add_onchain_mint_quote(&mut tx, &quote).await?;
     tx.commit().await?;
+    mint.metrics
+        .increment(metrics::QUOTES_CREATED, &[("kind", "mint")], 1);
     Ok(Json(quote.into()))
 }
 
@@ -94,39 +139,100 @@ pub async fn get_mint_quote_onchain(
 ) -> Result<Json<PostMintQuoteOnchainResponse>, MonexoMintError> {
     info!("get_quote onchain: {}", quote_id);
 
+    let (quote, amount_paid) =
+        current_mint_quote(&mint, &Uuid::from_str(quote_id.as_str())?).await?;
+    Ok(Json(PostMintQuoteOnchainResponse {
+        amount_paid,
+        ..quote.into()
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/mint/quote/btconchain/{quote_id}/subscribe",
+    responses(
+        (status = 200, description = "server-sent events with the mint quote whenever its state changes, the stream ends once the quote is paid or expired", body = [PostMintQuoteOnchainResponse])
+    ),
+    params(
+        ("quote_id" = String, Path, description = "quote id"),
+    )
+)]
+#[instrument(name = "subscribe_mint_quote_btconchain", skip(mint), err)]
+pub async fn subscribe_mint_quote_onchain(
+    Path(quote_id): Path<String>,
+    State(mint): State<Mint>,
+) -> Result<Sse<impl Stream<Item = Result<Event, MonexoMintError>>>, MonexoMintError> {
+    let quote_id = Uuid::from_str(quote_id.as_str())?;
+    // unknown quotes fail before the stream is opened
     let mut tx = mint.db.begin_tx().await?;
-    let quote = mint
-        .db
-        .get_onchain_mint_quote(&mut tx, &Uuid::from_str(quote_id.as_str())?)
-        .await?;
+    mint.db.get_onchain_mint_quote(&mut tx, &quote_id).await?;
     tx.commit().await?;
 
-    let state = match quote.state {
-        MintOnchainState::Issued => quote.state,
-        _ => {
-            let monexo_wallet_keypair =
-                Keypair::read_from_file(mint.config.derivation_path.unwrap())
-                    .expect("Failed to load keypair");
-
-            let monexo_wallet_pub_key = monexo_wallet_keypair
-                .try_pubkey()
-                .expect("Failed to load mint pubkey");
-
-            let verified = is_paid_onchain(
-                quote.amount,
-                &quote.reference,
-                &monexo_wallet_pub_key.to_string(),
-            )
-            .await;
+    let receiver = mint.quote_subscriptions.subscribe(quote_id, |poll| {
+        tokio::spawn(poll_mint_quote(mint.clone(), quote_id, poll));
+    });
+
+    // ends with the poll or at the deadline, a wallet that still waits subscribes again
+    let deadline = tokio::time::Instant::now() + QUOTE_SUBSCRIPTION_MAX_LIFETIME;
+    let events = futures::stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            if !matches!(
+                tokio::time::timeout_at(deadline, receiver.changed()).await,
+                Ok(Ok(()))
+            ) {
+                return None;
+            }
+            let Some(response) = receiver.borrow_and_update().clone() else {
+                continue;
+            };
+            let event = serde_json::to_string(&response)
+                .map(|data| Event::default().data(data))
+                .map_err(MonexoMintError::from);
+            return Some((event, receiver));
+        }
+    });
 
-            match verified {
-                false => MintOnchainState::Unpaid,
-                true => MintOnchainState::Paid,
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Checks the mint quote for its subscribers until it is paid or expired, or every subscriber
+/// is gone
+async fn poll_mint_quote(mint: Mint, quote_id: Uuid, poll: QuotePoll) {
+    while poll.has_subscribers() {
+        let (quote, amount_paid) = match current_mint_quote(&mint, &quote_id).await {
+            Ok(current) => current,
+            Err(err) => {
+                warn!("Checking subscribed mint quote {quote_id} failed: {err}");
+                return;
             }
+        };
+        let ended = match quote.state {
+            MintOnchainState::Paid | MintOnchainState::Issued => true,
+            MintOnchainState::Unpaid | MintOnchainState::Pending => is_expired(quote.expiry),
+        };
+        poll.publish(PostMintQuoteOnchainResponse {
+            amount_paid,
+            ..quote.into()
+        });
+        if ended {
+            return;
         }
-    };
+        tokio::time::sleep(QUOTE_SUBSCRIPTION_INTERVAL).await;
+    }
+}
+
+/// Loads the mint quote with its current state, checking the chain if it isn't known to be paid.
+/// Also returns the amount received so far if the chain was checked.
+async fn current_mint_quote(
+    mint: &Mint,
+    quote_id: &Uuid,
+) -> Result<(OnchainMintQuote, Option<u64>), MonexoMintError> {
+    let mut tx = mint.db.begin_tx().await?;
+    let quote = mint.db.get_onchain_mint_quote(&mut tx, quote_id).await?;
+    tx.commit().await?;
 
-    Ok(Json(OnchainMintQuote { state, ..quote }.into()))
+    let (state, amount_paid) = mint_quote_onchain_state(mint, &quote).await?;
+    Ok((OnchainMintQuote { state, ..quote }, amount_paid))
 }
 
 #[utoipa::path(
@@ -142,17 +248,59 @@ pub async fn post_mint_onchain(
     State(mint): State<Mint>,
     Json(request): Json<PostMintOnchainRequest>,
 ) -> Result<Json<PostMintOnchainResponse>, MonexoMintError> {
-    // TODO Figure out the quote has been paid, only then do you mint the tokens
-    // TODO Check that the sum of secrets is equal to the quote.amount
-
     let mut tx = mint.db.begin_tx().await?;
-    let signatures = mint
-        .mint_tokens(&mut tx, request.quote.clone(), &request.outputs, false)
-        .await?;
+    let idempotency_key = request.idempotency_key.as_deref();
+    let request_hash = request_hash(&PostMintOnchainRequest {
+        idempotency_key: None,
+        ..request.clone()
+    })?;
+    if let Some(response) = mint
+        .get_idempotent_response(&mut tx, MINT_ENDPOINT, idempotency_key, &request_hash)
+        .await?
+    {
+        return Ok(Json(response));
+    }
 
-    let old_quote = &mint
+    // concurrent requests for the quote wait until this one issued it or failed
+    let old_quote = mint
         .db
-        .get_onchain_mint_quote(&mut tx, &Uuid::from_str(request.quote.as_str())?)
+        .lock_onchain_mint_quote(&mut tx, &Uuid::from_str(request.quote.as_str())?)
+        .await?;
+
+    let expected_amount = old_quote.amount - old_quote.fee_total;
+    if request.outputs.total_amount() != expected_amount {
+        return Err(MonexoMintError::SwapAmountMismatch(format!(
+            "Output amount {} does not match quote amount {}",
+            request.outputs.total_amount(),
+            expected_amount
+        )));
+    }
+
+    match mint_quote_onchain_state(&mint, &old_quote).await?.0 {
+        MintOnchainState::Paid => {}
+        // a quote paid before it expired is still issued, only an unpaid one expires
+        MintOnchainState::Unpaid | MintOnchainState::Pending if is_expired(old_quote.expiry) => {
+            return Err(MonexoMintError::QuoteExpired(request.quote));
+        }
+        MintOnchainState::Unpaid | MintOnchainState::Pending => {
+            return Err(MonexoMintError::InvoiceNotPaidYet(request.quote));
+        }
+        MintOnchainState::Issued => {
+            // a retried request for the same outputs gets the already issued signatures
+            return match mint.get_blind_signatures(&mut tx, &request.outputs).await? {
+                Some(signatures) if !signatures.is_empty() => {
+                    Ok(Json(PostMintOnchainResponse { signatures }))
+                }
+                _ => Err(MonexoMintError::InvalidQuote(format!(
+                    "{} has already been issued",
+                    request.quote
+                ))),
+            };
+        }
+    }
+
+    let signatures = mint
+        .mint_tokens(&mut tx, request.quote.clone(), &request.outputs, false)
         .await?;
 
     mint.db
@@ -164,8 +312,21 @@ pub async fn post_mint_onchain(
             },
         )
         .await?;
+    let response = PostMintOnchainResponse { signatures };
+    mint.add_idempotent_response(
+        &mut tx,
+        MINT_ENDPOINT,
+        idempotency_key,
+        &request_hash,
+        &response,
+    )
+    .await?;
     tx.commit().await?;
-    Ok(Json(PostMintOnchainResponse { signatures }))
+    mint.metrics
+        .increment(metrics::TOKENS_MINTED, &[], expected_amount);
+    // the quote state is stored now, so it is never checked onchain again
+    mint.payment_cache.invalidate(&old_quote.reference);
+    Ok(Json(response))
 }
 
 #[utoipa::path(
@@ -183,7 +344,7 @@ pub async fn post_melt_quote_onchain(
 ) -> Result<Json<Vec<PostMeltQuoteOnchainResponse>>, MonexoMintError> {
     let PostMeltQuoteOnchainRequest { address, amount } = melt_request;
 
-    let onchain_config = mint.config.onchain_backend.unwrap_or_default();
+    let onchain_config = mint.config.onchain_backend.clone().unwrap_or_default();
 
     if amount < onchain_config.min_amount {
         return Err(MonexoMintError::InvalidAmount(format!(
@@ -200,22 +361,38 @@ pub async fn post_melt_quote_onchain(
     }
 
     let reference = Keypair::new().pubkey().to_string();
+    let (fee_micro_usd, create_recipient_account) =
+        estimate_melt_fee(&mint, &onchain_config, amount, &address).await;
+
+    // the fee is paid from the amount, the rent of a new recipient account can exceed it
+    let fee_mint = ((amount as f64) * onchain_config.fee_percent / 100.0) as u64;
+    let fee_total = fee_micro_usd * (100 + onchain_config.fee_margin_percent) / 100 + fee_mint;
+    if amount <= fee_total {
+        return Err(MonexoMintError::InvalidAmount(format!(
+            "amount {amount} doesn't cover the fee of {fee_total}"
+        )));
+    }
 
     let quote = OnchainMeltQuote {
         quote_id: Uuid::new_v4(),
         address,
         reference,
         amount,
-        fee_total: ((amount as f64) * 0.01) as u64,
-        fee_sat_per_vbyte: 0, //fee_response.sat_per_vbyte,
+        fee_total,
+        fee_micro_usd,
+        fee_mint,
         expiry: quote_onchain_expiry(),
         state: MeltOnchainState::Unpaid,
         description: None,
+        txid: None,
+        create_recipient_account,
     };
 
     let mut tx = mint.db.begin_tx().await?;
     mint.db.add_onchain_melt_quote(&mut tx, &quote).await?;
     tx.commit().await?;
+    mint.metrics
+        .increment(metrics::QUOTES_CREATED, &[("kind", "melt")], 1);
 
     Ok(Json(vec![quote.into()]))
 }
@@ -237,19 +414,27 @@ pub async fn get_melt_quote_onchain(
 ) -> Result<Json<PostMeltQuoteOnchainResponse>, MonexoMintError> {
     info!("get_melt_quote onchain: {}", quote_id);
     let mut tx = mint.db.begin_tx().await?;
-    let quote = mint
+    let mut quote = mint
         .db
         .get_onchain_melt_quote(&mut tx, &Uuid::from_str(quote_id.as_str())?)
         .await?;
-    let expected_paid_amount = quote.amount - quote.fee_total;
-    let paid = is_paid_onchain(expected_paid_amount, &quote.reference, &quote.address).await;
+    let transfer = mint.db.get_melt_transfer(&mut tx, &quote.quote_id).await?;
+    tx.commit().await?;
 
-    let state = match paid {
-        true => MeltOnchainState::Paid,
-        false => MeltOnchainState::Unpaid,
-    };
+    // a transfer that can't land anymore releases the inputs, the quote can be melted again
+    if let Some(transfer) = transfer {
+        if let MeltTransferStatus::Released = mint.check_melt_transfer(&quote, &transfer).await? {
+            quote = OnchainMeltQuote {
+                state: MeltOnchainState::Unpaid,
+                txid: None,
+                ..quote
+            };
+        }
+    }
 
-    if paid {
+    let state = melt_quote_onchain_state(&mint, &quote).await?;
+    if state != quote.state {
+        let mut tx = mint.db.begin_tx().await?;
         mint.db
             .update_onchain_melt_quote(
                 &mut tx,
@@ -259,6 +444,7 @@ pub async fn get_melt_quote_onchain(
                 },
             )
             .await?;
+        tx.commit().await?;
     }
 
     Ok(Json(OnchainMeltQuote { state, ..quote }.into()))
@@ -278,89 +464,354 @@ pub async fn post_melt_onchain(
     Json(melt_request): Json<PostMeltOnchainRequest>,
 ) -> Result<Json<PostMeltOnchainResponse>, MonexoMintError> {
     let mut tx = mint.db.begin_tx().await?;
+    let idempotency_key = melt_request.idempotency_key.as_deref();
+    let request_hash = request_hash(&PostMeltOnchainRequest {
+        idempotency_key: None,
+        ..melt_request.clone()
+    })?;
+    if let Some(response) = mint
+        .get_idempotent_response(&mut tx, MELT_ENDPOINT, idempotency_key, &request_hash)
+        .await?
+    {
+        return Ok(Json(response));
+    }
+
     let quote = mint
         .db
         .get_onchain_melt_quote(&mut tx, &Uuid::from_str(melt_request.quote.as_str())?)
         .await?;
+    tx.commit().await?;
 
-    let txid = mint.melt_onchain(&quote, &melt_request.inputs).await?;
-    let expected_paid_amount = quote.amount - quote.fee_total;
-    let paid = is_paid_onchain(expected_paid_amount, &quote.reference, &quote.address).await;
+    if quote.state == MeltOnchainState::Paid {
+        return Err(MonexoMintError::InvalidQuote(format!(
+            "{} has already been melted",
+            melt_request.quote
+        )));
+    }
 
-    // FIXME  compute correct state
-    let state = match paid {
-        true => MeltOnchainState::Paid,
-        false => MeltOnchainState::Unpaid,
-    };
+    // a pending quote resumes its stored transfer, even once the quote expired
+    if quote.state == MeltOnchainState::Unpaid && is_expired(quote.expiry) {
+        return Err(MonexoMintError::QuoteExpired(melt_request.quote));
+    }
 
-    mint.db
-        .update_onchain_melt_quote(
-            &mut tx,
-            &OnchainMeltQuote {
-                state: state.clone(),
-                ..quote
-            },
-        )
+    // stores the quote as pending with the signed transfer before broadcasting it, a retry of a
+    // pending quote checks the stored transfer and broadcasts it again
+    let (txid, change) = mint
+        .melt_onchain(&quote, &melt_request.inputs, &melt_request.outputs)
         .await?;
-    tx.commit().await?;
+    let quote = OnchainMeltQuote {
+        state: MeltOnchainState::Pending,
+        txid: Some(txid.to_string()),
+        ..quote
+    };
 
-    Ok(Json(PostMeltOnchainResponse {
+    let state = melt_quote_onchain_state(&mint, &quote).await?;
+    let mut tx = mint.db.begin_tx().await?;
+    if state == MeltOnchainState::Paid {
+        mint.db
+            .update_onchain_melt_quote(
+                &mut tx,
+                &OnchainMeltQuote {
+                    state: state.clone(),
+                    ..quote
+                },
+            )
+            .await?;
+    }
+
+    let response = PostMeltOnchainResponse {
         state,
         txid: Some(txid.to_string()),
-    }))
+        change,
+    };
+    mint.add_idempotent_response(
+        &mut tx,
+        MELT_ENDPOINT,
+        idempotency_key,
+        &request_hash,
+        &response,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(Json(response))
+}
+
+/// Returns the state of a melt quote. A quote is pending once the mint broadcasted its
+/// transaction and paid once the transaction has the configured number of confirmations.
+async fn melt_quote_onchain_state(
+    mint: &Mint,
+    quote: &OnchainMeltQuote,
+) -> Result<MeltOnchainState, MonexoMintError> {
+    if quote.state == MeltOnchainState::Paid {
+        return Ok(MeltOnchainState::Paid);
+    }
+    if quote.txid.is_none() {
+        return Ok(MeltOnchainState::Unpaid);
+    }
+
+    let payment = check_payment(
+        mint,
+        mint.config
+            .onchain_backend
+            .as_ref()
+            .map(|onchain| onchain.min_confirmations)
+            .unwrap_or_default(),
+        // the mint sends exactly the quoted amount minus its fee
+        ExpectedAmount::Exact(amount_to_send(quote)?),
+        &[quote.reference.as_str()],
+        &quote.address,
+    )
+    .await?;
+
+    Ok(match payment.paid {
+        true => MeltOnchainState::Paid,
+        false => MeltOnchainState::Pending,
+    })
+}
+
+/// Returns the state of a mint quote, checking the chain if it isn't known to be paid yet. The
+/// amount the quote received so far is returned along with it if the chain was checked.
+async fn mint_quote_onchain_state(
+    mint: &Mint,
+    quote: &OnchainMintQuote,
+) -> Result<(MintOnchainState, Option<u64>), MonexoMintError> {
+    if matches!(
+        quote.state,
+        MintOnchainState::Paid | MintOnchainState::Issued
+    ) {
+        return Ok((quote.state.clone(), None));
+    }
+
+    let payment = match mint.payment_cache.get(&quote.reference) {
+        Some(payment) => payment,
+        None => check_mint_quote_payment(mint, quote).await?,
+    };
+
+    let state = match payment.paid {
+        true => MintOnchainState::Paid,
+        false => MintOnchainState::Unpaid,
+    };
+    Ok((state, Some(payment.amount_paid)))
+}
+
+/// Checks the payment of a mint quote onchain and caches the result
+pub(crate) async fn check_mint_quote_payment<DB: Database>(
+    mint: &Mint<DB>,
+    quote: &OnchainMintQuote,
+) -> Result<OnchainPayment, MonexoMintError> {
+    let monexo_wallet_keypair = mint.solana_keypair()?;
+
+    let payment = check_payment(
+        mint,
+        mint.config
+            .onchain_backend
+            .as_ref()
+            .map(|onchain| onchain.min_confirmations)
+            .unwrap_or_default(),
+        ExpectedAmount::AtLeast(quote.amount),
+        &quote.references(),
+        &monexo_wallet_keypair.pubkey().to_string(),
+    )
+    .await?;
+    mint.payment_cache.insert(&quote.reference, payment);
+    Ok(payment)
 }
 
 #[allow(dead_code)]
-fn quote_onchain_expiry() -> u64 {
+pub(crate) fn quote_onchain_expiry() -> u64 {
     let now = Utc::now() + Duration::try_minutes(30).expect("invalid duration");
     now.timestamp() as u64
 }
 
+/// Returns true if the expiry of a quote, a unix timestamp, has passed
+fn is_expired(expiry: u64) -> bool {
+    expiry < Utc::now().timestamp() as u64
+}
+
+/// Checks a payment onchain, waiting for a free slot if the configured number of concurrent
+/// rpc requests is reached
+async fn check_payment<DB: Database>(
+    mint: &Mint<DB>,
+    min_confirmations: u8,
+    amount: ExpectedAmount,
+    transaction_references: &[&str],
+    destination_wallet_pub_key: &str,
+) -> Result<OnchainPayment, MonexoMintError> {
+    let _permit = mint
+        .rpc_limiter
+        .acquire()
+        .await
+        .map_err(|err| MonexoMintError::OnchainBackend(err.to_string()))?;
+    let result = is_paid_onchain(
+        &mint.solana_rpc_url(),
+        mint.solana_commitment()?,
+        min_confirmations,
+        amount,
+        transaction_references,
+        destination_wallet_pub_key,
+    )
+    .await;
+
+    let outcome = match &result {
+        Ok(payment) if payment.paid => "paid",
+        Ok(_) => "unpaid",
+        Err(_) => {
+            // the check only fails if the rpc node can't be reached or returns invalid data
+            mint.metrics.increment(metrics::RPC_ERRORS, &[], 1);
+            "error"
+        }
+    };
+    mint.metrics
+        .increment(metrics::ONCHAIN_VERIFICATIONS, &[("result", outcome)], 1);
+    result
+}
+
+/// Checks that the transactions with the given references paid the expected `amount` to the
+/// destination and have at least `min_confirmations` confirmations. Transactions are only seen
+/// once they reached the `commitment` level. An [`ExpectedAmount::AtLeast`] may be paid by
+/// several transactions whose transfers add up to the amount, an [`ExpectedAmount::Exact`] has
+/// to be paid by a single transaction. The returned amount is the sum of the transfers for an
+/// [`ExpectedAmount::AtLeast`] and the transfer of the last checked transaction otherwise.
 async fn is_paid_onchain(
-    amount: u64,
-    transaction_reference: &str,
+    rpc_url: &str,
+    commitment: CommitmentConfig,
+    min_confirmations: u8,
+    amount: ExpectedAmount,
+    transaction_references: &[&str],
     destination_wallet_pub_key: &str,
-) -> bool {
+) -> Result<OnchainPayment, MonexoMintError> {
     // Expected values:
     // let usdc_spl_mint = "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU";
     // let transaction_reference = "5t6gQ7Mnr3mmsFYquFGwgEKokq9wrrUgCpwWab93LmLL";
     // let expected_owner = "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM";
     // let expected_amount_str = "10"; // as reported in uiAmountString
 
-    let client = RpcClient::new("https://api.devnet.solana.com".into());
-    let config = GetConfirmedSignaturesForAddress2Config {
-        limit: Some(20),
-        commitment: Some(CommitmentConfig::confirmed()),
-        ..GetConfirmedSignaturesForAddress2Config::default()
-    };
+    let client = RpcClient::new_with_commitment(rpc_url.to_owned(), commitment);
+
+    // a transaction may carry several references of the quote, it is only counted once
+    let mut checked_signatures = HashSet::new();
+    let mut total_paid = 0u64;
+    for transaction_reference in transaction_references {
+        let config = GetConfirmedSignaturesForAddress2Config {
+            limit: Some(20),
+            commitment: Some(commitment),
+            ..GetConfirmedSignaturesForAddress2Config::default()
+        };
+
+        let reference = Pubkey::from_str(transaction_reference).map_err(|err| {
+            MonexoMintError::OnchainBackend(format!("invalid transaction reference: {err}"))
+        })?;
+        let signatures = client
+            .get_signatures_for_address_with_config(&reference, config)
+            .await
+            .map_err(|err| MonexoMintError::OnchainBackend(err.to_string()))?;
+
+        if signatures.is_empty() {
+            debug!("No transaction signatures found for {transaction_reference}");
+            continue;
+        }
 
-    let reference =
-        Pubkey::from_str(&transaction_reference).expect("reference is not a valid public key");
-    let signatures = client
-        .get_signatures_for_address_with_config(&reference, config)
-        .await
-        .expect("onchain backend not configured");
+        // the payer may have sent several transactions to the reference, e.g. when retrying
+        for signature in signatures
+            .into_iter()
+            .filter(|signature| signature.err.is_none())
+        {
+            if !checked_signatures.insert(signature.signature.clone()) {
+                continue;
+            }
+            let signature = Signature::from_str(&signature.signature).map_err(|err| {
+                MonexoMintError::OnchainBackend(format!("invalid transaction signature: {err}"))
+            })?;
+            let paid = paid_amount(
+                &client,
+                &signature,
+                min_confirmations,
+                transaction_references,
+                destination_wallet_pub_key,
+            )
+            .await?;
 
-    let first_signature = match signatures.first() {
-        Some(sig) => {
-            Signature::from_str(&sig.signature).expect("could not parse transaction signature")
+            total_paid = match amount {
+                ExpectedAmount::AtLeast(_) => total_paid.saturating_add(paid),
+                ExpectedAmount::Exact(_) => paid,
+            };
+            if amount.is_met_by(total_paid) {
+                debug!("Transaction verification passed for {transaction_reference}");
+                return Ok(OnchainPayment {
+                    paid: true,
+                    amount_paid: total_paid,
+                });
+            }
         }
-        None => {
-            eprintln!("No transaction signatures found");
-            return false;
+    }
+    Ok(OnchainPayment {
+        paid: false,
+        amount_paid: total_paid,
+    })
+}
+
+/// Amount of usdc a payment must transfer, in the token's base units
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExpectedAmount {
+    /// the payer may send more and split it across transactions, e.g. a wallet paying a mint
+    /// quote
+    AtLeast(u64),
+    /// the transfer must match exactly, e.g. the mint paying out a melt quote
+    Exact(u64),
+}
+
+impl ExpectedAmount {
+    fn is_met_by(self, transferred: u64) -> bool {
+        match self {
+            Self::AtLeast(amount) => transferred >= amount,
+            Self::Exact(amount) => transferred == amount,
         }
-    };
+    }
+}
 
-    let tx = match client
-        .get_transaction(&first_signature, UiTransactionEncoding::JsonParsed)
+/// Returns the usdc a single transaction sent to any of the references paid to the destination,
+/// in base units. That is the lower of the balance change of the destination and the amount of
+/// the transfer instructions carrying a reference, 0 if the transaction isn't a valid payment.
+async fn paid_amount(
+    client: &RpcClient,
+    signature: &Signature,
+    min_confirmations: u8,
+    transaction_references: &[&str],
+    destination_wallet_pub_key: &str,
+) -> Result<u64, MonexoMintError> {
+    let status = client
+        .get_signature_statuses(&[*signature])
         .await
-    {
-        Ok(tx) => tx,
-        _ => {
-            eprintln!("could not fetch transaction details");
-            return false;
+        .map_err(|err| MonexoMintError::OnchainBackend(err.to_string()))?
+        .value
+        .into_iter()
+        .next()
+        .flatten();
+
+    // confirmations is None once the transaction is rooted
+    match status.map(|status| status.confirmations) {
+        None => {
+            debug!("Status of transaction {signature} not found");
+            return Ok(0);
         }
-    };
+        Some(Some(confirmations)) if confirmations < usize::from(min_confirmations) => {
+            debug!("Transaction {signature} has only {confirmations} confirmations");
+            return Ok(0);
+        }
+        Some(_) => {}
+    }
+
+    let tx = client
+        .get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::JsonParsed),
+                commitment: Some(client.commitment()),
+                max_supported_transaction_version: None,
+            },
+        )
+        .await
+        .map_err(|err| MonexoMintError::OnchainBackend(err.to_string()))?;
 
     let usdc_spl_mint = "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU";
 
@@ -369,27 +820,37 @@ async fn is_paid_onchain(
         Some(m) => m,
         None => {
             eprintln!("No meta data in transaction");
-            return false;
+            return Ok(0);
         }
     };
 
-    let pre_ata_token_balance = get_mint_token_balance(
-        &meta.pre_token_balances,
-        &usdc_spl_mint,
-        destination_wallet_pub_key,
-    );
-
-    let post_ata_token_balance = get_mint_token_balance(
-        &meta.post_token_balances,
-        usdc_spl_mint,
-        destination_wallet_pub_key,
-    );
+    let (Some(pre_ata_token_balance), Some(post_ata_token_balance)) = (
+        get_mint_token_balance(
+            &meta.pre_token_balances,
+            usdc_spl_mint,
+            destination_wallet_pub_key,
+        ),
+        get_mint_token_balance(
+            &meta.post_token_balances,
+            usdc_spl_mint,
+            destination_wallet_pub_key,
+        ),
+    ) else {
+        warn!("Token balance of destination is not an integer amount");
+        return Ok(0);
+    };
 
-    let mint_balance_change = post_ata_token_balance - pre_ata_token_balance;
+    let Some(mint_balance_change) = post_ata_token_balance.checked_sub(pre_ata_token_balance)
+    else {
+        warn!(
+            "Token balance of destination decreased from {pre_ata_token_balance} to {post_ata_token_balance}"
+        );
+        return Ok(0);
+    };
 
-    if mint_balance_change < amount {
+    if mint_balance_change == 0 {
         eprintln!("Post token balance verification at destination failed.");
-        return false;
+        return Ok(0);
     }
 
     // === 2. Verify the transfer instruction details ===
@@ -399,7 +860,7 @@ async fn is_paid_onchain(
         solana_transaction_status::EncodedTransaction::Json(ui_tx) => ui_tx,
         _ => {
             eprintln!("Transaction is not JSON parsed");
-            return false;
+            return Ok(0);
         }
     };
 
@@ -407,102 +868,158 @@ async fn is_paid_onchain(
         UiMessage::Parsed(msg) => msg,
         _ => {
             eprintln!("Transaction message is not parsed");
-            return false;
+            return Ok(0);
         }
     };
 
-    // Iterate over the instructions to find our transfer
-    let mut transfer_verified = false;
+    // the reference is appended as a read-only, non-signer account of the transfer
+    let reference_in_accounts = parsed_msg
+        .account_keys
+        .iter()
+        .any(|account| transaction_references.contains(&account.pubkey.as_str()));
+
+    // Iterate over the instructions to find our transfers
+    let mut transferred = 0u64;
     for inst in &parsed_msg.instructions {
         // We expect the instructions to be of the parsed variant.
-        if let UiInstruction::Parsed(parsed_inst_1) = inst {
-            // We're looking for a transferChecked instruction from the spl-token program.
-            if let UiParsedInstruction::Parsed(parsed_inst) = parsed_inst_1 {
-                if parsed_inst.program == "spl-token"
-                    && parsed_inst.parsed.get("type").and_then(|t| t.as_str())
-                        == Some("transferChecked")
-                {
-                    if let Some(info) = parsed_inst.parsed.get("info").and_then(|v| v.as_object()) {
-                        // Check that the mint is correct.
-                        let mint = info.get("mint").and_then(|v| v.as_str()).unwrap_or("");
-                        if mint != usdc_spl_mint {
-                            continue;
-                        }
-
-                        // Check for the reference in the signers array.
-                        if let Some(signers) = info.get("signers").and_then(|v| v.as_array()) {
-                            let reference_found = signers
-                                .iter()
-                                .any(|s| s.as_str() == Some(transaction_reference));
-                            if !reference_found {
-                                continue;
-                            }
-                        } else {
-                            continue;
-                        }
-
-                        // Check that the transferred amount is 10 USDC.
-                        if let Some(token_amount) = info.get("tokenAmount") {
-                            if token_amount.get("amount").and_then(|s| s.as_str())
-                                == Some(amount.to_string().as_str())
-                            {
-                                transfer_verified = true;
-                                break;
-                            }
-                        }
+        // We're looking for a transferChecked instruction from the spl-token or spl-token-2022
+        // program.
+        if let UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed_inst)) = inst {
+            if matches!(parsed_inst.program.as_str(), "spl-token" | "spl-token-2022")
+                && parsed_inst.parsed.get("type").and_then(|t| t.as_str())
+                    == Some("transferChecked")
+            {
+                if let Some(info) = parsed_inst.parsed.get("info").and_then(|v| v.as_object()) {
+                    // Check that the mint is correct.
+                    let mint = info.get("mint").and_then(|v| v.as_str()).unwrap_or("");
+                    if mint != usdc_spl_mint {
+                        continue;
+                    }
+
+                    // jsonParsed lists the accounts after the authority as multisig signers,
+                    // so the reference shows up there if the account keys aren't reported
+                    let reference_found = reference_in_accounts
+                        || info
+                            .get("signers")
+                            .and_then(|v| v.as_array())
+                            .is_some_and(|signers| {
+                                signers
+                                    .iter()
+                                    .filter_map(|s| s.as_str())
+                                    .any(|s| transaction_references.contains(&s))
+                            });
+                    if !reference_found {
+                        continue;
+                    }
+
+                    // the raw amount in base units, the ui amounts are rounded floats
+                    if let Some(amount) = info
+                        .get("tokenAmount")
+                        .and_then(|token_amount| token_amount.get("amount"))
+                        .and_then(|amount| amount.as_str())
+                        .and_then(|amount| amount.parse::<u64>().ok())
+                    {
+                        transferred = transferred.saturating_add(amount);
                     }
                 }
             }
         }
     }
 
-    if !transfer_verified {
+    if transferred == 0 {
         eprintln!("Transfer instruction verification failed.");
-        return false;
     }
-
-    println!("Transaction verification passed.");
-    true
+    // a transfer from another source in the same transaction doesn't count towards the payment
+    Ok(transferred.min(mint_balance_change))
 }
 
 ///
 /// Gets a Vec<UiTransactionTokenBalance> and determins the balance on of mint tokens
-/// on the given asscoiated token account address
+/// on the given asscoiated token account address in base units. Uses the raw `amount`, as the
+/// ui amounts are rounded floats. Returns None if the amount is not an integer.
 ///
 fn get_mint_token_balance(
     token_balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
     token_address: &str,
     wallet_pub_key: &str,
-) -> u64 {
+) -> Option<u64> {
     let token_balances = match token_balances {
         OptionSerializer::Some(balances) => balances,
         _ => {
             eprintln!("No pre token balances found in transaction meta");
-            return 0;
+            return Some(0);
         }
     };
 
     let ata_token_balance = match token_balances.iter().find(|token_balance| {
-        token_balance.mint == token_address.to_string()
+        token_balance.mint == token_address
             && token_balance.owner == OptionSerializer::Some(wallet_pub_key.to_string())
     }) {
         Some(token_balance) => token_balance.ui_token_amount.amount.as_str(),
         _ => {
-            return 0;
+            return Some(0);
         }
     };
 
-    ata_token_balance.parse::<u64>().unwrap_or(0)
+    ata_token_balance.parse::<u64>().ok()
+}
+
+/// Estimates the network fee of a melt in micro-usd and whether the transfer has to create the
+/// recipient's token account. Falls back to the configured default fee if the fee or the SOL
+/// price can't be fetched.
+async fn estimate_melt_fee(
+    mint: &Mint,
+    onchain_config: &OnchainConfig,
+    amount: u64,
+    address: &str,
+) -> (u64, bool) {
+    let estimated_fee = match mint.solana_keypair() {
+        Ok(keypair) => {
+            get_estimated_fees(
+                &mint.solana_rpc_url(),
+                mint.price_oracle.as_ref(),
+                amount,
+                &keypair.pubkey().to_string(),
+                address,
+            )
+            .await
+        }
+        Err(err) => Err(err),
+    };
+
+    match estimated_fee {
+        Ok(estimate) => estimate,
+        Err(err) => {
+            warn!(
+                "fee estimation failed, using default fee {}: {}",
+                onchain_config.default_fee, err
+            );
+            (onchain_config.default_fee, false)
+        }
+    }
+}
+
+/// Returns the size of an associated token account of the program, Token-2022 accounts carry
+/// the immutable owner extension
+fn token_account_len(token_program: &Pubkey) -> Result<usize, MonexoMintError> {
+    if *token_program == spl_token_2022::id() {
+        return Ok(
+            spl_token_2022::extension::ExtensionType::try_calculate_account_len::<
+                spl_token_2022::state::Account,
+            >(&[spl_token_2022::extension::ExtensionType::ImmutableOwner])?,
+        );
+    }
+    Ok(spl_token::state::Account::LEN)
 }
 
-#[allow(dead_code)]
 async fn get_estimated_fees(
+    rpc_url: &str,
+    price_oracle: &dyn PriceOracle,
     amount: u64,
     source_address: &str,
     destination_address: &str,
-) -> Result<f64, MonexoMintError> {
-    let rpc_url = "https://api.devnet.solana.com";
-    let client = RpcClient::new(rpc_url.to_string());
+) -> Result<(u64, bool), MonexoMintError> {
+    let client = RpcClient::new(rpc_url.to_owned());
 
     // Fetch the latest blockhash
     let latest_blockhash = client.get_latest_blockhash().await?;
@@ -513,17 +1030,24 @@ async fn get_estimated_fees(
     let usdc_mint = Pubkey::from_str("4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU")
         .expect("reference is not a valid public key"); // USDC Mint on Devnet
 
+    let token_program = token_program_id(&client, &usdc_mint).await?;
+
     // Get ATA (Associated Token Account) addresses
-    let source_ata =
-        spl_associated_token_account::get_associated_token_address(&source_address, &usdc_mint);
-    let destination_ata = spl_associated_token_account::get_associated_token_address(
-        &destination_address,
+    let source_ata = spl_associated_token_account::get_associated_token_address_with_program_id(
+        &source_address,
         &usdc_mint,
+        &token_program,
     );
+    let destination_ata =
+        spl_associated_token_account::get_associated_token_address_with_program_id(
+            &destination_address,
+            &usdc_mint,
+            &token_program,
+        );
 
     // Create `transfer_checked` instruction
-    let transfer_ix = spl_token::instruction::transfer_checked(
-        &spl_token::ID,   // Token program ID
+    let transfer_ix = spl_token_2022::instruction::transfer_checked(
+        &token_program,   // Token program ID
         &source_ata,      // Sender ATA
         &usdc_mint,       // USDC Mint
         &destination_ata, // Recipient ATA
@@ -533,48 +1057,1363 @@ async fn get_estimated_fees(
         6,
     )?;
 
+    // the transfer creates the recipient's token account if it doesn't exist, the rent of the
+    // account is paid by the mint and charged to the wallet
+    let create_recipient_account = client
+        .get_account_with_commitment(&destination_ata, client.commitment())
+        .await?
+        .value
+        .is_none();
+    let mut instructions = vec![];
+    let mut rent_lamports = 0;
+    if create_recipient_account {
+        instructions.push(
+            spl_associated_token_account::instruction::create_associated_token_account(
+                &source_address,
+                &destination_address,
+                &usdc_mint,
+                &token_program,
+            ),
+        );
+        rent_lamports = client
+            .get_minimum_balance_for_rent_exemption(token_account_len(&token_program)?)
+            .await?;
+    }
+    instructions.push(transfer_ix);
+
     // Create message
     let message = solana_sdk::message::Message::new_with_blockhash(
-        &[transfer_ix],
+        &instructions,
         Some(&source_address),
         &latest_blockhash,
     );
 
     // Get estimated fee // 5000
-    let fee_lamports = client.get_fee_for_message(&message).await?;
-
-    // Convert fee to SOL // 0.000005
-    let fee_sol = fee_lamports as f64 / 1_000_000_000.0; // 1 SOL = 1B lamports
+    let fee_lamports = client.get_fee_for_message(&message).await? + rent_lamports;
 
     // Fetch SOL/USDC price 173.19
-    let sol_usdc_price = fetch_sol_usdc_price().await?;
+    let sol_usdc_price = price_oracle.sol_usdc_price().await?;
 
-    // Convert fee to USDC // 0.00086595
-    let fee_usdc = fee_sol * sol_usdc_price;
+    // Convert fee to micro-usd // 866
+    let fee_micro_usd = lamports_to_micro_usd(fee_lamports, sol_usdc_price);
 
-    info!("fee_usdc: {}", fee_usdc);
+    info!(
+        "fee_micro_usd: {}, create_recipient_account: {}",
+        fee_micro_usd, create_recipient_account
+    );
 
-    Ok(fee_usdc)
+    Ok((fee_micro_usd, create_recipient_account))
 }
 
-#[allow(dead_code)]
-async fn fetch_sol_usdc_price() -> Result<f64, MonexoMintError> {
-    let url = "https://api.coingecko.com/api/v3/simple/price?ids=solana&vs_currencies=usd";
-    let response = reqwest::Client::new()
-        .get(url)
-        .send()
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use axum::{
+        extract::{Path, State},
+        response::IntoResponse,
+        Json,
+    };
+    use chrono::Utc;
+    use monexo_core::{
+        blind::BlindedMessage,
+        dhke::Dhke,
+        primitives::{
+            CurrencyUnit, MeltOnchainState, MintOnchainState, OnchainMeltQuote, OnchainMintQuote,
+            PostMeltOnchainRequest, PostMeltOnchainResponse, PostMeltQuoteOnchainRequest,
+            PostMintOnchainRequest, PostMintQuoteOnchainRequest, PostMintQuoteOnchainResponse,
+        },
+        proof::Proofs,
+    };
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_sdk::{
+        commitment_config::CommitmentConfig,
+        pubkey::Pubkey,
+        signature::{Keypair, Signature},
+        signer::Signer,
+    };
+    use solana_transaction_status_client_types::option_serializer::OptionSerializer;
+    use uuid::Uuid;
+
+    use crate::{
+        config::OnchainConfig,
+        database::{postgres::PostgresDB, Database},
+        error::MonexoMintError,
+        mint::{token_program_id, Mint},
+        payment_cache::OnchainPayment,
+        price::PriceOracle,
+        test_utils::{create_db, create_mint, melt_quote, KeypairFile, RpcStub},
+    };
+
+    use super::{
+        check_payment, get_estimated_fees, get_melt_quote_onchain, get_mint_quote_onchain,
+        get_mint_token_balance, is_paid_onchain, post_melt_onchain, post_melt_quote_onchain,
+        post_mint_onchain, post_mint_quote_onchain, subscribe_mint_quote_onchain, ExpectedAmount,
+        MAX_QUOTE_REFERENCES,
+    };
+
+    #[derive(Debug)]
+    struct StubPriceOracle(Option<f64>);
+
+    #[async_trait]
+    impl PriceOracle for StubPriceOracle {
+        async fn sol_usdc_price(&self) -> Result<f64, MonexoMintError> {
+            self.0
+                .ok_or_else(|| MonexoMintError::OnchainBackend("rate limited".to_owned()))
+        }
+    }
+
+    async fn post_melt_quote_fees(
+        price_oracle: StubPriceOracle,
+        recipient_account_exists: bool,
+        amount: u64,
+        fee_percent: f64,
+    ) -> anyhow::Result<OnchainMeltQuote> {
+        let (_node, db) = create_db().await?;
+
+        let keypair = KeypairFile::new()?;
+
+        let mint = Mint {
+            price_oracle: Arc::new(price_oracle),
+            ..create_mint(
+                db,
+                Some(OnchainConfig {
+                    solana_rpc_url: RpcStub::default()
+                        .with_recipient_account(recipient_account_exists)
+                        .start()
+                        .await?
+                        .url,
+                    fee_percent,
+                    fee_margin_percent: 10,
+                    default_fee: 2_000,
+                    ..Default::default()
+                }),
+                Some(&keypair),
+            )
+        };
+
+        let result = post_melt_quote_onchain(
+            State(mint.clone()),
+            Json(PostMeltQuoteOnchainRequest {
+                address: Keypair::new().pubkey().to_string(),
+                amount,
+            }),
+        )
+        .await;
+
+        let Json(quotes) = result?;
+        assert_eq!(1, quotes.len());
+
+        let mut tx = mint.db.begin_tx().await?;
+        let stored_quote = mint
+            .db
+            .get_onchain_melt_quote(&mut tx, &Uuid::parse_str(&quotes[0].quote)?)
+            .await?;
+        tx.commit().await?;
+        assert_eq!(stored_quote.fee_total, quotes[0].fee);
+        Ok(stored_quote)
+    }
+
+    async fn add_quote(
+        db: &PostgresDB,
+        amount: u64,
+        fee_total: u64,
+        state: MintOnchainState,
+    ) -> anyhow::Result<OnchainMintQuote> {
+        let quote = OnchainMintQuote {
+            quote_id: Uuid::new_v4(),
+            reference: Keypair::new().pubkey().to_string(),
+            additional_references: vec![],
+            fee_total,
+            amount,
+            expiry: super::quote_onchain_expiry(),
+            state,
+        };
+        let mut tx = db.begin_tx().await?;
+        db.add_onchain_mint_quote(&mut tx, &quote).await?;
+        tx.commit().await?;
+        Ok(quote)
+    }
+
+    /// Adds a melt quote paying 10 to the address of the transaction fixtures and returns the
+    /// state reported by the route and the stored state afterwards
+    async fn get_melt_quote_state(
+        rpc_url: String,
+        state: MeltOnchainState,
+        txid: Option<&str>,
+    ) -> anyhow::Result<(MeltOnchainState, MeltOnchainState)> {
+        let (_node, db) = create_db().await?;
+        let quote = OnchainMeltQuote {
+            fee_total: 2,
+            fee_micro_usd: 2,
+            state,
+            txid: txid.map(ToOwned::to_owned),
+            ..melt_quote(12)
+        };
+        let mut tx = db.begin_tx().await?;
+        db.add_onchain_melt_quote(&mut tx, &quote).await?;
+        db.update_onchain_melt_quote(&mut tx, &quote).await?;
+        tx.commit().await?;
+
+        let mint = create_mint(
+            db,
+            Some(OnchainConfig {
+                solana_rpc_url: rpc_url,
+                min_confirmations: 3,
+                ..Default::default()
+            }),
+            None,
+        );
+
+        let Json(response) =
+            get_melt_quote_onchain(Path(quote.quote_id.to_string()), State(mint.clone())).await?;
+        let mut tx = mint.db.begin_tx().await?;
+        let stored_quote = mint
+            .db
+            .get_onchain_melt_quote(&mut tx, &quote.quote_id)
+            .await?;
+        tx.commit().await?;
+        assert_eq!(txid.map(ToOwned::to_owned), stored_quote.txid);
+        Ok((response.state, stored_quote.state))
+    }
+
+    fn create_outputs(mint: &Mint, amounts: &[u64]) -> Vec<BlindedMessage> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(index, amount)| BlindedMessage {
+                amount: *amount,
+                b_: Dhke::hash_to_curve(format!("output{index}").as_bytes())
+                    .expect("no valid point found"),
+                id: mint.keysets[&CurrencyUnit::Usd].keyset_id.clone(),
+            })
+            .collect()
+    }
+
+    async fn post_mint_amount_mismatch(amounts: &[u64]) -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = add_quote(&db, 10, 2, MintOnchainState::Unpaid).await?;
+        let mint = create_mint(db, None, None);
+
+        let result = post_mint_onchain(
+            State(mint.clone()),
+            Json(PostMintOnchainRequest {
+                quote: quote.quote_id.to_string(),
+                outputs: create_outputs(&mint, amounts),
+                idempotency_key: None,
+            }),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(MonexoMintError::SwapAmountMismatch(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_mint_onchain_over_mint() -> anyhow::Result<()> {
+        post_mint_amount_mismatch(&[8, 2]).await
+    }
+
+    #[tokio::test]
+    async fn test_post_mint_onchain_under_mint() -> anyhow::Result<()> {
+        post_mint_amount_mismatch(&[4]).await
+    }
+
+    #[tokio::test]
+    async fn test_post_mint_onchain_expired_quote() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = OnchainMintQuote {
+            quote_id: Uuid::new_v4(),
+            reference: Keypair::new().pubkey().to_string(),
+            additional_references: vec![],
+            fee_total: 0,
+            amount: 10,
+            expiry: (Utc::now().timestamp() - 60) as u64,
+            state: MintOnchainState::Unpaid,
+        };
+        let mut tx = db.begin_tx().await?;
+        db.add_onchain_mint_quote(&mut tx, &quote).await?;
+        tx.commit().await?;
+        let keypair = KeypairFile::new()?;
+        // the chain doesn't know a payment of the quote either
+        let mint = create_mint(
+            db,
+            Some(OnchainConfig {
+                solana_rpc_url: RpcStub::default().start().await?.url,
+                ..Default::default()
+            }),
+            Some(&keypair),
+        );
+        let outputs = create_outputs(&mint, &[8, 2]);
+
+        let result = post_mint_onchain(
+            State(mint.clone()),
+            Json(PostMintOnchainRequest {
+                quote: quote.quote_id.to_string(),
+                outputs: outputs.clone(),
+                idempotency_key: None,
+            }),
+        )
+        .await;
+        assert!(matches!(result, Err(MonexoMintError::QuoteExpired(_))));
+
+        let mut tx = mint.db.begin_tx().await?;
+        assert_eq!(
+            None,
+            mint.db
+                .get_blind_signature_by_b_(&mut tx, &outputs[0].b_)
+                .await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_mint_onchain_expired_paid_quote() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = OnchainMintQuote {
+            quote_id: Uuid::new_v4(),
+            reference: Keypair::new().pubkey().to_string(),
+            additional_references: vec![],
+            fee_total: 0,
+            amount: 10,
+            expiry: (Utc::now().timestamp() - 60) as u64,
+            state: MintOnchainState::Paid,
+        };
+        let mut tx = db.begin_tx().await?;
+        db.add_onchain_mint_quote(&mut tx, &quote).await?;
+        tx.commit().await?;
+        let mint = create_mint(db, None, None);
+
+        // the quote was paid before it expired, so it is still issued
+        let Json(response) = post_mint_onchain(
+            State(mint.clone()),
+            Json(PostMintOnchainRequest {
+                quote: quote.quote_id.to_string(),
+                outputs: create_outputs(&mint, &[8, 2]),
+                idempotency_key: None,
+            }),
+        )
+        .await?;
+        assert_eq!(2, response.signatures.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_mint_onchain_twice() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = add_quote(&db, 10, 0, MintOnchainState::Paid).await?;
+        let mint = create_mint(db, None, None);
+        let request = PostMintOnchainRequest {
+            quote: quote.quote_id.to_string(),
+            outputs: create_outputs(&mint, &[8, 2]),
+            idempotency_key: None,
+        };
+
+        let first = post_mint_onchain(State(mint.clone()), Json(request.clone())).await?;
+        let second = post_mint_onchain(State(mint.clone()), Json(request)).await?;
+
+        assert_eq!(2, first.signatures.len());
+        assert_eq!(first.signatures, second.signatures);
+
+        let result = post_mint_onchain(
+            State(mint.clone()),
+            Json(PostMintOnchainRequest {
+                quote: quote.quote_id.to_string(),
+                outputs: create_outputs(&mint, &[2, 8]),
+                idempotency_key: None,
+            }),
+        )
+        .await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_mint_onchain_idempotency_key() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = add_quote(&db, 10, 0, MintOnchainState::Paid).await?;
+        let mint = create_mint(db, None, None);
+        let outputs = create_outputs(&mint, &[8, 2]);
+
+        let first = post_mint_onchain(
+            State(mint.clone()),
+            Json(PostMintOnchainRequest {
+                quote: quote.quote_id.to_string(),
+                outputs: outputs.clone(),
+                idempotency_key: Some("mint-once".to_string()),
+            }),
+        )
+        .await?;
+
+        // the retry gets the first response without signing its outputs again
+        let second = post_mint_onchain(
+            State(mint.clone()),
+            Json(PostMintOnchainRequest {
+                quote: quote.quote_id.to_string(),
+                outputs: outputs.clone(),
+                idempotency_key: Some("mint-once".to_string()),
+            }),
+        )
+        .await?;
+        assert_eq!(first.signatures, second.signatures);
+
+        // the key can't be reused for other outputs
+        let result = post_mint_onchain(
+            State(mint.clone()),
+            Json(PostMintOnchainRequest {
+                quote: quote.quote_id.to_string(),
+                outputs: create_outputs(&mint, &[2, 8]),
+                idempotency_key: Some("mint-once".to_string()),
+            }),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(MonexoMintError::IdempotencyKeyReused(_))
+        ));
+
+        let mut tx = mint.db.begin_tx().await?;
+        for (output, signature) in outputs.iter().zip(first.signatures.iter()) {
+            assert_eq!(
+                Some(signature.clone()),
+                mint.db
+                    .get_blind_signature_by_b_(&mut tx, &output.b_)
+                    .await?
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_mint_quote_onchain() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = add_quote(&db, 10, 0, MintOnchainState::Unpaid).await?;
+        let mint = create_mint(
+            db,
+            Some(OnchainConfig {
+                payment_cache_ttl: 60,
+                ..Default::default()
+            }),
+            None,
+        );
+
+        // the onchain verification is mocked by the payment cache, so no rpc node is needed
+        mint.payment_cache
+            .insert(&quote.reference, OnchainPayment::default());
+        let events =
+            subscribe_mint_quote_onchain(Path(quote.quote_id.to_string()), State(mint.clone()))
+                .await?;
+        let payment_cache = mint.payment_cache.clone();
+        let reference = quote.reference.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            payment_cache.insert(
+                &reference,
+                OnchainPayment {
+                    paid: true,
+                    amount_paid: 10,
+                },
+            );
+        });
+
+        // the stream ends once the quote is paid
+        let body = axum::body::to_bytes(events.into_response().into_body(), usize::MAX).await?;
+        let states = String::from_utf8(body.to_vec())?
+            .split("\n\n")
+            .filter_map(|event| event.strip_prefix("data: "))
+            .map(|data| Ok(serde_json::from_str::<PostMintQuoteOnchainResponse>(data)?.state))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(
+            vec![MintOnchainState::Unpaid, MintOnchainState::Paid],
+            states
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_mint_quote_onchain_expired() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = OnchainMintQuote {
+            quote_id: Uuid::new_v4(),
+            reference: Keypair::new().pubkey().to_string(),
+            additional_references: vec![],
+            fee_total: 0,
+            amount: 10,
+            expiry: 1,
+            state: MintOnchainState::Unpaid,
+        };
+        let mut tx = db.begin_tx().await?;
+        db.add_onchain_mint_quote(&mut tx, &quote).await?;
+        tx.commit().await?;
+        let mint = create_mint(
+            db,
+            Some(OnchainConfig {
+                payment_cache_ttl: 60,
+                ..Default::default()
+            }),
+            None,
+        );
+        mint.payment_cache
+            .insert(&quote.reference, OnchainPayment::default());
+
+        // both subscribers get the last state of the single poll, which ends at the expiry
+        let first =
+            subscribe_mint_quote_onchain(Path(quote.quote_id.to_string()), State(mint.clone()))
+                .await?;
+        let second =
+            subscribe_mint_quote_onchain(Path(quote.quote_id.to_string()), State(mint.clone()))
+                .await?;
+        for events in [first, second] {
+            let body = axum::body::to_bytes(events.into_response().into_body(), usize::MAX).await?;
+            let states = String::from_utf8(body.to_vec())?
+                .split("\n\n")
+                .filter_map(|event| event.strip_prefix("data: "))
+                .map(|data| Ok(serde_json::from_str::<PostMintQuoteOnchainResponse>(data)?.state))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            assert_eq!(vec![MintOnchainState::Unpaid], states);
+        }
+        assert!(mint.quote_subscriptions.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_mint_quote_onchain_cached() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = add_quote(&db, 10, 0, MintOnchainState::Unpaid).await?;
+        let rpc = RpcStub::default().start().await?;
+
+        let keypair = KeypairFile::new()?;
+
+        let mint = create_mint(
+            db,
+            Some(OnchainConfig {
+                solana_rpc_url: rpc.url.clone(),
+                payment_cache_ttl: 60,
+                ..Default::default()
+            }),
+            Some(&keypair),
+        );
+
+        for _ in 0..3 {
+            let Json(response) =
+                get_mint_quote_onchain(Path(quote.quote_id.to_string()), State(mint.clone()))
+                    .await?;
+            assert_eq!(MintOnchainState::Unpaid, response.state);
+            assert_eq!(Some(0), response.amount_paid);
+        }
+        assert_eq!(1, rpc.requests("getSignaturesForAddress").len());
+
+        mint.payment_cache.invalidate(&quote.reference);
+        let result =
+            get_mint_quote_onchain(Path(quote.quote_id.to_string()), State(mint.clone())).await;
+        assert!(result.is_ok());
+
+        assert_eq!(2, rpc.requests("getSignaturesForAddress").len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_payment_commitment() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let rpc = RpcStub::default().start().await?;
+        let mint = create_mint(
+            db,
+            Some(OnchainConfig {
+                solana_rpc_url: rpc.url.clone(),
+                solana_commitment: "finalized".to_owned(),
+                ..Default::default()
+            }),
+            None,
+        );
+
+        let reference = Keypair::new().pubkey().to_string();
+        assert!(matches!(
+            check_payment(
+                &mint,
+                0,
+                ExpectedAmount::AtLeast(10),
+                &[reference.as_str()],
+                &reference
+            )
+            .await
+            .map(|payment| payment.paid),
+            Ok(false)
+        ));
+        assert_eq!(
+            vec![serde_json::json!("finalized")],
+            rpc.requests("getSignaturesForAddress")
+                .iter()
+                .map(|request| request["params"][1]["commitment"].clone())
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_payment_concurrency_limit() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let rpc = RpcStub::default()
+            .with_delay(std::time::Duration::from_millis(50))
+            .start()
+            .await?;
+        let mint = create_mint(
+            db,
+            Some(OnchainConfig {
+                solana_rpc_url: rpc.url.clone(),
+                max_concurrent_rpc_requests: 2,
+                ..Default::default()
+            }),
+            None,
+        );
+
+        let checks = (0..6)
+            .map(|_| {
+                let mint = mint.clone();
+                tokio::spawn(async move {
+                    let reference = Keypair::new().pubkey().to_string();
+                    check_payment(
+                        &mint,
+                        0,
+                        ExpectedAmount::AtLeast(10),
+                        &[reference.as_str()],
+                        &reference,
+                    )
+                    .await
+                    .map(|payment| payment.paid)
+                })
+            })
+            .collect::<Vec<_>>();
+        for check in checks {
+            assert!(matches!(check.await?, Ok(false)));
+        }
+
+        assert_eq!(2, rpc.max_in_flight());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_melt_quote_onchain_unpaid() -> anyhow::Result<()> {
+        // without a broadcasted transaction the rpc node is never asked
+        let states = get_melt_quote_state(
+            "http://127.0.0.1:1".to_string(),
+            MeltOnchainState::Unpaid,
+            None,
+        )
+        .await?;
+        assert_eq!((MeltOnchainState::Unpaid, MeltOnchainState::Unpaid), states);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_melt_quote_onchain_pending() -> anyhow::Result<()> {
+        let states = get_melt_quote_state(
+            RpcStub::default().with_signature(1).start().await?.url,
+            MeltOnchainState::Pending,
+            Some("1111111111111111111111111111111111111111111111111111111111111112"),
+        )
+        .await?;
+        assert_eq!(
+            (MeltOnchainState::Pending, MeltOnchainState::Pending),
+            states
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_melt_quote_onchain_paid() -> anyhow::Result<()> {
+        let states = get_melt_quote_state(
+            RpcStub::default()
+                .with_transactions(&["get_transaction_paid.json"])?
+                .start()
+                .await?
+                .url,
+            MeltOnchainState::Pending,
+            Some("1111111111111111111111111111111111111111111111111111111111111112"),
+        )
+        .await?;
+        assert_eq!((MeltOnchainState::Paid, MeltOnchainState::Paid), states);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_melt_onchain_already_melted() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = OnchainMeltQuote {
+            reference: Keypair::new().pubkey().to_string(),
+            fee_total: 2,
+            fee_micro_usd: 2,
+            state: MeltOnchainState::Paid,
+            txid: Some(Signature::default().to_string()),
+            ..melt_quote(12)
+        };
+        let mut tx = db.begin_tx().await?;
+        db.add_onchain_melt_quote(&mut tx, &quote).await?;
+        db.update_onchain_melt_quote(&mut tx, &quote).await?;
+        tx.commit().await?;
+        let mint = create_mint(db, None, None);
+
+        let result = post_melt_onchain(
+            State(mint),
+            Json(PostMeltOnchainRequest {
+                quote: quote.quote_id.to_string(),
+                inputs: Proofs::new(vec![]),
+                outputs: vec![],
+                idempotency_key: None,
+            }),
+        )
+        .await;
+        assert!(matches!(result, Err(MonexoMintError::InvalidQuote(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_melt_onchain_expired_quote() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = OnchainMeltQuote {
+            reference: Keypair::new().pubkey().to_string(),
+            fee_total: 2,
+            fee_micro_usd: 2,
+            expiry: (Utc::now().timestamp() - 60) as u64,
+            ..melt_quote(12)
+        };
+        let mut tx = db.begin_tx().await?;
+        db.add_onchain_melt_quote(&mut tx, &quote).await?;
+        tx.commit().await?;
+        let mint = create_mint(db, None, None);
+
+        let result = post_melt_onchain(
+            State(mint),
+            Json(PostMeltOnchainRequest {
+                quote: quote.quote_id.to_string(),
+                inputs: Proofs::new(vec![]),
+                outputs: vec![],
+                idempotency_key: None,
+            }),
+        )
+        .await;
+        assert!(matches!(result, Err(MonexoMintError::QuoteExpired(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_melt_onchain_idempotency_key() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let quote = OnchainMeltQuote {
+            reference: Keypair::new().pubkey().to_string(),
+            fee_total: 2,
+            fee_micro_usd: 2,
+            state: MeltOnchainState::Paid,
+            txid: Some(Signature::default().to_string()),
+            ..melt_quote(12)
+        };
+        let mint = create_mint(db, None, None);
+        let request = PostMeltOnchainRequest {
+            quote: quote.quote_id.to_string(),
+            inputs: Proofs::new(vec![]),
+            outputs: vec![],
+            idempotency_key: Some("melt-once".to_string()),
+        };
+
+        // the first request melted the quote and stored its response
+        let response = PostMeltOnchainResponse {
+            state: MeltOnchainState::Paid,
+            txid: quote.txid.clone(),
+            change: vec![],
+        };
+        let request_hash = super::request_hash(&PostMeltOnchainRequest {
+            idempotency_key: None,
+            ..request.clone()
+        })?;
+        let mut tx = mint.db.begin_tx().await?;
+        mint.db.add_onchain_melt_quote(&mut tx, &quote).await?;
+        mint.db.update_onchain_melt_quote(&mut tx, &quote).await?;
+        mint.add_idempotent_response(
+            &mut tx,
+            super::MELT_ENDPOINT,
+            Some("melt-once"),
+            &request_hash,
+            &response,
+        )
+        .await?;
+        tx.commit().await?;
+
+        let retried = post_melt_onchain(State(mint.clone()), Json(request.clone())).await?;
+        assert_eq!(response.txid, retried.txid);
+        assert_eq!(response.state, retried.state);
+
+        // the key can't be reused for other outputs
+        let result = post_melt_onchain(
+            State(mint.clone()),
+            Json(PostMeltOnchainRequest {
+                outputs: create_outputs(&mint, &[1]),
+                ..request.clone()
+            }),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(MonexoMintError::IdempotencyKeyReused(_))
+        ));
+
+        // a different key is a new request for an already melted quote
+        let result = post_melt_onchain(
+            State(mint.clone()),
+            Json(PostMeltOnchainRequest {
+                idempotency_key: Some("melt-twice".to_string()),
+                ..request
+            }),
+        )
+        .await;
+        assert!(matches!(result, Err(MonexoMintError::InvalidQuote(_))));
+
+        let mut tx = mint.db.begin_tx().await?;
+        assert!(mint.db.get_used_proofs(&mut tx).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_paid_onchain_below_min_confirmations() -> anyhow::Result<()> {
+        let result = is_paid_onchain(
+            &RpcStub::default().with_signature(1).start().await?.url,
+            CommitmentConfig::confirmed(),
+            3,
+            ExpectedAmount::AtLeast(10),
+            &[Keypair::new().pubkey().to_string().as_str()],
+            "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+        )
+        .await
+        .map(|payment| payment.paid);
+
+        assert!(matches!(result, Ok(false)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_paid_onchain_second_transaction() -> anyhow::Result<()> {
+        let result = is_paid_onchain(
+            &RpcStub::default()
+                .with_transactions(&[
+                    "get_transaction_unmatched.json",
+                    "get_transaction_paid.json",
+                ])?
+                .start()
+                .await?
+                .url,
+            CommitmentConfig::confirmed(),
+            1,
+            ExpectedAmount::AtLeast(10),
+            &["5t6gQ7Mnr3mmsFYquFGwgEKokq9wrrUgCpwWab93LmLL"],
+            "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+        )
+        .await
+        .map(|payment| payment.paid);
+
+        assert!(matches!(result, Ok(true)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_expected_amount() {
+        assert!(ExpectedAmount::AtLeast(10).is_met_by(10));
+        assert!(ExpectedAmount::AtLeast(10).is_met_by(11));
+        assert!(!ExpectedAmount::AtLeast(10).is_met_by(9));
+        assert!(ExpectedAmount::Exact(10).is_met_by(10));
+        assert!(!ExpectedAmount::Exact(10).is_met_by(11));
+        assert!(!ExpectedAmount::Exact(10).is_met_by(9));
+    }
+
+    fn usdc_balance(amount: &str, ui_amount: f64) -> serde_json::Value {
+        serde_json::json!({
+            "accountIndex": 1,
+            "mint": "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU",
+            "owner": "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+            "programId": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+            "uiTokenAmount": {
+                "amount": amount,
+                "decimals": 6,
+                "uiAmount": ui_amount,
+                "uiAmountString": ui_amount.to_string(),
+            },
+        })
+    }
+
+    #[test]
+    fn test_get_mint_token_balance_near_rounding_boundary() -> anyhow::Result<()> {
+        let balance = |balances: Vec<serde_json::Value>| -> anyhow::Result<Option<u64>> {
+            Ok(get_mint_token_balance(
+                &serde_json::from_value(serde_json::Value::Array(balances))
+                    .map(OptionSerializer::Some)?,
+                "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU",
+                "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+            ))
+        };
+
+        // 10.000001 usdc is one base unit more than 10 usdc
+        let pre = balance(vec![usdc_balance("10000000", 10.0)])?.expect("integer amount");
+        let post = balance(vec![usdc_balance("20000001", 20.000001)])?.expect("integer amount");
+        let change = post.saturating_sub(pre);
+        assert_eq!(10_000_001, change);
+        assert!(ExpectedAmount::AtLeast(10_000_000).is_met_by(change));
+        assert!(ExpectedAmount::AtLeast(10_000_001).is_met_by(change));
+        assert!(!ExpectedAmount::AtLeast(10_000_002).is_met_by(change));
+        assert!(!ExpectedAmount::Exact(10_000_000).is_met_by(change));
+
+        // one base unit short of 10 usdc
+        let post = balance(vec![usdc_balance("19999999", 19.999999)])?.expect("integer amount");
+        let change = post.saturating_sub(pre);
+        assert_eq!(9_999_999, change);
+        assert!(!ExpectedAmount::AtLeast(10_000_000).is_met_by(change));
+
+        assert_eq!(Some(0), balance(vec![])?);
+        assert_eq!(None, balance(vec![usdc_balance("10.000001", 10.000001)])?);
+        assert_eq!(
+            Some(0),
+            get_mint_token_balance(
+                &OptionSerializer::None,
+                "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU",
+                "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_paid_onchain_amount_semantics() -> anyhow::Result<()> {
+        // the fixture transfers 10 base units, shown as 0.00001 usdc in the ui amounts
+        let rpc_url = RpcStub::default()
+            .with_transactions(&["get_transaction_paid.json"])?
+            .start()
+            .await?
+            .url;
+        for (amount, paid) in [
+            (ExpectedAmount::AtLeast(10), true),
+            (ExpectedAmount::AtLeast(9), true),
+            (ExpectedAmount::AtLeast(11), false),
+            (ExpectedAmount::Exact(10), true),
+            (ExpectedAmount::Exact(9), false),
+            (ExpectedAmount::Exact(11), false),
+        ] {
+            let result = is_paid_onchain(
+                &rpc_url,
+                CommitmentConfig::confirmed(),
+                1,
+                amount,
+                &["5t6gQ7Mnr3mmsFYquFGwgEKokq9wrrUgCpwWab93LmLL"],
+                "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+            )
+            .await?
+            .paid;
+            assert_eq!(paid, result, "{amount:?}");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_paid_onchain_split_across_references() -> anyhow::Result<()> {
+        // two transfers of 5 usdc, each to another reference of the same 10 usdc quote
+        let rpc_url = RpcStub::default()
+            .with_transactions(&[
+                "get_transaction_split_first.json",
+                "get_transaction_split_second.json",
+            ])?
+            .start()
+            .await?
+            .url;
+        let references = [
+            "5t6gQ7Mnr3mmsFYquFGwgEKokq9wrrUgCpwWab93LmLL",
+            "9SSpCSUfEVZ2XCm6hp4gufonJwGEdJn6A1Zbcje8CVpa",
+        ];
+
+        for (amount, references, paid, amount_paid) in [
+            (
+                ExpectedAmount::AtLeast(10_000_000),
+                &references[..],
+                true,
+                10_000_000,
+            ),
+            (
+                ExpectedAmount::AtLeast(10_000_001),
+                &references[..],
+                false,
+                10_000_000,
+            ),
+            // only the first transfer carries the first reference
+            (
+                ExpectedAmount::AtLeast(10_000_000),
+                &references[..1],
+                false,
+                5_000_000,
+            ),
+            (
+                ExpectedAmount::AtLeast(5_000_000),
+                &references[..1],
+                true,
+                5_000_000,
+            ),
+            // an exact amount has to be paid by a single transaction
+            (
+                ExpectedAmount::Exact(10_000_000),
+                &references[..],
+                false,
+                5_000_000,
+            ),
+        ] {
+            let result = is_paid_onchain(
+                &rpc_url,
+                CommitmentConfig::confirmed(),
+                1,
+                amount,
+                references,
+                "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+            )
+            .await?;
+            assert_eq!(
+                OnchainPayment { paid, amount_paid },
+                result,
+                "{amount:?} {references:?}"
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_paid_onchain_send_coins_transaction() -> anyhow::Result<()> {
+        // laid out like the transfers of the mint: the reference is a read-only account
+        let result = is_paid_onchain(
+            &RpcStub::default()
+                .with_transactions(&["get_transaction_send_coins.json"])?
+                .start()
+                .await?
+                .url,
+            CommitmentConfig::confirmed(),
+            1,
+            ExpectedAmount::Exact(10),
+            &["5t6gQ7Mnr3mmsFYquFGwgEKokq9wrrUgCpwWab93LmLL"],
+            "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+        )
         .await
-        .expect("could not reach price exchange provider")
-        .text()
+        .map(|payment| payment.paid);
+
+        assert!(matches!(result, Ok(true)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_paid_onchain_token_2022() -> anyhow::Result<()> {
+        let result = is_paid_onchain(
+            &RpcStub::default()
+                .with_transactions(&["get_transaction_token_2022.json"])?
+                .start()
+                .await?
+                .url,
+            CommitmentConfig::confirmed(),
+            1,
+            ExpectedAmount::Exact(10),
+            &["5t6gQ7Mnr3mmsFYquFGwgEKokq9wrrUgCpwWab93LmLL"],
+            "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+        )
         .await
-        .expect("could not reach price exchange provider");
+        .map(|payment| payment.paid);
+
+        assert!(matches!(result, Ok(true)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_token_program_id() -> anyhow::Result<()> {
+        let usdc_mint = Pubkey::from_str("4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU")?;
+        for token_program in [spl_token::ID, spl_token_2022::ID] {
+            let client = RpcClient::new(
+                RpcStub::default()
+                    .with_recipient_account(true)
+                    .with_token_program(token_program)
+                    .start()
+                    .await?
+                    .url,
+            );
+            assert_eq!(token_program, token_program_id(&client, &usdc_mint).await?);
+        }
+
+        let client = RpcClient::new(
+            RpcStub::default()
+                .with_recipient_account(true)
+                .with_token_program(solana_sdk::system_program::ID)
+                .start()
+                .await?
+                .url,
+        );
+        let result = token_program_id(&client, &usdc_mint).await;
+        assert!(matches!(result, Err(MonexoMintError::OnchainBackend(_))));
+        Ok(())
+    }
 
-    let json: serde_json::Value =
-        serde_json::from_str(&response).expect("could not reach price exchange provider");
-    let price = json["solana"]["usd"]
-        .as_f64()
-        .ok_or("Failed to get SOL/USDC price")
-        .expect("failed parse exchange response");
+    #[tokio::test]
+    async fn test_get_estimated_fees_token_programs() -> anyhow::Result<()> {
+        for token_program in [spl_token::ID, spl_token_2022::ID] {
+            let estimate = get_estimated_fees(
+                &RpcStub::default()
+                    .with_recipient_account(false)
+                    .with_token_program(token_program)
+                    .start()
+                    .await?
+                    .url,
+                &StubPriceOracle(Some(100.0)),
+                100_000,
+                &Keypair::new().pubkey().to_string(),
+                &Keypair::new().pubkey().to_string(),
+            )
+            .await?;
+            // (5000 lamports fee + 2_039_280 lamports rent) * 100 usdc per sol
+            assert_eq!((204_428, true), estimate, "{token_program}");
+        }
+        Ok(())
+    }
 
-    Ok(price)
+    #[tokio::test]
+    async fn test_is_paid_onchain_other_reference() -> anyhow::Result<()> {
+        let result = is_paid_onchain(
+            &RpcStub::default()
+                .with_transactions(&["get_transaction_send_coins.json"])?
+                .start()
+                .await?
+                .url,
+            CommitmentConfig::confirmed(),
+            1,
+            ExpectedAmount::Exact(10),
+            &["HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM"],
+            "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+        )
+        .await
+        .map(|payment| payment.paid);
+
+        assert!(matches!(result, Ok(false)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_paid_onchain_balance_decreased() -> anyhow::Result<()> {
+        // the transfer instruction matches, but the destination's balance went from 100 to 90
+        let result = is_paid_onchain(
+            &RpcStub::default()
+                .with_transactions(&["get_transaction_balance_decreased.json"])?
+                .start()
+                .await?
+                .url,
+            CommitmentConfig::confirmed(),
+            1,
+            ExpectedAmount::AtLeast(10),
+            &["5t6gQ7Mnr3mmsFYquFGwgEKokq9wrrUgCpwWab93LmLL"],
+            "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+        )
+        .await
+        .map(|payment| payment.paid);
+
+        assert!(matches!(result, Ok(false)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_paid_onchain_no_matching_transaction() -> anyhow::Result<()> {
+        let result = is_paid_onchain(
+            &RpcStub::default()
+                .with_transactions(&["get_transaction_unmatched.json"])?
+                .start()
+                .await?
+                .url,
+            CommitmentConfig::confirmed(),
+            1,
+            ExpectedAmount::AtLeast(10),
+            &["5t6gQ7Mnr3mmsFYquFGwgEKokq9wrrUgCpwWab93LmLL"],
+            "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+        )
+        .await
+        .map(|payment| payment.paid);
+
+        assert!(matches!(result, Ok(false)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_paid_onchain_invalid_reference() -> anyhow::Result<()> {
+        let result = is_paid_onchain(
+            "http://127.0.0.1:8899",
+            CommitmentConfig::confirmed(),
+            1,
+            ExpectedAmount::AtLeast(10),
+            &["not-a-reference"],
+            "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM",
+        )
+        .await
+        .map(|payment| payment.paid);
+
+        assert!(matches!(result, Err(MonexoMintError::OnchainBackend(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_mint_quote_onchain_fee_percent() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let mint = create_mint(
+            db,
+            Some(OnchainConfig {
+                fee_percent: 2.5,
+                ..Default::default()
+            }),
+            None,
+        );
+
+        let Json(quote) = post_mint_quote_onchain(
+            State(mint.clone()),
+            Json(PostMintQuoteOnchainRequest {
+                amount: 100_000,
+                references: None,
+            }),
+        )
+        .await?;
+        assert_eq!(2_500, quote.fee);
+
+        let mut tx = mint.db.begin_tx().await?;
+        let stored_quote = mint
+            .db
+            .get_onchain_mint_quote(&mut tx, &Uuid::parse_str(&quote.quote)?)
+            .await?;
+        tx.commit().await?;
+        assert_eq!(2_500, stored_quote.fee_total);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_mint_quote_onchain_references() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+        let mint = create_mint(db, Some(OnchainConfig::default()), None);
+
+        let Json(quote) = post_mint_quote_onchain(
+            State(mint.clone()),
+            Json(PostMintQuoteOnchainRequest {
+                amount: 100_000,
+                references: Some(3),
+            }),
+        )
+        .await?;
+        assert_eq!(2, quote.additional_references.len());
+        assert!(!quote.additional_references.contains(&quote.reference));
+
+        let mut tx = mint.db.begin_tx().await?;
+        let stored_quote = mint
+            .db
+            .get_onchain_mint_quote(&mut tx, &Uuid::parse_str(&quote.quote)?)
+            .await?;
+        tx.commit().await?;
+        assert_eq!(
+            quote.additional_references,
+            stored_quote.additional_references
+        );
+        assert_eq!(3, stored_quote.references().len());
+
+        for references in [0, MAX_QUOTE_REFERENCES + 1] {
+            let result = post_mint_quote_onchain(
+                State(mint.clone()),
+                Json(PostMintQuoteOnchainRequest {
+                    amount: 100_000,
+                    references: Some(references),
+                }),
+            )
+            .await;
+            assert!(matches!(
+                result,
+                Err(MonexoMintError::InvalidReferenceCount(count)) if count == references
+            ));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_melt_quote_onchain_estimated_fee() -> anyhow::Result<()> {
+        // 5000 lamports at 200 usd per SOL
+        let quote = post_melt_quote_fees(StubPriceOracle(Some(200.0)), true, 100_000, 0.0).await?;
+        assert_eq!(1_000, quote.fee_micro_usd);
+        assert_eq!(1_100, quote.fee_total);
+        assert!(!quote.create_recipient_account);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_melt_quote_onchain_missing_recipient_account() -> anyhow::Result<()> {
+        // 5000 lamports fee and 2039280 lamports rent at 200 usd per SOL
+        let quote =
+            post_melt_quote_fees(StubPriceOracle(Some(200.0)), false, 1_000_000, 0.0).await?;
+        assert_eq!(408_856, quote.fee_micro_usd);
+        assert_eq!(449_741, quote.fee_total);
+        assert!(quote.create_recipient_account);
+
+        // the rent alone exceeds a melt of the minimum amount
+        let result = post_melt_quote_fees(StubPriceOracle(Some(200.0)), false, 100_000, 0.0).await;
+        assert!(matches!(
+            result.map_err(|err| err.downcast::<MonexoMintError>()),
+            Err(Ok(MonexoMintError::InvalidAmount(_)))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_melt_quote_onchain_fee_percent() -> anyhow::Result<()> {
+        // the mint's fee adds to the network fee of 5000 lamports at 200 usd per SOL
+        let quote = post_melt_quote_fees(StubPriceOracle(Some(200.0)), true, 100_000, 2.5).await?;
+        assert_eq!(1_000, quote.fee_micro_usd);
+        assert_eq!(2_500, quote.fee_mint);
+        assert_eq!(3_600, quote.fee_total);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_melt_quote_onchain_default_fee() -> anyhow::Result<()> {
+        let quote = post_melt_quote_fees(StubPriceOracle(None), true, 100_000, 0.0).await?;
+        assert_eq!(2_000, quote.fee_micro_usd);
+        assert_eq!(2_200, quote.fee_total);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_mint_onchain_unpaid_quote() -> anyhow::Result<()> {
+        let (_node, db) = create_db().await?;
+
+        let keypair = KeypairFile::new()?;
+
+        let quote = add_quote(&db, 8, 0, MintOnchainState::Unpaid).await?;
+
+        let mint = create_mint(
+            db,
+            Some(OnchainConfig {
+                solana_rpc_url: RpcStub::default().start().await?.url,
+                ..Default::default()
+            }),
+            Some(&keypair),
+        );
+
+        let result = post_mint_onchain(
+            State(mint.clone()),
+            Json(PostMintOnchainRequest {
+                quote: quote.quote_id.to_string(),
+                outputs: create_outputs(&mint, &[8]),
+                idempotency_key: None,
+            }),
+        )
+        .await;
+
+        assert!(matches!(result, Err(MonexoMintError::InvoiceNotPaidYet(_))));
+
+        let mut tx = mint.db.begin_tx().await?;
+        let stored_quote = mint
+            .db
+            .get_onchain_mint_quote(&mut tx, &quote.quote_id)
+            .await?;
+        tx.commit().await?;
+        assert_eq!(MintOnchainState::Unpaid, stored_quote.state);
+        Ok(())
+    }
 }
@@ -0,0 +1,6 @@
+-- Last known info of each mint, used when the mint is unreachable
+CREATE TABLE IF NOT EXISTS mint_info (
+    mint_url TEXT PRIMARY KEY,
+    info TEXT NOT NULL CHECK (json_valid(info)),
+    time_updated TIMESTAMP
+);
@@ -0,0 +1,9 @@
+-- Outputs of swaps that have been sent to the mint, kept until the resulting proofs are stored
+CREATE TABLE IF NOT EXISTS pending_outputs (
+    secret TEXT NOT NULL PRIMARY KEY,
+    mint_url TEXT NOT NULL,
+    keyset_id TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    blinding_factor TEXT NOT NULL,
+    time_created TIMESTAMP
+);
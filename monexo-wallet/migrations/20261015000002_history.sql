@@ -0,0 +1,8 @@
+-- Record of the wallet's transactions, written in the same transaction as the affected proofs
+CREATE TABLE IF NOT EXISTS transactions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    mint_url TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    time_created TIMESTAMP
+);
@@ -0,0 +1,8 @@
+-- Mint quotes that have been created but not minted yet, so minting can be resumed after a crash
+CREATE TABLE IF NOT EXISTS mint_quotes (
+    quote TEXT NOT NULL PRIMARY KEY,
+    mint_url TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    fee INTEGER NOT NULL,
+    time_created TIMESTAMP
+);
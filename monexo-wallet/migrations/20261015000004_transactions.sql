@@ -0,0 +1,5 @@
+-- The history records sends, mints and melts too, together with their unit and an optional memo
+ALTER TABLE transactions ADD COLUMN unit TEXT NOT NULL DEFAULT 'usd';
+ALTER TABLE transactions ADD COLUMN memo TEXT;
+ALTER TABLE transactions ADD COLUMN timestamp INTEGER NOT NULL DEFAULT 0;
+UPDATE transactions SET timestamp = CAST(strftime('%s', time_created) AS INTEGER) WHERE time_created IS NOT NULL;
@@ -0,0 +1,2 @@
+-- Unit of the keyset, keysets stored before were usd keysets
+ALTER TABLE keysets ADD COLUMN currency_unit TEXT NOT NULL DEFAULT 'usd';
@@ -0,0 +1,27 @@
+-- Mints known to the wallet, keysets belong to a mint and proofs to the mint of their keyset.
+-- Keysets stored before are of the mint the wallet used if it knows a single one, otherwise they
+-- have no mint until their mint is added again
+CREATE TABLE IF NOT EXISTS mints (
+    mint_url TEXT NOT NULL PRIMARY KEY,
+    time_created TIMESTAMP
+);
+
+ALTER TABLE keysets ADD COLUMN mint_url TEXT;
+
+UPDATE keysets SET mint_url = (
+    SELECT mint_url FROM mint_info
+    UNION SELECT mint_url FROM mint_quotes
+    UNION SELECT mint_url FROM pending_outputs
+    UNION SELECT mint_url FROM transactions
+)
+WHERE (
+    SELECT COUNT(*) FROM (
+        SELECT mint_url FROM mint_info
+        UNION SELECT mint_url FROM mint_quotes
+        UNION SELECT mint_url FROM pending_outputs
+        UNION SELECT mint_url FROM transactions
+    )
+) = 1;
+
+INSERT INTO mints (mint_url, time_created)
+SELECT DISTINCT mint_url, CURRENT_TIMESTAMP FROM keysets WHERE mint_url IS NOT NULL;
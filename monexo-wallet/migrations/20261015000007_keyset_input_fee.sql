@@ -0,0 +1,2 @@
+-- Fee in parts per thousand the mint charges for every input of a swap, keysets stored before had no fee
+ALTER TABLE keysets ADD COLUMN input_fee_ppk INTEGER NOT NULL DEFAULT 0;
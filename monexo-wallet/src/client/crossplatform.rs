@@ -3,19 +3,28 @@ use monexo_core::{
     blind::BlindedMessage,
     keyset::Keysets,
     primitives::{
-        KeysResponse, MintInfoResponse, PostMeltOnchainRequest, PostMeltOnchainResponse,
-        PostMeltQuoteOnchainRequest, PostMeltQuoteOnchainResponse, PostMintOnchainRequest,
-        PostMintOnchainResponse, PostMintQuoteOnchainRequest, PostMintQuoteOnchainResponse,
-        PostSwapRequest, PostSwapResponse,
+        KeysResponse, MintInfoResponse, MintOnchainState, PostCheckStateRequest,
+        PostCheckStateResponse, PostCurrencyExchangeRequest, PostCurrencyExchangeResponse,
+        PostMeltOnchainRequest, PostMeltOnchainResponse, PostMeltQuoteOnchainRequest,
+        PostMeltQuoteOnchainResponse, PostMintOnchainRequest, PostMintOnchainResponse,
+        PostMintQuoteOnchainRequest, PostMintQuoteOnchainResponse, PostRestoreRequest,
+        PostRestoreResponse, PostSwapRequest, PostSwapResponse,
     },
     proof::Proofs,
 };
 
+use rand::Rng;
+
 use crate::{error::MonexoWalletError, http::CrossPlatformHttpClient};
 
 use super::CashuClient;
 use url::Url;
 
+/// Returns a new random key for a mint or melt request, its retries are sent with the same key
+fn idempotency_key() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; 16]>())
+}
+
 #[async_trait(?Send)]
 impl CashuClient for CrossPlatformHttpClient {
     async fn get_keys(&self, mint_url: &Url) -> Result<KeysResponse, MonexoWalletError> {
@@ -46,6 +55,22 @@ impl CashuClient for CrossPlatformHttpClient {
         self.do_post(&mint_url.join("v1/swap")?, &body).await
     }
 
+    async fn post_exchange(
+        &self,
+        mint_url: &Url,
+        amount: u64,
+        inputs: Proofs,
+        outputs: Vec<BlindedMessage>,
+    ) -> Result<PostCurrencyExchangeResponse, MonexoWalletError> {
+        let body = PostCurrencyExchangeRequest {
+            amount,
+            inputs,
+            outputs,
+        };
+
+        self.do_post(&mint_url.join("v1/exchange")?, &body).await
+    }
+
     async fn post_mint_onchain(
         &self,
         mint_url: &Url,
@@ -55,8 +80,9 @@ impl CashuClient for CrossPlatformHttpClient {
         let body = PostMintOnchainRequest {
             quote,
             outputs: blinded_messages,
+            idempotency_key: Some(idempotency_key()),
         };
-        self.do_post(&mint_url.join("v1/mint/btconchain")?, &body)
+        self.do_post_idempotent(&mint_url.join("v1/mint/btconchain")?, &body)
             .await
     }
 
@@ -64,8 +90,9 @@ impl CashuClient for CrossPlatformHttpClient {
         &self,
         mint_url: &Url,
         amount: u64,
+        references: Option<u8>,
     ) -> Result<PostMintQuoteOnchainResponse, MonexoWalletError> {
-        let body = PostMintQuoteOnchainRequest { amount };
+        let body = PostMintQuoteOnchainRequest { amount, references };
         self.do_post(&mint_url.join("v1/mint/quote/btconchain")?, &body)
             .await
     }
@@ -79,14 +106,42 @@ impl CashuClient for CrossPlatformHttpClient {
             .await
     }
 
+    async fn await_mint_quote_onchain(
+        &self,
+        mint_url: &Url,
+        quote: String,
+    ) -> Result<PostMintQuoteOnchainResponse, MonexoWalletError> {
+        let url = mint_url.join(&format!("v1/mint/quote/btconchain/{}/subscribe", quote))?;
+        let response = self
+            .do_subscribe(&url, |response: &PostMintQuoteOnchainResponse| {
+                matches!(
+                    response.state,
+                    MintOnchainState::Paid | MintOnchainState::Issued
+                )
+            })
+            .await?;
+
+        match response {
+            Some(response) => Ok(response),
+            // the stream closed without any event, ask for the quote instead
+            None => self.get_mint_quote_onchain(mint_url, quote).await,
+        }
+    }
+
     async fn post_melt_onchain(
         &self,
         mint_url: &Url,
         inputs: Proofs,
         quote: String,
+        outputs: Vec<BlindedMessage>,
     ) -> Result<PostMeltOnchainResponse, MonexoWalletError> {
-        let body = PostMeltOnchainRequest { quote, inputs };
-        self.do_post(&mint_url.join("v1/melt/btconchain")?, &body)
+        let body = PostMeltOnchainRequest {
+            quote,
+            inputs,
+            outputs,
+            idempotency_key: Some(idempotency_key()),
+        };
+        self.do_post_idempotent(&mint_url.join("v1/melt/btconchain")?, &body)
             .await
     }
 
@@ -110,6 +165,24 @@ impl CashuClient for CrossPlatformHttpClient {
             .await
     }
 
+    async fn post_restore(
+        &self,
+        mint_url: &Url,
+        outputs: Vec<BlindedMessage>,
+    ) -> Result<PostRestoreResponse, MonexoWalletError> {
+        let body = PostRestoreRequest { outputs };
+        self.do_post(&mint_url.join("v1/restore")?, &body).await
+    }
+
+    async fn post_check_state(
+        &self,
+        mint_url: &Url,
+        ys: Vec<String>,
+    ) -> Result<PostCheckStateResponse, MonexoWalletError> {
+        let body = PostCheckStateRequest { ys };
+        self.do_post(&mint_url.join("v1/checkstate")?, &body).await
+    }
+
     async fn get_info(&self, mint_url: &Url) -> Result<MintInfoResponse, MonexoWalletError> {
         self.do_get(&mint_url.join("v1/info")?).await
     }
@@ -120,3 +193,70 @@ impl CashuClient for CrossPlatformHttpClient {
             .map(|s| s == 200)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use monexo_core::primitives::PostMintOnchainRequest;
+    use url::Url;
+
+    use crate::{client::CashuClient, http::CrossPlatformHttpClient};
+
+    /// Starts a server that answers the mint requests and returns their bodies
+    fn start_mint_server(
+        requests: usize,
+    ) -> anyhow::Result<(Url, std::thread::JoinHandle<Vec<String>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let handle = std::thread::spawn(move || {
+            listener
+                .incoming()
+                .take(requests)
+                .filter_map(Result::ok)
+                .map(|mut stream| {
+                    let mut buf = [0u8; 4096];
+                    let read = stream.read(&mut buf).unwrap_or_default();
+                    let body = r#"{"signatures":[]}"#;
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let request = String::from_utf8_lossy(&buf[..read]).to_string();
+                    request
+                        .split_once("\r\n\r\n")
+                        .map(|(_, body)| body.to_owned())
+                        .unwrap_or_default()
+                })
+                .collect()
+        });
+        Ok((url, handle))
+    }
+
+    #[tokio::test]
+    async fn test_post_mint_onchain_sends_idempotency_key() -> anyhow::Result<()> {
+        let (url, handle) = start_mint_server(2)?;
+        let client = CrossPlatformHttpClient::new();
+
+        client
+            .post_mint_onchain(&url, "quote".to_owned(), vec![])
+            .await?;
+        client
+            .post_mint_onchain(&url, "quote".to_owned(), vec![])
+            .await?;
+
+        // every operation gets its own key
+        let keys = handle
+            .join()
+            .expect("server thread panicked")
+            .iter()
+            .map(|body| Ok(serde_json::from_str::<PostMintOnchainRequest>(body)?.idempotency_key))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert!(keys.iter().all(Option::is_some));
+        assert_ne!(keys[0], keys[1]);
+        Ok(())
+    }
+}
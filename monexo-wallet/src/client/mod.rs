@@ -8,8 +8,9 @@ use monexo_core::{
     blind::BlindedMessage,
     keyset::Keysets,
     primitives::{
-        KeysResponse, MintInfoResponse, PostMeltOnchainResponse, PostMeltQuoteOnchainResponse,
-        PostMintOnchainResponse, PostMintQuoteOnchainResponse, PostSwapResponse,
+        KeysResponse, MintInfoResponse, PostCheckStateResponse, PostCurrencyExchangeResponse,
+        PostMeltOnchainResponse, PostMeltQuoteOnchainResponse, PostMintOnchainResponse,
+        PostMintQuoteOnchainResponse, PostRestoreResponse, PostSwapResponse,
     },
     proof::Proofs,
 };
@@ -37,6 +38,15 @@ pub trait CashuClient {
         output: Vec<BlindedMessage>,
     ) -> Result<PostSwapResponse, MonexoWalletError>;
 
+    /// Exchanges the `inputs` worth `amount` for signatures on `outputs` of another currency
+    async fn post_exchange(
+        &self,
+        mint_url: &Url,
+        amount: u64,
+        inputs: Proofs,
+        outputs: Vec<BlindedMessage>,
+    ) -> Result<PostCurrencyExchangeResponse, MonexoWalletError>;
+
     async fn post_mint_onchain(
         &self,
         mint_url: &Url,
@@ -44,10 +54,13 @@ pub trait CashuClient {
         blinded_messages: Vec<BlindedMessage>,
     ) -> Result<PostMintOnchainResponse, MonexoWalletError>;
 
+    /// Creates a mint quote whose payment may be split across `references` transfers, the mint
+    /// uses a single reference if None
     async fn post_mint_quote_onchain(
         &self,
         mint_url: &Url,
         amount: u64,
+        references: Option<u8>,
     ) -> Result<PostMintQuoteOnchainResponse, MonexoWalletError>;
 
     async fn get_mint_quote_onchain(
@@ -56,11 +69,22 @@ pub trait CashuClient {
         quote: String,
     ) -> Result<PostMintQuoteOnchainResponse, MonexoWalletError>;
 
+    /// Waits on the event stream of the quote until the mint reports it paid or issued, returns
+    /// the last state received if the stream closes before
+    async fn await_mint_quote_onchain(
+        &self,
+        mint_url: &Url,
+        quote: String,
+    ) -> Result<PostMintQuoteOnchainResponse, MonexoWalletError>;
+
+    /// Melts the proofs, the mint returns the change of an overpaid fee as signatures on the
+    /// blank `outputs`
     async fn post_melt_onchain(
         &self,
         mint_url: &Url,
         proofs: Proofs,
         quote: String,
+        outputs: Vec<BlindedMessage>,
     ) -> Result<PostMeltOnchainResponse, MonexoWalletError>;
 
     async fn post_melt_quote_onchain(
@@ -76,6 +100,18 @@ pub trait CashuClient {
         quote: String,
     ) -> Result<PostMeltQuoteOnchainResponse, MonexoWalletError>;
 
+    async fn post_restore(
+        &self,
+        mint_url: &Url,
+        outputs: Vec<BlindedMessage>,
+    ) -> Result<PostRestoreResponse, MonexoWalletError>;
+
+    async fn post_check_state(
+        &self,
+        mint_url: &Url,
+        ys: Vec<String>,
+    ) -> Result<PostCheckStateResponse, MonexoWalletError>;
+
     async fn get_info(&self, mint_url: &Url) -> Result<MintInfoResponse, MonexoWalletError>;
 
     async fn is_v1_supported(&self, mint_url: &Url) -> Result<bool, MonexoWalletError>;
@@ -36,6 +36,10 @@ pub enum MonexoWalletError {
     #[error("Sqlite Error {0}")]
     Sqlite(#[from] sqlx::sqlite::SqliteError),
 
+    #[cfg(target_arch = "wasm32")]
+    #[error("Storage Error {0}")]
+    Storage(String),
+
     #[error("Bip32Error {0}")]
     Bip32(#[from] bip32::Error),
 
@@ -75,6 +79,45 @@ pub enum MonexoWalletError {
     #[error("Not enough tokens")]
     NotEnoughTokens,
 
+    #[error("No proofs add up to exactly {0}")]
+    NoExactAmount(u64),
+
+    #[error("Token already spent: {0}")]
+    TokenAlreadySpent(String),
+
+    #[error("Keyset not found: {0}")]
+    KeysetNotFound(String),
+
+    #[error("No active keyset found, the mint may have deactivated its keysets")]
+    NoActiveKeyset,
+
+    #[error("Keys of the mint don't match its keysets: {0}")]
+    KeysetMismatch(String),
+
     #[error("Invalid Proofs")]
     InvalidProofs,
+
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(String),
+
+    #[error("Error in swap: input {0} != output {1}")]
+    SwapAmountMismatch(u64, u64),
+
+    #[error("Mint {0} does not support exchanging {1} to {2}")]
+    ExchangeNotSupported(String, String, String),
+
+    #[error("Mint {0} is unreachable or does not support swap")]
+    MintUnreachable(String),
+
+    #[error("Token is from mint {1} and can't be received at mint {0}")]
+    MintMismatch(String, String),
+
+    #[error("No proofs of the token can be spent")]
+    NoSpendableProofs,
+
+    #[error("Invalid history kind {0}")]
+    InvalidHistoryKind(String),
+
+    #[error("Quote {0} was not settled within {1} seconds")]
+    QuoteTimeout(String, u64),
 }
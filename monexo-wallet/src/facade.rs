@@ -0,0 +1,619 @@
+//! High level api for applications embedding the wallet.
+//!
+//! [`WalletFacade`] works with a single mint and unit and takes care of what the CLI does by
+//! hand: it adds the mint, picks the active keyset and polls quotes until they are settled.
+//! Waiting for a quote gives up with [`MonexoWalletError::QuoteTimeout`] after the configured
+//! maximum wait.
+//!
+//! # Concurrency
+//!
+//! All operations take `&self` and may run concurrently with [`WalletFacade::balance`] and
+//! [`WalletFacade::receive`]. Operations that spend proofs ([`WalletFacade::send`] and
+//! [`WalletFacade::pay_onchain`]) select proofs from the localstore without locking them, so
+//! concurrent spends may select the same proofs and all but one of them fail at the mint. Run
+//! spending operations one after another, e.g. by keeping the facade behind a mutex.
+
+use std::time::Duration;
+
+use futures::stream::{self, Stream};
+use monexo_core::{
+    primitives::{CurrencyUnit, MeltOnchainState},
+    token::TokenV3,
+};
+use tokio::time::Instant;
+use url::Url;
+
+use crate::{
+    client::CashuClient,
+    error::MonexoWalletError,
+    localstore::{LocalStore, WalletKeyset},
+    wallet::Wallet,
+};
+
+/// interval in which pending quotes are checked if not configured otherwise
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// time after which waiting for a quote is given up if not configured otherwise
+const DEFAULT_MAX_WAIT: Duration = Duration::from_secs(600);
+
+/// Progress of [`WalletFacade::mint`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MintStatus {
+    /// the quote has been created, paying `amount` onchain with the `reference` mints `amount`
+    /// minus `fee`
+    QuoteCreated {
+        quote: String,
+        reference: String,
+        amount: u64,
+        fee: u64,
+    },
+    /// the mint received the payment
+    Paid,
+    /// the tokens have been minted and stored
+    Minted { amount: u64 },
+}
+
+/// Outcome of [`WalletFacade::pay_onchain`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentStatus {
+    pub state: MeltOnchainState,
+    pub txid: Option<String>,
+    /// fee the mint charged for the transaction
+    pub fee: u64,
+}
+
+enum MintStep {
+    CreateQuote,
+    AwaitPayment {
+        quote: String,
+        amount: u64,
+        deadline: Instant,
+    },
+    Mint {
+        quote: String,
+        amount: u64,
+        deadline: Instant,
+    },
+    Done,
+}
+
+pub struct WalletFacade<L, C>
+where
+    L: LocalStore,
+    C: CashuClient + Default,
+{
+    wallet: Wallet<L, C>,
+    mint_url: Url,
+    unit: CurrencyUnit,
+    poll_interval: Duration,
+    max_wait: Duration,
+}
+
+impl<L, C> WalletFacade<L, C>
+where
+    L: LocalStore,
+    C: CashuClient + Default,
+{
+    /// Creates a facade for the usd keysets of the mint, the mint is added to the wallet if it
+    /// doesn't know it yet
+    pub async fn new(wallet: Wallet<L, C>, mint_url: Url) -> Result<Self, MonexoWalletError> {
+        if !wallet.list_mints().await?.contains(&mint_url) {
+            wallet.add_mint(&mint_url).await?;
+        }
+
+        Ok(Self {
+            wallet,
+            mint_url,
+            unit: CurrencyUnit::Usd,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            max_wait: DEFAULT_MAX_WAIT,
+        })
+    }
+
+    pub fn with_unit(self, unit: CurrencyUnit) -> Self {
+        Self { unit, ..self }
+    }
+
+    pub fn with_poll_interval(self, poll_interval: Duration) -> Self {
+        Self {
+            poll_interval,
+            ..self
+        }
+    }
+
+    /// Sets how long [`WalletFacade::mint`] and [`WalletFacade::pay_onchain`] wait for a quote
+    /// to be settled
+    pub fn with_max_wait(self, max_wait: Duration) -> Self {
+        Self { max_wait, ..self }
+    }
+
+    /// Returns the wallet for operations the facade doesn't cover
+    pub const fn wallet(&self) -> &Wallet<L, C> {
+        &self.wallet
+    }
+
+    /// Returns the balance at the mint
+    pub async fn balance(&self) -> Result<u64, MonexoWalletError> {
+        self.wallet.get_mint_balance(&self.mint_url).await
+    }
+
+    /// Creates a quote for `amount` and mints the tokens as soon as the mint received the
+    /// payment. The stream ends after the tokens have been minted or the first error, which is
+    /// [`MonexoWalletError::QuoteTimeout`] if the quote isn't settled within the maximum wait.
+    pub fn mint(
+        &self,
+        amount: u64,
+    ) -> impl Stream<Item = Result<MintStatus, MonexoWalletError>> + '_ {
+        stream::unfold(MintStep::CreateQuote, move |step| async move {
+            let (status, next) = match step {
+                MintStep::CreateQuote => {
+                    match self
+                        .wallet
+                        .create_quote_onchain(&self.mint_url, amount, None)
+                        .await
+                    {
+                        Ok(quote) => (
+                            Ok(MintStatus::QuoteCreated {
+                                quote: quote.quote.clone(),
+                                reference: quote.reference,
+                                amount,
+                                fee: quote.fee,
+                            }),
+                            MintStep::AwaitPayment {
+                                quote: quote.quote,
+                                amount: amount.saturating_sub(quote.fee),
+                                deadline: Instant::now() + self.max_wait,
+                            },
+                        ),
+                        Err(err) => (Err(err), MintStep::Done),
+                    }
+                }
+                MintStep::AwaitPayment {
+                    quote,
+                    amount,
+                    deadline,
+                } => match self.await_quote_paid(&quote, deadline).await {
+                    Ok(()) => (
+                        Ok(MintStatus::Paid),
+                        MintStep::Mint {
+                            quote,
+                            amount,
+                            deadline,
+                        },
+                    ),
+                    Err(err) => (Err(err), MintStep::Done),
+                },
+                MintStep::Mint {
+                    quote,
+                    amount,
+                    deadline,
+                } => match self.mint_tokens(quote, amount, deadline).await {
+                    Ok(tokens) => (
+                        Ok(MintStatus::Minted {
+                            amount: tokens.total_amount(),
+                        }),
+                        MintStep::Done,
+                    ),
+                    Err(err) => (Err(err), MintStep::Done),
+                },
+                MintStep::Done => return None,
+            };
+            Some((status, next))
+        })
+    }
+
+    /// Sends `amount` and returns the serialized token
+    pub async fn send(&self, amount: u64) -> Result<String, MonexoWalletError> {
+        let keyset = self.active_keyset().await?;
+        let tokens = self
+            .wallet
+            .send_tokens(&self.mint_url, &keyset, amount)
+            .await?;
+        Ok(tokens.serialize()?)
+    }
+
+    /// Receives a serialized token at its own mint, which is added to the wallet if it's unknown.
+    /// Returns the received amount.
+    pub async fn receive(&self, token: &str) -> Result<u64, MonexoWalletError> {
+        let tokens = TokenV3::deserialize(token)?;
+        let mint_url = tokens.mint().unwrap_or_else(|| self.mint_url.clone());
+        if !self.wallet.list_mints().await?.contains(&mint_url) {
+            self.wallet.add_mint(&mint_url).await?;
+        }
+
+        let unit = tokens.currency_unit.clone().unwrap_or(CurrencyUnit::Usd);
+        let keyset = self
+            .wallet
+            .get_active_keyset(&mint_url, &unit)
+            .await?
+            .ok_or(MonexoWalletError::NoActiveKeyset)?;
+        self.wallet
+            .receive_tokens(&mint_url, &keyset, &tokens)
+            .await
+    }
+
+    /// Pays `amount` to the onchain address and waits until the mint's transaction is settled.
+    /// Fails with [`MonexoWalletError::QuoteTimeout`] if it's still pending after the maximum
+    /// wait.
+    pub async fn pay_onchain(
+        &self,
+        address: &str,
+        amount: u64,
+    ) -> Result<PaymentStatus, MonexoWalletError> {
+        let keyset = self.active_keyset().await?;
+        let quote = self
+            .wallet
+            .get_melt_quote_onchain(&self.mint_url, address.to_owned(), amount)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                MonexoWalletError::UnexpectedResponse("mint returned no melt quote".to_owned())
+            })?;
+
+        let response = self
+            .wallet
+            .pay_onchain(&self.mint_url, &keyset, &quote)
+            .await?;
+        let deadline = Instant::now() + self.max_wait;
+        let mut state = response.state;
+        while state == MeltOnchainState::Pending {
+            self.wait_before_poll(&quote.quote, deadline).await?;
+            state = self
+                .wallet
+                .get_onchain_state(&self.mint_url, quote.quote.clone())
+                .await?;
+        }
+
+        Ok(PaymentStatus {
+            state,
+            txid: response.txid,
+            fee: quote.fee,
+        })
+    }
+
+    async fn active_keyset(&self) -> Result<WalletKeyset, MonexoWalletError> {
+        self.wallet
+            .get_active_keyset(&self.mint_url, &self.unit)
+            .await?
+            .ok_or(MonexoWalletError::NoActiveKeyset)
+    }
+
+    /// Sleeps for the poll interval, fails if the deadline passes before
+    async fn wait_before_poll(
+        &self,
+        quote: &str,
+        deadline: Instant,
+    ) -> Result<(), MonexoWalletError> {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(self.quote_timeout(quote));
+        }
+        tokio::time::sleep(self.poll_interval.min(deadline - now)).await;
+        Ok(())
+    }
+
+    fn quote_timeout(&self, quote: &str) -> MonexoWalletError {
+        MonexoWalletError::QuoteTimeout(quote.to_owned(), self.max_wait.as_secs())
+    }
+
+    /// Waits until the mint reports the quote paid, subscriptions that end before are renewed
+    async fn await_quote_paid(
+        &self,
+        quote: &str,
+        deadline: Instant,
+    ) -> Result<(), MonexoWalletError> {
+        loop {
+            let paid = tokio::time::timeout_at(
+                deadline,
+                self.wallet
+                    .await_quote_paid(&self.mint_url, quote.to_owned()),
+            )
+            .await
+            .map_err(|_| self.quote_timeout(quote))??;
+            if paid {
+                return Ok(());
+            }
+            self.wait_before_poll(quote, deadline).await?;
+        }
+    }
+
+    /// Mints the tokens of a paid quote, retrying while the mint hasn't processed the payment
+    async fn mint_tokens(
+        &self,
+        quote: String,
+        amount: u64,
+        deadline: Instant,
+    ) -> Result<TokenV3, MonexoWalletError> {
+        let keyset = self.active_keyset().await?;
+        loop {
+            match self
+                .wallet
+                .mint_tokens(&self.mint_url, &keyset, amount.into(), quote.clone())
+                .await
+            {
+                Err(MonexoWalletError::InvoiceNotPaidYet(_, _)) => {
+                    self.wait_before_poll(&quote, deadline).await?;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use monexo_core::{
+        keyset::MintKeyset,
+        primitives::{
+            MeltOnchainState, MintOnchainState, PostMeltOnchainResponse,
+            PostMeltQuoteOnchainResponse, PostMintOnchainResponse, PostMintQuoteOnchainResponse,
+        },
+        proof::{Proof, Proofs},
+    };
+    use url::Url;
+
+    use super::{MintStatus, PaymentStatus, WalletFacade};
+    use crate::{
+        client::MockCashuClient,
+        error::MonexoWalletError,
+        localstore::{sqlite::SqliteLocalStore, LocalStore},
+        wallet::{
+            tests::{create_mock, sign_outputs},
+            WalletBuilder,
+        },
+    };
+
+    /// Creates a facade for the mock's mint whose wallet holds proofs of the given amounts
+    async fn create_facade(
+        client: MockCashuClient,
+        amounts: &[u64],
+    ) -> anyhow::Result<WalletFacade<SqliteLocalStore, MockCashuClient>> {
+        let mint_keys = MintKeyset::new("mykey", "");
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore
+            .add_proofs(
+                &mut tx,
+                &Proofs::new(
+                    amounts
+                        .iter()
+                        .map(|amount| {
+                            Proof::new(
+                                *amount,
+                                format!("secret{amount}"),
+                                mint_keys.public_keys[&1],
+                                mint_keys.keyset_id.clone(),
+                            )
+                        })
+                        .collect(),
+                ),
+            )
+            .await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::default()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        Ok(
+            WalletFacade::new(wallet, Url::parse("http://127.0.0.1:3338")?)
+                .await?
+                .with_poll_interval(Duration::from_millis(1)),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_mint() -> anyhow::Result<()> {
+        let mint_keys = [MintKeyset::new("mykey", "")];
+        let mut client = create_mock();
+        client
+            .expect_post_mint_quote_onchain()
+            .returning(|_, _, _| {
+                Ok(PostMintQuoteOnchainResponse {
+                    quote: "quote-1".to_owned(),
+                    reference: "reference".to_owned(),
+                    additional_references: vec![],
+                    fee: 4,
+                    state: MintOnchainState::Unpaid,
+                    expiry: 0,
+                    amount_paid: None,
+                })
+            });
+        // the first subscription ends before the quote is paid
+        let mut states = vec![MintOnchainState::Paid, MintOnchainState::Unpaid];
+        client
+            .expect_await_mint_quote_onchain()
+            .times(2)
+            .returning(move |_, quote| {
+                Ok(PostMintQuoteOnchainResponse {
+                    quote,
+                    reference: "reference".to_owned(),
+                    additional_references: vec![],
+                    fee: 4,
+                    state: states.pop().expect("no state left"),
+                    expiry: 0,
+                    amount_paid: None,
+                })
+            });
+        client
+            .expect_post_mint_onchain()
+            .returning(move |_, _, outputs| {
+                Ok(PostMintOnchainResponse {
+                    signatures: sign_outputs(&mint_keys, &outputs).signatures,
+                })
+            });
+        let facade = create_facade(client, &[]).await?;
+
+        let statuses = facade
+            .mint(64)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            vec![
+                MintStatus::QuoteCreated {
+                    quote: "quote-1".to_owned(),
+                    reference: "reference".to_owned(),
+                    amount: 64,
+                    fee: 4,
+                },
+                MintStatus::Paid,
+                MintStatus::Minted { amount: 60 },
+            ],
+            statuses
+        );
+        assert_eq!(60, facade.balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_timeout() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        let quote = PostMintQuoteOnchainResponse {
+            quote: "quote-1".to_owned(),
+            reference: "reference".to_owned(),
+            additional_references: vec![],
+            fee: 4,
+            state: MintOnchainState::Unpaid,
+            expiry: 0,
+            amount_paid: None,
+        };
+        let mint_quote = quote.clone();
+        client
+            .expect_post_mint_quote_onchain()
+            .returning(move |_, _, _| Ok(mint_quote.clone()));
+        // the quote is never paid
+        client
+            .expect_await_mint_quote_onchain()
+            .returning(move |_, _| Ok(quote.clone()));
+        let facade = create_facade(client, &[])
+            .await?
+            .with_max_wait(Duration::from_millis(20));
+
+        let statuses = facade.mint(64).collect::<Vec<_>>().await;
+        assert_eq!(2, statuses.len());
+        assert!(matches!(
+            statuses.last(),
+            Some(Err(MonexoWalletError::QuoteTimeout(quote, _))) if quote == "quote-1"
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_and_receive() -> anyhow::Result<()> {
+        let mint_keys = [MintKeyset::new("mykey", "")];
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, outputs| Ok(sign_outputs(&mint_keys, &outputs)));
+        let facade = create_facade(client, &[2, 8, 32]).await?;
+
+        let token = facade.send(10).await?;
+        assert!(token.starts_with("cashuA"));
+        assert_eq!(32, facade.balance().await?);
+
+        assert_eq!(10, facade.receive(&token).await?);
+        assert_eq!(42, facade.balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_onchain() -> anyhow::Result<()> {
+        let mint_keys = [MintKeyset::new("mykey", "")];
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, outputs| Ok(sign_outputs(&mint_keys, &outputs)));
+        let quote = PostMeltQuoteOnchainResponse {
+            quote: "quote-1".to_owned(),
+            description: None,
+            amount: 32,
+            fee: 4,
+            state: MeltOnchainState::Unpaid,
+            expiry: 0,
+        };
+        let melt_quote = quote.clone();
+        client
+            .expect_post_melt_quote_onchain()
+            .returning(move |_, _, _| Ok(vec![melt_quote.clone()]));
+        client.expect_post_melt_onchain().returning(|_, _, _, _| {
+            Ok(PostMeltOnchainResponse {
+                state: MeltOnchainState::Pending,
+                txid: Some("txid".to_owned()),
+                change: vec![],
+            })
+        });
+        // the transaction is confirmed on the second check
+        let mut states = vec![MeltOnchainState::Paid, MeltOnchainState::Pending];
+        client
+            .expect_get_melt_quote_onchain()
+            .times(2)
+            .returning(move |_, _| {
+                Ok(PostMeltQuoteOnchainResponse {
+                    state: states.pop().expect("no state left"),
+                    ..quote.clone()
+                })
+            });
+        let facade = create_facade(client, &[64]).await?;
+
+        let status = facade.pay_onchain("address", 32).await?;
+        assert_eq!(
+            PaymentStatus {
+                state: MeltOnchainState::Paid,
+                txid: Some("txid".to_owned()),
+                fee: 4,
+            },
+            status
+        );
+        assert_eq!(32, facade.balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_onchain_timeout() -> anyhow::Result<()> {
+        let mint_keys = [MintKeyset::new("mykey", "")];
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, outputs| Ok(sign_outputs(&mint_keys, &outputs)));
+        let quote = PostMeltQuoteOnchainResponse {
+            quote: "quote-1".to_owned(),
+            description: None,
+            amount: 32,
+            fee: 4,
+            state: MeltOnchainState::Pending,
+            expiry: 0,
+        };
+        let melt_quote = quote.clone();
+        client
+            .expect_post_melt_quote_onchain()
+            .returning(move |_, _, _| Ok(vec![melt_quote.clone()]));
+        client.expect_post_melt_onchain().returning(|_, _, _, _| {
+            Ok(PostMeltOnchainResponse {
+                state: MeltOnchainState::Pending,
+                txid: Some("txid".to_owned()),
+                change: vec![],
+            })
+        });
+        // the transaction is never confirmed
+        client
+            .expect_get_melt_quote_onchain()
+            .returning(move |_, _| Ok(quote.clone()));
+        let facade = create_facade(client, &[64])
+            .await?
+            .with_max_wait(Duration::from_millis(20));
+
+        let result = facade.pay_onchain("address", 32).await;
+        assert!(matches!(
+            result,
+            Err(MonexoWalletError::QuoteTimeout(quote, _)) if quote == "quote-1"
+        ));
+        Ok(())
+    }
+}
@@ -8,6 +8,11 @@ pub mod wasm;
 pub struct CrossPlatformHttpClient {
     #[cfg(not(target_arch = "wasm32"))]
     client: ::reqwest::Client,
+    #[cfg(not(target_arch = "wasm32"))]
+    max_retries: u32,
+    /// sent as bearer token, mints configured with an api key reject requests without it
+    #[cfg(not(target_arch = "wasm32"))]
+    api_key: Option<String>,
 }
 
 impl Default for CrossPlatformHttpClient {
@@ -1,61 +1,97 @@
+use std::time::Duration;
+
+use monexo_core::primitives::CashuErrorResponse;
 use reqwest::{
-    header::{HeaderValue, CONTENT_TYPE},
-    Response, StatusCode,
+    header::{HeaderValue, ACCEPT, CONTENT_TYPE},
+    RequestBuilder, Response, StatusCode,
 };
-use serde_json::Value;
 use url::Url;
 
 use crate::error::MonexoWalletError;
 
 use super::CrossPlatformHttpClient;
 
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+/// time a subscription is kept open before the wallet opens it again
+pub const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(300);
+
 impl CrossPlatformHttpClient {
     pub fn new() -> Self {
+        Self::with_config(DEFAULT_TIMEOUT, DEFAULT_MAX_RETRIES)
+    }
+
+    /// Creates a client that aborts requests after `timeout` and retries transient failures up
+    /// to `max_retries` times with exponential backoff
+    pub fn with_config(timeout: Duration, max_retries: u32) -> Self {
         Self {
-            client: reqwest::Client::new(),
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .expect("failed to build http client"),
+            max_retries,
+            api_key: None,
+        }
+    }
+
+    /// Sends the api key as bearer token with every request
+    pub fn with_api_key(self, api_key: Option<String>) -> Self {
+        Self { api_key, ..self }
+    }
+
+    /// Sends the request and retries it with exponential backoff if it could not be delivered.
+    /// `idempotent` requests are also retried on timeouts and gateway errors, the others are not
+    /// because the mint might have processed them already.
+    async fn send_with_retry(
+        &self,
+        request: impl Fn() -> RequestBuilder,
+        idempotent: bool,
+    ) -> Result<Response, MonexoWalletError> {
+        let mut attempt = 0;
+        loop {
+            match self.authorized(request()).send().await {
+                Ok(resp)
+                    if idempotent && attempt < self.max_retries && is_transient(resp.status()) => {}
+                Err(err)
+                    if attempt < self.max_retries
+                        && (err.is_connect() || (idempotent && err.is_timeout())) => {}
+                result => return Ok(result?),
+            }
+
+            tokio::time::sleep(RETRY_BASE_DELAY * 2u32.saturating_pow(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    /// Adds the api key as bearer token if one is set
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
         }
     }
 
     async fn extract_response_data<T: serde::de::DeserializeOwned>(
         response: Response,
     ) -> Result<T, MonexoWalletError> {
-        match response.status() {
-            StatusCode::OK => {
-                let response_text = response.text().await?;
-                match serde_json::from_str::<T>(&response_text) {
-                    Ok(data) => Ok(data),
-                    Err(_) => {
-                        // FIXME cleanup code
-                        let data: Value = serde_json::from_str(&response_text)
-                            .map_err(|_| MonexoWalletError::UnexpectedResponse(response_text))
-                            .expect("invalid value");
-                        let detail = data["detail"].as_str().expect("detail not found");
-                        // let data = serde_json::from_str::<CashuErrorResponse>(&response_text)
-                        //     .map_err(|_| MonexoWalletError::UnexpectedResponse(response_text))
-                        //     .unwrap();
-
-                        Err(MonexoWalletError::MintError(detail.to_owned()))
-                    }
-                }
-            }
-            _ => {
-                let response_text = response.text().await?;
-                let data: Value = serde_json::from_str(&response_text)
-                    .map_err(|_| MonexoWalletError::UnexpectedResponse(response_text))
-                    .expect("invalid value");
-                let detail = data["detail"].as_str().expect("detail not found");
-
-                // FIXME: use the error code to return a proper error
-                Err(MonexoWalletError::MintError(detail.to_owned()))
+        let status = response.status();
+        let response_text = response.text().await?;
+        if status == StatusCode::OK {
+            if let Ok(data) = serde_json::from_str::<T>(&response_text) {
+                return Ok(data);
             }
         }
+        Err(error_from_response(response_text))
     }
 
     pub async fn do_get<T: serde::de::DeserializeOwned>(
         &self,
         url: &Url,
     ) -> Result<T, MonexoWalletError> {
-        let resp = self.client.get(url.clone()).send().await?;
+        let resp = self
+            .send_with_retry(|| self.client.get(url.clone()), true)
+            .await?;
         Self::extract_response_data::<T>(resp).await
     }
 
@@ -64,18 +100,341 @@ impl CrossPlatformHttpClient {
         url: &Url,
         body: &B,
     ) -> Result<T, MonexoWalletError> {
+        self.post(url, body, false).await
+    }
+
+    /// Posts a body that carries an idempotency key, the mint answers a retry with the response
+    /// of the first request so it is also retried on timeouts
+    pub async fn do_post_idempotent<T: serde::de::DeserializeOwned, B: serde::Serialize>(
+        &self,
+        url: &Url,
+        body: &B,
+    ) -> Result<T, MonexoWalletError> {
+        self.post(url, body, true).await
+    }
+
+    async fn post<T: serde::de::DeserializeOwned, B: serde::Serialize>(
+        &self,
+        url: &Url,
+        body: &B,
+        idempotent: bool,
+    ) -> Result<T, MonexoWalletError> {
+        let content_type = HeaderValue::from_str("application/json")?;
+        let body = serde_json::to_string(body)?;
         let resp = self
-            .client
-            .post(url.clone())
-            .header(CONTENT_TYPE, HeaderValue::from_str("application/json")?)
-            .body(serde_json::to_string(body)?)
-            .send()
+            .send_with_retry(
+                || {
+                    self.client
+                        .post(url.clone())
+                        .header(CONTENT_TYPE, content_type.clone())
+                        .body(body.clone())
+                },
+                idempotent,
+            )
             .await?;
         Self::extract_response_data::<T>(resp).await
     }
 
+    /// Reads the server-sent events of the url until the data of one, parsed as `T`, is
+    /// `is_final`. Returns the last event received if the stream ends or times out before.
+    pub async fn do_subscribe<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &Url,
+        is_final: impl Fn(&T) -> bool,
+    ) -> Result<Option<T>, MonexoWalletError> {
+        let mut resp = self
+            .send_with_retry(
+                || {
+                    self.client
+                        .get(url.clone())
+                        .header(ACCEPT, "text/event-stream")
+                        .timeout(SUBSCRIPTION_TIMEOUT)
+                },
+                true,
+            )
+            .await?;
+        if resp.status() != StatusCode::OK {
+            return Err(error_from_response(resp.text().await?));
+        }
+
+        let mut buffer = Vec::new();
+        let mut last = None;
+        loop {
+            match resp.chunk().await {
+                Ok(Some(chunk)) => buffer.extend_from_slice(&chunk),
+                Ok(None) => return Ok(last),
+                Err(err) if err.is_timeout() => return Ok(last),
+                Err(err) => return Err(err.into()),
+            }
+
+            while let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+                let event = String::from_utf8(buffer.drain(..end + 2).collect())?;
+                let Some(data) = event_data(&event) else {
+                    continue;
+                };
+                let data = serde_json::from_str::<T>(&data)?;
+                if is_final(&data) {
+                    return Ok(Some(data));
+                }
+                last = Some(data);
+            }
+        }
+    }
+
     pub async fn get_status(&self, url: &Url) -> Result<u16, MonexoWalletError> {
-        let resp = self.client.get(url.to_owned()).send().await?;
+        let resp = self
+            .authorized(self.client.get(url.to_owned()))
+            .send()
+            .await?;
         Ok(resp.status().as_u16())
     }
 }
+
+/// Returns the data of a server-sent event, None for events without data like keep-alives
+fn event_data(event: &str) -> Option<String> {
+    let lines = event
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| data.strip_prefix(' ').unwrap_or(data))
+        .collect::<Vec<_>>();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Gateway errors are returned by proxies in front of the mint and are worth retrying
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Maps an error body of the mint to a wallet error, bodies that aren't a valid
+/// `CashuErrorResponse` are returned as `UnexpectedResponse`
+fn error_from_response(response_text: String) -> MonexoWalletError {
+    let Ok(CashuErrorResponse { code, detail }) =
+        serde_json::from_str::<CashuErrorResponse>(&response_text)
+    else {
+        return MonexoWalletError::UnexpectedResponse(response_text);
+    };
+
+    match code {
+        CashuErrorResponse::QUOTE_NOT_PAID => {
+            MonexoWalletError::InvoiceNotPaidYet(code.into(), detail)
+        }
+        CashuErrorResponse::TRANSACTION_NOT_BALANCED => MonexoWalletError::NotEnoughTokens,
+        CashuErrorResponse::TOKEN_ALREADY_SPENT => MonexoWalletError::TokenAlreadySpent(detail),
+        CashuErrorResponse::KEYSET_NOT_FOUND => MonexoWalletError::KeysetNotFound(detail),
+        _ => MonexoWalletError::MintError(detail),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use url::Url;
+
+    use super::error_from_response;
+    use crate::{error::MonexoWalletError, http::CrossPlatformHttpClient};
+
+    /// Starts a server that lets the first request time out and answers the following ones
+    fn start_mock_server(delay: Duration) -> anyhow::Result<(Url, Arc<AtomicUsize>)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { return };
+                let first = counter.fetch_add(1, Ordering::SeqCst) == 0;
+                std::thread::spawn(move || {
+                    let mut buf = [0u8; 4096];
+                    let _ = stream.read(&mut buf);
+                    if first {
+                        std::thread::sleep(delay);
+                        return;
+                    }
+                    let body = r#"{"paid":true}"#;
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                });
+            }
+        });
+        Ok((url, requests))
+    }
+
+    /// Starts a server answering every request with the given server-sent events
+    fn start_event_server(events: &'static str) -> anyhow::Result<Url> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { return };
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n{}",
+                    events
+                );
+            }
+        });
+        Ok(url)
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_until_final_event() -> anyhow::Result<()> {
+        let url = start_event_server(
+            ":\n\ndata: {\"state\":\"UNPAID\"}\n\ndata: {\"state\":\"PAID\"}\n\ndata: {\"state\":\"ISSUED\"}\n\n",
+        )?;
+        let client = CrossPlatformHttpClient::new();
+
+        let result = client
+            .do_subscribe::<serde_json::Value>(&url, |event| event["state"] == "PAID")
+            .await?;
+        assert_eq!(Some(serde_json::json!({"state": "PAID"})), result);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_returns_last_event_when_closed() -> anyhow::Result<()> {
+        let url = start_event_server("data: {\"state\":\"UNPAID\"}\n\n")?;
+        let client = CrossPlatformHttpClient::new();
+
+        let result = client
+            .do_subscribe::<serde_json::Value>(&url, |event| event["state"] == "PAID")
+            .await?;
+        assert_eq!(Some(serde_json::json!({"state": "UNPAID"})), result);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_retries_after_timeout() -> anyhow::Result<()> {
+        let (url, requests) = start_mock_server(Duration::from_millis(500))?;
+        let client = CrossPlatformHttpClient::with_config(Duration::from_millis(200), 2);
+
+        let result = client.do_get::<serde_json::Value>(&url).await?;
+        assert_eq!(serde_json::json!({"paid": true}), result);
+        assert_eq!(2, requests.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_not_retried_after_timeout() -> anyhow::Result<()> {
+        let (url, requests) = start_mock_server(Duration::from_millis(500))?;
+        let client = CrossPlatformHttpClient::with_config(Duration::from_millis(200), 2);
+
+        let result = client
+            .do_post::<serde_json::Value, _>(&url, &serde_json::json!({}))
+            .await;
+        assert!(matches!(result, Err(MonexoWalletError::Reqwest(err)) if err.is_timeout()));
+        assert_eq!(1, requests.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_idempotent_retries_after_timeout() -> anyhow::Result<()> {
+        let (url, requests) = start_mock_server(Duration::from_millis(500))?;
+        let client = CrossPlatformHttpClient::with_config(Duration::from_millis(200), 2);
+
+        let result = client
+            .do_post_idempotent::<serde_json::Value, _>(&url, &serde_json::json!({}))
+            .await?;
+        assert_eq!(serde_json::json!({"paid": true}), result);
+        assert_eq!(2, requests.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_api_key_sent_as_bearer_token() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let handle = std::thread::spawn(move || -> anyhow::Result<String> {
+            let (mut stream, _) = listener.accept()?;
+            let mut buf = [0u8; 4096];
+            let read = stream.read(&mut buf)?;
+            let body = "{}";
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )?;
+            Ok(String::from_utf8_lossy(&buf[..read]).to_lowercase())
+        });
+        let client = CrossPlatformHttpClient::new().with_api_key(Some("secret".to_owned()));
+
+        client.do_get::<serde_json::Value>(&url).await?;
+        let request = handle.join().expect("server thread panicked")?;
+        assert!(request.contains("authorization: bearer secret"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_quote_not_paid() {
+        let result =
+            error_from_response(r#"{"code":20001,"detail":"quote is not paid"}"#.to_string());
+        assert!(matches!(
+            result,
+            MonexoWalletError::InvoiceNotPaidYet(20001, detail) if detail == "quote is not paid"
+        ));
+    }
+
+    #[test]
+    fn test_error_token_already_spent() {
+        let result = error_from_response(r#"{"code":11001,"detail":"spent"}"#.to_string());
+        assert!(
+            matches!(result, MonexoWalletError::TokenAlreadySpent(detail) if detail == "spent")
+        );
+    }
+
+    #[test]
+    fn test_error_transaction_not_balanced() {
+        let result =
+            error_from_response(r#"{"code":11002,"detail":"Swap amount mismatch"}"#.to_string());
+        assert!(matches!(result, MonexoWalletError::NotEnoughTokens));
+    }
+
+    #[test]
+    fn test_error_keyset_not_found() {
+        let result = error_from_response(
+            r#"{"code":12001,"detail":"Keyset not found 00ffffffffffffff"}"#.to_string(),
+        );
+        assert!(matches!(
+            result,
+            MonexoWalletError::KeysetNotFound(detail) if detail == "Keyset not found 00ffffffffffffff"
+        ));
+    }
+
+    #[test]
+    fn test_error_unknown_code() {
+        let result = error_from_response(r#"{"code":0,"detail":"Invalid amount"}"#.to_string());
+        assert!(
+            matches!(result, MonexoWalletError::MintError(detail) if detail == "Invalid amount")
+        );
+    }
+
+    #[test]
+    fn test_error_without_detail() {
+        let body = r#"{"code":20001}"#.to_string();
+        let result = error_from_response(body.clone());
+        assert!(matches!(result, MonexoWalletError::UnexpectedResponse(text) if text == body));
+    }
+
+    #[test]
+    fn test_error_not_json() {
+        let body = "<html>502 Bad Gateway</html>".to_string();
+        let result = error_from_response(body.clone());
+        assert!(matches!(result, MonexoWalletError::UnexpectedResponse(text) if text == body));
+    }
+}
@@ -1,6 +1,8 @@
 pub mod client;
 pub mod config_path;
 pub mod error;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod facade;
 pub mod http;
 pub mod localstore;
 pub mod secret;
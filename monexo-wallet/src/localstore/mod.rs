@@ -1,103 +1,344 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
 use async_trait::async_trait;
-use monexo_core::{keyset::KeysetId, proof::Proofs};
+use monexo_core::{
+    amount::{Amount, SplitAmount},
+    blind::BlindingFactor,
+    keyset::KeysetId,
+    primitives::{CurrencyUnit, MintInfoResponse},
+    proof::Proofs,
+};
 use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use url::Url;
 
 use crate::error::MonexoWalletError;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub mod sqlite;
 
-#[derive(Debug, Clone)]
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WalletKeyset {
     /// primary key
     pub id: Option<u64>,
+    /// mint the keyset belongs to, None for keysets stored before the wallet tracked mints
+    pub mint_url: Option<Url>,
     pub keyset_id: KeysetId,
-    // pub currency_unit: CurrencyUnit,
+    pub currency_unit: CurrencyUnit,
     /// last index used for deriving keys from the master key
     pub last_index: u64,
     pub public_keys: HashMap<u64, PublicKey>,
     pub active: bool,
+    /// fee in parts per thousand of the unit the mint charges for every input of a swap
+    #[serde(default)]
+    pub input_fee_ppk: u64,
+}
+
+/// Output of a swap that has been sent to the mint, but whose proof hasn't been stored yet
+#[derive(Debug, Clone)]
+pub struct PendingOutput {
+    pub mint_url: Url,
+    pub keyset_id: String,
+    pub amount: u64,
+    pub secret: String,
+    pub blinding_factor: BlindingFactor,
+}
+
+/// Mint quote that has been created, but whose tokens haven't been minted yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletMintQuote {
+    pub quote: String,
+    pub mint_url: Url,
+    /// amount that has to be paid to the mint
+    pub amount: u64,
+    /// fee the mint deducts from the amount when minting
+    pub fee: u64,
+}
+
+/// Kind of a history entry, mints and receives add to the balance, melts and sends remove from it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryKind {
+    Mint,
+    Melt,
+    Send,
+    Receive,
+}
+
+impl HistoryKind {
+    /// Returns true if the transaction added to the balance
+    pub const fn is_incoming(&self) -> bool {
+        matches!(self, Self::Mint | Self::Receive)
+    }
+}
+
+impl Display for HistoryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mint => write!(f, "mint"),
+            Self::Melt => write!(f, "melt"),
+            Self::Send => write!(f, "send"),
+            Self::Receive => write!(f, "receive"),
+        }
+    }
+}
+
+impl FromStr for HistoryKind {
+    type Err = MonexoWalletError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mint" => Ok(Self::Mint),
+            "melt" => Ok(Self::Melt),
+            "send" => Ok(Self::Send),
+            "receive" => Ok(Self::Receive),
+            _ => Err(MonexoWalletError::InvalidHistoryKind(s.to_owned())),
+        }
+    }
+}
+
+/// Entry of the wallet's transaction history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub kind: HistoryKind,
+    pub mint_url: Url,
+    pub amount: u64,
+    pub unit: CurrencyUnit,
+    pub memo: Option<String>,
+    /// unix time in seconds
+    pub timestamp: u64,
+}
+
+impl HistoryEntry {
+    /// Creates an entry without memo at the current time
+    pub fn new(kind: HistoryKind, mint_url: &Url, amount: u64, unit: CurrencyUnit) -> Self {
+        Self {
+            kind,
+            mint_url: mint_url.to_owned(),
+            amount,
+            unit,
+            memo: None,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn with_memo(self, memo: Option<String>) -> Self {
+        Self { memo, ..self }
+    }
 }
 
-impl WalletKeysetFilter for Vec<WalletKeyset> {
-    fn get_active(&self) -> Option<&WalletKeyset> {
-        self.iter().find(|k| k.active)
+impl WalletKeysetFilter for [WalletKeyset] {
+    fn get_active(&self) -> Result<&WalletKeyset, MonexoWalletError> {
+        self.iter()
+            .find(|k| k.active)
+            .ok_or(MonexoWalletError::NoActiveKeyset)
+    }
+
+    fn get_active_for_currency(
+        &self,
+        unit: &CurrencyUnit,
+    ) -> Result<&WalletKeyset, MonexoWalletError> {
+        self.iter()
+            .find(|k| k.active && k.currency_unit == *unit)
+            .ok_or(MonexoWalletError::NoActiveKeyset)
     }
 }
 
 pub trait WalletKeysetFilter {
-    fn get_active(&self) -> Option<&WalletKeyset>;
+    /// Returns the first active keyset, fails if the mint deactivated all keysets
+    fn get_active(&self) -> Result<&WalletKeyset, MonexoWalletError>;
+
+    /// Returns the active keyset of the unit, fails if there is none
+    fn get_active_for_currency(
+        &self,
+        unit: &CurrencyUnit,
+    ) -> Result<&WalletKeyset, MonexoWalletError>;
+}
+
+impl KeysetSplit for Amount {
+    fn split_for_keyset(&self, keyset: &WalletKeyset) -> SplitAmount {
+        self.split_with_max(keyset.max_denomination())
+    }
+}
+
+pub trait KeysetSplit {
+    /// Splits an amount into denominations the keyset has keys for
+    fn split_for_keyset(&self, keyset: &WalletKeyset) -> SplitAmount;
 }
 
 impl WalletKeyset {
     pub fn new(
         keyset_id: &KeysetId,
+        currency_unit: CurrencyUnit,
         last_index: u64,
         public_keys: HashMap<u64, PublicKey>,
         active: bool,
     ) -> Self {
         Self {
             id: None,
+            mint_url: None,
             keyset_id: keyset_id.to_owned(),
+            currency_unit,
             last_index,
             public_keys,
             active,
+            input_fee_ppk: 0,
         }
     }
+
+    /// Returns true if the keyset is of the mint. A keyset stored before the wallet tracked mints
+    /// is of no mint until its mint is added again and lists it.
+    pub fn belongs_to(&self, mint_url: &Url) -> bool {
+        self.mint_url.as_ref() == Some(mint_url)
+    }
+
+    pub fn with_mint_url(self, mint_url: &Url) -> Self {
+        Self {
+            mint_url: Some(mint_url.to_owned()),
+            ..self
+        }
+    }
+
+    pub fn with_input_fee_ppk(self, input_fee_ppk: u64) -> Self {
+        Self {
+            input_fee_ppk,
+            ..self
+        }
+    }
+
+    /// Returns the largest amount the keyset has a key for or 0 if it has no keys
+    pub fn max_denomination(&self) -> u64 {
+        self.public_keys.keys().max().copied().unwrap_or_default()
+    }
+}
+
+/// A transaction of a [`LocalStore`]. Its changes are visible to other transactions once it is
+/// committed and discarded if it is dropped.
+#[async_trait(?Send)]
+pub trait LocalStoreTx {
+    async fn commit(self) -> Result<(), MonexoWalletError>;
 }
 
 #[cfg(not(target_arch = "wasm32"))]
+#[async_trait(?Send)]
+impl<DB: sqlx::Database> LocalStoreTx for sqlx::Transaction<'static, DB> {
+    async fn commit(self) -> Result<(), MonexoWalletError> {
+        Ok(sqlx::Transaction::commit(self).await?)
+    }
+}
+
 #[async_trait(?Send)]
 pub trait LocalStore {
-    type DB: sqlx::Database;
-    async fn begin_tx(&self) -> Result<sqlx::Transaction<Self::DB>, MonexoWalletError>;
+    type Tx: LocalStoreTx;
+    async fn begin_tx(&self) -> Result<Self::Tx, MonexoWalletError>;
 
     async fn delete_proofs(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         proofs: &Proofs,
     ) -> Result<(), MonexoWalletError>;
 
-    async fn add_proofs(
-        &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-        proofs: &Proofs,
-    ) -> Result<(), MonexoWalletError>;
+    async fn add_proofs(&self, tx: &mut Self::Tx, proofs: &Proofs)
+        -> Result<(), MonexoWalletError>;
+
+    async fn get_proofs(&self, tx: &mut Self::Tx) -> Result<Proofs, MonexoWalletError>;
 
-    async fn get_proofs(
+    /// Returns the proofs of the mint's keysets, see [`WalletKeyset::belongs_to`]
+    async fn get_proofs_by_mint(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
     ) -> Result<Proofs, MonexoWalletError>;
 
-    async fn get_keysets(
-        &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-    ) -> Result<Vec<WalletKeyset>, MonexoWalletError>;
+    async fn get_keysets(&self, tx: &mut Self::Tx) -> Result<Vec<WalletKeyset>, MonexoWalletError>;
 
     async fn upsert_keyset(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         keyset: &WalletKeyset,
     ) -> Result<(), MonexoWalletError>;
 
     async fn update_keyset_last_index(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         keyset: &WalletKeyset,
     ) -> Result<(), MonexoWalletError>;
 
-    async fn add_seed(
+    /// Adds the mint to the known mints, does nothing if it is known already
+    async fn add_mint(&self, tx: &mut Self::Tx, mint_url: &Url) -> Result<(), MonexoWalletError>;
+
+    /// Returns the known mints, oldest mint first
+    async fn get_mints(&self, tx: &mut Self::Tx) -> Result<Vec<Url>, MonexoWalletError>;
+
+    async fn add_seed(&self, tx: &mut Self::Tx, seed_words: &str) -> Result<(), MonexoWalletError>;
+
+    async fn get_seed(&self, tx: &mut Self::Tx) -> Result<Option<String>, MonexoWalletError>;
+
+    async fn upsert_mint_info(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+        info: &MintInfoResponse,
+    ) -> Result<(), MonexoWalletError>;
+
+    async fn get_mint_info(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+    ) -> Result<Option<MintInfoResponse>, MonexoWalletError>;
+
+    async fn add_pending_outputs(
+        &self,
+        tx: &mut Self::Tx,
+        outputs: &[PendingOutput],
+    ) -> Result<(), MonexoWalletError>;
+
+    async fn get_pending_outputs(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<PendingOutput>, MonexoWalletError>;
+
+    async fn delete_pending_outputs(
+        &self,
+        tx: &mut Self::Tx,
+        secrets: &[String],
+    ) -> Result<(), MonexoWalletError>;
+
+    async fn add_mint_quote(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-        seed_words: &str,
+        tx: &mut Self::Tx,
+        quote: &WalletMintQuote,
     ) -> Result<(), MonexoWalletError>;
 
-    async fn get_seed(
+    /// Returns the quotes that haven't been minted yet, oldest quote first
+    async fn get_pending_mint_quotes(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-    ) -> Result<Option<String>, MonexoWalletError>;
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<WalletMintQuote>, MonexoWalletError>;
+
+    async fn delete_mint_quote(
+        &self,
+        tx: &mut Self::Tx,
+        quote: &str,
+    ) -> Result<(), MonexoWalletError>;
+
+    async fn add_transaction(
+        &self,
+        tx: &mut Self::Tx,
+        entry: &HistoryEntry,
+    ) -> Result<(), MonexoWalletError>;
+
+    /// Returns the history, latest entry first
+    async fn list_transactions(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<HistoryEntry>, MonexoWalletError>;
 }
 
 #[cfg(test)]
@@ -105,7 +346,72 @@ mod tests {
     use rand::thread_rng;
     use std::collections::HashMap;
 
+    use monexo_core::{keyset::KeysetId, primitives::CurrencyUnit};
     use secp256k1::PublicKey;
+    use url::Url;
+
+    use super::{WalletKeyset, WalletKeysetFilter};
+    use crate::error::MonexoWalletError;
+
+    fn create_keyset(keyset_id: &str, unit: CurrencyUnit, active: bool) -> WalletKeyset {
+        WalletKeyset::new(
+            &KeysetId::new(keyset_id).expect("invalid keyset id"),
+            unit,
+            0,
+            HashMap::new(),
+            active,
+        )
+    }
+
+    #[test]
+    fn test_get_active_empty() {
+        let keysets: Vec<WalletKeyset> = vec![];
+        assert!(matches!(
+            keysets.get_active(),
+            Err(MonexoWalletError::NoActiveKeyset)
+        ));
+
+        let keysets = [create_keyset("00f4683f9caf8793", CurrencyUnit::Usd, false)];
+        assert!(matches!(
+            keysets.get_active(),
+            Err(MonexoWalletError::NoActiveKeyset)
+        ));
+    }
+
+    #[test]
+    fn test_get_active_for_currency() -> anyhow::Result<()> {
+        let keysets = [
+            create_keyset("00f4683f9caf8793", CurrencyUnit::Usd, false),
+            create_keyset("00ffd48b8f5ecf80", CurrencyUnit::Ugx, true),
+            create_keyset("00456a94ab4e1c46", CurrencyUnit::Usd, true),
+        ];
+
+        let usd_keyset = keysets.get_active_for_currency(&CurrencyUnit::Usd)?;
+        assert_eq!(KeysetId::new("00456a94ab4e1c46")?, usd_keyset.keyset_id);
+        let ugx_keyset = keysets.get_active_for_currency(&CurrencyUnit::Ugx)?;
+        assert_eq!(KeysetId::new("00ffd48b8f5ecf80")?, ugx_keyset.keyset_id);
+        assert!(matches!(
+            keysets.get_active_for_currency(&CurrencyUnit::Sat),
+            Err(MonexoWalletError::NoActiveKeyset)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wallet_keyset_json() -> anyhow::Result<()> {
+        let secp = secp256k1::Secp256k1::new();
+        let secret_key = secp256k1::SecretKey::new(&mut thread_rng());
+        let keyset = WalletKeyset {
+            id: Some(1),
+            public_keys: HashMap::from([(1, PublicKey::from_secret_key(&secp, &secret_key))]),
+            ..create_keyset("00f4683f9caf8793", CurrencyUnit::Usd, true)
+        }
+        .with_mint_url(&Url::parse("http://127.0.0.1:3338")?);
+
+        let json = serde_json::to_string(&keyset)?;
+        assert_eq!(keyset, serde_json::from_str::<WalletKeyset>(&json)?);
+        Ok(())
+    }
 
     fn generate_test_map() -> HashMap<u32, PublicKey> {
         let mut map = HashMap::new();
@@ -1,15 +1,16 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
 
 use monexo_core::{
     keyset::KeysetId,
+    primitives::{CurrencyUnit, MintInfoResponse},
     proof::{Proof, Proofs},
 };
 use secp256k1::PublicKey;
-use sqlx::sqlite::SqliteError;
+use url::Url;
 
 use crate::error::MonexoWalletError;
 
-use super::{LocalStore, WalletKeyset};
+use super::{HistoryEntry, LocalStore, PendingOutput, WalletKeyset, WalletMintQuote};
 use async_trait::async_trait;
 
 #[derive(Clone, Debug)]
@@ -19,15 +20,15 @@ pub struct SqliteLocalStore {
 
 #[async_trait(?Send)]
 impl LocalStore for SqliteLocalStore {
-    type DB = sqlx::Sqlite;
+    type Tx = sqlx::Transaction<'static, sqlx::Sqlite>;
 
-    async fn begin_tx(&self) -> Result<sqlx::Transaction<Self::DB>, MonexoWalletError> {
+    async fn begin_tx(&self) -> Result<Self::Tx, MonexoWalletError> {
         Ok(self.pool.begin().await?)
     }
 
     async fn delete_proofs(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         proofs: &Proofs,
     ) -> Result<(), MonexoWalletError> {
         let proof_secrets = proofs
@@ -55,7 +56,7 @@ impl LocalStore for SqliteLocalStore {
 
     async fn add_proofs(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         proofs: &Proofs,
     ) -> Result<(), MonexoWalletError> {
         for proof in proofs.proofs() {
@@ -70,10 +71,7 @@ impl LocalStore for SqliteLocalStore {
         Ok(())
     }
 
-    async fn get_proofs(
-        &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-    ) -> Result<Proofs, MonexoWalletError> {
+    async fn get_proofs(&self, tx: &mut Self::Tx) -> Result<Proofs, MonexoWalletError> {
         let rows = sqlx::query!("SELECT keyset_id, amount, C, secret FROM proofs;")
             .fetch_all(&mut **tx)
             .await?;
@@ -87,28 +85,53 @@ impl LocalStore for SqliteLocalStore {
                 c: row.C.parse().expect("Invalid Pubkey"),
                 secret: row.secret,
                 script: None,
+                dleq: None,
             })
             .collect::<Vec<Proof>>()
             .into())
     }
 
-    async fn get_keysets(
+    async fn get_proofs_by_mint(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-    ) -> Result<Vec<WalletKeyset>, MonexoWalletError> {
-        let rows =
-            sqlx::query!("SELECT id, keyset_id, active, last_index, public_keys FROM keysets;")
-                .fetch_all(&mut **tx)
-                .await?;
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+    ) -> Result<Proofs, MonexoWalletError> {
+        let mint_url = mint_url.as_str();
+        let rows = sqlx::query!(
+            "SELECT proofs.keyset_id, proofs.amount, proofs.C, proofs.secret FROM proofs JOIN keysets ON proofs.keyset_id = keysets.keyset_id WHERE keysets.mint_url = $1;",
+            mint_url
+        )
+        .fetch_all(&mut **tx)
+        .await?;
 
         Ok(rows
-            .iter()
+            .into_iter()
+            .map(|row| Proof {
+                keyset_id: row.keyset_id,
+                amount: row.amount as u64,
+                c: row.C.parse().expect("Invalid Pubkey"),
+                secret: row.secret,
+                script: None,
+                dleq: None,
+            })
+            .collect::<Vec<Proof>>()
+            .into())
+    }
+
+    async fn get_keysets(&self, tx: &mut Self::Tx) -> Result<Vec<WalletKeyset>, MonexoWalletError> {
+        let rows = sqlx::query!(
+            "SELECT id, keyset_id, currency_unit, active, last_index, public_keys, mint_url, input_fee_ppk FROM keysets;"
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.iter()
             .map(|row| {
                 let id: i64 = row.id;
-                // let mint_url: Url = Url::parse(&row.mint_url).expect("invalid URL in localstore");
+                let mint_url = row.mint_url.as_deref().map(Url::parse).transpose()?;
                 let keyset_id: KeysetId =
                     KeysetId::new(&row.keyset_id).expect("invalid keyset_id in localstore");
-                // let currency_unit: String = row.currency_unit.clone();
+                let Ok(currency_unit) = CurrencyUnit::from_str(&row.currency_unit);
                 let active: bool = row.active;
                 let last_index: i64 = row.last_index;
                 let public_keys: String = row.public_keys.clone();
@@ -116,31 +139,33 @@ impl LocalStore for SqliteLocalStore {
                     serde_json::from_str(&public_keys).expect("invalid json in localstore");
                 Ok(WalletKeyset {
                     id: Some(id as u64),
-                    // mint_url,
+                    mint_url,
                     keyset_id,
-                    // currency_unit: currency_unit.into(),
+                    currency_unit,
                     active,
                     last_index: last_index as u64,
                     public_keys,
+                    input_fee_ppk: row.input_fee_ppk as u64,
                 })
             })
-            .collect::<Result<Vec<WalletKeyset>, SqliteError>>()?)
+            .collect::<Result<Vec<WalletKeyset>, MonexoWalletError>>()
     }
 
     async fn upsert_keyset(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         keyset: &WalletKeyset,
     ) -> Result<(), MonexoWalletError> {
         let keyset_id = keyset.keyset_id.to_string();
-        // let mint_url = keyset.mint_url.as_str();
-        // let currency_unit = keyset.currency_unit.to_string();
+        let mint_url = keyset.mint_url.as_ref().map(Url::as_str);
+        let currency_unit = keyset.currency_unit.to_string();
         let last_index = keyset.last_index as i64;
         let public_keys = serde_json::to_string(&keyset.public_keys)?;
+        let input_fee_ppk = keyset.input_fee_ppk as i64;
         sqlx::query!(
-            r#"INSERT INTO keysets (keyset_id, last_index, public_keys, active) VALUES ($1, $2, $3, $4)
-            ON CONFLICT(keyset_id) DO UPDATE SET public_keys = $3, active = $4;
-            "#,keyset_id, last_index, public_keys, keyset.active)
+            r#"INSERT INTO keysets (keyset_id, currency_unit, last_index, public_keys, active, mint_url, input_fee_ppk) VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT(keyset_id) DO UPDATE SET currency_unit = $2, public_keys = $4, active = $5, mint_url = COALESCE($6, mint_url), input_fee_ppk = $7;
+            "#,keyset_id, currency_unit, last_index, public_keys, keyset.active, mint_url, input_fee_ppk)
         .execute(&mut **tx)
         .await?;
         Ok(())
@@ -148,7 +173,7 @@ impl LocalStore for SqliteLocalStore {
 
     async fn update_keyset_last_index(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         keyset: &WalletKeyset,
     ) -> Result<(), MonexoWalletError> {
         let id = match keyset.id {
@@ -167,21 +192,37 @@ impl LocalStore for SqliteLocalStore {
         Ok(())
     }
 
-    async fn add_seed(
-        &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-        seed_words: &str,
-    ) -> Result<(), MonexoWalletError> {
+    async fn add_mint(&self, tx: &mut Self::Tx, mint_url: &Url) -> Result<(), MonexoWalletError> {
+        let mint_url = mint_url.as_str();
+        sqlx::query!(
+            r#"INSERT INTO mints (mint_url, time_created) VALUES ($1, CURRENT_TIMESTAMP)
+            ON CONFLICT(mint_url) DO NOTHING;
+            "#,
+            mint_url
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_mints(&self, tx: &mut Self::Tx) -> Result<Vec<Url>, MonexoWalletError> {
+        let rows = sqlx::query!("SELECT mint_url FROM mints ORDER BY rowid;")
+            .fetch_all(&mut **tx)
+            .await?;
+
+        rows.iter()
+            .map(|row| Ok(Url::parse(&row.mint_url)?))
+            .collect()
+    }
+
+    async fn add_seed(&self, tx: &mut Self::Tx, seed_words: &str) -> Result<(), MonexoWalletError> {
         sqlx::query!("INSERT INTO seed (seed_words) VALUES ($1);", seed_words)
             .execute(&mut **tx)
             .await?;
         Ok(())
     }
 
-    async fn get_seed(
-        &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-    ) -> Result<Option<String>, MonexoWalletError> {
+    async fn get_seed(&self, tx: &mut Self::Tx) -> Result<Option<String>, MonexoWalletError> {
         let row = sqlx::query!("SELECT seed_words FROM seed;")
             .fetch_all(&mut **tx)
             .await?;
@@ -191,6 +232,200 @@ impl LocalStore for SqliteLocalStore {
             _ => Err(MonexoWalletError::MultipleSeeds),
         }
     }
+
+    async fn upsert_mint_info(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+        info: &MintInfoResponse,
+    ) -> Result<(), MonexoWalletError> {
+        let mint_url = mint_url.as_str();
+        let info = serde_json::to_string(info)?;
+        sqlx::query!(
+            r#"INSERT INTO mint_info (mint_url, info, time_updated) VALUES ($1, $2, CURRENT_TIMESTAMP)
+            ON CONFLICT(mint_url) DO UPDATE SET info = $2, time_updated = CURRENT_TIMESTAMP;
+            "#,
+            mint_url,
+            info
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_mint_info(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+    ) -> Result<Option<MintInfoResponse>, MonexoWalletError> {
+        let mint_url = mint_url.as_str();
+        let row = sqlx::query!("SELECT info FROM mint_info WHERE mint_url = $1;", mint_url)
+            .fetch_optional(&mut **tx)
+            .await?;
+
+        Ok(row.map(|row| serde_json::from_str(&row.info)).transpose()?)
+    }
+
+    async fn add_pending_outputs(
+        &self,
+        tx: &mut Self::Tx,
+        outputs: &[PendingOutput],
+    ) -> Result<(), MonexoWalletError> {
+        for output in outputs {
+            let mint_url = output.mint_url.as_str();
+            let amount = output.amount as i64;
+            let blinding_factor = output.blinding_factor.as_hex();
+            sqlx::query!(
+                "INSERT INTO pending_outputs (secret, mint_url, keyset_id, amount, blinding_factor, time_created) VALUES ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP);",
+                output.secret,
+                mint_url,
+                output.keyset_id,
+                amount,
+                blinding_factor
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_pending_outputs(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<PendingOutput>, MonexoWalletError> {
+        let rows = sqlx::query!(
+            "SELECT secret, mint_url, keyset_id, amount, blinding_factor FROM pending_outputs;"
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(PendingOutput {
+                    mint_url: Url::parse(&row.mint_url)?,
+                    keyset_id: row.keyset_id,
+                    amount: row.amount as u64,
+                    secret: row.secret,
+                    blinding_factor: row.blinding_factor.as_str().try_into()?,
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_pending_outputs(
+        &self,
+        tx: &mut Self::Tx,
+        secrets: &[String],
+    ) -> Result<(), MonexoWalletError> {
+        for secret in secrets {
+            sqlx::query!("DELETE FROM pending_outputs WHERE secret = $1;", secret)
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn add_mint_quote(
+        &self,
+        tx: &mut Self::Tx,
+        quote: &WalletMintQuote,
+    ) -> Result<(), MonexoWalletError> {
+        let mint_url = quote.mint_url.as_str();
+        let amount = quote.amount as i64;
+        let fee = quote.fee as i64;
+        sqlx::query!(
+            "INSERT INTO mint_quotes (quote, mint_url, amount, fee, time_created) VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP);",
+            quote.quote,
+            mint_url,
+            amount,
+            fee
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_pending_mint_quotes(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<WalletMintQuote>, MonexoWalletError> {
+        let rows = sqlx::query!(
+            "SELECT quote, mint_url, amount, fee FROM mint_quotes ORDER BY time_created;"
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(WalletMintQuote {
+                    quote: row.quote,
+                    mint_url: Url::parse(&row.mint_url)?,
+                    amount: row.amount as u64,
+                    fee: row.fee as u64,
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_mint_quote(
+        &self,
+        tx: &mut Self::Tx,
+        quote: &str,
+    ) -> Result<(), MonexoWalletError> {
+        sqlx::query!("DELETE FROM mint_quotes WHERE quote = $1;", quote)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_transaction(
+        &self,
+        tx: &mut Self::Tx,
+        entry: &HistoryEntry,
+    ) -> Result<(), MonexoWalletError> {
+        let kind = entry.kind.to_string();
+        let mint_url = entry.mint_url.as_str();
+        let amount = entry.amount as i64;
+        let unit = entry.unit.to_string();
+        let timestamp = entry.timestamp as i64;
+        sqlx::query!(
+            "INSERT INTO transactions (kind, mint_url, amount, unit, memo, timestamp) VALUES ($1, $2, $3, $4, $5, $6);",
+            kind,
+            mint_url,
+            amount,
+            unit,
+            entry.memo,
+            timestamp
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_transactions(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<HistoryEntry>, MonexoWalletError> {
+        let rows = sqlx::query!(
+            "SELECT kind, mint_url, amount, unit, memo, timestamp FROM transactions ORDER BY id DESC;"
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let Ok(unit) = CurrencyUnit::from_str(&row.unit);
+                Ok(HistoryEntry {
+                    kind: row.kind.parse()?,
+                    mint_url: Url::parse(&row.mint_url)?,
+                    amount: row.amount as u64,
+                    unit,
+                    memo: row.memo,
+                    timestamp: row.timestamp as u64,
+                })
+            })
+            .collect()
+    }
 }
 
 impl SqliteLocalStore {
@@ -221,8 +456,15 @@ impl SqliteLocalStore {
 #[cfg(test)]
 mod tests {
     use super::SqliteLocalStore;
-    use crate::localstore::LocalStore;
-    use monexo_core::{fixture::read_fixture, token::TokenV3};
+    use crate::localstore::{HistoryEntry, HistoryKind, LocalStore, WalletKeyset, WalletMintQuote};
+    use monexo_core::{
+        fixture::read_fixture,
+        keyset::{KeysetId, MintKeyset},
+        primitives::CurrencyUnit,
+        proof::Proof,
+        token::TokenV3,
+    };
+    use url::Url;
 
     #[tokio::test]
     async fn test_add_proofs() -> anyhow::Result<()> {
@@ -303,4 +545,177 @@ mod tests {
         tx.commit().await?;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_keysets() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+
+        let keys = MintKeyset::new("mykey", "0/0/0/1");
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&keys.keyset_id)?,
+            CurrencyUnit::Ugx,
+            0,
+            keys.public_keys.clone(),
+            true,
+        )
+        .with_input_fee_ppk(100);
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+
+        let loaded_keysets = localstore.get_keysets(&mut tx).await?;
+        assert_eq!(1, loaded_keysets.len());
+        let mut loaded_keyset = loaded_keysets[0].clone();
+        assert!(loaded_keyset.id.is_some());
+        assert_eq!(
+            WalletKeyset {
+                id: loaded_keyset.id,
+                ..keyset.clone()
+            },
+            loaded_keyset
+        );
+
+        loaded_keyset.last_index = 42;
+        localstore
+            .update_keyset_last_index(&mut tx, &loaded_keyset)
+            .await?;
+        // upserting an existing keyset keeps its index
+        localstore
+            .upsert_keyset(
+                &mut tx,
+                &WalletKeyset {
+                    active: false,
+                    ..keyset
+                },
+            )
+            .await?;
+
+        let reloaded_keysets = localstore.get_keysets(&mut tx).await?;
+        tx.commit().await?;
+        assert_eq!(
+            vec![WalletKeyset {
+                active: false,
+                ..loaded_keyset
+            }],
+            reloaded_keysets
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mints() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        localstore.add_mint(&mut tx, &mint_url).await?;
+        localstore.add_mint(&mut tx, &mint_url).await?;
+        assert_eq!(vec![mint_url.clone()], localstore.get_mints(&mut tx).await?);
+
+        // upserting a keyset without mint keeps the stored mint
+        let keys = MintKeyset::new("mykey", "0/0/0/0");
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            0,
+            keys.public_keys.clone(),
+            true,
+        );
+        localstore
+            .upsert_keyset(&mut tx, &keyset.clone().with_mint_url(&mint_url))
+            .await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+
+        let keysets = localstore.get_keysets(&mut tx).await?;
+        tx.commit().await?;
+        assert_eq!(Some(mint_url), keysets[0].mint_url);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proofs_by_mint() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+
+        // the proofs of a keyset without mint belong to no mint
+        for (derivation_path, keyset_mint_url) in [("0/0/0/0", Some(&mint_url)), ("0/0/0/1", None)]
+        {
+            let keys = MintKeyset::new("mykey", derivation_path);
+            let keyset = WalletKeyset::new(
+                &KeysetId::new(&keys.keyset_id)?,
+                CurrencyUnit::Usd,
+                0,
+                keys.public_keys.clone(),
+                true,
+            );
+            let keyset = match keyset_mint_url {
+                Some(keyset_mint_url) => keyset.with_mint_url(keyset_mint_url),
+                None => keyset,
+            };
+            localstore.upsert_keyset(&mut tx, &keyset).await?;
+            localstore
+                .add_proofs(
+                    &mut tx,
+                    &Proof::new(
+                        8,
+                        format!("secret{derivation_path}"),
+                        keys.public_keys[&1],
+                        keys.keyset_id.clone(),
+                    )
+                    .into(),
+                )
+                .await?;
+        }
+
+        let proofs = localstore.get_proofs_by_mint(&mut tx, &mint_url).await?;
+        tx.commit().await?;
+        assert_eq!(1, proofs.len());
+        assert_eq!("secret0/0/0/0", proofs.proofs()[0].secret);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_quotes() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+
+        let quote = WalletMintQuote {
+            quote: "quote-1".to_owned(),
+            mint_url: Url::parse("http://127.0.0.1:3338")?,
+            amount: 10_000_000,
+            fee: 1_000,
+        };
+        localstore.add_mint_quote(&mut tx, &quote).await?;
+        assert_eq!(
+            vec![quote.clone()],
+            localstore.get_pending_mint_quotes(&mut tx).await?
+        );
+
+        localstore.delete_mint_quote(&mut tx, &quote.quote).await?;
+        assert!(localstore
+            .get_pending_mint_quotes(&mut tx)
+            .await?
+            .is_empty());
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_transactions() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let mint = HistoryEntry::new(HistoryKind::Mint, &mint_url, 64, CurrencyUnit::Usd);
+        let send = HistoryEntry::new(HistoryKind::Send, &mint_url, 20, CurrencyUnit::Ugx)
+            .with_memo(Some("coffee".to_owned()));
+        localstore.add_transaction(&mut tx, &mint).await?;
+        localstore.add_transaction(&mut tx, &send).await?;
+
+        assert_eq!(
+            vec![send, mint],
+            localstore.list_transactions(&mut tx).await?
+        );
+        tx.commit().await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,511 @@
+use std::{cell::RefCell, rc::Rc};
+
+use async_trait::async_trait;
+use monexo_core::{
+    primitives::MintInfoResponse,
+    proof::{Proof, Proofs},
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::MonexoWalletError;
+
+use super::{HistoryEntry, LocalStore, LocalStoreTx, PendingOutput, WalletKeyset, WalletMintQuote};
+
+/// Everything the wallet stores, persisted as one json document
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct StoreState {
+    proofs: Vec<Proof>,
+    keysets: Vec<WalletKeyset>,
+    /// oldest mint first
+    mints: Vec<Url>,
+    seed: Option<String>,
+    mint_info: Vec<(Url, MintInfoResponse)>,
+    pending_outputs: Vec<StoredPendingOutput>,
+    /// oldest quote first
+    mint_quotes: Vec<WalletMintQuote>,
+    /// oldest entry first
+    transactions: Vec<HistoryEntry>,
+}
+
+impl StoreState {
+    /// Same semantics as the sqlite store: the last index and a known mint are kept
+    fn upsert_keyset(&mut self, keyset: &WalletKeyset) {
+        if let Some(stored) = self
+            .keysets
+            .iter_mut()
+            .find(|stored| stored.keyset_id == keyset.keyset_id)
+        {
+            stored.currency_unit = keyset.currency_unit.clone();
+            stored.public_keys = keyset.public_keys.clone();
+            stored.active = keyset.active;
+            stored.input_fee_ppk = keyset.input_fee_ppk;
+            if keyset.mint_url.is_some() {
+                stored.mint_url = keyset.mint_url.clone();
+            }
+            return;
+        }
+
+        let id = self
+            .keysets
+            .iter()
+            .filter_map(|stored| stored.id)
+            .max()
+            .unwrap_or_default()
+            + 1;
+        self.keysets.push(WalletKeyset {
+            id: Some(id),
+            ..keyset.clone()
+        });
+    }
+}
+
+/// A [`PendingOutput`] with its blinding factor as hex
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredPendingOutput {
+    mint_url: Url,
+    keyset_id: String,
+    amount: u64,
+    secret: String,
+    blinding_factor: String,
+}
+
+impl From<&PendingOutput> for StoredPendingOutput {
+    fn from(output: &PendingOutput) -> Self {
+        Self {
+            mint_url: output.mint_url.clone(),
+            keyset_id: output.keyset_id.clone(),
+            amount: output.amount,
+            secret: output.secret.clone(),
+            blinding_factor: output.blinding_factor.as_hex(),
+        }
+    }
+}
+
+impl TryFrom<&StoredPendingOutput> for PendingOutput {
+    type Error = MonexoWalletError;
+
+    fn try_from(output: &StoredPendingOutput) -> Result<Self, Self::Error> {
+        Ok(Self {
+            mint_url: output.mint_url.clone(),
+            keyset_id: output.keyset_id.clone(),
+            amount: output.amount,
+            secret: output.secret.clone(),
+            blinding_factor: output.blinding_factor.as_str().try_into()?,
+        })
+    }
+}
+
+/// Localstore for browsers. Keeps the wallet in memory and writes it to `localStorage` on every
+/// commit, if created with [`WasmLocalStore::with_local_storage`].
+#[derive(Clone, Debug)]
+pub struct WasmLocalStore {
+    /// key of the wallet in `localStorage`, None if the wallet is only kept in memory
+    storage_key: Option<String>,
+    state: Rc<RefCell<StoreState>>,
+}
+
+type Change = Box<dyn Fn(&mut StoreState)>;
+
+/// Works on a copy of the store's state and records its changes. The commit applies the changes
+/// to the state the store has then, so transactions committed in the meantime aren't lost.
+/// Dropping the transaction discards its changes.
+pub struct WasmTransaction {
+    store: WasmLocalStore,
+    state: StoreState,
+    changes: Vec<Change>,
+}
+
+impl WasmTransaction {
+    /// Applies the change to the copy of the transaction and records it for the commit
+    fn apply(&mut self, change: impl Fn(&mut StoreState) + 'static) {
+        change(&mut self.state);
+        self.changes.push(Box::new(change));
+    }
+}
+
+#[async_trait(?Send)]
+impl LocalStoreTx for WasmTransaction {
+    async fn commit(self) -> Result<(), MonexoWalletError> {
+        let mut state = self.store.state.borrow().clone();
+        for change in &self.changes {
+            change(&mut state);
+        }
+        self.store.persist(&state)?;
+        *self.store.state.borrow_mut() = state;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl LocalStore for WasmLocalStore {
+    type Tx = WasmTransaction;
+
+    async fn begin_tx(&self) -> Result<Self::Tx, MonexoWalletError> {
+        Ok(WasmTransaction {
+            store: self.clone(),
+            state: self.state.borrow().clone(),
+            changes: vec![],
+        })
+    }
+
+    async fn delete_proofs(
+        &self,
+        tx: &mut Self::Tx,
+        proofs: &Proofs,
+    ) -> Result<(), MonexoWalletError> {
+        let secrets = proofs
+            .proofs()
+            .into_iter()
+            .map(|p| p.secret)
+            .collect::<Vec<_>>();
+        tx.apply(move |state| {
+            state
+                .proofs
+                .retain(|proof| !secrets.contains(&proof.secret))
+        });
+        Ok(())
+    }
+
+    async fn add_proofs(
+        &self,
+        tx: &mut Self::Tx,
+        proofs: &Proofs,
+    ) -> Result<(), MonexoWalletError> {
+        let proofs = proofs.proofs();
+        tx.apply(move |state| state.proofs.extend(proofs.iter().cloned()));
+        Ok(())
+    }
+
+    async fn get_proofs(&self, tx: &mut Self::Tx) -> Result<Proofs, MonexoWalletError> {
+        Ok(tx.state.proofs.clone().into())
+    }
+
+    async fn get_proofs_by_mint(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+    ) -> Result<Proofs, MonexoWalletError> {
+        let keyset_ids = tx
+            .state
+            .keysets
+            .iter()
+            .filter(|keyset| keyset.belongs_to(mint_url))
+            .map(|keyset| keyset.keyset_id.to_string())
+            .collect::<Vec<_>>();
+
+        Ok(tx
+            .state
+            .proofs
+            .iter()
+            .filter(|proof| keyset_ids.contains(&proof.keyset_id))
+            .cloned()
+            .collect::<Vec<Proof>>()
+            .into())
+    }
+
+    async fn get_keysets(&self, tx: &mut Self::Tx) -> Result<Vec<WalletKeyset>, MonexoWalletError> {
+        Ok(tx.state.keysets.clone())
+    }
+
+    async fn upsert_keyset(
+        &self,
+        tx: &mut Self::Tx,
+        keyset: &WalletKeyset,
+    ) -> Result<(), MonexoWalletError> {
+        let keyset = keyset.clone();
+        tx.apply(move |state| state.upsert_keyset(&keyset));
+        Ok(())
+    }
+
+    async fn update_keyset_last_index(
+        &self,
+        tx: &mut Self::Tx,
+        keyset: &WalletKeyset,
+    ) -> Result<(), MonexoWalletError> {
+        if keyset.id.is_none() {
+            return Err(MonexoWalletError::IdNotSet);
+        }
+
+        // matched by keyset id, the id of a keyset added in this transaction can change when the
+        // transaction is committed
+        let (keyset_id, last_index) = (keyset.keyset_id.clone(), keyset.last_index);
+        tx.apply(move |state| {
+            if let Some(stored) = state
+                .keysets
+                .iter_mut()
+                .find(|stored| stored.keyset_id == keyset_id)
+            {
+                stored.last_index = last_index;
+            }
+        });
+        Ok(())
+    }
+
+    async fn add_mint(&self, tx: &mut Self::Tx, mint_url: &Url) -> Result<(), MonexoWalletError> {
+        let mint_url = mint_url.clone();
+        tx.apply(move |state| {
+            if !state.mints.contains(&mint_url) {
+                state.mints.push(mint_url.clone());
+            }
+        });
+        Ok(())
+    }
+
+    async fn get_mints(&self, tx: &mut Self::Tx) -> Result<Vec<Url>, MonexoWalletError> {
+        Ok(tx.state.mints.clone())
+    }
+
+    async fn add_seed(&self, tx: &mut Self::Tx, seed_words: &str) -> Result<(), MonexoWalletError> {
+        if tx.state.seed.is_some() {
+            return Err(MonexoWalletError::MultipleSeeds);
+        }
+        let seed_words = seed_words.to_owned();
+        tx.apply(move |state| {
+            state.seed.get_or_insert_with(|| seed_words.clone());
+        });
+        Ok(())
+    }
+
+    async fn get_seed(&self, tx: &mut Self::Tx) -> Result<Option<String>, MonexoWalletError> {
+        Ok(tx.state.seed.clone())
+    }
+
+    async fn upsert_mint_info(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+        info: &MintInfoResponse,
+    ) -> Result<(), MonexoWalletError> {
+        let (mint_url, info) = (mint_url.clone(), info.clone());
+        tx.apply(move |state| {
+            state.mint_info.retain(|(stored, _)| *stored != mint_url);
+            state.mint_info.push((mint_url.clone(), info.clone()));
+        });
+        Ok(())
+    }
+
+    async fn get_mint_info(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+    ) -> Result<Option<MintInfoResponse>, MonexoWalletError> {
+        Ok(tx
+            .state
+            .mint_info
+            .iter()
+            .find(|(stored, _)| stored == mint_url)
+            .map(|(_, info)| info.clone()))
+    }
+
+    async fn add_pending_outputs(
+        &self,
+        tx: &mut Self::Tx,
+        outputs: &[PendingOutput],
+    ) -> Result<(), MonexoWalletError> {
+        let outputs = outputs
+            .iter()
+            .map(StoredPendingOutput::from)
+            .collect::<Vec<_>>();
+        tx.apply(move |state| state.pending_outputs.extend(outputs.iter().cloned()));
+        Ok(())
+    }
+
+    async fn get_pending_outputs(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<PendingOutput>, MonexoWalletError> {
+        tx.state
+            .pending_outputs
+            .iter()
+            .map(PendingOutput::try_from)
+            .collect()
+    }
+
+    async fn delete_pending_outputs(
+        &self,
+        tx: &mut Self::Tx,
+        secrets: &[String],
+    ) -> Result<(), MonexoWalletError> {
+        let secrets = secrets.to_vec();
+        tx.apply(move |state| {
+            state
+                .pending_outputs
+                .retain(|output| !secrets.contains(&output.secret))
+        });
+        Ok(())
+    }
+
+    async fn add_mint_quote(
+        &self,
+        tx: &mut Self::Tx,
+        quote: &WalletMintQuote,
+    ) -> Result<(), MonexoWalletError> {
+        let quote = quote.clone();
+        tx.apply(move |state| state.mint_quotes.push(quote.clone()));
+        Ok(())
+    }
+
+    async fn get_pending_mint_quotes(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<WalletMintQuote>, MonexoWalletError> {
+        Ok(tx.state.mint_quotes.clone())
+    }
+
+    async fn delete_mint_quote(
+        &self,
+        tx: &mut Self::Tx,
+        quote: &str,
+    ) -> Result<(), MonexoWalletError> {
+        let quote = quote.to_owned();
+        tx.apply(move |state| state.mint_quotes.retain(|stored| stored.quote != quote));
+        Ok(())
+    }
+
+    async fn add_transaction(
+        &self,
+        tx: &mut Self::Tx,
+        entry: &HistoryEntry,
+    ) -> Result<(), MonexoWalletError> {
+        let entry = entry.clone();
+        tx.apply(move |state| state.transactions.push(entry.clone()));
+        Ok(())
+    }
+
+    async fn list_transactions(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<HistoryEntry>, MonexoWalletError> {
+        Ok(tx.state.transactions.iter().rev().cloned().collect())
+    }
+}
+
+impl WasmLocalStore {
+    /// Creates a store whose content is lost when the page is closed
+    pub fn with_in_memory() -> Self {
+        Self {
+            storage_key: None,
+            state: Rc::new(RefCell::new(StoreState::default())),
+        }
+    }
+
+    /// Loads the wallet stored under the key in `localStorage` or creates an empty one
+    pub fn with_local_storage(storage_key: &str) -> Result<Self, MonexoWalletError> {
+        let state = match local_storage()?
+            .get_item(storage_key)
+            .map_err(|err| MonexoWalletError::Storage(format!("{err:?}")))?
+        {
+            Some(json) => serde_json::from_str(&json)?,
+            None => StoreState::default(),
+        };
+
+        Ok(Self {
+            storage_key: Some(storage_key.to_owned()),
+            state: Rc::new(RefCell::new(state)),
+        })
+    }
+
+    fn persist(&self, state: &StoreState) -> Result<(), MonexoWalletError> {
+        let Some(storage_key) = &self.storage_key else {
+            return Ok(());
+        };
+
+        local_storage()?
+            .set_item(storage_key, &serde_json::to_string(state)?)
+            .map_err(|err| MonexoWalletError::Storage(format!("{err:?}")))
+    }
+}
+
+fn local_storage() -> Result<web_sys::Storage, MonexoWalletError> {
+    web_sys::window()
+        .ok_or_else(|| MonexoWalletError::Storage("no window".to_owned()))?
+        .local_storage()
+        .map_err(|err| MonexoWalletError::Storage(format!("{err:?}")))?
+        .ok_or_else(|| MonexoWalletError::Storage("localStorage is not available".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use monexo_core::token::TokenV3;
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    use super::WasmLocalStore;
+    use crate::localstore::{LocalStore, LocalStoreTx};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn read_token_60() -> anyhow::Result<TokenV3> {
+        Ok(include_str!("../fixtures/token_60.cashu")
+            .trim()
+            .to_string()
+            .try_into()?)
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_add_proofs() -> anyhow::Result<()> {
+        let db = WasmLocalStore::with_in_memory();
+        let mut tx = db.begin_tx().await?;
+        let tokens = read_token_60()?;
+
+        db.add_proofs(&mut tx, &tokens.proofs()).await?;
+
+        let loaded_proofs = db.get_proofs(&mut tx).await?;
+        assert_eq!(tokens.proofs(), loaded_proofs);
+        tx.commit().await?;
+
+        let mut tx = db.begin_tx().await?;
+        assert_eq!(tokens.proofs(), db.get_proofs(&mut tx).await?);
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_uncommitted_proofs_are_discarded() -> anyhow::Result<()> {
+        let db = WasmLocalStore::with_in_memory();
+        let mut tx = db.begin_tx().await?;
+        db.add_proofs(&mut tx, &read_token_60()?.proofs()).await?;
+        drop(tx);
+
+        let mut tx = db.begin_tx().await?;
+        assert!(db.get_proofs(&mut tx).await?.is_empty());
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_concurrent_transactions_are_kept() -> anyhow::Result<()> {
+        let db = WasmLocalStore::with_in_memory();
+        let proofs = read_token_60()?.proofs().proofs();
+        let (first, second) = proofs.split_at(1);
+
+        let mut first_tx = db.begin_tx().await?;
+        let mut second_tx = db.begin_tx().await?;
+        db.add_proofs(&mut first_tx, &first.to_vec().into()).await?;
+        db.add_proofs(&mut second_tx, &second.to_vec().into())
+            .await?;
+        first_tx.commit().await?;
+        second_tx.commit().await?;
+
+        let mut tx = db.begin_tx().await?;
+        assert_eq!(proofs.len(), db.get_proofs(&mut tx).await?.len());
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_proofs_in_local_storage() -> anyhow::Result<()> {
+        let storage_key = "monexo-wallet-test-proofs";
+        let tokens = read_token_60()?;
+
+        let db = WasmLocalStore::with_local_storage(storage_key)?;
+        let mut tx = db.begin_tx().await?;
+        let stored = db.get_proofs(&mut tx).await?;
+        db.delete_proofs(&mut tx, &stored).await?;
+        db.add_proofs(&mut tx, &tokens.proofs()).await?;
+        tx.commit().await?;
+
+        let reloaded = WasmLocalStore::with_local_storage(storage_key)?;
+        let mut tx = reloaded.begin_tx().await?;
+        assert_eq!(tokens.proofs(), reloaded.get_proofs(&mut tx).await?);
+        Ok(())
+    }
+}
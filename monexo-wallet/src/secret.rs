@@ -115,7 +115,6 @@ mod tests {
     #[test]
     fn test_generate_seed_words() -> anyhow::Result<()> {
         let seed_words = DeterministicSecret::generate_random_seed_words()?;
-        println!("{}", seed_words);
         assert_eq!(12, seed_words.split_whitespace().count());
         Ok(())
     }
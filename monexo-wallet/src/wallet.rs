@@ -1,28 +1,85 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use monexo_core::{
-    amount::Amount,
-    blind::{BlindedMessage, BlindedSignature, BlindingFactor, TotalAmount},
+    amount::{sum_amounts, Amount},
+    blind::{verify_dleq, BlindedMessage, BlindedSignature, BlindingFactor, TotalAmount},
     dhke::Dhke,
-    keyset::KeysetId,
+    error::MonexoCoreError,
+    keyset::{input_fee, KeysetId},
     primitives::{
         CurrencyUnit, MeltOnchainState, MintInfoResponse, MintOnchainState,
         PostMeltOnchainResponse, PostMeltQuoteOnchainResponse, PostMintQuoteOnchainResponse,
+        ProofState,
     },
-    proof::{Proof, Proofs},
-    token::TokenV3,
+    proof::{Proof, Proofs, SelectionStrategy},
+    token::{Token, TokenV3},
 };
 use secp256k1::PublicKey;
+use tracing::{debug, warn};
 use url::Url;
 
 use crate::{
     client::CashuClient,
     error::MonexoWalletError,
     http::CrossPlatformHttpClient,
-    localstore::{LocalStore, WalletKeyset},
+    localstore::{
+        HistoryEntry, HistoryKind, KeysetSplit, LocalStore, LocalStoreTx, PendingOutput,
+        WalletKeyset, WalletMintQuote,
+    },
     secret::DeterministicSecret,
 };
 
+/// Info of a mint as seen by the wallet
+#[derive(Debug, Clone, PartialEq)]
+pub struct MintInfo {
+    pub info: MintInfoResponse,
+    /// true if the mint couldn't be reached and the info is from the localstore or a default
+    pub is_stale: bool,
+}
+
+/// Result of [`Wallet::deep_audit`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditReport {
+    /// amount of the unspent proofs the mint has issued for the wallet's seed, including tokens
+    /// that have been sent but not yet claimed by the receiver
+    pub mint_balance: u64,
+    /// amount of the proofs in the localstore
+    pub local_balance: u64,
+    /// unspent proofs issued for the wallet's seed that are missing in the localstore
+    pub missing_proofs: Proofs,
+    /// proofs in the localstore the mint reports as spent
+    pub spent_local_proofs: Proofs,
+}
+
+/// State of a mint quote as reported by the mint, see [`Wallet::get_quote_status`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MintQuoteStatus {
+    pub state: MintOnchainState,
+    /// micro usd the mint has received so far for an unpaid quote, None if it didn't report it
+    pub amount_paid: Option<u64>,
+}
+
+impl MintQuoteStatus {
+    pub fn is_paid(&self) -> bool {
+        matches!(
+            self.state,
+            MintOnchainState::Paid | MintOnchainState::Issued
+        )
+    }
+}
+
+/// Proofs of a keyset per amount, see [`Wallet::denomination_report`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeysetDenominations {
+    pub keyset_id: KeysetId,
+    pub unit: CurrencyUnit,
+    /// number of proofs per amount, smallest amount first
+    pub denominations: BTreeMap<u64, usize>,
+}
+
+/// number of outputs sent to the mint in one restore request
+const RESTORE_BATCH_SIZE: usize = 100;
+
 #[derive(Clone)]
 pub struct Wallet<L, C>
 where
@@ -119,40 +176,197 @@ where
         WalletBuilder::default()
     }
 
+    /// Creates a mint quote and stores it until its tokens are minted. The payment may be split
+    /// into `references` transfers, each carrying another reference of the quote.
     pub async fn create_quote_onchain(
         &self,
         mint_url: &Url,
         amount: u64,
+        references: Option<u8>,
     ) -> Result<PostMintQuoteOnchainResponse, MonexoWalletError> {
-        self.client.post_mint_quote_onchain(mint_url, amount).await
+        let quote = self
+            .client
+            .post_mint_quote_onchain(mint_url, amount, references)
+            .await?;
+
+        // keep the quote until the tokens are minted, so minting can be resumed after a crash
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .add_mint_quote(
+                &mut tx,
+                &WalletMintQuote {
+                    quote: quote.quote.clone(),
+                    mint_url: mint_url.to_owned(),
+                    amount,
+                    fee: quote.fee,
+                },
+            )
+            .await?;
+        tx.commit().await?;
+        Ok(quote)
+    }
+
+    /// Returns the quotes whose tokens haven't been minted yet
+    pub async fn get_pending_mint_quotes(&self) -> Result<Vec<WalletMintQuote>, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let quotes = self.localstore.get_pending_mint_quotes(&mut tx).await?;
+        tx.commit().await?;
+        Ok(quotes)
+    }
+
+    /// Mints the tokens of a stored quote if it has been paid. Returns `None` if the quote isn't
+    /// paid yet. The outputs of a quote the mint has already issued are restored before the quote
+    /// is removed from the localstore, the restored tokens are returned.
+    pub async fn resume_mint_quote(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        quote: &WalletMintQuote,
+    ) -> Result<Option<TokenV3>, MonexoWalletError> {
+        let state = self
+            .client
+            .get_mint_quote_onchain(&quote.mint_url, quote.quote.clone())
+            .await?
+            .state;
+
+        match state {
+            MintOnchainState::Paid => {
+                let amount = quote.amount.checked_sub(quote.fee).ok_or_else(|| {
+                    MonexoWalletError::InvalidAmount(format!(
+                        "fee {} of quote {} exceeds its amount {}",
+                        quote.fee, quote.quote, quote.amount
+                    ))
+                })?;
+                Ok(Some(
+                    self.mint_tokens(
+                        &quote.mint_url,
+                        wallet_keyset,
+                        amount.into(),
+                        quote.quote.clone(),
+                    )
+                    .await?,
+                ))
+            }
+            MintOnchainState::Issued => {
+                // the wallet died after the mint signed the outputs, before the proofs were stored
+                let mut tx = self.localstore.begin_tx().await?;
+                let pending_outputs = self.localstore.get_pending_outputs(&mut tx).await?;
+                tx.commit().await?;
+
+                let mut outputs_by_keyset: HashMap<String, Vec<PendingOutput>> = HashMap::new();
+                for output in pending_outputs
+                    .into_iter()
+                    .filter(|output| output.mint_url == quote.mint_url)
+                {
+                    outputs_by_keyset
+                        .entry(output.keyset_id.clone())
+                        .or_default()
+                        .push(output);
+                }
+                let mut restored = vec![];
+                for (keyset_id, outputs) in outputs_by_keyset {
+                    restored.extend(
+                        self.resume_swap(&quote.mint_url, &keyset_id, outputs)
+                            .await?
+                            .proofs(),
+                    );
+                }
+
+                let mut tx = self.localstore.begin_tx().await?;
+                self.localstore
+                    .delete_mint_quote(&mut tx, &quote.quote)
+                    .await?;
+                tx.commit().await?;
+                Ok((!restored.is_empty())
+                    .then(|| (quote.mint_url.clone(), Proofs::from(restored)).into()))
+            }
+            MintOnchainState::Unpaid | MintOnchainState::Pending => Ok(None),
+        }
     }
 
     pub async fn is_quote_paid(
         &self,
         mint_url: &Url,
         quote: String,
+    ) -> Result<bool, MonexoWalletError> {
+        Ok(self.get_quote_status(mint_url, quote).await?.is_paid())
+    }
+
+    /// Returns the state of the quote and the amount the mint received for it so far, so an
+    /// underpaid quote can be told apart from one that hasn't been paid at all
+    pub async fn get_quote_status(
+        &self,
+        mint_url: &Url,
+        quote: String,
+    ) -> Result<MintQuoteStatus, MonexoWalletError> {
+        let response = self.client.get_mint_quote_onchain(mint_url, quote).await?;
+        Ok(MintQuoteStatus {
+            state: response.state,
+            amount_paid: response.amount_paid,
+        })
+    }
+
+    /// Waits until the mint reports the quote as paid, without polling it. Returns false if the
+    /// subscription ended before, e.g. because it timed out.
+    pub async fn await_quote_paid(
+        &self,
+        mint_url: &Url,
+        quote: String,
     ) -> Result<bool, MonexoWalletError> {
         Ok(matches!(
             self.client
-                .get_mint_quote_onchain(mint_url, quote)
+                .await_mint_quote_onchain(mint_url, quote)
                 .await?
                 .state,
             MintOnchainState::Paid | MintOnchainState::Issued
         ))
     }
 
+    /// Polls the quote every `interval` until the mint reports it paid. `on_status` is called
+    /// with the status of every poll. Returns false if it isn't paid within `max_wait`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn wait_for_mint_payment(
+        &self,
+        mint_url: &Url,
+        quote: String,
+        interval: std::time::Duration,
+        max_wait: std::time::Duration,
+        mut on_status: impl FnMut(&MintQuoteStatus),
+    ) -> Result<bool, MonexoWalletError> {
+        let deadline = tokio::time::Instant::now() + max_wait;
+        loop {
+            let status = self.get_quote_status(mint_url, quote.clone()).await?;
+            on_status(&status);
+            if status.is_paid() {
+                return Ok(true);
+            }
+
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            tokio::time::sleep(interval.min(deadline - now)).await;
+        }
+    }
+
     pub async fn is_onchain_paid(
         &self,
         mint_url: &Url,
         quote: String,
     ) -> Result<bool, MonexoWalletError> {
-        // FIXME add method get_onchain_state
+        Ok(self.get_onchain_state(mint_url, quote).await? == MeltOnchainState::Paid)
+    }
+
+    /// Returns the state of the onchain transaction of the melt quote
+    pub async fn get_onchain_state(
+        &self,
+        mint_url: &Url,
+        quote: String,
+    ) -> Result<MeltOnchainState, MonexoWalletError> {
         Ok(self
             .client
             .get_melt_quote_onchain(mint_url, quote)
             .await?
-            .state
-            == MeltOnchainState::Paid)
+            .state)
     }
 
     pub async fn get_wallet_keysets(&self) -> Result<Vec<WalletKeyset>, MonexoWalletError> {
@@ -162,31 +376,90 @@ where
         Ok(keysets)
     }
 
-    /// Stores the mints keys in the localstore
+    /// Returns the keysets of the mint
+    pub async fn get_wallet_keysets_for_mint(
+        &self,
+        mint_url: &Url,
+    ) -> Result<Vec<WalletKeyset>, MonexoWalletError> {
+        Ok(self
+            .get_wallet_keysets()
+            .await?
+            .into_iter()
+            .filter(|keyset| keyset.belongs_to(mint_url))
+            .collect())
+    }
+
+    /// Returns the active keyset of the unit at the mint, new proofs are created in this keyset.
+    /// Keysets stored without a mint are never returned, they might belong to another mint.
+    pub async fn get_active_keyset(
+        &self,
+        mint_url: &Url,
+        unit: &CurrencyUnit,
+    ) -> Result<Option<WalletKeyset>, MonexoWalletError> {
+        Ok(self.get_wallet_keysets().await?.into_iter().find(|keyset| {
+            keyset.active
+                && keyset.currency_unit == *unit
+                && keyset.mint_url.as_ref() == Some(mint_url)
+        }))
+    }
+
+    /// Adds the mint to the known mints and stores its keysets
+    pub async fn add_mint(&self, mint_url: &Url) -> Result<Vec<WalletKeyset>, MonexoWalletError> {
+        self.add_mint_keysets(mint_url).await
+    }
+
+    /// Returns the known mints, oldest mint first
+    pub async fn list_mints(&self) -> Result<Vec<Url>, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let mints = self.localstore.get_mints(&mut tx).await?;
+        tx.commit().await?;
+        Ok(mints)
+    }
+
+    /// Stores the mint and its keys in the localstore
     pub async fn add_mint_keysets(
         &self,
         mint_url: &Url,
     ) -> Result<Vec<WalletKeyset>, MonexoWalletError> {
         let mint_keysets = self.client.get_keysets(mint_url).await?;
 
+        debug!(
+            "Adding {} keysets of {mint_url}",
+            mint_keysets.keysets.len()
+        );
         let mut tx = self.localstore.begin_tx().await?;
+        self.localstore.add_mint(&mut tx, mint_url).await?;
         let mut result = vec![];
+        let mut mismatched = vec![];
         for keyset in mint_keysets.keysets.iter() {
+            // ignore keysets with units the wallet doesn't support
+            if !keyset.unit.is_supported() {
+                continue;
+            }
+
             let keysets = self
                 .client
                 .get_keys_by_id(mint_url, keyset.id.clone())
                 .await;
 
             let public_keys = match keysets {
-                Ok(k) => k
+                Ok(k) => match k
                     .keysets
                     .into_iter()
                     .find(|k| k.id == keyset.id && k.unit == keyset.unit)
-                    .expect("no valid keyset found")
-                    .keys
-                    .clone(),
-                Err(_) => {
-                    //println!("Ignoring keyset without public_keys {:?}", keyset.id);
+                {
+                    Some(k) => k.keys,
+                    None => {
+                        warn!(
+                            "Ignoring keyset {} ({}), the mint returned no matching keys",
+                            keyset.id, keyset.unit
+                        );
+                        mismatched.push(keyset.id.clone());
+                        continue;
+                    }
+                },
+                Err(err) => {
+                    debug!("Ignoring keyset {} without public keys: {err}", keyset.id);
                     continue;
                 }
             };
@@ -195,19 +468,62 @@ where
             let keyset_id = match KeysetId::new(&keyset.id) {
                 Ok(id) => id,
                 Err(_) => {
-                    //println!("Ignoring legacy keyset {:?}", keyset.id);
+                    debug!("Ignoring legacy keyset {}", keyset.id);
                     continue;
                 }
             };
 
-            let wallet_keyset = WalletKeyset::new(&keyset_id, 0, public_keys, keyset.active);
+            let wallet_keyset = WalletKeyset::new(
+                &keyset_id,
+                keyset.unit.clone(),
+                0,
+                public_keys,
+                keyset.active,
+            )
+            .with_mint_url(mint_url)
+            .with_input_fee_ppk(keyset.input_fee_ppk);
 
-            result.push(wallet_keyset.clone());
-            self.localstore
-                .upsert_keyset(&mut tx, &wallet_keyset)
-                .await?;
+            result.push(wallet_keyset);
+        }
+
+        // a mint has one active keyset per unit, if it reports more the last one is used
+        let mut active_units = HashSet::new();
+        for keyset in result.iter_mut().rev() {
+            if keyset.active && !active_units.insert(keyset.currency_unit.clone()) {
+                keyset.active = false;
+            }
+        }
+        for keyset in result.iter() {
+            self.localstore.upsert_keyset(&mut tx, keyset).await?;
+        }
+
+        // keysets the mint rotated away from are kept, the wallet may still hold proofs of them
+        let mint_keyset_ids = mint_keysets
+            .keysets
+            .iter()
+            .map(|keyset| keyset.id.as_str())
+            .collect::<Vec<_>>();
+        for stored in self.localstore.get_keysets(&mut tx).await? {
+            if stored.active
+                && stored.mint_url.as_ref() == Some(mint_url)
+                && !mint_keyset_ids.contains(&stored.keyset_id.to_string().as_str())
+            {
+                self.localstore
+                    .upsert_keyset(
+                        &mut tx,
+                        &WalletKeyset {
+                            active: false,
+                            ..stored
+                        },
+                    )
+                    .await?;
+            }
         }
         tx.commit().await?;
+
+        if result.is_empty() && !mismatched.is_empty() {
+            return Err(MonexoWalletError::KeysetMismatch(mismatched.join(", ")));
+        }
         Ok(result)
     }
 
@@ -218,546 +534,4112 @@ where
         Ok(total_amount)
     }
 
-    pub async fn send_tokens(
-        &self,
-        mint_url: &Url,
-        wallet_keyset: &WalletKeyset,
-        amount: u64,
-    ) -> Result<TokenV3, MonexoWalletError> {
-        let balance = self.get_balance().await?;
-        if amount > balance {
-            return Err(MonexoWalletError::NotEnoughTokens);
-        }
+    /// Returns the balance of the proofs of the mint's keysets
+    pub async fn get_mint_balance(&self, mint_url: &Url) -> Result<u64, MonexoWalletError> {
+        Ok(self.get_mint_proofs(mint_url).await?.total_amount())
+    }
 
+    /// Returns the proofs of the mint's keysets, they are only valid at this mint
+    pub async fn get_mint_proofs(&self, mint_url: &Url) -> Result<Proofs, MonexoWalletError> {
         let mut tx = self.localstore.begin_tx().await?;
-        let all_proofs = self
+        let proofs = self
             .localstore
-            .get_proofs(&mut tx)
-            .await?
-            .proofs_by_keyset(&wallet_keyset.keyset_id);
+            .get_proofs_by_mint(&mut tx, mint_url)
+            .await?;
         tx.commit().await?;
+        Ok(proofs)
+    }
 
-        let selected_proofs = all_proofs.proofs_for_amount(amount)?;
-        let selected_tokens = (mint_url.to_owned(), selected_proofs.clone()).into();
+    /// Returns the balance per currency unit, proofs of unknown keysets are ignored
+    pub async fn get_balance_by_currency(
+        &self,
+    ) -> Result<HashMap<CurrencyUnit, u64>, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let proofs = self.localstore.get_proofs(&mut tx).await?;
+        let keysets = self.localstore.get_keysets(&mut tx).await?;
+        tx.commit().await?;
 
-        let (remaining_tokens, result) = self
-            .swap_tokens(mint_url, wallet_keyset, &selected_tokens, amount.into())
-            .await?;
+        let mut balances = HashMap::new();
+        for keyset in keysets {
+            let amount = proofs.proofs_by_keyset(&keyset.keyset_id).total_amount();
+            if amount > 0 {
+                *balances.entry(keyset.currency_unit).or_insert(0) += amount;
+            }
+        }
+        Ok(balances)
+    }
 
+    /// Returns how many proofs of each amount the wallet holds per keyset, keysets without
+    /// proofs are left out. Few large proofs are the reason a send needs a swap.
+    pub async fn denomination_report(&self) -> Result<Vec<KeysetDenominations>, MonexoWalletError> {
         let mut tx = self.localstore.begin_tx().await?;
-        self.localstore
-            .delete_proofs(&mut tx, &selected_proofs)
-            .await?;
-
-        self.localstore
-            .add_proofs(&mut tx, &remaining_tokens.proofs())
-            .await?;
+        let proofs = self.localstore.get_proofs(&mut tx).await?;
+        let keysets = self.localstore.get_keysets(&mut tx).await?;
         tx.commit().await?;
-        Ok(result)
+
+        Ok(keysets
+            .into_iter()
+            .filter_map(|keyset| {
+                let denominations = proofs
+                    .proofs_by_keyset(&keyset.keyset_id)
+                    .denomination_histogram();
+                (!denominations.is_empty()).then_some(KeysetDenominations {
+                    keyset_id: keyset.keyset_id,
+                    unit: keyset.currency_unit,
+                    denominations,
+                })
+            })
+            .collect())
     }
 
-    pub async fn receive_tokens(
+    /// Swaps all proofs of the keyset at the mint into the fewest proofs that add up to their
+    /// total, which keeps later sends from needing a swap for awkward denominations.
+    ///
+    /// Does nothing if the proofs already are the optimal set. The mint deducts its input fee
+    /// from the swapped proofs, so the balance only stays the same for keysets without a fee.
+    /// Returns the number of proofs before and after the consolidation.
+    pub async fn consolidate(
         &self,
         mint_url: &Url,
         wallet_keyset: &WalletKeyset,
-        tokens: &TokenV3,
-    ) -> Result<(), MonexoWalletError> {
-        let total_amount = tokens.total_amount();
-        let (_, redeemed_tokens) = self
-            .swap_tokens(mint_url, wallet_keyset, tokens, total_amount.into())
+    ) -> Result<(usize, usize), MonexoWalletError> {
+        if !wallet_keyset.belongs_to(mint_url) {
+            return Err(MonexoWalletError::KeysetNotFound(
+                wallet_keyset.keyset_id.to_string(),
+            ));
+        }
+
+        let proofs = self
+            .get_mint_proofs(mint_url)
+            .await?
+            .proofs_by_keyset(&wallet_keyset.keyset_id);
+        let total = proofs.total_amount();
+        let optimal_count = Amount::from(total).split_for_keyset(wallet_keyset).len();
+        if proofs.len() <= optimal_count {
+            return Ok((proofs.len(), proofs.len()));
+        }
+
+        let tokens = (mint_url.to_owned(), proofs.clone()).into();
+        let (consolidated, _) = self
+            .swap_tokens(mint_url, wallet_keyset, &tokens, 0.into())
             .await?;
+
         let mut tx = self.localstore.begin_tx().await?;
+        self.localstore.delete_proofs(&mut tx, &proofs).await?;
         self.localstore
-            .add_proofs(&mut tx, &redeemed_tokens.proofs())
+            .add_proofs(&mut tx, &consolidated.proofs())
+            .await?;
+        self.delete_pending_outputs(&mut tx, &[&consolidated])
             .await?;
         tx.commit().await?;
-        Ok(())
+        Ok((proofs.len(), consolidated.proofs().len()))
     }
 
-    // pub async fn get_mint_quote(
-    //     &self,
-    //     mint_url: &Url,
-    //     amount: Amount,
-    //     currency: CurrencyUnit,
-    // ) -> Result<PostMintQuoteBolt11Response, MonexoWalletError> {
-    //     self.client
-    //         .post_mint_quote_bolt11(mint_url, amount.0, currency)
-    //         .await
-    // }
-
-    pub async fn get_melt_quote_onchain(
+    pub async fn send_tokens(
         &self,
         mint_url: &Url,
-        address: String,
+        wallet_keyset: &WalletKeyset,
         amount: u64,
-    ) -> Result<Vec<PostMeltQuoteOnchainResponse>, MonexoWalletError> {
-        self.client
-            .post_melt_quote_onchain(mint_url, address, amount)
+    ) -> Result<TokenV3, MonexoWalletError> {
+        self.send_tokens_with_memo(mint_url, wallet_keyset, amount, None)
             .await
     }
 
-    pub async fn pay_onchain(
+    /// Sends `amount` like [`Wallet::send_tokens`] and attaches the memo to the token and the
+    /// history entry of the send
+    pub async fn send_tokens_with_memo(
         &self,
         mint_url: &Url,
         wallet_keyset: &WalletKeyset,
-        melt_quote: &PostMeltQuoteOnchainResponse,
-    ) -> Result<PostMeltOnchainResponse, MonexoWalletError> {
-        let mut tx = self.localstore.begin_tx().await?;
-        let all_proofs = self.localstore.get_proofs(&mut tx).await?;
-        tx.commit().await?;
-
-        let ln_amount = melt_quote.amount;
-
-        if ln_amount > all_proofs.total_amount() {
-            return Err(MonexoWalletError::NotEnoughTokens);
+        amount: u64,
+        memo: Option<String>,
+    ) -> Result<TokenV3, MonexoWalletError> {
+        if !wallet_keyset.belongs_to(mint_url) {
+            return Err(MonexoWalletError::KeysetNotFound(
+                wallet_keyset.keyset_id.to_string(),
+            ));
         }
-        let selected_proofs = all_proofs.proofs_for_amount(ln_amount)?;
-
-        let mut tx = self.localstore.begin_tx().await?;
-        let total_proofs = {
-            let selected_tokens = (mint_url.to_owned(), selected_proofs.clone()).into();
-            let swap_result = self
-                .swap_tokens(mint_url, wallet_keyset, &selected_tokens, ln_amount.into())
-                .await?;
-            self.localstore
-                .delete_proofs(&mut tx, &selected_proofs)
-                .await?;
-            self.localstore
-                .add_proofs(&mut tx, &swap_result.0.proofs())
-                .await?;
 
-            swap_result.1.proofs()
-        };
+        let send = self.swap_for_send(mint_url, wallet_keyset, amount).await?;
+        self.store_sends(mint_url, &wallet_keyset.currency_unit, &[send], memo)
+            .await
+    }
 
-        let melt_response = self
-            .client
-            .post_melt_onchain(&mint_url, total_proofs.clone(), melt_quote.quote.clone())
+    /// Selects proofs of the keyset that cover `amount` and swaps them at the mint unless they
+    /// add up to it already. The localstore is left as it is, returns the selected inputs, the
+    /// change and the token to send.
+    async fn swap_for_send(
+        &self,
+        mint_url: &Url,
+        wallet_keyset: &WalletKeyset,
+        amount: u64,
+    ) -> Result<(Proofs, TokenV3, TokenV3), MonexoWalletError> {
+        let selected_proofs = self
+            .select_send_proofs(mint_url, wallet_keyset, amount)
             .await?;
 
-        if melt_response.state == MeltOnchainState::Paid {
-            self.localstore
-                .delete_proofs(&mut tx, &total_proofs)
-                .await?;
+        // proofs that add up to the amount can be sent without swapping them first
+        if selected_proofs.total_amount() == amount {
+            let send_tokens = (
+                mint_url.to_owned(),
+                wallet_keyset.currency_unit.clone(),
+                selected_proofs.clone(),
+            )
+                .into();
+            return Ok((selected_proofs, TokenV3::empty(), send_tokens));
         }
-        tx.commit().await?;
-        Ok(melt_response)
+
+        // the swap creates the new proofs in the active keyset, so proofs of a keyset the mint
+        // rotated away from are moved to the current one
+        let output_keyset = self
+            .get_active_keyset(mint_url, &wallet_keyset.currency_unit)
+            .await?
+            .unwrap_or_else(|| wallet_keyset.clone());
+        let selected_tokens = (mint_url.to_owned(), selected_proofs.clone()).into();
+        let (remaining_tokens, result) = self
+            .swap_tokens(mint_url, &output_keyset, &selected_tokens, amount.into())
+            .await?;
+        Ok((selected_proofs, remaining_tokens, result))
     }
 
-    async fn create_secrets(
+    /// Stores the outcome of [`Wallet::swap_for_send`] for one or more keysets in a single
+    /// transaction: the inputs are removed, the change is kept and one history entry is written
+    /// for the sent amount. Returns the sent proofs as one token.
+    async fn store_sends(
         &self,
-        keyset_id: &KeysetId,
-        amount: u32,
-    ) -> Result<Vec<(String, BlindingFactor)>, MonexoWalletError> {
+        mint_url: &Url,
+        unit: &CurrencyUnit,
+        sends: &[(Proofs, TokenV3, TokenV3)],
+        memo: Option<String>,
+    ) -> Result<TokenV3, MonexoWalletError> {
         let mut tx = self.localstore.begin_tx().await?;
-        let all_keysets = self.localstore.get_keysets(&mut tx).await?;
-        let keyset = all_keysets
-            .iter()
-            .find(|k| k.keyset_id == *keyset_id)
-            .expect("keyset not found create-secrets");
-
-        let start_index = (keyset.last_index + 1) as u32;
-        let secret_range = self.secret.derive_range(keyset_id, start_index, amount)?;
+        for (inputs, change, send_tokens) in sends {
+            self.localstore.delete_proofs(&mut tx, inputs).await?;
+            self.localstore
+                .add_proofs(&mut tx, &change.proofs())
+                .await?;
+            self.delete_pending_outputs(&mut tx, &[change, send_tokens])
+                .await?;
+        }
 
+        let send_proofs: Proofs = sends
+            .iter()
+            .flat_map(|(_, _, send_tokens)| send_tokens.proofs().proofs())
+            .collect::<Vec<_>>()
+            .into();
         self.localstore
-            .update_keyset_last_index(
+            .add_transaction(
                 &mut tx,
-                &WalletKeyset {
-                    last_index: (start_index + amount - 1) as u64,
-                    ..keyset.clone()
-                },
+                &HistoryEntry::new(
+                    HistoryKind::Send,
+                    mint_url,
+                    send_proofs.total_amount(),
+                    unit.clone(),
+                )
+                .with_memo(memo.clone()),
             )
             .await?;
         tx.commit().await?;
-        Ok(secret_range)
+
+        Ok(TokenV3::from((mint_url.to_owned(), unit.clone(), send_proofs)).with_memo(memo))
     }
 
-    pub async fn swap_tokens(
+    /// Puts the proofs of sends that were swapped but not sent back into the localstore, so a
+    /// failed [`Wallet::send`] doesn't lose them. The spent inputs are removed.
+    async fn restore_sends(
         &self,
-        mint_url: &Url,
-        wallet_keyset: &WalletKeyset,
-        tokens: &TokenV3,
-        splt_amount: Amount,
-    ) -> Result<(TokenV3, TokenV3), MonexoWalletError> {
-        let total_token_amount = tokens.total_amount();
-        let first_amount: Amount = (total_token_amount - splt_amount.0).into();
-        let first_secrets = self
-            .create_secrets(&wallet_keyset.keyset_id, first_amount.split().len() as u32)
-            .await?;
-        let first_outputs = self.create_blinded_messages(
-            &wallet_keyset.keyset_id,
-            first_amount,
-            first_secrets.clone(),
-        )?;
-
-        // ############################################################################
+        sends: &[(Proofs, TokenV3, TokenV3)],
+    ) -> Result<(), MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        for (inputs, change, send_tokens) in sends {
+            self.localstore.delete_proofs(&mut tx, inputs).await?;
+            for tokens in [change, send_tokens] {
+                self.localstore
+                    .add_proofs(&mut tx, &tokens.proofs())
+                    .await?;
+            }
+            self.delete_pending_outputs(&mut tx, &[change, send_tokens])
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
 
-        let second_amount = splt_amount.clone();
-        let second_secrets = self
-            .create_secrets(&wallet_keyset.keyset_id, second_amount.split().len() as u32)
+    /// Sends `amount` without contacting the mint.
+    ///
+    /// Only works if proofs of the keyset add up to exactly `amount`, otherwise
+    /// [`MonexoWalletError::NoExactAmount`] is returned and nothing is spent.
+    pub async fn send_tokens_offline(
+        &self,
+        mint_url: &Url,
+        wallet_keyset: &WalletKeyset,
+        amount: u64,
+    ) -> Result<TokenV3, MonexoWalletError> {
+        let selected_proofs = self
+            .select_send_proofs(mint_url, wallet_keyset, amount)
             .await?;
-        let second_outputs = self.create_blinded_messages(
-            &wallet_keyset.keyset_id,
-            second_amount,
-            second_secrets.clone(),
-        )?;
-
-        let mut total_outputs = vec![];
-        total_outputs.extend(get_blinded_msg(first_outputs.clone()));
-        total_outputs.extend(get_blinded_msg(second_outputs.clone()));
-
-        if tokens.total_amount() != total_outputs.total_amount() {
-            return Err(MonexoWalletError::InvalidProofs);
+        if selected_proofs.total_amount() != amount {
+            return Err(MonexoWalletError::NoExactAmount(amount));
         }
 
-        let split_result = self
-            .client
-            .post_swap(&mint_url, tokens.proofs(), total_outputs)
-            .await?;
+        self.spend_exact_proofs(mint_url, wallet_keyset, selected_proofs, None)
+            .await
+    }
 
-        if split_result.signatures.is_empty() {
-            return Ok((TokenV3::empty(), TokenV3::empty()));
+    /// Selects proofs of the keyset at the mint, fails with
+    /// [`MonexoWalletError::NotEnoughTokens`] if they don't cover the amount. Proofs of other
+    /// mints are never selected, they are only valid at their own mint.
+    async fn select_send_proofs(
+        &self,
+        mint_url: &Url,
+        wallet_keyset: &WalletKeyset,
+        amount: u64,
+    ) -> Result<Proofs, MonexoWalletError> {
+        let keyset_proofs = self
+            .get_mint_proofs(mint_url)
+            .await?
+            .proofs_by_keyset(&wallet_keyset.keyset_id);
+        if keyset_proofs.total_amount() < amount {
+            return Err(MonexoWalletError::NotEnoughTokens);
         }
 
-        let len_first = first_secrets.len();
-        let secrets = [first_secrets, second_secrets].concat();
-        let outputs = [first_outputs, second_outputs].concat();
-
-        let secrets = secrets.into_iter().map(|(s, _)| s).collect::<Vec<String>>();
+        // proofs that add up to the amount are sent without a swap and don't cost a fee
+        let selected_proofs = keyset_proofs.select_proofs(amount, SelectionStrategy::ExactMatch)?;
+        if selected_proofs.total_amount() == amount {
+            return Ok(selected_proofs);
+        }
 
-        let proofs = self
-            .create_proofs_from_blinded_signatures(
-                &wallet_keyset.keyset_id,
-                &wallet_keyset.public_keys,
-                split_result.signatures,
-                secrets,
-                outputs,
-            )?
-            .proofs();
+        self.select_proofs_with_fee(&keyset_proofs, amount, SelectionStrategy::ExactMatch)
+            .await
+    }
 
-        let first_tokens: TokenV3 = (
-            mint_url.to_owned(),
-            CurrencyUnit::Usd,
-            proofs[0..len_first].to_vec().into(),
-        )
-            .into();
-        let second_tokens: TokenV3 = (
-            mint_url.to_owned(),
-            CurrencyUnit::Usd,
-            proofs[len_first..proofs.len()].to_vec().into(),
-        )
-            .into();
+    /// Selects proofs that cover `amount` and the fee the mint charges for swapping them. Every
+    /// additional proof may raise the fee, so the selection is repeated until it covers both.
+    async fn select_proofs_with_fee(
+        &self,
+        proofs: &Proofs,
+        amount: u64,
+        strategy: SelectionStrategy,
+    ) -> Result<Proofs, MonexoWalletError> {
+        let mut required = amount;
+        loop {
+            if proofs.total_amount() < required {
+                return Err(MonexoWalletError::NotEnoughTokens);
+            }
 
-        if tokens.total_amount() != first_tokens.total_amount() + second_tokens.total_amount() {
-            println!(
-                "Error in swap: input {:?} != output {:?} + {:?}",
-                tokens.total_amount(),
-                first_tokens.total_amount(),
-                second_tokens.total_amount()
-            );
+            let selected_proofs = proofs.select_proofs(required, strategy)?;
+            let fee = self.swap_fee(&selected_proofs).await?;
+            if selected_proofs.total_amount() >= amount + fee {
+                return Ok(selected_proofs);
+            }
+            required = amount + fee;
         }
+    }
 
-        Ok((first_tokens, second_tokens))
+    /// Returns the fee the mint charges for swapping the proofs, proofs of keysets the wallet
+    /// doesn't know are assumed to cost nothing
+    async fn swap_fee(&self, proofs: &Proofs) -> Result<u64, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let keysets = self.localstore.get_keysets(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(input_fee(proofs.proofs().iter().map(|proof| {
+            keysets
+                .iter()
+                .find(|keyset| keyset.keyset_id.to_string() == proof.keyset_id)
+                .map(|keyset| keyset.input_fee_ppk)
+                .unwrap_or_default()
+        })))
     }
 
-    pub async fn get_mint_info(
+    /// Removes the proofs from the localstore and returns them as token
+    async fn spend_exact_proofs(
         &self,
         mint_url: &Url,
-    ) -> Result<MintInfoResponse, MonexoWalletError> {
-        self.client.get_info(mint_url).await
+        wallet_keyset: &WalletKeyset,
+        proofs: Proofs,
+        memo: Option<String>,
+    ) -> Result<TokenV3, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore.delete_proofs(&mut tx, &proofs).await?;
+        self.localstore
+            .add_transaction(
+                &mut tx,
+                &HistoryEntry::new(
+                    HistoryKind::Send,
+                    mint_url,
+                    proofs.total_amount(),
+                    wallet_keyset.currency_unit.clone(),
+                )
+                .with_memo(memo.clone()),
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(TokenV3::from((
+            mint_url.to_owned(),
+            wallet_keyset.currency_unit.clone(),
+            proofs,
+        ))
+        .with_memo(memo))
     }
 
-    pub async fn mint_tokens(
+    /// Exchanges `amount` of the unit of `from_keyset` for the unit of `to_keyset` at the rate
+    /// the mint returns in its info.
+    ///
+    /// Proofs of `from_keyset` are swapped first if none add up to exactly `amount`. The
+    /// exchanged proofs are stored and returned as token.
+    pub async fn exchange_currency(
         &self,
         mint_url: &Url,
-        wallet_keyset: &WalletKeyset,
-        amount: Amount,
-        quote_id: String,
+        from_keyset: &WalletKeyset,
+        to_keyset: &WalletKeyset,
+        amount: u64,
     ) -> Result<TokenV3, MonexoWalletError> {
-        let split_amount = amount.split();
+        let rate = self.client.get_info(mint_url).await?.ugx_exchange_rate;
+        let target_amount = match (rate, &from_keyset.currency_unit, &to_keyset.currency_unit) {
+            (Some(rate), CurrencyUnit::Usd, CurrencyUnit::Ugx) => (amount as f64 * rate) as u64,
+            (Some(rate), CurrencyUnit::Ugx, CurrencyUnit::Usd) => (amount as f64 / rate) as u64,
+            _ => {
+                return Err(MonexoWalletError::ExchangeNotSupported(
+                    mint_url.to_string(),
+                    from_keyset.currency_unit.to_string(),
+                    to_keyset.currency_unit.to_string(),
+                ))
+            }
+        };
+        if target_amount == 0 {
+            return Err(MonexoWalletError::NotEnoughTokens);
+        }
 
-        let secret_range = self
-            .create_secrets(&wallet_keyset.keyset_id, split_amount.len() as u32)
+        let mut inputs = self
+            .select_send_proofs(mint_url, from_keyset, amount)
             .await?;
+        if inputs.total_amount() != amount {
+            let selected_tokens = (mint_url.to_owned(), inputs.clone()).into();
+            let (remaining_tokens, exact_tokens) = self
+                .swap_tokens(mint_url, from_keyset, &selected_tokens, amount.into())
+                .await?;
 
-        let blinded_messages = split_amount
-            .into_iter()
-            .zip(secret_range)
-            .map(|(amount, (secret, blinding_factor))| {
-                let b_ = self.dhke.step1_alice(&secret, &blinding_factor)?;
-                Ok((
-                    BlindedMessage {
-                        amount,
-                        b_,
-                        id: wallet_keyset.keyset_id.to_string(), // FIXME use keyset_id
-                    },
-                    blinding_factor,
-                    secret,
-                ))
-            })
-            .collect::<Result<Vec<(_, _, _)>, MonexoWalletError>>()?;
+            let mut tx = self.localstore.begin_tx().await?;
+            self.localstore.delete_proofs(&mut tx, &inputs).await?;
+            self.localstore
+                .add_proofs(&mut tx, &remaining_tokens.proofs())
+                .await?;
+            self.localstore
+                .add_proofs(&mut tx, &exact_tokens.proofs())
+                .await?;
+            self.delete_pending_outputs(&mut tx, &[&remaining_tokens, &exact_tokens])
+                .await?;
+            tx.commit().await?;
+            inputs = exact_tokens.proofs();
+        }
 
-        let signatures = self
-            .client
-            .post_mint_onchain(
-                mint_url,
-                quote_id,
-                blinded_messages
-                    .clone()
-                    .into_iter()
-                    .map(|(msg, _, _)| msg)
-                    .collect::<Vec<BlindedMessage>>(),
+        let target_amount: Amount = target_amount.into();
+        let secrets = self
+            .create_secrets(
+                &to_keyset.keyset_id,
+                target_amount.split_for_keyset(to_keyset).len() as u32,
             )
-            .await?
-            .signatures;
-
-        // step 3: unblind signatures
-        let current_keyset_id = wallet_keyset.keyset_id.to_string(); // FIXME
+            .await?;
+        let outputs = self.create_blinded_messages(to_keyset, target_amount, secrets.clone())?;
+        let blinded_messages = get_blinded_msg(outputs.clone());
+        self.add_pending_outputs(mint_url, &secrets, &blinded_messages)
+            .await?;
 
-        let proofs = signatures
-            .iter()
-            .zip(blinded_messages)
-            .map(|(p, (_, priv_key, secret))| {
-                let key = wallet_keyset
-                    .public_keys
-                    .get(&p.amount)
-                    .expect("msg amount not found in mint keys");
-                let pub_alice = self.dhke.step3_alice(p.c_, priv_key, *key).unwrap();
-                Proof::new(p.amount, secret, pub_alice, current_keyset_id.clone())
-            })
-            .collect::<Vec<Proof>>()
-            .into();
+        let response = self
+            .client
+            .post_exchange(mint_url, amount, inputs.clone(), blinded_messages)
+            .await?;
+        let proofs = self.create_proofs_from_blinded_signatures(
+            &to_keyset.keyset_id,
+            &to_keyset.public_keys,
+            response.signatures,
+            secrets.into_iter().map(|(secret, _)| secret).collect(),
+            outputs,
+        )?;
+        let tokens: TokenV3 = (mint_url.to_owned(), to_keyset.currency_unit.clone(), proofs).into();
 
-        let tokens: TokenV3 = (mint_url.to_owned(), proofs).into();
         let mut tx = self.localstore.begin_tx().await?;
+        self.localstore.delete_proofs(&mut tx, &inputs).await?;
         self.localstore
             .add_proofs(&mut tx, &tokens.proofs())
             .await?;
+        self.delete_pending_outputs(&mut tx, &[&tokens]).await?;
         tx.commit().await?;
-
         Ok(tokens)
     }
 
-    pub async fn create_blank(
+    /// Sends `amount` of `unit`, combining proofs from every keyset of that unit if no single
+    /// keyset holds enough.
+    pub async fn send(
         &self,
-        fee_reserve: Amount,
-        keyset_id: &KeysetId,
-    ) -> Result<Vec<(BlindedMessage, BlindingFactor, String)>, MonexoWalletError> {
-        if fee_reserve.0 == 0 {
-            return Ok(vec![]);
-        }
-
-        let fee_reserve_float = fee_reserve.0 as f64;
-        let count = (fee_reserve_float.log2().ceil() as u64).max(1);
+        mint_url: &Url,
+        unit: CurrencyUnit,
+        amount: u64,
+    ) -> Result<TokenV3, MonexoWalletError> {
+        let unit_keyset_ids = self
+            .client
+            .get_keysets(mint_url)
+            .await?
+            .keysets
+            .into_iter()
+            .filter(|keyset| keyset.unit == unit)
+            .map(|keyset| keyset.id)
+            .collect::<Vec<String>>();
 
-        let secret_range = self.create_secrets(keyset_id, count as u32).await?;
-        let blinded_messages = secret_range
+        let mut tx = self.localstore.begin_tx().await?;
+        let all_proofs = self.localstore.get_proofs(&mut tx).await?;
+        let mut keysets = self
+            .localstore
+            .get_keysets(&mut tx)
+            .await?
             .into_iter()
-            .map(|(secret, blinding_factor)| {
-                let b_ = self.dhke.step1_alice(secret.clone(), &blinding_factor)?;
-                Ok((
-                    BlindedMessage {
-                        amount: 1,
-                        b_,
-                        id: keyset_id.to_string(),
-                    },
-                    blinding_factor,
-                    secret,
-                ))
+            .filter(|keyset| {
+                keyset.belongs_to(mint_url)
+                    && unit_keyset_ids.contains(&keyset.keyset_id.to_string())
             })
-            .collect::<Result<Vec<(_, _, _)>, MonexoWalletError>>()?;
+            .map(|keyset| {
+                let balance = all_proofs
+                    .proofs_by_keyset(&keyset.keyset_id)
+                    .total_amount();
+                (keyset, balance)
+            })
+            .filter(|(_, balance)| *balance > 0)
+            .collect::<Vec<(WalletKeyset, u64)>>();
+        tx.commit().await?;
 
-        Ok(blinded_messages)
+        if keysets.iter().map(|(_, balance)| balance).sum::<u64>() < amount {
+            return Err(MonexoWalletError::NotEnoughTokens);
+        }
+
+        // use the keysets with the highest balance first to keep the number of swaps low
+        keysets.sort_by_key(|(_, balance)| std::cmp::Reverse(*balance));
+
+        // all swaps run before anything is stored, a swap that fails puts back what the previous
+        // ones produced instead of leaving a partial send
+        let mut sends = vec![];
+        let mut remaining = amount;
+        for (keyset, balance) in keysets {
+            if remaining == 0 {
+                break;
+            }
+
+            let send_amount = remaining.min(balance);
+            match self.swap_for_send(mint_url, &keyset, send_amount).await {
+                Ok(send) => sends.push(send),
+                Err(err) => {
+                    self.restore_sends(&sends).await?;
+                    return Err(err);
+                }
+            }
+            remaining -= send_amount;
+        }
+
+        self.store_sends(mint_url, &unit, &sends, None).await
     }
 
-    #[allow(dead_code)]
-    fn create_blinded_messages(
+    /// Redeems a token at its own mint and returns the received amount. The mint's `/v1/info`
+    /// is checked first, so a token from a dead mint or a mint that doesn't support swap (NUT-03)
+    /// fails with `MintUnreachable` before any swap is attempted.
+    pub async fn receive(
         &self,
-        keyset_id: &KeysetId,
-        amount: Amount,
-        secrets_factors: Vec<(String, BlindingFactor)>,
-    ) -> Result<Vec<(BlindedMessage, BlindingFactor)>, MonexoWalletError> {
-        let split_amount = amount.split();
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+    ) -> Result<u64, MonexoWalletError> {
+        let mint_url = tokens.mint().ok_or(MonexoCoreError::InvalidToken)?;
 
-        split_amount
+        let info = self
+            .client
+            .get_info(&mint_url)
+            .await
+            .map_err(|_| MonexoWalletError::MintUnreachable(mint_url.to_string()))?;
+        // swap is mandatory, a mint not listing it still supports it
+        if info.nuts.nut03.is_some_and(|nut03| !nut03.supported) {
+            return Err(MonexoWalletError::MintUnreachable(mint_url.to_string()));
+        }
+
+        self.receive_tokens(&mint_url, wallet_keyset, tokens).await
+    }
+
+    /// Receives only the proofs of the token the mint reports as unspent (NUT-07), so a token
+    /// that has been partially spent can still be redeemed. Returns the received amount, fails
+    /// with [`MonexoWalletError::NoSpendableProofs`] if no proof is unspent.
+    pub async fn receive_tokens_partial(
+        &self,
+        mint_url: &Url,
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+    ) -> Result<u64, MonexoWalletError> {
+        let proofs = tokens.proofs();
+        let ys = proofs
+            .proofs()
+            .iter()
+            .map(|proof| Ok(proof.y()?.to_string()))
+            .collect::<Result<Vec<_>, MonexoWalletError>>()?;
+        let unspent_ys = self
+            .client
+            .post_check_state(mint_url, ys.clone())
+            .await?
+            .states
             .into_iter()
-            .zip(secrets_factors)
-            .map(|(amount, (secret, blinding_factor))| {
-                let b_ = self.dhke.step1_alice(secret, &blinding_factor)?;
-                Ok((
-                    BlindedMessage {
-                        amount,
-                        b_,
-                        id: keyset_id.to_string(),
-                    },
-                    blinding_factor,
-                ))
-            })
-            .collect::<Result<Vec<(_, _)>, MonexoWalletError>>()
+            .filter(|status| status.state == ProofState::Unspent)
+            .map(|status| status.y)
+            .collect::<Vec<_>>();
+
+        let unspent_proofs: Proofs = proofs
+            .proofs()
+            .into_iter()
+            .zip(ys)
+            .filter(|(_, y)| unspent_ys.contains(y))
+            .map(|(proof, _)| proof)
+            .collect::<Vec<_>>()
+            .into();
+        if unspent_proofs.is_empty() {
+            return Err(MonexoWalletError::NoSpendableProofs);
+        }
+
+        let unspent_tokens = TokenV3 {
+            tokens: vec![Token {
+                mint: tokens.mint(),
+                proofs: unspent_proofs,
+            }],
+            ..tokens.clone()
+        };
+        self.receive_tokens(mint_url, wallet_keyset, &unspent_tokens)
+            .await
     }
 
-    #[allow(dead_code)]
-    fn create_proofs_from_blinded_signatures(
+    /// Swaps the tokens and stores the redeemed proofs together with the history entry of the
+    /// receive in a single transaction, so neither can be recorded without the other. Returns
+    /// the received amount.
+    ///
+    /// Tokens of another mint fail with [`MonexoWalletError::MintMismatch`] before any swap is
+    /// attempted, tokens without mint are swapped at `mint_url`. Keysets of a mint the wallet
+    /// doesn't know yet are fetched first.
+    pub async fn receive_tokens(
         &self,
-        keyset_id: &KeysetId,
-        pub_keys: &HashMap<u64, PublicKey>,
-        signatures: Vec<BlindedSignature>,
-        secrets: Vec<String>,
-        outputs: Vec<(BlindedMessage, BlindingFactor)>,
-    ) -> Result<Proofs, MonexoWalletError> {
-        let current_keyset_id = keyset_id.to_string(); // FIXME
+        mint_url: &Url,
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+    ) -> Result<u64, MonexoWalletError> {
+        if let Some(token_mint_url) = tokens.mint() {
+            if token_mint_url != *mint_url {
+                return Err(MonexoWalletError::MintMismatch(
+                    mint_url.to_string(),
+                    token_mint_url.to_string(),
+                ));
+            }
+        }
+        self.fetch_unknown_keysets(mint_url, wallet_keyset, &tokens.proofs())
+            .await?;
+
+        // the mint keeps the fee for swapping the received proofs
+        let fee = self.swap_fee(&tokens.proofs()).await?;
+        let total_amount = tokens.total_amount().saturating_sub(fee);
+        let (remaining_tokens, redeemed_tokens) = self
+            .swap_tokens(mint_url, wallet_keyset, tokens, total_amount.into())
+            .await?;
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .add_proofs(&mut tx, &redeemed_tokens.proofs())
+            .await?;
+        self.delete_pending_outputs(&mut tx, &[&remaining_tokens, &redeemed_tokens])
+            .await?;
+        self.localstore
+            .add_transaction(
+                &mut tx,
+                &HistoryEntry::new(
+                    HistoryKind::Receive,
+                    mint_url,
+                    redeemed_tokens.total_amount(),
+                    tokens.currency_unit.clone().unwrap_or(CurrencyUnit::Usd),
+                )
+                .with_memo(tokens.memo.clone()),
+            )
+            .await?;
+        tx.commit().await?;
+        Ok(redeemed_tokens.total_amount())
+    }
+
+    /// Stores the keysets of the mint if the wallet doesn't know the keyset of a proof or the
+    /// output keyset. They are fetched only once, proofs of keysets the mint still doesn't list
+    /// are left for the mint to reject, but the output keyset must be known to create outputs.
+    async fn fetch_unknown_keysets(
+        &self,
+        mint_url: &Url,
+        wallet_keyset: &WalletKeyset,
+        proofs: &Proofs,
+    ) -> Result<(), MonexoWalletError> {
+        let is_known = |keysets: &[WalletKeyset], keyset_id: &str| {
+            keysets
+                .iter()
+                .any(|keyset| keyset.keyset_id.to_string() == keyset_id)
+        };
+        let output_keyset_id = wallet_keyset.keyset_id.to_string();
+
+        let keysets = self.get_wallet_keysets().await?;
+        if is_known(&keysets, &output_keyset_id)
+            && proofs
+                .proofs()
+                .iter()
+                .all(|proof| is_known(&keysets, &proof.keyset_id))
+        {
+            return Ok(());
+        }
+
+        debug!("Fetching the keysets of {mint_url} for unknown keysets of the token");
+        self.add_mint_keysets(mint_url).await?;
+        if !is_known(&self.get_wallet_keysets().await?, &output_keyset_id) {
+            return Err(MonexoWalletError::KeysetNotFound(output_keyset_id));
+        }
+        Ok(())
+    }
+
+    pub async fn get_history(&self) -> Result<Vec<HistoryEntry>, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let history = self.localstore.list_transactions(&mut tx).await?;
+        tx.commit().await?;
+        Ok(history)
+    }
+
+    // pub async fn get_mint_quote(
+    //     &self,
+    //     mint_url: &Url,
+    //     amount: Amount,
+    //     currency: CurrencyUnit,
+    // ) -> Result<PostMintQuoteBolt11Response, MonexoWalletError> {
+    //     self.client
+    //         .post_mint_quote_bolt11(mint_url, amount.0, currency)
+    //         .await
+    // }
+
+    pub async fn get_melt_quote_onchain(
+        &self,
+        mint_url: &Url,
+        address: String,
+        amount: u64,
+    ) -> Result<Vec<PostMeltQuoteOnchainResponse>, MonexoWalletError> {
+        self.client
+            .post_melt_quote_onchain(mint_url, address, amount)
+            .await
+    }
+
+    pub async fn pay_onchain(
+        &self,
+        mint_url: &Url,
+        wallet_keyset: &WalletKeyset,
+        melt_quote: &PostMeltQuoteOnchainResponse,
+    ) -> Result<PostMeltOnchainResponse, MonexoWalletError> {
+        if !wallet_keyset.belongs_to(mint_url) {
+            return Err(MonexoWalletError::KeysetNotFound(
+                wallet_keyset.keyset_id.to_string(),
+            ));
+        }
+
+        // only proofs of the keyset at this mint can pay its quote
+        let keyset_proofs = self
+            .get_mint_proofs(mint_url)
+            .await?
+            .proofs_by_keyset(&wallet_keyset.keyset_id);
+
+        let ln_amount = melt_quote.amount;
+
+        let selected_proofs = self
+            .select_proofs_with_fee(&keyset_proofs, ln_amount, SelectionStrategy::LargestFirst)
+            .await?;
+
+        // the mint returns the part of the fee reserve it didn't need on these blanks. Their
+        // secrets are derived before the transaction below is opened, deriving them updates the
+        // keyset in a transaction of its own.
+        let blanks = self
+            .create_blank(melt_quote.fee.into(), &wallet_keyset.keyset_id)
+            .await?;
+
+        let mut tx = self.localstore.begin_tx().await?;
+        let total_proofs = {
+            let selected_tokens = (mint_url.to_owned(), selected_proofs.clone()).into();
+            let swap_result = self
+                .swap_tokens(mint_url, wallet_keyset, &selected_tokens, ln_amount.into())
+                .await?;
+            self.localstore
+                .delete_proofs(&mut tx, &selected_proofs)
+                .await?;
+            self.localstore
+                .add_proofs(&mut tx, &swap_result.0.proofs())
+                .await?;
+            self.delete_pending_outputs(&mut tx, &[&swap_result.0, &swap_result.1])
+                .await?;
+
+            swap_result.1.proofs()
+        };
+
+        let melt_response = self
+            .client
+            .post_melt_onchain(
+                mint_url,
+                total_proofs.clone(),
+                melt_quote.quote.clone(),
+                blanks.iter().map(|(blank, _, _)| blank.clone()).collect(),
+            )
+            .await?;
+
+        // the mint spends the proofs as soon as it broadcasted the transaction
+        if matches!(
+            melt_response.state,
+            MeltOnchainState::Pending | MeltOnchainState::Paid
+        ) {
+            // the inputs are spent anyway, change that can't be unblinded must not keep them
+            // in the localstore
+            let change = self
+                .create_change_proofs(wallet_keyset, melt_response.change.clone(), blanks)
+                .unwrap_or_else(|err| {
+                    warn!("Ignoring the change of melt {}: {err}", melt_quote.quote);
+                    Proofs::empty()
+                });
+            self.localstore
+                .delete_proofs(&mut tx, &total_proofs)
+                .await?;
+            // the spent inputs and the change of the swap before are stored even if the mint
+            // returned more change than the melt was worth
+            let Some(melted) = ln_amount.checked_sub(change.total_amount()) else {
+                tx.commit().await?;
+                return Err(MonexoWalletError::UnexpectedResponse(format!(
+                    "change of {} exceeds the melt amount of {ln_amount}",
+                    change.total_amount()
+                )));
+            };
+            self.localstore.add_proofs(&mut tx, &change).await?;
+            self.localstore
+                .add_transaction(
+                    &mut tx,
+                    &HistoryEntry::new(
+                        HistoryKind::Melt,
+                        mint_url,
+                        melted,
+                        wallet_keyset.currency_unit.clone(),
+                    ),
+                )
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(melt_response)
+    }
+
+    /// Recovers the proofs of swaps that were interrupted after their outputs had been sent to
+    /// the mint. Proofs the mint has signed and that are still unspent are stored, inputs the
+    /// mint reports as spent are removed. Returns the recovered amount.
+    pub async fn resume_swaps(&self) -> Result<u64, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let pending_outputs = self.localstore.get_pending_outputs(&mut tx).await?;
+        tx.commit().await?;
+
+        let mut swaps: HashMap<(Url, String), Vec<PendingOutput>> = HashMap::new();
+        for output in pending_outputs {
+            swaps
+                .entry((output.mint_url.clone(), output.keyset_id.clone()))
+                .or_default()
+                .push(output);
+        }
+
+        let mut recovered_amount = 0;
+        for ((mint_url, keyset_id), outputs) in swaps {
+            recovered_amount += self
+                .resume_swap(&mint_url, &keyset_id, outputs)
+                .await?
+                .total_amount();
+        }
+        Ok(recovered_amount)
+    }
+
+    /// Restores the pending outputs of the keyset and returns the recovered proofs
+    async fn resume_swap(
+        &self,
+        mint_url: &Url,
+        keyset_id: &str,
+        pending_outputs: Vec<PendingOutput>,
+    ) -> Result<Proofs, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let wallet_keyset = self
+            .localstore
+            .get_keysets(&mut tx)
+            .await?
+            .into_iter()
+            .find(|keyset| keyset.keyset_id.to_string() == keyset_id)
+            .ok_or_else(|| MonexoCoreError::KeysetNotFound(keyset_id.to_owned()))?;
+        let local_proofs = self
+            .localstore
+            .get_proofs(&mut tx)
+            .await?
+            .proofs_by_keyset(&wallet_keyset.keyset_id);
+        tx.commit().await?;
+
+        let outputs = pending_outputs
+            .iter()
+            .map(|output| {
+                Ok(BlindedMessage {
+                    amount: output.amount,
+                    b_: self
+                        .dhke
+                        .step1_alice(&output.secret, &output.blinding_factor)?,
+                    id: output.keyset_id.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, MonexoWalletError>>()?;
+        let restored = self.client.post_restore(mint_url, outputs.clone()).await?;
+
+        let (restored_outputs, secrets): (Vec<_>, Vec<_>) = restored
+            .outputs
+            .iter()
+            .map(|restored_output| {
+                outputs
+                    .iter()
+                    .zip(&pending_outputs)
+                    .find(|(output, _)| output.b_ == restored_output.b_)
+                    .map(|(output, pending)| {
+                        (
+                            (output.clone(), pending.blinding_factor.clone()),
+                            pending.secret.clone(),
+                        )
+                    })
+                    .ok_or(MonexoWalletError::InvalidProofs)
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .unzip();
+        let restored_proofs = self.create_proofs_from_blinded_signatures(
+            &wallet_keyset.keyset_id,
+            &wallet_keyset.public_keys,
+            restored.signatures,
+            secrets,
+            restored_outputs,
+        )?;
+
+        // the inputs of the interrupted swap are still in the localstore, but spent at the mint
+        let known_proofs = [local_proofs.proofs(), restored_proofs.proofs()].concat();
+        let ys = known_proofs
+            .iter()
+            .map(|proof| Ok(Dhke::hash_to_curve(proof.secret.as_bytes())?.to_string()))
+            .collect::<Result<Vec<_>, MonexoWalletError>>()?;
+        let spent_ys = self
+            .client
+            .post_check_state(mint_url, ys.clone())
+            .await?
+            .states
+            .into_iter()
+            .filter(|status| status.state == ProofState::Spent)
+            .map(|status| status.y)
+            .collect::<Vec<_>>();
+        let is_spent = |proof: &Proof| {
+            ys.iter()
+                .zip(&known_proofs)
+                .any(|(y, known)| known.secret == proof.secret && spent_ys.contains(y))
+        };
+
+        let spent_local_proofs: Proofs = local_proofs
+            .proofs()
+            .into_iter()
+            .filter(|proof| is_spent(proof))
+            .collect::<Vec<_>>()
+            .into();
+        let recovered_proofs: Proofs = restored_proofs
+            .proofs()
+            .into_iter()
+            .filter(|proof| !is_spent(proof))
+            .filter(|proof| {
+                !local_proofs
+                    .proofs()
+                    .iter()
+                    .any(|local| local.secret == proof.secret)
+            })
+            .collect::<Vec<_>>()
+            .into();
+
+        let mut tx = self.localstore.begin_tx().await?;
+        if !spent_local_proofs.is_empty() {
+            self.localstore
+                .delete_proofs(&mut tx, &spent_local_proofs)
+                .await?;
+        }
+        self.localstore
+            .add_proofs(&mut tx, &recovered_proofs)
+            .await?;
+        self.localstore
+            .delete_pending_outputs(
+                &mut tx,
+                &pending_outputs
+                    .into_iter()
+                    .map(|output| output.secret)
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+        tx.commit().await?;
+        Ok(recovered_proofs)
+    }
+
+    /// Asks the mint which of its local proofs are spent (NUT-07) and deletes those from the
+    /// localstore. Returns the deleted proofs.
+    pub async fn check_proofs_spent(&self, mint_url: &Url) -> Result<Proofs, MonexoWalletError> {
+        let local_proofs = self.get_mint_proofs(mint_url).await?;
+        if local_proofs.is_empty() {
+            return Ok(local_proofs);
+        }
+
+        let ys = local_proofs
+            .proofs()
+            .iter()
+            .map(|proof| Ok(proof.y()?.to_string()))
+            .collect::<Result<Vec<_>, MonexoWalletError>>()?;
+        let spent_ys = self
+            .client
+            .post_check_state(mint_url, ys.clone())
+            .await?
+            .states
+            .into_iter()
+            .filter(|status| status.state == ProofState::Spent)
+            .map(|status| status.y)
+            .collect::<Vec<_>>();
+
+        let spent_proofs: Proofs = local_proofs
+            .proofs()
+            .into_iter()
+            .zip(ys)
+            .filter(|(_, y)| spent_ys.contains(y))
+            .map(|(proof, _)| proof)
+            .collect::<Vec<_>>()
+            .into();
+
+        if !spent_proofs.is_empty() {
+            let mut tx = self.localstore.begin_tx().await?;
+            self.localstore
+                .delete_proofs(&mut tx, &spent_proofs)
+                .await?;
+            tx.commit().await?;
+        }
+        Ok(spent_proofs)
+    }
+
+    /// Recomputes the balance from the seed alone: derives all secrets up to the `last_index` of
+    /// every keyset of the mint, asks the mint which of them it has signed (NUT-09) and which of
+    /// those are spent (NUT-07), and compares the result with the mint's proofs in the
+    /// localstore. The localstore is not changed.
+    pub async fn deep_audit(&self, mint_url: &Url) -> Result<AuditReport, MonexoWalletError> {
+        let local_proofs = self.get_mint_proofs(mint_url).await?;
+        let mut issued_proofs = vec![];
+
+        let keysets = self
+            .get_wallet_keysets()
+            .await?
+            .into_iter()
+            .filter(|keyset| keyset.belongs_to(mint_url));
+        for keyset in keysets {
+            let secret_range =
+                self.secret
+                    .derive_range(&keyset.keyset_id, 0, keyset.last_index as u32 + 1)?;
+
+            for batch in secret_range.chunks(RESTORE_BATCH_SIZE) {
+                // the mint looks up outputs by B_ only, the amount is taken from its signature
+                let outputs = batch
+                    .iter()
+                    .map(|(secret, blinding_factor)| {
+                        Ok(BlindedMessage {
+                            amount: 0,
+                            b_: self.dhke.step1_alice(secret, blinding_factor)?,
+                            id: keyset.keyset_id.to_string(),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, MonexoWalletError>>()?;
+                let restored = self.client.post_restore(mint_url, outputs.clone()).await?;
+
+                let (restored_outputs, secrets): (Vec<_>, Vec<_>) = restored
+                    .outputs
+                    .iter()
+                    .zip(&restored.signatures)
+                    .map(|(restored_output, signature)| {
+                        outputs
+                            .iter()
+                            .zip(batch)
+                            .find(|(output, _)| output.b_ == restored_output.b_)
+                            .map(|(output, (secret, blinding_factor))| {
+                                (
+                                    (
+                                        BlindedMessage {
+                                            amount: signature.amount,
+                                            ..output.clone()
+                                        },
+                                        blinding_factor.clone(),
+                                    ),
+                                    secret.clone(),
+                                )
+                            })
+                            .ok_or(MonexoWalletError::InvalidProofs)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .unzip();
+                let proofs = self.create_proofs_from_blinded_signatures(
+                    &keyset.keyset_id,
+                    &keyset.public_keys,
+                    restored.signatures,
+                    secrets,
+                    restored_outputs,
+                )?;
+                issued_proofs.extend(proofs.proofs());
+            }
+        }
+
+        let known_proofs = issued_proofs
+            .iter()
+            .chain(local_proofs.proofs().iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        let ys = known_proofs
+            .iter()
+            .map(|proof| Ok(proof.y()?.to_string()))
+            .collect::<Result<Vec<_>, MonexoWalletError>>()?;
+        let spent_ys = match ys.is_empty() {
+            true => vec![],
+            false => self
+                .client
+                .post_check_state(mint_url, ys.clone())
+                .await?
+                .states
+                .into_iter()
+                .filter(|status| status.state == ProofState::Spent)
+                .map(|status| status.y)
+                .collect::<Vec<_>>(),
+        };
+        let is_spent = |proof: &Proof| {
+            ys.iter()
+                .zip(&known_proofs)
+                .any(|(y, known)| known.secret == proof.secret && spent_ys.contains(y))
+        };
+
+        let unspent_proofs: Proofs = issued_proofs
+            .into_iter()
+            .filter(|proof| !is_spent(proof))
+            .collect::<Vec<_>>()
+            .into();
+        let missing_proofs: Proofs = unspent_proofs
+            .proofs()
+            .into_iter()
+            .filter(|proof| {
+                !local_proofs
+                    .proofs()
+                    .iter()
+                    .any(|local| local.secret == proof.secret)
+            })
+            .collect::<Vec<_>>()
+            .into();
+        let spent_local_proofs: Proofs = local_proofs
+            .proofs()
+            .into_iter()
+            .filter(|proof| is_spent(proof))
+            .collect::<Vec<_>>()
+            .into();
+
+        Ok(AuditReport {
+            mint_balance: unspent_proofs.total_amount(),
+            local_balance: local_proofs.total_amount(),
+            missing_proofs,
+            spent_local_proofs,
+        })
+    }
+
+    /// Removes the pending outputs of a finished swap, must be called in the transaction that
+    /// stores the resulting proofs
+    async fn add_pending_outputs(
+        &self,
+        mint_url: &Url,
+        secrets: &[(String, BlindingFactor)],
+        outputs: &[BlindedMessage],
+    ) -> Result<(), MonexoWalletError> {
+        let pending_outputs = secrets
+            .iter()
+            .zip(outputs)
+            .map(|((secret, blinding_factor), output)| PendingOutput {
+                mint_url: mint_url.to_owned(),
+                keyset_id: output.id.clone(),
+                amount: output.amount,
+                secret: secret.clone(),
+                blinding_factor: blinding_factor.clone(),
+            })
+            .collect::<Vec<_>>();
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .add_pending_outputs(&mut tx, &pending_outputs)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_pending_outputs(
+        &self,
+        tx: &mut L::Tx,
+        tokens: &[&TokenV3],
+    ) -> Result<(), MonexoWalletError> {
+        let secrets = tokens
+            .iter()
+            .flat_map(|tokens| tokens.proofs().proofs())
+            .map(|proof| proof.secret)
+            .collect::<Vec<_>>();
+        self.localstore.delete_pending_outputs(tx, &secrets).await
+    }
+
+    async fn create_secrets(
+        &self,
+        keyset_id: &KeysetId,
+        amount: u32,
+    ) -> Result<Vec<(String, BlindingFactor)>, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let all_keysets = self.localstore.get_keysets(&mut tx).await?;
+        let keyset = all_keysets
+            .iter()
+            .find(|k| k.keyset_id == *keyset_id)
+            .expect("keyset not found create-secrets");
+
+        let start_index = (keyset.last_index + 1) as u32;
+        let secret_range = self.secret.derive_range(keyset_id, start_index, amount)?;
+
+        self.localstore
+            .update_keyset_last_index(
+                &mut tx,
+                &WalletKeyset {
+                    last_index: (start_index + amount - 1) as u64,
+                    ..keyset.clone()
+                },
+            )
+            .await?;
+        tx.commit().await?;
+        Ok(secret_range)
+    }
+
+    pub async fn swap_tokens(
+        &self,
+        mint_url: &Url,
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+        splt_amount: Amount,
+    ) -> Result<(TokenV3, TokenV3), MonexoWalletError> {
+        // the outputs are worth the inputs minus the fee the mint charges for them
+        let fee = self.swap_fee(&tokens.proofs()).await?;
+        let total_token_amount = tokens.total_amount();
+        if splt_amount.0 + fee > total_token_amount {
+            return Err(MonexoWalletError::InvalidAmount(format!(
+                "can't split {} from tokens worth {total_token_amount} with a fee of {fee}",
+                splt_amount.0
+            )));
+        }
+        let total_token_amount = total_token_amount - fee;
+
+        let first_amount = (Amount::from(total_token_amount) - splt_amount)?;
+        let first_secrets = self
+            .create_secrets(
+                &wallet_keyset.keyset_id,
+                first_amount.split_for_keyset(wallet_keyset).len() as u32,
+            )
+            .await?;
+        let first_outputs =
+            self.create_blinded_messages(wallet_keyset, first_amount, first_secrets.clone())?;
+
+        // ############################################################################
+
+        let second_amount = splt_amount;
+        let second_secrets = self
+            .create_secrets(
+                &wallet_keyset.keyset_id,
+                second_amount.split_for_keyset(wallet_keyset).len() as u32,
+            )
+            .await?;
+        let second_outputs =
+            self.create_blinded_messages(wallet_keyset, second_amount, second_secrets.clone())?;
+
+        let mut total_outputs = vec![];
+        total_outputs.extend(get_blinded_msg(first_outputs.clone()));
+        total_outputs.extend(get_blinded_msg(second_outputs.clone()));
+
+        check_swap_amounts(total_token_amount, total_outputs.total_amount())?;
+
+        // keep the outputs until the caller has stored the proofs, so they can be restored if
+        // the wallet dies after the mint signed them
+        self.add_pending_outputs(
+            mint_url,
+            &[first_secrets.clone(), second_secrets.clone()].concat(),
+            &total_outputs,
+        )
+        .await?;
+
+        let split_result = self
+            .client
+            .post_swap(mint_url, tokens.proofs(), total_outputs)
+            .await?;
+
+        // a mint that signs less than the outputs, or nothing at all, has still spent the inputs
+        check_swap_amounts(total_token_amount, split_result.signatures.total_amount())?;
+
+        let len_first = first_secrets.len();
+        let secrets = [first_secrets, second_secrets].concat();
+        let outputs = [first_outputs, second_outputs].concat();
+
+        let secrets = secrets.into_iter().map(|(s, _)| s).collect::<Vec<String>>();
+
+        let proofs = self
+            .create_proofs_from_blinded_signatures(
+                &wallet_keyset.keyset_id,
+                &wallet_keyset.public_keys,
+                split_result.signatures,
+                secrets,
+                outputs,
+            )?
+            .proofs();
+
+        let first_tokens: TokenV3 = (
+            mint_url.to_owned(),
+            wallet_keyset.currency_unit.clone(),
+            proofs[0..len_first].to_vec().into(),
+        )
+            .into();
+        let second_tokens: TokenV3 = (
+            mint_url.to_owned(),
+            wallet_keyset.currency_unit.clone(),
+            proofs[len_first..proofs.len()].to_vec().into(),
+        )
+            .into();
+
+        check_swap_amounts(
+            total_token_amount,
+            sum_amounts([first_tokens.total_amount(), second_tokens.total_amount()]),
+        )?;
+
+        Ok((first_tokens, second_tokens))
+    }
+
+    /// Fetches the info of the mint and stores it in the localstore.
+    ///
+    /// If the mint can't be reached, the last stored info is returned instead and marked as stale.
+    /// Without a stored info the error of the mint is returned.
+    pub async fn get_mint_info(&self, mint_url: &Url) -> Result<MintInfo, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let mint_info = match self.client.get_info(mint_url).await {
+            Ok(info) => {
+                self.localstore
+                    .upsert_mint_info(&mut tx, mint_url, &info)
+                    .await?;
+                MintInfo {
+                    info,
+                    is_stale: false,
+                }
+            }
+            Err(err) => match self.localstore.get_mint_info(&mut tx, mint_url).await? {
+                Some(info) => MintInfo {
+                    info,
+                    is_stale: true,
+                },
+                None => return Err(err),
+            },
+        };
+        tx.commit().await?;
+        Ok(mint_info)
+    }
+
+    pub async fn mint_tokens(
+        &self,
+        mint_url: &Url,
+        wallet_keyset: &WalletKeyset,
+        amount: Amount,
+        quote_id: String,
+    ) -> Result<TokenV3, MonexoWalletError> {
+        let secret_range = self
+            .create_secrets(
+                &wallet_keyset.keyset_id,
+                amount.split_for_keyset(wallet_keyset).len() as u32,
+            )
+            .await?;
+        let outputs = self.create_blinded_messages(wallet_keyset, amount, secret_range.clone())?;
+        let blinded_messages = get_blinded_msg(outputs.clone());
+
+        // kept until the proofs are stored, so they can be restored if the wallet dies before
+        self.add_pending_outputs(mint_url, &secret_range, &blinded_messages)
+            .await?;
+
+        let signatures = self
+            .client
+            .post_mint_onchain(mint_url, quote_id.clone(), blinded_messages)
+            .await?
+            .signatures;
+
+        let proofs = self.create_proofs_from_blinded_signatures(
+            &wallet_keyset.keyset_id,
+            &wallet_keyset.public_keys,
+            signatures,
+            secret_range.into_iter().map(|(secret, _)| secret).collect(),
+            outputs,
+        )?;
+
+        let tokens: TokenV3 = (mint_url.to_owned(), proofs).into();
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .add_proofs(&mut tx, &tokens.proofs())
+            .await?;
+        self.localstore
+            .delete_mint_quote(&mut tx, &quote_id)
+            .await?;
+        self.delete_pending_outputs(&mut tx, &[&tokens]).await?;
+        self.localstore
+            .add_transaction(
+                &mut tx,
+                &HistoryEntry::new(
+                    HistoryKind::Mint,
+                    mint_url,
+                    tokens.total_amount(),
+                    wallet_keyset.currency_unit.clone(),
+                ),
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(tokens)
+    }
+
+    pub async fn create_blank(
+        &self,
+        fee_reserve: Amount,
+        keyset_id: &KeysetId,
+    ) -> Result<Vec<(BlindedMessage, BlindingFactor, String)>, MonexoWalletError> {
+        if fee_reserve.0 == 0 {
+            return Ok(vec![]);
+        }
+
+        // NUT-08: the mint assigns powers of two to the blanks, so n blanks return any change up
+        // to 2^n - 1. The bit length of the fee reserve is the smallest n covering it.
+        let count = u64::BITS - fee_reserve.0.leading_zeros();
+
+        let secret_range = self.create_secrets(keyset_id, count).await?;
+        let blinded_messages = secret_range
+            .into_iter()
+            .map(|(secret, blinding_factor)| {
+                let b_ = self.dhke.step1_alice(secret.clone(), &blinding_factor)?;
+                Ok((
+                    BlindedMessage {
+                        amount: 1,
+                        b_,
+                        id: keyset_id.to_string(),
+                    },
+                    blinding_factor,
+                    secret,
+                ))
+            })
+            .collect::<Result<Vec<(_, _, _)>, MonexoWalletError>>()?;
+
+        Ok(blinded_messages)
+    }
+
+    /// Unblinds the change of a melt. The mint assigns the amounts of the change to the first
+    /// blanks (NUT-08), so the signatures are matched to the blanks in order.
+    fn create_change_proofs(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        signatures: Vec<BlindedSignature>,
+        blanks: Vec<(BlindedMessage, BlindingFactor, String)>,
+    ) -> Result<Proofs, MonexoWalletError> {
+        let (outputs, secrets) = signatures
+            .iter()
+            .zip(blanks)
+            .map(|(signature, (blank, blinding_factor, secret))| {
+                (
+                    (
+                        BlindedMessage {
+                            amount: signature.amount,
+                            ..blank
+                        },
+                        blinding_factor,
+                    ),
+                    secret,
+                )
+            })
+            .unzip();
+
+        self.create_proofs_from_blinded_signatures(
+            &wallet_keyset.keyset_id,
+            &wallet_keyset.public_keys,
+            signatures,
+            secrets,
+            outputs,
+        )
+    }
+
+    #[allow(dead_code)]
+    fn create_blinded_messages(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        amount: Amount,
+        secrets_factors: Vec<(String, BlindingFactor)>,
+    ) -> Result<Vec<(BlindedMessage, BlindingFactor)>, MonexoWalletError> {
+        let split_amount = amount.split_for_keyset(wallet_keyset);
+
+        split_amount
+            .into_iter()
+            .zip(secrets_factors)
+            .map(|(amount, (secret, blinding_factor))| {
+                let b_ = self.dhke.step1_alice(secret, &blinding_factor)?;
+                Ok((
+                    BlindedMessage {
+                        amount,
+                        b_,
+                        id: wallet_keyset.keyset_id.to_string(),
+                    },
+                    blinding_factor,
+                ))
+            })
+            .collect::<Result<Vec<(_, _)>, MonexoWalletError>>()
+    }
+
+    /// Unblinds the signatures of the mint into proofs, in the order of `outputs`.
+    ///
+    /// Signatures are matched to outputs by amount and keyset id instead of relying on the mint
+    /// to return them in request order. A signature with a DLEQ proof only matches the output it
+    /// was created for, a tampered proof matches none and fails with
+    /// [`MonexoWalletError::InvalidProofs`]. Signatures without DLEQ proof and with the same
+    /// amount and keyset id can't be told apart, they are taken in the order of the outputs.
+    fn create_proofs_from_blinded_signatures(
+        &self,
+        keyset_id: &KeysetId,
+        pub_keys: &HashMap<u64, PublicKey>,
+        signatures: Vec<BlindedSignature>,
+        secrets: Vec<String>,
+        outputs: Vec<(BlindedMessage, BlindingFactor)>,
+    ) -> Result<Proofs, MonexoWalletError> {
+        let current_keyset_id = keyset_id.to_string(); // FIXME
+
+        let mut signatures = signatures.into_iter().map(Some).collect::<Vec<_>>();
+
+        outputs
+            .into_iter()
+            .zip(secrets)
+            .map(|((output, blinding_factor), secret)| {
+                let key = pub_keys
+                    .get(&output.amount)
+                    .ok_or(MonexoWalletError::PubkeyNotFound)?;
+
+                let signature = signatures
+                    .iter_mut()
+                    .find(|signature| {
+                        signature.as_ref().is_some_and(|s| {
+                            s.amount == output.amount
+                                && s.id == output.id
+                                && verify_dleq(&output, s, key).is_ok()
+                        })
+                    })
+                    .and_then(Option::take)
+                    .ok_or(MonexoWalletError::InvalidProofs)?;
+                let pub_alice = self.dhke.step3_alice(signature.c_, blinding_factor, *key)?;
+
+                Ok(Proof::new(
+                    output.amount,
+                    secret,
+                    pub_alice,
+                    current_keyset_id.clone(),
+                ))
+            })
+            .collect::<Result<Vec<_>, MonexoWalletError>>()
+            .map(Proofs::from)
+    }
+
+    pub async fn get_proofs(&self) -> Result<Proofs, MonexoWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let proofs = self.localstore.get_proofs(&mut tx).await?;
+        tx.commit().await?;
+        Ok(proofs)
+    }
+}
+
+// FIXME implement for Vec<BlindedMessage, Secretkey>
+fn get_blinded_msg(blinded_messages: Vec<(BlindedMessage, BlindingFactor)>) -> Vec<BlindedMessage> {
+    blinded_messages
+        .into_iter()
+        .map(|(msg, _)| msg)
+        .collect::<Vec<BlindedMessage>>()
+}
+
+/// Fails if the outputs of a swap aren't worth exactly its inputs
+const fn check_swap_amounts(input: u64, output: u64) -> Result<(), MonexoWalletError> {
+    if input != output {
+        return Err(MonexoWalletError::SwapAmountMismatch(input, output));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use monexo_core::{
+        amount::Amount,
+        blind::{calculate_dleq, BlindedMessage, BlindedSignature, TotalAmount},
+        dhke::Dhke,
+        fixture::{read_fixture, read_fixture_as},
+        keyset::{Keyset, KeysetId, Keysets, MintKeyset},
+        primitives::{
+            CurrencyUnit, KeyResponse, KeysResponse, MeltOnchainState, MintInfoResponse,
+            MintOnchainState, Nuts, PostCheckStateResponse, PostCurrencyExchangeResponse,
+            PostMeltOnchainResponse, PostMeltQuoteOnchainResponse, PostMintOnchainResponse,
+            PostMintQuoteOnchainResponse, PostRestoreResponse, PostSwapResponse, ProofState,
+            ProofStatus,
+        },
+        proof::{Proof, Proofs},
+        token::TokenV3,
+    };
+    use secp256k1::PublicKey;
+    use url::Url;
+
+    use crate::{
+        client::MockCashuClient,
+        error::MonexoWalletError,
+        localstore::{
+            sqlite::SqliteLocalStore, HistoryKind, LocalStore, WalletKeyset, WalletMintQuote,
+        },
+        secret::DeterministicSecret,
+        wallet::{KeysetDenominations, MintQuoteStatus, Wallet, WalletBuilder},
+    };
+
+    /// Signs the outputs like a mint holding `mint_keys` would
+    pub(crate) fn sign_outputs(
+        mint_keys: &[MintKeyset],
+        outputs: &[BlindedMessage],
+    ) -> PostSwapResponse {
+        let dhke = Dhke::new();
+        let signatures = outputs
+            .iter()
+            .map(|output| {
+                let keys = mint_keys
+                    .iter()
+                    .find(|keys| keys.keyset_id == output.id)
+                    .expect("unknown keyset");
+                let private_key = &keys.private_keys[&output.amount];
+                let c_ = dhke
+                    .step2_bob(output.b_, private_key)
+                    .expect("signing failed");
+                BlindedSignature {
+                    amount: output.amount,
+                    c_,
+                    id: output.id.clone(),
+                    dleq: Some(calculate_dleq(private_key, &output.b_, &c_).expect("dleq failed")),
+                }
+            })
+            .collect();
+        PostSwapResponse { signatures }
+    }
+
+    pub(crate) fn create_mock() -> MockCashuClient {
+        let keys = MintKeyset::new("mykey", "");
+        let key_response = KeyResponse {
+            keys: keys.public_keys.clone(),
+            id: keys.keyset_id.clone(),
+            unit: CurrencyUnit::Usd,
+        };
+        let keys_response = KeysResponse::new(key_response.clone());
+        let keys_by_id_response = keys_response.clone();
+        let keysets = Keysets::new(vec![Keyset {
+            id: keys.keyset_id,
+            unit: CurrencyUnit::Usd,
+            active: true,
+            input_fee_ppk: 0,
+        }]);
+
+        let mut client = MockCashuClient::default();
+        client
+            .expect_get_keys()
+            .returning(move |_| Ok(keys_response.clone()));
+        client
+            .expect_get_keysets()
+            .returning(move |_| Ok(keysets.clone()));
+        client
+            .expect_get_keys_by_id()
+            .returning(move |_, _| Ok(keys_by_id_response.clone()));
+        client.expect_is_v1_supported().returning(move |_| Ok(true));
+        client
+    }
+
+    #[tokio::test]
+    async fn test_blank_blinded_messages_1000_sats() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let client = create_mock();
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        let result = wallet
+            .create_blank(1000.into(), &KeysetId::new("00d31cecf59d18c0")?)
+            .await;
+        println!("{:?}", result);
+        assert!(result.is_ok());
+        let result = result.unwrap();
+        assert!(result.len() == 10);
+        assert!(result.first().unwrap().0.amount == 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blank_blinded_messages_zero_sats() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let client = create_mock();
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        let result = wallet
+            .create_blank(0.into(), &KeysetId::new("00d31cecf59d18c0")?)
+            .await;
+        println!("{:?}", result);
+        assert!(result.is_ok());
+        assert!(result.unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blank_blinded_messages_cover_fee_reserve() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        for (fee_reserve, expected_count) in [(1, 1), (3, 2), (4, 3), (1000, 10), (4095, 12)] {
+            let result = wallet
+                .create_blank(fee_reserve.into(), &wallet_keyset.keyset_id)
+                .await?;
+            assert_eq!(expected_count, result.len(), "fee reserve {fee_reserve}");
+
+            // every change up to the fee reserve can be returned in distinct powers of two
+            let max_change = (1u64 << result.len()) - 1;
+            assert!(max_change >= fee_reserve, "fee reserve {fee_reserve}");
+            for change in [1, fee_reserve / 2, fee_reserve] {
+                assert!(Amount::from(change).split().len() <= result.len());
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blank_blinded_messages_serialize() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let client = create_mock();
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let result = wallet
+            .create_blank(4000.into(), &KeysetId::new("00d31cecf59d18c0")?)
+            .await?;
+        for (blinded_message, _, _) in result {
+            let out = serde_json::to_string(&blinded_message)?;
+            assert!(!out.is_empty());
+        }
+        Ok(())
+    }
+
+    // #[tokio::test]
+    // async fn test_mint_tokens() -> anyhow::Result<()> {
+    //     let mint_response =
+    //         read_fixture_as::<PostMintOnchainResponse>("post_mint_response_20.json")?;
+
+    //     let mut client = create_mock();
+    //     client
+    //         .expect_post_mint_onchain()
+    //         .returning(move |_, _, _| Ok(mint_response.clone()));
+
+    //     let localstore = SqliteLocalStore::with_in_memory().await?;
+    //     let wallet_keyset = create_test_wallet_keyset()?;
+    //     let mut tx = localstore.begin_tx().await?;
+    //     localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+    //     tx.commit().await?;
+
+    //     let wallet = WalletBuilder::new()
+    //         .with_client(client)
+    //         .with_localstore(localstore)
+    //         .build()
+    //         .await?;
+    //     let mint_url = Url::parse("http://127.0.0.1:3338")?;
+
+    //     let result = wallet
+    //         .mint_tokens(
+    //             &mint_url,
+    //             &wallet_keyset,
+    //             20.into(),
+    //             "hash".to_string(),
+    //         )
+    //         .await?;
+    //     assert_eq!(20, result.total_amount());
+    //     result.tokens.into_iter().for_each(|t| {
+    //         assert_eq!(mint_url, t.mint.expect("mint is empty"));
+    //     });
+    //     Ok(())
+    // }
+
+    #[tokio::test]
+    async fn test_swap() -> anyhow::Result<()> {
+        let split_response = read_fixture_as::<PostSwapResponse>("post_swap_response_24_40.json")?;
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, _| Ok(split_response.clone()));
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let tokens = read_fixture("token_64.cashu")?.try_into()?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet
+            .swap_tokens(&mint_url, &keyset, &tokens, 20.into())
+            .await?;
+
+        let first = result.0;
+
+        assert_eq!(CurrencyUnit::Usd, first.clone().currency_unit.unwrap());
+        assert_eq!(44, first.total_amount());
+
+        let second = result.1;
+
+        assert_eq!(CurrencyUnit::Usd, second.clone().currency_unit.unwrap());
+        assert_eq!(20, second.total_amount());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_prints_nothing() -> anyhow::Result<()> {
+        // libtest captures stdout, so the test checks the output of a child process running it
+        const CHILD_ENV: &str = "MONEXO_TEST_SWAP_CHILD";
+        if std::env::var_os(CHILD_ENV).is_none() {
+            let output = std::process::Command::new(std::env::current_exe()?)
+                .args([
+                    "wallet::tests::test_swap_prints_nothing",
+                    "--exact",
+                    "--nocapture",
+                ])
+                .env(CHILD_ENV, "1")
+                .output()?;
+            assert!(output.status.success());
+
+            let stdout = String::from_utf8(output.stdout)?;
+            let (_, swap_output) = stdout.split_once("<swap>").expect("swap didn't start");
+            let (swap_output, _) = swap_output.split_once("</swap>").expect("swap failed");
+            assert_eq!("", swap_output);
+            return Ok(());
+        }
+
+        let split_response = read_fixture_as::<PostSwapResponse>("post_swap_response_24_40.json")?;
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, _| Ok(split_response.clone()));
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let tokens = read_fixture("token_64.cashu")?.try_into()?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        print!("<swap>");
+        wallet
+            .swap_tokens(&mint_url, &keyset, &tokens, 20.into())
+            .await?;
+        print!("</swap>");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_split_exceeds_tokens() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let tokens = read_fixture("token_64.cashu")?.try_into()?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet
+            .swap_tokens(&mint_url, &keyset, &tokens, 65.into())
+            .await;
+
+        assert!(matches!(result, Err(MonexoWalletError::InvalidAmount(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_swap_amounts() {
+        assert!(super::check_swap_amounts(64, 64).is_ok());
+        assert!(matches!(
+            super::check_swap_amounts(64, 60),
+            Err(MonexoWalletError::SwapAmountMismatch(64, 60))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_ugx() -> anyhow::Result<()> {
+        let mint_keys = MintKeyset::new("mykey", "ugx");
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&mint_keys.keyset_id)?,
+            CurrencyUnit::Ugx,
+            0,
+            mint_keys.public_keys.clone(),
+            true,
+        )
+        .with_mint_url(&Url::parse("http://127.0.0.1:3338")?);
+
+        let signing_keys = [mint_keys.clone()];
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, outputs| Ok(sign_outputs(&signing_keys, &outputs)));
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        localstore
+            .add_proofs(
+                &mut tx,
+                &Proofs::new(vec![Proof::new(
+                    8,
+                    "secret8".to_string(),
+                    mint_keys.public_keys[&1],
+                    mint_keys.keyset_id.clone(),
+                )]),
+            )
+            .await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.send_tokens(&mint_url, &keyset, 5).await?;
+
+        assert_eq!(Some(CurrencyUnit::Ugx), result.currency_unit);
+        assert_eq!(5, result.total_amount());
+        assert_eq!(
+            Some(&3),
+            wallet
+                .get_balance_by_currency()
+                .await?
+                .get(&CurrencyUnit::Ugx)
+        );
+        let history = wallet.get_history().await?;
+        assert_eq!(CurrencyUnit::Ugx, history[0].unit);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_exact_match() -> anyhow::Result<()> {
+        let (localstore, keyset) = create_localstore_with_proofs(&[2, 4, 8, 32]).await?;
+
+        // the mock has no swap expectation, so a swap would fail the test
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.send_tokens(&mint_url, &keyset, 10).await?;
+
+        assert_eq!(10, result.total_amount());
+        assert_eq!(2, result.proofs().len());
+        assert_eq!(36, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_no_signatures() -> anyhow::Result<()> {
+        let (localstore, keyset) = create_localstore_with_proofs(&[2, 4, 8, 32]).await?;
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(|_, _, _| Ok(PostSwapResponse { signatures: vec![] }));
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.send_tokens(&mint_url, &keyset, 20).await;
+
+        assert!(matches!(
+            result,
+            Err(MonexoWalletError::SwapAmountMismatch(_, 0))
+        ));
+        assert_eq!(46, wallet.get_balance().await?);
+        assert!(wallet.get_history().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_with_memo() -> anyhow::Result<()> {
+        let (localstore, keyset) = create_localstore_with_proofs(&[2, 4, 8]).await?;
+
+        let signing_keys = [MintKeyset::new("mykey", "")];
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, outputs| Ok(sign_outputs(&signing_keys, &outputs)));
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let memo = Some("for the coffee".to_owned());
+        // 6 is sent without a swap, 5 needs one
+        for amount in [6, 5] {
+            let result = wallet
+                .send_tokens_with_memo(&mint_url, &keyset, amount, memo.clone())
+                .await?;
+            assert_eq!(amount, result.total_amount());
+            assert_eq!(memo, result.memo);
+            assert_eq!(memo, TokenV3::deserialize(result.serialize()?)?.memo);
+            assert_eq!(memo, wallet.get_history().await?[0].memo);
+        }
+
+        let result = wallet.send_tokens(&mint_url, &keyset, 1).await?;
+        assert_eq!(None, result.memo);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_input_fee() -> anyhow::Result<()> {
+        let (localstore, keyset) = create_localstore_with_proofs(&[8, 4, 2]).await?;
+        let keyset = keyset.with_input_fee_ppk(1000);
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        // 8 + 4 would cover 11 without a fee, but not the fee of 2 for swapping them
+        let signing_keys = [MintKeyset::new("mykey", "")];
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .times(1)
+            .returning(move |_, proofs, outputs| {
+                assert_eq!(3, proofs.len());
+                assert_eq!(11, outputs.total_amount());
+                Ok(sign_outputs(&signing_keys, &outputs))
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.send_tokens(&mint_url, &keyset, 11).await?;
+        assert_eq!(11, result.total_amount());
+        assert_eq!(0, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_input_fee_not_enough_tokens() -> anyhow::Result<()> {
+        let (localstore, keyset) = create_localstore_with_proofs(&[8, 4, 2]).await?;
+        let keyset = keyset.with_input_fee_ppk(1000);
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        // all proofs are worth 14, but swapping them costs 3 and no subset adds up to 13
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.send_tokens(&mint_url, &keyset, 13).await;
+        assert!(matches!(result, Err(MonexoWalletError::NotEnoughTokens)));
+        assert_eq!(14, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_consolidate() -> anyhow::Result<()> {
+        let (localstore, keyset) = create_localstore_with_proofs(&[4, 2, 1]).await?;
+        let mint_keys = MintKeyset::new("mykey", "");
+        let mut tx = localstore.begin_tx().await?;
+        localstore
+            .add_proofs(
+                &mut tx,
+                &Proofs::new(
+                    [4, 2, 1, 1, 1]
+                        .iter()
+                        .enumerate()
+                        .map(|(i, amount)| {
+                            Proof::new(
+                                *amount,
+                                format!("extra_secret{i}"),
+                                mint_keys.public_keys[&1],
+                                mint_keys.keyset_id.clone(),
+                            )
+                        })
+                        .collect(),
+                ),
+            )
+            .await?;
+        tx.commit().await?;
+
+        let signing_keys = [mint_keys];
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .times(1)
+            .returning(move |_, proofs, outputs| {
+                assert_eq!(8, proofs.len());
+                assert_eq!(1, outputs.len());
+                Ok(sign_outputs(&signing_keys, &outputs))
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let (before, after) = wallet.consolidate(&mint_url, &keyset).await?;
+        assert_eq!((8, 1), (before, after));
+        assert_eq!(16, wallet.get_balance().await?);
+        assert_eq!(1, wallet.get_proofs().await?.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_consolidate_already_optimal() -> anyhow::Result<()> {
+        let (localstore, keyset) = create_localstore_with_proofs(&[8, 4, 2]).await?;
+        // the mock panics if the wallet swaps
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        assert_eq!((3, 3), wallet.consolidate(&mint_url, &keyset).await?);
+        assert_eq!(14, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_offline() -> anyhow::Result<()> {
+        let (localstore, keyset) = create_localstore_with_proofs(&[2, 4, 8, 32]).await?;
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.send_tokens_offline(&mint_url, &keyset, 34).await?;
+
+        assert_eq!(34, result.total_amount());
+        assert_eq!(Some(CurrencyUnit::Usd), result.currency_unit);
+        assert_eq!(12, wallet.get_balance().await?);
+        assert_eq!(HistoryKind::Send, wallet.get_history().await?[0].kind);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_offline_no_exact_match() -> anyhow::Result<()> {
+        let (localstore, keyset) = create_localstore_with_proofs(&[2, 4, 8, 32]).await?;
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.send_tokens_offline(&mint_url, &keyset, 5).await;
+
+        assert!(matches!(result, Err(MonexoWalletError::NoExactAmount(5))));
+        assert_eq!(46, wallet.get_balance().await?);
+        assert!(wallet.get_history().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resume_interrupted_swap() -> anyhow::Result<()> {
+        let split_response = read_fixture_as::<PostSwapResponse>("post_swap_response_24_40.json")?;
+        let restore_signatures = split_response.signatures.clone();
+        let keyset = create_test_wallet_keyset()?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+
+        // the inputs of a swap are proofs of the wallet's keyset
+        let fixture: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+        let tokens: TokenV3 = (
+            mint_url.clone(),
+            Proofs::new(
+                fixture
+                    .proofs()
+                    .proofs()
+                    .into_iter()
+                    .map(|proof| Proof {
+                        keyset_id: keyset.keyset_id.to_string(),
+                        ..proof
+                    })
+                    .collect(),
+            ),
+        )
+            .into();
+        let spent_ys = tokens
+            .proofs()
+            .proofs()
+            .iter()
+            .map(|proof| Ok(Dhke::hash_to_curve(proof.secret.as_bytes())?.to_string()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, _| Ok(split_response.clone()));
+        client.expect_post_restore().returning(move |_, outputs| {
+            Ok(PostRestoreResponse {
+                outputs,
+                signatures: restore_signatures.clone(),
+            })
+        });
+        client.expect_post_check_state().returning(move |_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofStatus {
+                        state: match spent_ys.contains(&y) {
+                            true => ProofState::Spent,
+                            false => ProofState::Unspent,
+                        },
+                        y,
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        localstore.add_proofs(&mut tx, &tokens.proofs()).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        // the wallet dies after the mint signed the outputs, before the proofs are stored
+        wallet
+            .swap_tokens(&mint_url, &keyset, &tokens, 20.into())
+            .await?;
+
+        assert_eq!(64, wallet.resume_swaps().await?);
+        let proofs = wallet.get_proofs().await?;
+        assert_eq!(64, proofs.total_amount());
+        assert!(proofs
+            .proofs()
+            .iter()
+            .all(|proof| !tokens.proofs().proofs().contains(proof)));
+
+        // nothing is left to resume
+        assert_eq!(0, wallet.resume_swaps().await?);
+        assert_eq!(64, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_proofs_spent() -> anyhow::Result<()> {
+        let (localstore, mints) = create_localstore_with_two_mints().await?;
+        let (first_mint, second_mint) = (&mints[0].0, &mints[1].0);
+        let mut tx = localstore.begin_tx().await?;
+        let first_proofs = localstore.get_proofs_by_mint(&mut tx, first_mint).await?;
+        tx.commit().await?;
+        let first_ys = first_proofs
+            .proofs()
+            .iter()
+            .map(|proof| Ok(proof.y()?.to_string()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // only the proofs of the checked mint are sent, the mint reports all of them spent
+        let mut client = create_mock();
+        client
+            .expect_post_check_state()
+            .withf(move |_, ys| ys == &first_ys)
+            .times(1)
+            .returning(|_, ys| {
+                Ok(PostCheckStateResponse {
+                    states: ys
+                        .into_iter()
+                        .map(|y| ProofStatus {
+                            state: ProofState::Spent,
+                            y,
+                            witness: None,
+                        })
+                        .collect(),
+                })
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let deleted = wallet.check_proofs_spent(first_mint).await?;
+        assert_eq!(first_proofs, deleted);
+        assert_eq!(0, wallet.get_mint_balance(first_mint).await?);
+        assert_eq!(32, wallet.get_mint_balance(second_mint).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deep_audit() -> anyhow::Result<()> {
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let mint_keys = MintKeyset::new("mykey", "");
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&mint_keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            4,
+            mint_keys.public_keys.clone(),
+            true,
+        )
+        .with_mint_url(&mint_url);
+        // keysets and proofs of other mints are not part of the audit
+        let other_keys = MintKeyset::new("otherkey", "");
+        let other_keyset = WalletKeyset::new(
+            &KeysetId::new(&other_keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            4,
+            other_keys.public_keys.clone(),
+            true,
+        )
+        .with_mint_url(&Url::parse("http://127.0.0.1:3339")?);
+        let seed_words = DeterministicSecret::generate_random_seed_words()?;
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.add_seed(&mut tx, &seed_words).await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        localstore.upsert_keyset(&mut tx, &other_keyset).await?;
+        localstore
+            .add_proofs(
+                &mut tx,
+                &Proof::new(
+                    64,
+                    "other-secret".to_owned(),
+                    other_keys.public_keys[&64],
+                    other_keys.keyset_id.clone(),
+                )
+                .into(),
+            )
+            .await?;
+        tx.commit().await?;
+
+        let mut client = create_mock();
+        let secrets = DeterministicSecret::from_seed_words(&seed_words)?.derive_range(
+            &keyset.keyset_id,
+            0,
+            5,
+        )?;
+
+        // the mint issued secrets 1, 2 and 3, the wallet spent 2 and lost 3
+        let dhke = Dhke::new();
+        let issued = [(1, 8), (2, 16), (3, 32)]
+            .iter()
+            .map(|(index, amount)| {
+                let (secret, blinding_factor) = &secrets[*index];
+                let b_ = dhke.step1_alice(secret, blinding_factor)?;
+                let c = dhke.step2_bob(
+                    Dhke::hash_to_curve(secret.as_bytes())?,
+                    &mint_keys.private_keys[amount],
+                )?;
+                Ok((
+                    b_,
+                    Proof::new(*amount, secret.clone(), c, mint_keys.keyset_id.clone()),
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let spent_y = issued[1].1.y()?.to_string();
+        let local_proofs = Proofs::new(vec![issued[0].1.clone(), issued[1].1.clone()]);
+
+        let restore_keys = mint_keys.clone();
+        let issued_amounts = issued
+            .iter()
+            .map(|(b_, proof)| (*b_, proof.amount))
+            .collect::<Vec<_>>();
+        client
+            .expect_post_restore()
+            .times(1)
+            .returning(move |_, outputs| {
+                let outputs = outputs
+                    .into_iter()
+                    .filter_map(|output| {
+                        issued_amounts
+                            .iter()
+                            .find(|(b_, _)| *b_ == output.b_)
+                            .map(|(_, amount)| BlindedMessage {
+                                amount: *amount,
+                                ..output
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                let signatures =
+                    sign_outputs(std::slice::from_ref(&restore_keys), &outputs).signatures;
+                Ok(PostRestoreResponse {
+                    outputs,
+                    signatures,
+                })
+            });
+        client.expect_post_check_state().returning(move |_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofStatus {
+                        state: match y == spent_y {
+                            true => ProofState::Spent,
+                            false => ProofState::Unspent,
+                        },
+                        y,
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+
+        let mut tx = localstore.begin_tx().await?;
+        localstore.add_proofs(&mut tx, &local_proofs).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let report = wallet.deep_audit(&mint_url).await?;
+        assert_eq!(40, report.mint_balance);
+        assert_eq!(24, report.local_balance);
+        assert_eq!(
+            Proofs::new(vec![issued[2].1.clone()]),
+            report.missing_proofs
+        );
+        assert_eq!(
+            Proofs::new(vec![issued[1].1.clone()]),
+            report.spent_local_proofs
+        );
+
+        // the audit doesn't change the localstore
+        assert_eq!(24, wallet.get_mint_balance(&mint_url).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resume_mint_quote() -> anyhow::Result<()> {
+        let mint_keys = MintKeyset::new("mykey", "");
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&mint_keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            0,
+            mint_keys.public_keys.clone(),
+            true,
+        );
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+
+        let mut client = create_mock();
+        client
+            .expect_post_mint_quote_onchain()
+            .returning(|_, _, _| {
+                Ok(PostMintQuoteOnchainResponse {
+                    quote: "quote-1".to_owned(),
+                    reference: "reference".to_owned(),
+                    additional_references: vec![],
+                    fee: 4,
+                    state: MintOnchainState::Unpaid,
+                    expiry: 0,
+                    amount_paid: None,
+                })
+            });
+        client
+            .expect_get_mint_quote_onchain()
+            .times(1)
+            .returning(|_, quote| {
+                Ok(PostMintQuoteOnchainResponse {
+                    quote,
+                    reference: "reference".to_owned(),
+                    additional_references: vec![],
+                    fee: 4,
+                    state: MintOnchainState::Paid,
+                    expiry: 0,
+                    amount_paid: None,
+                })
+            });
+        client
+            .expect_post_mint_onchain()
+            .returning(move |_, _, outputs| {
+                Ok(PostMintOnchainResponse {
+                    signatures: sign_outputs(std::slice::from_ref(&mint_keys), &outputs).signatures,
+                })
+            });
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        // the wallet dies after the quote has been created and paid
+        wallet.create_quote_onchain(&mint_url, 64, None).await?;
+        let quotes = wallet.get_pending_mint_quotes().await?;
+        assert_eq!(1, quotes.len());
+        assert_eq!(4, quotes[0].fee);
+
+        let tokens = wallet.resume_mint_quote(&keyset, &quotes[0]).await?;
+        assert_eq!(Some(60), tokens.map(|tokens| tokens.total_amount()));
+        assert_eq!(60, wallet.get_balance().await?);
+        assert!(wallet.get_pending_mint_quotes().await?.is_empty());
+        let history = wallet.get_history().await?;
+        assert_eq!(HistoryKind::Mint, history[0].kind);
+        assert_eq!(60, history[0].amount);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resume_issued_mint_quote() -> anyhow::Result<()> {
+        let mint_keys = MintKeyset::new("mykey", "");
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&mint_keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            0,
+            mint_keys.public_keys.clone(),
+            true,
+        )
+        .with_mint_url(&Url::parse("http://127.0.0.1:3338")?);
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+
+        let mut client = create_mock();
+        client
+            .expect_get_mint_quote_onchain()
+            .times(1)
+            .returning(|_, quote| {
+                Ok(PostMintQuoteOnchainResponse {
+                    quote,
+                    reference: "reference".to_owned(),
+                    additional_references: vec![],
+                    fee: 4,
+                    state: MintOnchainState::Issued,
+                    expiry: 0,
+                    amount_paid: None,
+                })
+            });
+        // the mint signs the outputs, but the response never reaches the wallet
+        client
+            .expect_post_mint_onchain()
+            .returning(|_, _, _| Err(MonexoWalletError::MintError("timeout".to_owned())));
+        let restore_keys = mint_keys.clone();
+        client.expect_post_restore().returning(move |_, outputs| {
+            Ok(PostRestoreResponse {
+                signatures: sign_outputs(std::slice::from_ref(&restore_keys), &outputs).signatures,
+                outputs,
+            })
+        });
+        client.expect_post_check_state().returning(|_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofStatus {
+                        state: ProofState::Unspent,
+                        y,
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let quote = WalletMintQuote {
+            quote: "quote-1".to_owned(),
+            mint_url: mint_url.clone(),
+            amount: 64,
+            fee: 4,
+        };
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        localstore.add_mint_quote(&mut tx, &quote).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let result = wallet
+            .mint_tokens(&mint_url, &keyset, 60.into(), quote.quote.clone())
+            .await;
+        assert!(result.is_err());
+        assert_eq!(0, wallet.get_balance().await?);
+
+        let tokens = wallet.resume_mint_quote(&keyset, &quote).await?;
+        assert_eq!(Some(60), tokens.map(|tokens| tokens.total_amount()));
+        assert_eq!(60, wallet.get_balance().await?);
+        assert!(wallet.get_pending_mint_quotes().await?.is_empty());
+        assert_eq!(0, wallet.resume_swaps().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resume_mint_quote_fee_above_amount() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client
+            .expect_get_mint_quote_onchain()
+            .returning(|_, quote| {
+                Ok(PostMintQuoteOnchainResponse {
+                    quote,
+                    reference: "reference".to_owned(),
+                    additional_references: vec![],
+                    fee: 8,
+                    state: MintOnchainState::Paid,
+                    expiry: 0,
+                    amount_paid: None,
+                })
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        let quote = WalletMintQuote {
+            quote: "quote-1".to_owned(),
+            mint_url: Url::parse("http://127.0.0.1:3338")?,
+            amount: 4,
+            fee: 8,
+        };
+        let result = wallet
+            .resume_mint_quote(&create_test_wallet_keyset()?, &quote)
+            .await;
+        assert!(matches!(result, Err(MonexoWalletError::InvalidAmount(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_await_quote_paid() -> anyhow::Result<()> {
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let mut client = create_mock();
+        let mut states = vec![MintOnchainState::Paid, MintOnchainState::Unpaid];
+        client
+            .expect_await_mint_quote_onchain()
+            .times(2)
+            .returning(move |_, quote| {
+                Ok(PostMintQuoteOnchainResponse {
+                    quote,
+                    reference: "reference".to_owned(),
+                    additional_references: vec![],
+                    fee: 4,
+                    state: states.pop().expect("no state left"),
+                    expiry: 0,
+                    amount_paid: None,
+                })
+            });
+        client.expect_get_mint_quote_onchain().never();
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        // the first subscription timed out before the quote was paid
+        assert!(
+            !wallet
+                .await_quote_paid(&mint_url, "quote-1".to_owned())
+                .await?
+        );
+        assert!(
+            wallet
+                .await_quote_paid(&mint_url, "quote-1".to_owned())
+                .await?
+        );
+        Ok(())
+    }
+
+    /// Waits for the payment of a quote the mint reports paid on the `paid_after`th poll, never
+    /// if None. Returns the result and the number of polls.
+    async fn wait_for_mint_payment(
+        paid_after: Option<usize>,
+        max_wait: Duration,
+    ) -> anyhow::Result<(bool, usize)> {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counter = polls.clone();
+        let mut client = create_mock();
+        client
+            .expect_get_mint_quote_onchain()
+            .returning(move |_, quote| {
+                let poll = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                Ok(PostMintQuoteOnchainResponse {
+                    quote,
+                    reference: "reference".to_owned(),
+                    additional_references: vec![],
+                    fee: 4,
+                    state: match paid_after.is_some_and(|paid_after| poll >= paid_after) {
+                        true => MintOnchainState::Paid,
+                        false => MintOnchainState::Unpaid,
+                    },
+                    expiry: 0,
+                    amount_paid: None,
+                })
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        let paid = wallet
+            .wait_for_mint_payment(
+                &Url::parse("http://127.0.0.1:3338")?,
+                "quote-1".to_owned(),
+                Duration::from_millis(1),
+                max_wait,
+                |_| {},
+            )
+            .await?;
+        Ok((paid, polls.load(Ordering::SeqCst)))
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_mint_payment() -> anyhow::Result<()> {
+        assert_eq!(
+            (true, 3),
+            wait_for_mint_payment(Some(3), Duration::from_secs(60)).await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_mint_payment_timeout() -> anyhow::Result<()> {
+        let (paid, polls) = wait_for_mint_payment(None, Duration::from_millis(50)).await?;
+        assert!(!paid);
+        assert!(polls > 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_mint_payment_underpaid() -> anyhow::Result<()> {
+        // the wallet underpays the quote of 10 usd and tops it up later
+        let mut responses = vec![
+            // the mint doesn't check the chain anymore once the quote is paid
+            (MintOnchainState::Paid, None),
+            (MintOnchainState::Unpaid, Some(5_000_000)),
+            (MintOnchainState::Unpaid, Some(5_000_000)),
+            (MintOnchainState::Unpaid, Some(0)),
+        ];
+        let mut client = create_mock();
+        client
+            .expect_get_mint_quote_onchain()
+            .times(4)
+            .returning(move |_, quote| {
+                let (state, amount_paid) = responses.pop().expect("no response left");
+                Ok(PostMintQuoteOnchainResponse {
+                    quote,
+                    reference: "reference".to_owned(),
+                    additional_references: vec![],
+                    fee: 4,
+                    state,
+                    expiry: 0,
+                    amount_paid,
+                })
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+
+        assert_eq!(
+            MintQuoteStatus {
+                state: MintOnchainState::Unpaid,
+                amount_paid: Some(0),
+            },
+            wallet
+                .get_quote_status(&mint_url, "quote-1".to_owned())
+                .await?
+        );
+
+        let mut statuses = vec![];
+        let paid = wallet
+            .wait_for_mint_payment(
+                &mint_url,
+                "quote-1".to_owned(),
+                Duration::from_millis(1),
+                Duration::from_secs(60),
+                |status| statuses.push(status.clone()),
+            )
+            .await?;
+        assert!(paid);
+        assert_eq!(
+            vec![
+                (MintOnchainState::Unpaid, Some(5_000_000)),
+                (MintOnchainState::Unpaid, Some(5_000_000)),
+                (MintOnchainState::Paid, None),
+            ],
+            statuses
+                .into_iter()
+                .map(|status| (status.state, status.amount_paid))
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_mint_keysets_unknown_unit() -> anyhow::Result<()> {
+        let keys = MintKeyset::new("mykey", "");
+        let keys_response = KeysResponse::new(KeyResponse {
+            keys: keys.public_keys.clone(),
+            id: keys.keyset_id.clone(),
+            unit: CurrencyUnit::Usd,
+        });
+        let keysets = serde_json::from_value::<Keysets>(serde_json::json!({
+            "keysets": [
+                { "id": keys.keyset_id, "unit": "usd", "active": true },
+                { "id": "00ffd48b8f5ecf80", "unit": "eur", "active": true },
+            ]
+        }))?;
+
+        let mut client = MockCashuClient::default();
+        client
+            .expect_get_keysets()
+            .returning(move |_| Ok(keysets.clone()));
+        client
+            .expect_get_keys_by_id()
+            .returning(move |_, _| Ok(keys_response.clone()));
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        let result = wallet
+            .add_mint_keysets(&Url::parse("http://127.0.0.1:3338")?)
+            .await?;
+        assert_eq!(1, result.len());
+        assert_eq!(KeysetId::new(&keys.keyset_id)?, result[0].keyset_id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_mint_keysets_mismatched_unit() -> anyhow::Result<()> {
+        let usd_keys = MintKeyset::new("mykey", "0/0/0/0");
+        let ugx_keys = MintKeyset::new("mykey", "0/0/0/1");
+        let keysets = Keysets::new(vec![
+            Keyset {
+                id: usd_keys.keyset_id.clone(),
+                unit: CurrencyUnit::Usd,
+                active: true,
+                input_fee_ppk: 0,
+            },
+            Keyset {
+                id: ugx_keys.keyset_id.clone(),
+                unit: CurrencyUnit::Ugx,
+                active: true,
+                input_fee_ppk: 0,
+            },
+        ]);
+        let usd_keyset_id = usd_keys.keyset_id.clone();
+
+        // the mint returns the ugx keys with the wrong unit
+        let mut client = MockCashuClient::default();
+        client
+            .expect_get_keysets()
+            .returning(move |_| Ok(keysets.clone()));
+        client.expect_get_keys_by_id().returning(move |_, id| {
+            let (keys, unit) = if id == ugx_keys.keyset_id {
+                (&ugx_keys, CurrencyUnit::Usd)
+            } else {
+                (&usd_keys, CurrencyUnit::Usd)
+            };
+            Ok(KeysResponse::new(KeyResponse {
+                keys: keys.public_keys.clone(),
+                id: keys.keyset_id.clone(),
+                unit,
+            }))
+        });
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        let result = wallet
+            .add_mint_keysets(&Url::parse("http://127.0.0.1:3338")?)
+            .await?;
+        assert_eq!(1, result.len());
+        assert_eq!(KeysetId::new(&usd_keyset_id)?, result[0].keyset_id);
+        assert_eq!(CurrencyUnit::Usd, result[0].currency_unit);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_mint_keysets_no_matching_keys() -> anyhow::Result<()> {
+        let keys = MintKeyset::new("mykey", "");
+        let keysets = Keysets::new(vec![Keyset {
+            id: keys.keyset_id.clone(),
+            unit: CurrencyUnit::Ugx,
+            active: true,
+            input_fee_ppk: 0,
+        }]);
+        let keys_response = KeysResponse::new(KeyResponse {
+            keys: keys.public_keys.clone(),
+            id: keys.keyset_id.clone(),
+            unit: CurrencyUnit::Usd,
+        });
+
+        let mut client = MockCashuClient::default();
+        client
+            .expect_get_keysets()
+            .returning(move |_| Ok(keysets.clone()));
+        client
+            .expect_get_keys_by_id()
+            .returning(move |_, _| Ok(keys_response.clone()));
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        let result = wallet
+            .add_mint_keysets(&Url::parse("http://127.0.0.1:3338")?)
+            .await;
+        assert!(
+            matches!(result, Err(MonexoWalletError::KeysetMismatch(ids)) if ids == keys.keyset_id)
+        );
+        Ok(())
+    }
+
+    /// Creates a localstore with proofs worth 4 in one and 2 in a second usd keyset of the same
+    /// mint, returns it with the keysets the mint reports
+    async fn create_localstore_with_two_keysets(
+    ) -> anyhow::Result<(SqliteLocalStore, Keysets, [MintKeyset; 2])> {
+        let old_keys = MintKeyset::new("mykey", "0/0/0/0");
+        let new_keys = MintKeyset::new("mykey", "0/0/0/1");
+        let keysets = Keysets::new(
+            [&old_keys, &new_keys]
+                .iter()
+                .map(|keys| Keyset {
+                    id: keys.keyset_id.clone(),
+                    unit: CurrencyUnit::Usd,
+                    active: true,
+                    input_fee_ppk: 0,
+                })
+                .collect(),
+        );
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let c = old_keys.public_keys[&1];
+        let mut tx = localstore.begin_tx().await?;
+        for (keys, amount) in [(&old_keys, 4), (&new_keys, 2)] {
+            let keyset_id = KeysetId::new(&keys.keyset_id)?;
+            localstore
+                .upsert_keyset(
+                    &mut tx,
+                    &WalletKeyset::new(
+                        &keyset_id,
+                        CurrencyUnit::Usd,
+                        0,
+                        keys.public_keys.clone(),
+                        true,
+                    )
+                    .with_mint_url(&Url::parse("http://127.0.0.1:3338")?),
+                )
+                .await?;
+            localstore
+                .add_proofs(
+                    &mut tx,
+                    &Proof::new(amount, format!("secret{amount}"), c, keys.keyset_id.clone())
+                        .into(),
+                )
+                .await?;
+        }
+        tx.commit().await?;
+        Ok((localstore, keysets, [old_keys, new_keys]))
+    }
+
+    #[tokio::test]
+    async fn test_send_across_keysets() -> anyhow::Result<()> {
+        let (localstore, keysets, mint_keys) = create_localstore_with_two_keysets().await?;
+        let mut client = MockCashuClient::default();
+        client
+            .expect_get_keysets()
+            .returning(move |_| Ok(keysets.clone()));
+        client
+            .expect_post_swap()
+            .returning(move |_, _, outputs| Ok(sign_outputs(&mint_keys, &outputs)));
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.send(&mint_url, CurrencyUnit::Usd, 5).await?;
+
+        assert_eq!(5, result.total_amount());
+        assert_eq!(Some(CurrencyUnit::Usd), result.currency_unit);
+        assert_eq!(1, wallet.get_balance().await?);
+        // one send is one history entry, no matter how many keysets it used
+        let history = wallet.get_history().await?;
+        assert_eq!(1, history.len());
+        assert_eq!(5, history[0].amount);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_across_keysets_failed_swap() -> anyhow::Result<()> {
+        let (localstore, keysets, _) = create_localstore_with_two_keysets().await?;
+        let mut client = MockCashuClient::default();
+        client
+            .expect_get_keysets()
+            .returning(move |_| Ok(keysets.clone()));
+        // the proofs of the first keyset add up to its part, only the second keyset is swapped
+        client
+            .expect_post_swap()
+            .times(1)
+            .returning(|_, _, _| Err(MonexoWalletError::MintError("swap failed".to_string())));
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.send(&mint_url, CurrencyUnit::Usd, 5).await;
+
+        assert!(matches!(result, Err(MonexoWalletError::MintError(_))));
+        assert_eq!(6, wallet.get_balance().await?);
+        assert!(wallet.get_history().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_tampered_dleq() -> anyhow::Result<()> {
+        let mint_keys = MintKeyset::new("mykey", "");
+        let keyset_id = KeysetId::new(&mint_keys.keyset_id)?;
+        let keyset = WalletKeyset::new(
+            &keyset_id,
+            CurrencyUnit::Usd,
+            0,
+            mint_keys.public_keys.clone(),
+            true,
+        );
+
+        let signing_keys = [mint_keys.clone()];
+        let mut client = create_mock();
+        client.expect_post_swap().returning(move |_, _, outputs| {
+            let mut response = sign_outputs(&signing_keys, &outputs);
+            if let Some(dleq) = response.signatures[0].dleq.as_mut() {
+                std::mem::swap(&mut dleq.e, &mut dleq.s);
+            }
+            Ok(response)
+        });
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let c = mint_keys.public_keys[&1];
+        let tokens = (
+            Url::parse("http://127.0.0.1:3338")?,
+            Proofs::new(vec![Proof::new(
+                32,
+                "secret32".to_string(),
+                c,
+                mint_keys.keyset_id.clone(),
+            )]),
+        )
+            .into();
+        let result = wallet
+            .swap_tokens(
+                &Url::parse("http://127.0.0.1:3338")?,
+                &keyset,
+                &tokens,
+                16.into(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(MonexoWalletError::InvalidProofs)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_tampered_dleq() -> anyhow::Result<()> {
+        let mint_keys = MintKeyset::new("mykey", "");
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&mint_keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            0,
+            mint_keys.public_keys.clone(),
+            true,
+        );
+
+        let signing_keys = [mint_keys.clone()];
+        let mut client = create_mock();
+        client
+            .expect_post_mint_onchain()
+            .returning(move |_, _, outputs| {
+                let mut signatures = sign_outputs(&signing_keys, &outputs).signatures;
+                if let Some(dleq) = signatures[0].dleq.as_mut() {
+                    std::mem::swap(&mut dleq.e, &mut dleq.s);
+                }
+                Ok(PostMintOnchainResponse { signatures })
+            });
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let result = wallet
+            .mint_tokens(
+                &Url::parse("http://127.0.0.1:3338")?,
+                &keyset,
+                16.into(),
+                "quote".to_owned(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(MonexoWalletError::InvalidProofs)));
+        assert_eq!(0, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_reordered_signatures() -> anyhow::Result<()> {
+        let mint_keys = MintKeyset::new("mykey", "");
+        let keyset_id = KeysetId::new(&mint_keys.keyset_id)?;
+        let keyset = WalletKeyset::new(
+            &keyset_id,
+            CurrencyUnit::Usd,
+            0,
+            mint_keys.public_keys.clone(),
+            true,
+        );
+
+        let signing_keys = [mint_keys.clone()];
+        let mut client = create_mock();
+        client.expect_post_swap().returning(move |_, _, outputs| {
+            let mut response = sign_outputs(&signing_keys, &outputs);
+            response
+                .signatures
+                .sort_by_key(|s| std::cmp::Reverse(s.amount));
+            Ok(response)
+        });
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let c = mint_keys.public_keys[&1];
+        let tokens = (
+            Url::parse("http://127.0.0.1:3338")?,
+            Proofs::new(vec![
+                Proof::new(32, "secret32".to_string(), c, mint_keys.keyset_id.clone()),
+                Proof::new(16, "secret16".to_string(), c, mint_keys.keyset_id.clone()),
+            ]),
+        )
+            .into();
+        let (first, second) = wallet
+            .swap_tokens(
+                &Url::parse("http://127.0.0.1:3338")?,
+                &keyset,
+                &tokens,
+                20.into(),
+            )
+            .await?;
+
+        assert_eq!(28, first.total_amount());
+        assert_eq!(20, second.total_amount());
+
+        let dhke = Dhke::new();
+        for proof in [first.proofs().proofs(), second.proofs().proofs()].concat() {
+            assert!(dhke.verify(
+                mint_keys.private_keys[&proof.amount],
+                proof.c,
+                proof.secret.clone()
+            )?);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_reordered_signatures() -> anyhow::Result<()> {
+        let mint_keys = MintKeyset::new("mykey", "");
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&mint_keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            0,
+            mint_keys.public_keys.clone(),
+            true,
+        );
+
+        let signing_keys = [mint_keys.clone()];
+        let mut client = create_mock();
+        client
+            .expect_post_mint_onchain()
+            .returning(move |_, _, outputs| {
+                let mut signatures = sign_outputs(&signing_keys, &outputs).signatures;
+                signatures.reverse();
+                Ok(PostMintOnchainResponse { signatures })
+            });
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let tokens = wallet
+            .mint_tokens(
+                &Url::parse("http://127.0.0.1:3338")?,
+                &keyset,
+                13.into(),
+                "quote".to_owned(),
+            )
+            .await?;
+
+        assert_eq!(13, tokens.total_amount());
+        let dhke = Dhke::new();
+        for proof in tokens.proofs().proofs() {
+            assert!(dhke.verify(
+                mint_keys.private_keys[&proof.amount],
+                proof.c,
+                proof.secret.clone()
+            )?);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_mint_info_fallback() -> anyhow::Result<()> {
+        let mint_info = MintInfoResponse {
+            name: Some("monexo".to_string()),
+            version: None,
+            usdc_address: "HVasUUKPrmrAuBpDFiu8BxQKzrMYY5DvyuNXamvaG2nM".to_string(),
+            usdc_token_mint: "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU".to_string(),
+            ..Default::default()
+        };
+        let response = mint_info.clone();
+
+        let mut client = create_mock();
+        let mut reachable = true;
+        client.expect_get_info().returning(move |_| {
+            let result = match reachable {
+                true => Ok(response.clone()),
+                false => Err(MonexoWalletError::MintError("unreachable".to_string())),
+            };
+            reachable = false;
+            result
+        });
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.get_mint_info(&mint_url).await?;
+        assert_eq!(mint_info, result.info);
+        assert!(!result.is_stale);
+
+        let result = wallet.get_mint_info(&mint_url).await?;
+        assert_eq!(mint_info, result.info);
+        assert!(result.is_stale);
+
+        let result = wallet
+            .get_mint_info(&Url::parse("http://127.0.0.1:3339")?)
+            .await;
+        assert!(matches!(result, Err(MonexoWalletError::MintError(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_unreachable_mint() -> anyhow::Result<()> {
+        let token: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+
+        // post_swap is not expected, the mock panics if the wallet tries to swap
+        let mut client = create_mock();
+        client
+            .expect_get_info()
+            .returning(|_| Err(MonexoWalletError::MintError("unreachable".to_string())));
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        let result = wallet.receive(&create_test_wallet_keyset()?, &token).await;
+        assert!(matches!(
+            result,
+            Err(MonexoWalletError::MintUnreachable(url)) if Some(url.clone()) == token.mint().map(|u| u.to_string())
+        ));
+        assert_eq!(0, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_mint_without_swap() -> anyhow::Result<()> {
+        let token: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+
+        // post_swap is not expected, the mock panics if the wallet tries to swap
+        let mut client = create_mock();
+        client.expect_get_info().returning(|_| {
+            Ok(MintInfoResponse {
+                nuts: Nuts {
+                    nut03: Some(false.into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        });
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        let result = wallet.receive(&create_test_wallet_keyset()?, &token).await;
+        assert!(matches!(result, Err(MonexoWalletError::MintUnreachable(_))));
+        assert_eq!(0, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens() -> anyhow::Result<()> {
+        let keys = MintKeyset::new("mykey", "");
+        let mint_keys = [keys.clone()];
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, outputs| Ok(sign_outputs(&mint_keys, &outputs)));
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            0,
+            keys.public_keys.clone(),
+            true,
+        );
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let tokens: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        wallet.receive_tokens(&mint_url, &keyset, &tokens).await?;
+
+        assert_eq!(64, wallet.get_balance().await?);
+        let history = wallet.get_history().await?;
+        assert_eq!(1, history.len());
+        assert_eq!(HistoryKind::Receive, history[0].kind);
+        assert_eq!(mint_url, history[0].mint_url);
+        assert_eq!(64, history[0].amount);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens_mint_mismatch() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        // the mock has no swap expectation, so a swap would fail the test
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let tokens: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+        let mint_url = Url::parse("http://127.0.0.1:3339")?;
+        let result = wallet.receive_tokens(&mint_url, &keyset, &tokens).await;
+
+        assert!(matches!(
+            result,
+            Err(MonexoWalletError::MintMismatch(ref wallet_mint, ref token_mint))
+                if *wallet_mint == mint_url.to_string()
+                    && *token_mint == "http://127.0.0.1:3338/"
+        ));
+        assert_eq!(0, wallet.get_balance().await?);
+        assert!(wallet.get_history().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens_unknown_keyset() -> anyhow::Result<()> {
+        let keys = MintKeyset::new("mykey", "");
+        let mint_keys = [keys.clone()];
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, outputs| Ok(sign_outputs(&mint_keys, &outputs)));
+
+        // the localstore starts without keysets, they are fetched from the token's mint
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            0,
+            keys.public_keys.clone(),
+            true,
+        );
+        let tokens: TokenV3 = (
+            mint_url.clone(),
+            CurrencyUnit::Usd,
+            Proofs::new(vec![Proof::new(
+                8,
+                "secret8".to_string(),
+                keys.public_keys[&1],
+                keys.keyset_id.clone(),
+            )]),
+        )
+            .into();
+        wallet.receive_tokens(&mint_url, &keyset, &tokens).await?;
+
+        assert_eq!(8, wallet.get_balance().await?);
+        let keysets = wallet.get_wallet_keysets().await?;
+        assert_eq!(1, keysets.len());
+        assert_eq!(Some(mint_url), keysets[0].mint_url);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens_keyset_not_found() -> anyhow::Result<()> {
+        // the mint doesn't list the output keyset, its keysets are fetched only once
+        let mut client = MockCashuClient::default();
+        client
+            .expect_get_keysets()
+            .times(1)
+            .returning(|_| Ok(Keysets::new(vec![])));
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+
+        let keyset = create_test_wallet_keyset()?;
+        let tokens: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.receive_tokens(&mint_url, &keyset, &tokens).await;
+
+        assert!(matches!(result, Err(MonexoWalletError::KeysetNotFound(_))));
+        assert_eq!(0, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    /// Receives token_60 (4, 8, 16, 32) partially, the mint reports proofs of the given amounts
+    /// as spent. Returns the result of the receive and the balance afterwards.
+    async fn receive_partial_with_spent(
+        spent_amounts: &'static [u64],
+    ) -> anyhow::Result<(Result<u64, MonexoWalletError>, u64)> {
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let spent_ys = tokens
+            .proofs()
+            .proofs()
+            .iter()
+            .filter(|proof| spent_amounts.contains(&proof.amount))
+            .map(|proof| Ok(proof.y()?.to_string()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let keys = MintKeyset::new("mykey", "");
+        let mint_keys = [keys.clone()];
+        let mut client = create_mock();
+        client.expect_post_check_state().returning(move |_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofStatus {
+                        state: match spent_ys.contains(&y) {
+                            true => ProofState::Spent,
+                            false => ProofState::Unspent,
+                        },
+                        y,
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+        client
+            .expect_post_swap()
+            .returning(move |_, proofs, outputs| {
+                // spent proofs are never sent to the mint
+                assert!(proofs
+                    .proofs()
+                    .iter()
+                    .all(|proof| !spent_amounts.contains(&proof.amount)));
+                Ok(sign_outputs(&mint_keys, &outputs))
+            });
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            0,
+            keys.public_keys.clone(),
+            true,
+        );
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet
+            .receive_tokens_partial(&mint_url, &keyset, &tokens)
+            .await;
+        Ok((result, wallet.get_balance().await?))
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens_partial() -> anyhow::Result<()> {
+        let (result, balance) = receive_partial_with_spent(&[8, 32]).await?;
+        assert_eq!(20, result?);
+        assert_eq!(20, balance);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens_partial_all_spent() -> anyhow::Result<()> {
+        let (result, balance) = receive_partial_with_spent(&[4, 8, 16, 32]).await?;
+        assert!(matches!(result, Err(MonexoWalletError::NoSpendableProofs)));
+        assert_eq!(0, balance);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens_failed_swap() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(|_, _, _| Err(MonexoWalletError::MintError("swap failed".to_string())));
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let tokens: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let result = wallet.receive_tokens(&mint_url, &keyset, &tokens).await;
+
+        assert!(result.is_err());
+        assert_eq!(0, wallet.get_balance().await?);
+        assert!(wallet.get_history().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_balance() -> anyhow::Result<()> {
+        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
+        let fixture: TokenV3 = fixture.try_into()?;
+        let local_store = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = local_store.begin_tx().await?;
+        local_store.add_proofs(&mut tx, &fixture.proofs()).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(local_store)
+            .build()
+            .await?;
+
+        let result = wallet.get_balance().await?;
+        assert_eq!(60, result);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_denomination_report() -> anyhow::Result<()> {
+        let (localstore, keyset) = create_localstore_with_proofs(&[1, 2, 8]).await?;
+        let mut tx = localstore.begin_tx().await?;
+        // a second proof of the same amount needs its own secret
+        let mint_keys = MintKeyset::new("mykey", "");
+        localstore
+            .add_proofs(
+                &mut tx,
+                &Proof::new(
+                    2,
+                    "secret2b".to_owned(),
+                    mint_keys.public_keys[&1],
+                    mint_keys.keyset_id.clone(),
+                )
+                .into(),
+            )
+            .await?;
+        localstore
+            .upsert_keyset(
+                &mut tx,
+                &WalletKeyset::new(
+                    &KeysetId::new(&MintKeyset::new("mykey", "ugx").keyset_id)?,
+                    CurrencyUnit::Ugx,
+                    0,
+                    HashMap::new(),
+                    true,
+                ),
+            )
+            .await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let report = wallet.denomination_report().await?;
+        assert_eq!(
+            vec![KeysetDenominations {
+                keyset_id: keyset.keyset_id,
+                unit: CurrencyUnit::Usd,
+                denominations: BTreeMap::from([(1, 1), (2, 2), (8, 1)]),
+            }],
+            report
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_mint() -> anyhow::Result<()> {
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+
+        let keysets = wallet.add_mint(&mint_url).await?;
+        assert_eq!(1, keysets.len());
+        assert_eq!(vec![mint_url.clone()], wallet.list_mints().await?);
+        // the stored keysets have an id the returned ones don't have yet
+        let stored = wallet.get_wallet_keysets_for_mint(&mint_url).await?;
+        assert_eq!(
+            keysets
+                .into_iter()
+                .map(|keyset| keyset.keyset_id)
+                .collect::<Vec<_>>(),
+            stored
+                .into_iter()
+                .map(|keyset| keyset.keyset_id)
+                .collect::<Vec<_>>()
+        );
+
+        // adding a mint twice keeps a single entry
+        wallet.add_mint(&mint_url).await?;
+        assert_eq!(vec![mint_url], wallet.list_mints().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_keyset_rotation() -> anyhow::Result<()> {
+        let old_keys = MintKeyset::new("mykey", "0/0/0/0");
+        let new_keys = MintKeyset::new("mykey", "0/0/0/1");
+        let before = Keysets::new(vec![Keyset {
+            id: old_keys.keyset_id.clone(),
+            unit: CurrencyUnit::Usd,
+            active: true,
+            input_fee_ppk: 0,
+        }]);
+        let after = Keysets::new(vec![
+            Keyset {
+                id: old_keys.keyset_id.clone(),
+                unit: CurrencyUnit::Usd,
+                active: false,
+                input_fee_ppk: 0,
+            },
+            Keyset {
+                id: new_keys.keyset_id.clone(),
+                unit: CurrencyUnit::Usd,
+                active: true,
+                input_fee_ppk: 0,
+            },
+        ]);
+
+        let mint_keys = [old_keys.clone(), new_keys.clone()];
+        let keys = mint_keys.clone();
+        // the mint rotates its keyset after the first request
+        let requests = AtomicUsize::new(0);
+        let mut client = MockCashuClient::default();
+        client.expect_get_keysets().returning(move |_| {
+            match requests.fetch_add(1, Ordering::SeqCst) {
+                0 => Ok(before.clone()),
+                _ => Ok(after.clone()),
+            }
+        });
+        client.expect_get_keys_by_id().returning(move |_, id| {
+            let keys = keys
+                .iter()
+                .find(|keys| keys.keyset_id == id)
+                .expect("unknown keyset");
+            Ok(KeysResponse::new(KeyResponse {
+                keys: keys.public_keys.clone(),
+                id: keys.keyset_id.clone(),
+                unit: CurrencyUnit::Usd,
+            }))
+        });
+        client
+            .expect_post_swap()
+            .returning(move |_, _, outputs| Ok(sign_outputs(&mint_keys, &outputs)));
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(SqliteLocalStore::with_in_memory().await?)
+            .build()
+            .await?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let old_keyset_id = KeysetId::new(&old_keys.keyset_id)?;
+        let new_keyset_id = KeysetId::new(&new_keys.keyset_id)?;
 
-        let blinding_factors = outputs
-            .into_iter()
-            .map(|(_, secret)| secret)
-            .collect::<Vec<BlindingFactor>>();
+        wallet.add_mint(&mint_url).await?;
+        let old_keyset = wallet
+            .get_active_keyset(&mint_url, &CurrencyUnit::Usd)
+            .await?
+            .expect("no active keyset");
+        assert_eq!(old_keyset_id, old_keyset.keyset_id);
+        let mut tx = wallet.localstore.begin_tx().await?;
+        wallet
+            .localstore
+            .add_proofs(
+                &mut tx,
+                &Proof::new(
+                    4,
+                    "secret4".to_owned(),
+                    old_keys.public_keys[&4],
+                    old_keys.keyset_id.clone(),
+                )
+                .into(),
+            )
+            .await?;
+        tx.commit().await?;
+
+        // the old keyset is kept inactive
+        wallet.add_mint(&mint_url).await?;
+        let keysets = wallet.get_wallet_keysets_for_mint(&mint_url).await?;
+        assert_eq!(
+            vec![
+                (old_keyset_id.clone(), false),
+                (new_keyset_id.clone(), true)
+            ],
+            keysets
+                .iter()
+                .map(|keyset| (keyset.keyset_id.clone(), keyset.active))
+                .collect::<Vec<_>>()
+        );
 
-        Ok(signatures
+        // the proofs of the old keyset can still be sent, the new proofs use the active keyset
+        let token = wallet.send_tokens(&mint_url, &old_keyset, 3).await?;
+        assert_eq!(3, token.total_amount());
+        let new_keyset_id = new_keyset_id.to_string();
+        assert!(token
+            .proofs()
+            .proofs()
             .iter()
-            .zip(blinding_factors)
-            .zip(secrets)
-            .map(|((p, blinding_factor), secret)| {
-                let key = pub_keys
-                    .get(&p.amount)
-                    .ok_or(MonexoWalletError::PubkeyNotFound)?;
-                let pub_alice = self
-                    .dhke
-                    .step3_alice(p.c_, blinding_factor.to_owned(), *key)?;
-                Ok(Proof::new(
-                    p.amount,
-                    secret,
-                    pub_alice,
-                    current_keyset_id.clone(),
-                ))
-            })
-            .collect::<Result<Vec<_>, MonexoWalletError>>()?
-            .into())
+            .all(|proof| proof.keyset_id == new_keyset_id));
+        assert!(wallet
+            .get_proofs()
+            .await?
+            .proofs()
+            .iter()
+            .all(|proof| proof.keyset_id == new_keyset_id));
+        assert_eq!(1, wallet.get_balance().await?);
+        Ok(())
     }
 
-    pub async fn get_proofs(&self) -> Result<Proofs, MonexoWalletError> {
-        let mut tx = self.localstore.begin_tx().await?;
-        let proofs = self.localstore.get_proofs(&mut tx).await?;
+    /// Creates a localstore with a usd keyset at each of two mints, holding a proof of 8 at the
+    /// first and a proof of 32 at the second mint
+    async fn create_localstore_with_two_mints(
+    ) -> anyhow::Result<(SqliteLocalStore, Vec<(Url, WalletKeyset)>)> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        let mut mints = vec![];
+        for (mint_url, seed, amount) in [
+            ("http://127.0.0.1:3338", "mykey", 8),
+            ("http://127.0.0.1:3339", "otherkey", 32),
+        ] {
+            let mint_url = Url::parse(mint_url)?;
+            let keys = MintKeyset::new(seed, "0/0/0/0");
+            let keyset = WalletKeyset::new(
+                &KeysetId::new(&keys.keyset_id)?,
+                CurrencyUnit::Usd,
+                0,
+                keys.public_keys.clone(),
+                true,
+            )
+            .with_mint_url(&mint_url);
+            localstore.add_mint(&mut tx, &mint_url).await?;
+            localstore.upsert_keyset(&mut tx, &keyset).await?;
+            localstore
+                .add_proofs(
+                    &mut tx,
+                    &Proofs::new(vec![Proof::new(
+                        amount,
+                        format!("secret{amount}"),
+                        keys.public_keys[&1],
+                        keys.keyset_id.clone(),
+                    )]),
+                )
+                .await?;
+            mints.push((mint_url, keyset));
+        }
         tx.commit().await?;
-        Ok(proofs)
+        Ok((localstore, mints))
     }
-}
 
-// FIXME implement for Vec<BlindedMessage, Secretkey>
-fn get_blinded_msg(blinded_messages: Vec<(BlindedMessage, BlindingFactor)>) -> Vec<BlindedMessage> {
-    blinded_messages
-        .into_iter()
-        .map(|(msg, _)| msg)
-        .collect::<Vec<BlindedMessage>>()
-}
+    #[tokio::test]
+    async fn test_get_mint_balance_two_mints() -> anyhow::Result<()> {
+        let (localstore, mints) = create_localstore_with_two_mints().await?;
+        let (first_mint, second_mint) = (&mints[0].0, &mints[1].0);
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
 
-    use monexo_core::{
-        fixture::{read_fixture, read_fixture_as},
-        keyset::{Keyset, KeysetId, Keysets, MintKeyset},
-        primitives::{CurrencyUnit, KeyResponse, KeysResponse, PostSwapResponse},
-        token::TokenV3,
-    };
-    use secp256k1::PublicKey;
-    use url::Url;
+        assert_eq!(
+            vec![first_mint.clone(), second_mint.clone()],
+            wallet.list_mints().await?
+        );
+        assert_eq!(8, wallet.get_mint_balance(first_mint).await?);
+        assert_eq!(32, wallet.get_mint_balance(second_mint).await?);
+        assert_eq!(
+            0,
+            wallet
+                .get_mint_balance(&Url::parse("http://127.0.0.1:3340")?)
+                .await?
+        );
+        assert_eq!(40, wallet.get_balance().await?);
+        Ok(())
+    }
 
-    use crate::{
-        client::MockCashuClient,
-        localstore::{sqlite::SqliteLocalStore, LocalStore, WalletKeyset},
-        wallet::WalletBuilder,
-    };
+    #[tokio::test]
+    async fn test_send_tokens_two_mints() -> anyhow::Result<()> {
+        let (localstore, mints) = create_localstore_with_two_mints().await?;
+        let (first_mint, first_keyset) = &mints[0];
+        let (second_mint, second_keyset) = &mints[1];
 
-    fn create_mock() -> MockCashuClient {
-        let keys = MintKeyset::new("mykey", "");
-        let key_response = KeyResponse {
-            keys: keys.public_keys.clone(),
-            id: keys.keyset_id.clone(),
-            unit: CurrencyUnit::Usd,
+        // the mock has no swap expectation, so a swap would fail the test
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        // the total balance covers the amount, but the first mint's balance doesn't
+        let result = wallet.send_tokens(first_mint, first_keyset, 16).await;
+        assert!(matches!(result, Err(MonexoWalletError::NotEnoughTokens)));
+
+        let result = wallet.send_tokens(first_mint, second_keyset, 8).await;
+        assert!(matches!(result, Err(MonexoWalletError::KeysetNotFound(_))));
+        assert_eq!(40, wallet.get_balance().await?);
+
+        let result = wallet.send_tokens(first_mint, first_keyset, 8).await?;
+        assert_eq!(Some(first_mint.clone()), result.mint());
+        assert!(result
+            .proofs()
+            .proofs()
+            .iter()
+            .all(|proof| proof.keyset_id == first_keyset.keyset_id.to_string()));
+        assert_eq!(0, wallet.get_mint_balance(first_mint).await?);
+        assert_eq!(32, wallet.get_mint_balance(second_mint).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_onchain_two_mints() -> anyhow::Result<()> {
+        let (localstore, mints) = create_localstore_with_two_mints().await?;
+        let (first_mint, first_keyset) = &mints[0];
+        let second_keyset = &mints[1].1;
+
+        // the mock has no swap or melt expectation, so paying would fail the test
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        let quote = PostMeltQuoteOnchainResponse {
+            quote: "quote".to_owned(),
+            description: None,
+            amount: 16,
+            fee: 0,
+            state: MeltOnchainState::Unpaid,
+            expiry: 0,
         };
-        let keys_response = KeysResponse::new(key_response.clone());
-        let keys_by_id_response = keys_response.clone();
-        let keysets = Keysets::new(vec![Keyset {
-            id: keys.keyset_id,
-            unit: CurrencyUnit::Usd,
-            active: true,
-        }]);
 
-        let mut client = MockCashuClient::default();
-        client
-            .expect_get_keys()
-            .returning(move |_| Ok(keys_response.clone()));
-        client
-            .expect_get_keysets()
-            .returning(move |_| Ok(keysets.clone()));
-        client
-            .expect_get_keys_by_id()
-            .returning(move |_, _| Ok(keys_by_id_response.clone()));
-        client.expect_is_v1_supported().returning(move |_| Ok(true));
-        client
+        // the second mint's proofs would cover the quote, but they can't pay at the first mint
+        let result = wallet.pay_onchain(first_mint, first_keyset, &quote).await;
+        assert!(matches!(result, Err(MonexoWalletError::NotEnoughTokens)));
+
+        let result = wallet.pay_onchain(first_mint, second_keyset, &quote).await;
+        assert!(matches!(result, Err(MonexoWalletError::KeysetNotFound(_))));
+        assert_eq!(40, wallet.get_balance().await?);
+        Ok(())
     }
 
     #[tokio::test]
-    async fn test_blank_blinded_messages_1000_sats() -> anyhow::Result<()> {
+    async fn test_get_balance_by_currency() -> anyhow::Result<()> {
+        let usd_keys = MintKeyset::new("mykey", "usd");
+        let ugx_keys = MintKeyset::new("mykey", "ugx");
+
         let localstore = SqliteLocalStore::with_in_memory().await?;
-        let wallet_keyset = create_test_wallet_keyset()?;
         let mut tx = localstore.begin_tx().await?;
-        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        for (keys, unit, amount) in [
+            (&usd_keys, CurrencyUnit::Usd, 8),
+            (&ugx_keys, CurrencyUnit::Ugx, 32),
+        ] {
+            localstore
+                .upsert_keyset(
+                    &mut tx,
+                    &WalletKeyset::new(
+                        &KeysetId::new(&keys.keyset_id)?,
+                        unit,
+                        0,
+                        keys.public_keys.clone(),
+                        true,
+                    ),
+                )
+                .await?;
+            localstore
+                .add_proofs(
+                    &mut tx,
+                    &Proofs::new(vec![Proof::new(
+                        amount,
+                        format!("secret{amount}"),
+                        keys.public_keys[&1],
+                        keys.keyset_id.clone(),
+                    )]),
+                )
+                .await?;
+        }
         tx.commit().await?;
 
-        let client = create_mock();
-
         let wallet = WalletBuilder::new()
-            .with_client(client)
+            .with_client(create_mock())
             .with_localstore(localstore)
             .build()
             .await?;
-        let result = wallet
-            .create_blank(1000.into(), &KeysetId::new("00d31cecf59d18c0")?)
-            .await;
-        println!("{:?}", result);
-        assert!(result.is_ok());
-        let result = result.unwrap();
-        assert!(result.len() == 10);
-        assert!(result.first().unwrap().0.amount == 1);
 
+        let balances = wallet.get_balance_by_currency().await?;
+        assert_eq!(2, balances.len());
+        assert_eq!(Some(&8), balances.get(&CurrencyUnit::Usd));
+        assert_eq!(Some(&32), balances.get(&CurrencyUnit::Ugx));
+        assert_eq!(40, wallet.get_balance().await?);
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_blank_blinded_messages_zero_sats() -> anyhow::Result<()> {
-        let localstore = SqliteLocalStore::with_in_memory().await?;
-        let wallet_keyset = create_test_wallet_keyset()?;
+    async fn test_exchange_currency() -> anyhow::Result<()> {
+        let (localstore, usd_keyset) = create_localstore_with_proofs(&[2, 8, 32]).await?;
+        let ugx_keys = MintKeyset::new("mykey", "ugx");
+        let ugx_keyset = WalletKeyset::new(
+            &KeysetId::new(&ugx_keys.keyset_id)?,
+            CurrencyUnit::Ugx,
+            0,
+            ugx_keys.public_keys.clone(),
+            false,
+        );
         let mut tx = localstore.begin_tx().await?;
-        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        localstore.upsert_keyset(&mut tx, &ugx_keyset).await?;
         tx.commit().await?;
 
-        let client = create_mock();
+        let mut client = create_mock();
+        client.expect_get_info().returning(|_| {
+            Ok(MintInfoResponse {
+                ugx_exchange_rate: Some(2.5),
+                ..Default::default()
+            })
+        });
+        let signing_keys = [ugx_keys.clone()];
+        client
+            .expect_post_exchange()
+            .returning(move |_, amount, inputs, outputs| {
+                assert_eq!(10, amount);
+                assert_eq!(10, inputs.total_amount());
+                Ok(PostCurrencyExchangeResponse {
+                    signatures: sign_outputs(&signing_keys, &outputs).signatures,
+                })
+            });
 
         let wallet = WalletBuilder::new()
             .with_client(client)
             .with_localstore(localstore)
             .build()
             .await?;
+
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
         let result = wallet
-            .create_blank(0.into(), &KeysetId::new("00d31cecf59d18c0")?)
-            .await;
-        println!("{:?}", result);
-        assert!(result.is_ok());
-        assert!(result.unwrap().is_empty());
+            .exchange_currency(&mint_url, &usd_keyset, &ugx_keyset, 10)
+            .await?;
 
+        assert_eq!(Some(CurrencyUnit::Ugx), result.currency_unit);
+        assert_eq!(25, result.total_amount());
+        let balances = wallet.get_balance_by_currency().await?;
+        assert_eq!(Some(&32), balances.get(&CurrencyUnit::Usd));
+        assert_eq!(Some(&25), balances.get(&CurrencyUnit::Ugx));
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_blank_blinded_messages_serialize() -> anyhow::Result<()> {
-        let localstore = SqliteLocalStore::with_in_memory().await?;
-        let wallet_keyset = create_test_wallet_keyset()?;
-        let mut tx = localstore.begin_tx().await?;
-        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
-        tx.commit().await?;
+    async fn test_exchange_currency_without_rate() -> anyhow::Result<()> {
+        let (localstore, usd_keyset) = create_localstore_with_proofs(&[2, 8]).await?;
+        let ugx_keys = MintKeyset::new("mykey", "ugx");
+        let ugx_keyset = WalletKeyset::new(
+            &KeysetId::new(&ugx_keys.keyset_id)?,
+            CurrencyUnit::Ugx,
+            0,
+            ugx_keys.public_keys.clone(),
+            false,
+        );
 
-        let client = create_mock();
+        let mut client = create_mock();
+        client
+            .expect_get_info()
+            .returning(|_| Ok(MintInfoResponse::default()));
 
         let wallet = WalletBuilder::new()
             .with_client(client)
@@ -765,116 +4647,182 @@ mod tests {
             .build()
             .await?;
 
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
         let result = wallet
-            .create_blank(4000.into(), &KeysetId::new("00d31cecf59d18c0")?)
-            .await?;
-        for (blinded_message, _, _) in result {
-            let out = serde_json::to_string(&blinded_message)?;
-            assert!(!out.is_empty());
-        }
+            .exchange_currency(&mint_url, &usd_keyset, &ugx_keyset, 10)
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(MonexoWalletError::ExchangeNotSupported(..))
+        ));
+        assert_eq!(10, wallet.get_balance().await?);
         Ok(())
     }
 
-    // #[tokio::test]
-    // async fn test_mint_tokens() -> anyhow::Result<()> {
-    //     let mint_response =
-    //         read_fixture_as::<PostMintOnchainResponse>("post_mint_response_20.json")?;
-
-    //     let mut client = create_mock();
-    //     client
-    //         .expect_post_mint_onchain()
-    //         .returning(move |_, _, _| Ok(mint_response.clone()));
-
-    //     let localstore = SqliteLocalStore::with_in_memory().await?;
-    //     let wallet_keyset = create_test_wallet_keyset()?;
-    //     let mut tx = localstore.begin_tx().await?;
-    //     localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
-    //     tx.commit().await?;
+    /// Pays a melt quote of 80 with a fee reserve of 30 from proofs worth 96. The mock mint
+    /// assigns `change` to the blanks and signs them, `tamper` breaks the change signatures.
+    /// Returns the balance and the melted amount in the history.
+    async fn pay_onchain_with_change(change: &[u64], tamper: bool) -> anyhow::Result<(u64, u64)> {
+        let (wallet, result) = try_pay_onchain_with_change(change, tamper).await?;
+        result?;
 
-    //     let wallet = WalletBuilder::new()
-    //         .with_client(client)
-    //         .with_localstore(localstore)
-    //         .build()
-    //         .await?;
-    //     let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let melted = wallet
+            .get_history()
+            .await?
+            .into_iter()
+            .find(|entry| entry.kind == HistoryKind::Melt)
+            .expect("melt not in history")
+            .amount;
+        Ok((wallet.get_balance().await?, melted))
+    }
 
-    //     let result = wallet
-    //         .mint_tokens(
-    //             &mint_url,
-    //             &wallet_keyset,
-    //             20.into(),
-    //             "hash".to_string(),
-    //         )
-    //         .await?;
-    //     assert_eq!(20, result.total_amount());
-    //     result.tokens.into_iter().for_each(|t| {
-    //         assert_eq!(mint_url, t.mint.expect("mint is empty"));
-    //     });
-    //     Ok(())
-    // }
+    /// Pays like [`pay_onchain_with_change`] and returns the wallet with the result of the payment
+    async fn try_pay_onchain_with_change(
+        change: &[u64],
+        tamper: bool,
+    ) -> anyhow::Result<(
+        Wallet<SqliteLocalStore, MockCashuClient>,
+        Result<PostMeltOnchainResponse, MonexoWalletError>,
+    )> {
+        let mint_keys = [MintKeyset::new("mykey", "")];
+        let (localstore, keyset) = create_localstore_with_proofs(&[64, 32]).await?;
 
-    #[tokio::test]
-    async fn test_swap() -> anyhow::Result<()> {
-        let split_response = read_fixture_as::<PostSwapResponse>("post_swap_response_24_40.json")?;
-        let mut client = create_mock();
+        let mut client = MockCashuClient::default();
+        let swap_keys = mint_keys.clone();
         client
             .expect_post_swap()
-            .returning(move |_, _, _| Ok(split_response.clone()));
-        let localstore = SqliteLocalStore::with_in_memory().await?;
-        let keyset = create_test_wallet_keyset()?;
-        let mut tx = localstore.begin_tx().await?;
-        localstore.upsert_keyset(&mut tx, &keyset).await?;
-        tx.commit().await?;
+            .returning(move |_, _, outputs| Ok(sign_outputs(&swap_keys, &outputs)));
+        let change = change.to_vec();
+        client
+            .expect_post_melt_onchain()
+            .returning(move |_, _, _, blanks| {
+                let outputs = change
+                    .iter()
+                    .zip(blanks)
+                    .map(|(amount, blank)| BlindedMessage {
+                        amount: *amount,
+                        ..blank
+                    })
+                    .collect::<Vec<_>>();
+                let mut signatures = sign_outputs(&mint_keys, &outputs).signatures;
+                if tamper {
+                    signatures.iter_mut().for_each(|signature| {
+                        signature.c_ = mint_keys[0].public_keys[&1];
+                    });
+                }
+                Ok(PostMeltOnchainResponse {
+                    state: MeltOnchainState::Pending,
+                    txid: Some("txid".to_owned()),
+                    change: signatures,
+                })
+            });
 
         let wallet = WalletBuilder::new()
             .with_client(client)
             .with_localstore(localstore)
             .build()
             .await?;
+        let quote = PostMeltQuoteOnchainResponse {
+            quote: "quote".to_owned(),
+            description: None,
+            amount: 80,
+            fee: 30,
+            state: MeltOnchainState::Unpaid,
+            expiry: 0,
+        };
 
-        let tokens = read_fixture("token_64.cashu")?.try_into()?;
         let mint_url = Url::parse("http://127.0.0.1:3338")?;
-        let result = wallet
-            .swap_tokens(&mint_url, &keyset, &tokens, 20.into())
-            .await?;
-
-        let first = result.0;
-
-        assert_eq!(CurrencyUnit::Usd, first.clone().currency_unit.unwrap());
-        assert_eq!(24, first.total_amount());
+        let result = wallet.pay_onchain(&mint_url, &keyset, &quote).await;
+        Ok((wallet, result))
+    }
 
-        let second = result.1;
+    #[tokio::test]
+    async fn test_pay_onchain_change() -> anyhow::Result<()> {
+        // the transfer needed 6 of the fee reserve of 30, the mint returns 24 on 2 of 5 blanks
+        assert_eq!(
+            (96 - 80 + 24, 56),
+            pay_onchain_with_change(&[16, 8], false).await?
+        );
+        Ok(())
+    }
 
-        assert_eq!(CurrencyUnit::Usd, second.clone().currency_unit.unwrap());
-        assert_eq!(40, second.total_amount());
+    #[tokio::test]
+    async fn test_pay_onchain_without_change() -> anyhow::Result<()> {
+        // a mint not supporting change returns no signatures
+        assert_eq!((96 - 80, 80), pay_onchain_with_change(&[], false).await?);
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_get_balance() -> anyhow::Result<()> {
-        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
-        let fixture: TokenV3 = fixture.try_into()?;
-        let local_store = SqliteLocalStore::with_in_memory().await?;
-        let mut tx = local_store.begin_tx().await?;
-        local_store.add_proofs(&mut tx, &fixture.proofs()).await?;
-        tx.commit().await?;
+    async fn test_pay_onchain_invalid_change() -> anyhow::Result<()> {
+        // the inputs are spent even if the change can't be unblinded
+        assert_eq!(
+            (96 - 80, 80),
+            pay_onchain_with_change(&[16, 8], true).await?
+        );
+        Ok(())
+    }
 
-        let wallet = WalletBuilder::new()
-            .with_client(create_mock())
-            .with_localstore(local_store)
-            .build()
-            .await?;
+    #[tokio::test]
+    async fn test_pay_onchain_change_exceeds_amount() -> anyhow::Result<()> {
+        let (wallet, result) = try_pay_onchain_with_change(&[64, 32], false).await?;
 
-        let result = wallet.get_balance().await?;
-        assert_eq!(60, result);
+        assert!(matches!(
+            result,
+            Err(MonexoWalletError::UnexpectedResponse(_))
+        ));
+        // the spent inputs are removed and the change of the swap before is kept
+        assert_eq!(96 - 80, wallet.get_balance().await?);
         Ok(())
     }
 
+    /// Creates a localstore with a single active usd keyset and proofs of the given amounts
+    async fn create_localstore_with_proofs(
+        amounts: &[u64],
+    ) -> anyhow::Result<(SqliteLocalStore, WalletKeyset)> {
+        let mint_keys = MintKeyset::new("mykey", "");
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&mint_keys.keyset_id)?,
+            CurrencyUnit::Usd,
+            0,
+            mint_keys.public_keys.clone(),
+            true,
+        )
+        .with_mint_url(&Url::parse("http://127.0.0.1:3338")?);
+
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        localstore
+            .add_proofs(
+                &mut tx,
+                &Proofs::new(
+                    amounts
+                        .iter()
+                        .map(|amount| {
+                            Proof::new(
+                                *amount,
+                                format!("secret{amount}"),
+                                mint_keys.public_keys[&1],
+                                mint_keys.keyset_id.clone(),
+                            )
+                        })
+                        .collect(),
+                ),
+            )
+            .await?;
+        tx.commit().await?;
+        Ok((localstore, keyset))
+    }
+
     fn create_test_wallet_keyset() -> anyhow::Result<WalletKeyset> {
         let pub_keys = read_fixture_as::<HashMap<u64, PublicKey>>("pub_keys.json")?;
         let keyset_id = KeysetId::new("00d31cecf59d18c0")?;
 
-        let wallet_keyset = WalletKeyset::new(&keyset_id, 0, pub_keys.clone(), true);
+        let wallet_keyset =
+            WalletKeyset::new(&keyset_id, CurrencyUnit::Usd, 0, pub_keys.clone(), true)
+                .with_mint_url(&Url::parse("http://127.0.0.1:3338")?);
         Ok(wallet_keyset)
     }
 }